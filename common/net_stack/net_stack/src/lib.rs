@@ -8,7 +8,12 @@ mod socket;
 mod socket_pair;
 
 extern crate alloc;
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
 use core::{
     cmp,
     fmt::Debug,
@@ -29,18 +34,35 @@ use basic::{
 use downcast_rs::{impl_downcast, DowncastSync};
 use interface::{
     define_unwind_for_NetDomain, Basic, DeviceBase, DomainType, NetDeviceDomain, NetDomain,
-    SocketArgTuple, SocketID,
+    SocketControl, SocketID, TaskDomain, SCM_MAX_FD,
 };
 use log::{debug, info};
 use lose_net_stack::{connection::NetServer, MacAddress};
 use shared_heap::{DBox, DVec};
 use spin::Once;
 
-use crate::{error::to_alien_error, nic::NetMod, socket::Socket, socket_pair::SocketPair};
+use crate::{
+    error::to_alien_error,
+    nic::NetMod,
+    socket::Socket,
+    socket_pair::{Ancillary, SocketPair},
+};
 
 static NET_INTERFACE: Once<Arc<dyn NetDeviceDomain>> = Once::new();
+static TASK_DOMAIN: Once<Arc<dyn TaskDomain>> = Once::new();
 static SOCKET_MAP: Mutex<BTreeMap<SocketID, Arc<dyn SocketFile>>> = Mutex::new(BTreeMap::new());
 static SOCKET_ID: AtomicUsize = AtomicUsize::new(0);
+/// Tasks waiting in epoll for a socket to change state, keyed by socket
+static POLL_WAITERS: Mutex<BTreeMap<SocketID, PollWaiters>> = Mutex::new(BTreeMap::new());
+/// The other end of each socket pair
+static PEERS: Mutex<BTreeMap<SocketID, SocketID>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Default)]
+struct PollWaiters {
+    tids: Vec<usize>,
+    /// How often the waiters of the socket were woken, which re-arms `EPOLLET` entries
+    wakes: usize,
+}
 
 pub trait SocketFile: Send + Sync + DowncastSync {
     fn write_at(&self, _offset: usize, buffer: &[u8]) -> AlienResult<usize>;
@@ -95,6 +117,8 @@ impl DeviceBase for NetStack {
                 .analysis_net_data(&mut buf.as_mut_slice()[..len]);
             shared_buf = buf;
         }
+        // we do not know which sockets the data was for
+        wake_poll_waiters(inet_sockets())?;
         info!("<handle_irq> net stack handle irq success");
         Ok(())
     }
@@ -120,15 +144,20 @@ impl NetDomain for NetStack {
     }
 
     fn socket_pair(&self, _domain: Domain, _ty: SocketType) -> AlienResult<(SocketID, SocketID)> {
-        let socket_pair = SocketPair::new();
-        let id = add_socket_file(socket_pair);
-        // same socket pair
-        Ok((id, id))
+        let (end1, end2) = SocketPair::new();
+        let id1 = add_socket_file(end1);
+        let id2 = add_socket_file(end2);
+        let mut peers = PEERS.lock();
+        peers.insert(id1, id2);
+        peers.insert(id2, id1);
+        Ok((id1, id2))
     }
 
     fn remove_socket(&self, socket_id: SocketID) -> AlienResult<()> {
+        let peer = PEERS.lock().remove(&socket_id);
         remove_socket_file(socket_id);
-        Ok(())
+        // the peer of a socket pair sees the hang up
+        wake_poll_waiters(peer)
     }
 
     fn bind(
@@ -218,41 +247,51 @@ impl NetDomain for NetStack {
             .map_err(to_alien_error)
     }
 
-    // todo!(for reuse arg, if there is no data, we set socket_arg_tuple.len ==0 and return ok(it));
-    fn recv_from(
+    fn recv_msg(
         &self,
         socket_id: SocketID,
-        mut socket_arg_tuple: DBox<SocketArgTuple>,
-    ) -> AlienResult<DBox<SocketArgTuple>> {
+        mut buf: DVec<u8>,
+        mut control: DBox<SocketControl>,
+    ) -> AlienResult<(DVec<u8>, usize, DBox<SocketControl>)> {
         let socket = SOCKET_MAP
             .lock()
             .get(&socket_id)
             .ok_or(AlienError::EINVAL)?
-            .clone()
-            .downcast_arc::<Socket>()
-            .map_err(|_| AlienError::EINVAL)?;
-        let res = socket.recv_from();
-
-        match res {
-            Err(AlienError::EBLOCKING) => {
-                socket_arg_tuple.len = 0;
-                Ok(socket_arg_tuple)
-            }
-            Ok((data, remote_addr)) => {
-                let socket_add_in = SocketAddrIn {
-                    family: Domain::AF_INET as u16,
-                    in_port: remote_addr.port().to_be(),
-                    addr: *remote_addr.ip(),
-                    sin_zero: [0; 8],
-                };
-                let rlen = cmp::min(data.len(), socket_arg_tuple.buf.len());
-                socket_arg_tuple.buf.as_mut_slice()[..rlen].copy_from_slice(&data[..rlen]);
-                *socket_arg_tuple.addr = socket_add_in;
-                socket_arg_tuple.len = rlen;
-                Ok(socket_arg_tuple)
+            .clone();
+        control.rights_len = 0;
+        control.cred = None;
+        let socket = match socket.downcast_arc::<SocketPair>() {
+            Ok(pair) => {
+                let (rlen, ancillary) = pair.recv(buf.as_mut_slice())?;
+                if let Some(ancillary) = ancillary {
+                    let count = cmp::min(ancillary.rights.len(), SCM_MAX_FD);
+                    control.rights[..count].copy_from_slice(&ancillary.rights[..count]);
+                    control.rights_len = count;
+                    control.cred = ancillary.cred;
+                }
+                // the peer may wait for room
+                wake_poll_waiters(receivers(socket_id))?;
+                return Ok((buf, rlen, control));
             }
-            Err(e) => Err(e),
-        }
+            Err(socket) => socket
+                .downcast_arc::<Socket>()
+                .map_err(|_| AlienError::EINVAL)?,
+        };
+        let (data, remote_addr) = socket.recv_from()?;
+        control.addr = SocketAddrIn {
+            family: Domain::AF_INET as u16,
+            in_port: remote_addr.port().to_be(),
+            addr: *remote_addr.ip(),
+            sin_zero: [0; 8],
+        };
+        let copied = cmp::min(data.len(), buf.len());
+        buf.as_mut_slice()[..copied].copy_from_slice(&data[..copied]);
+        // the caller reports a datagram cut off by a short buffer
+        let rlen = match socket.net_type {
+            SocketType::SOCK_DGRAM => data.len(),
+            _ => copied,
+        };
+        Ok((buf, rlen, control))
     }
 
     fn sendto(
@@ -280,9 +319,43 @@ impl NetDomain for NetStack {
         let addr = remote_addr.map(|addr| *addr.deref());
 
         let rlen = socket.inner.clone().sendto(buf.as_slice(), addr).unwrap();
+        wake_poll_waiters(receivers(socket_id))?;
         Ok(rlen)
     }
 
+    fn send_msg(
+        &self,
+        socket_id: SocketID,
+        buf: &DVec<u8>,
+        remote_addr: Option<&DBox<SocketAddrV4>>,
+        control: &DBox<SocketControl>,
+    ) -> AlienResult<usize> {
+        let socket = SOCKET_MAP
+            .lock()
+            .get(&socket_id)
+            .ok_or(AlienError::EINVAL)?
+            .clone();
+        let pair = match socket.downcast_arc::<SocketPair>() {
+            Ok(pair) => pair,
+            // only unix sockets carry control messages
+            Err(_) if control.rights_len != 0 || control.cred.is_some() => {
+                return Err(AlienError::EINVAL)
+            }
+            Err(_) => return self.sendto(socket_id, buf, remote_addr),
+        };
+        if remote_addr.is_some() {
+            return Err(AlienError::EISCONN);
+        }
+        let rights_len = cmp::min(control.rights_len, SCM_MAX_FD);
+        let ancillary = Ancillary {
+            rights: control.rights[..rights_len].to_vec(),
+            cred: control.cred,
+        };
+        let len = pair.send(buf.as_slice(), ancillary)?;
+        wake_poll_waiters(receivers(socket_id))?;
+        Ok(len)
+    }
+
     fn shutdown(&self, socket_id: SocketID, _how: ShutdownFlag) -> AlienResult<()> {
         let socket = SOCKET_MAP
             .lock()
@@ -291,7 +364,8 @@ impl NetDomain for NetStack {
             .clone()
             .downcast_arc::<Socket>()
             .map_err(|_| AlienError::EINVAL)?;
-        socket.inner.clone().close().map_err(to_alien_error)
+        socket.inner.clone().close().map_err(to_alien_error)?;
+        wake_poll_waiters(receivers(socket_id))
     }
 
     fn remote_addr(
@@ -361,7 +435,9 @@ impl NetDomain for NetStack {
             .get(&socket_id)
             .ok_or(AlienError::EINVAL)?
             .clone();
-        socket.write_at(offset as usize, buf.as_slice())
+        let len = socket.write_at(offset as usize, buf.as_slice())?;
+        wake_poll_waiters(receivers(socket_id))?;
+        Ok(len)
     }
 
     fn poll(&self, socket_id: SocketID, events: PollEvents) -> AlienResult<PollEvents> {
@@ -372,16 +448,87 @@ impl NetDomain for NetStack {
             .clone();
         socket.poll(events)
     }
+
+    fn poll_register(&self, socket_id: SocketID, tid: usize) -> AlienResult<()> {
+        let mut waiters = POLL_WAITERS.lock();
+        let waiters = waiters.get_mut(&socket_id).ok_or(AlienError::EINVAL)?;
+        if !waiters.tids.contains(&tid) {
+            waiters.tids.push(tid);
+        }
+        Ok(())
+    }
+
+    fn poll_unregister(&self, socket_id: SocketID, tid: usize) -> AlienResult<()> {
+        if let Some(waiters) = POLL_WAITERS.lock().get_mut(&socket_id) {
+            waiters.tids.retain(|t| *t != tid);
+        }
+        Ok(())
+    }
+
+    fn poll_wakes(&self, socket_id: SocketID) -> AlienResult<usize> {
+        let waiters = POLL_WAITERS.lock();
+        let waiters = waiters.get(&socket_id).ok_or(AlienError::EINVAL)?;
+        Ok(waiters.wakes)
+    }
+}
+
+/// Wake up the tasks waiting in epoll on `sockets`.
+fn wake_poll_waiters(sockets: impl IntoIterator<Item = SocketID>) -> AlienResult<()> {
+    let mut tids = BTreeSet::new();
+    {
+        let mut waiters = POLL_WAITERS.lock();
+        for socket_id in sockets {
+            if let Some(waiters) = waiters.get_mut(&socket_id) {
+                waiters.wakes += 1;
+                tids.extend(waiters.tids.drain(..));
+            }
+        }
+    }
+    for tid in tids {
+        basic::wake_up_wait_task(tid)?;
+    }
+    Ok(())
+}
+
+/// The internet sockets, which all may have received a packet the stack got.
+fn inet_sockets() -> Vec<SocketID> {
+    SOCKET_MAP
+        .lock()
+        .iter()
+        .filter(|(_, socket)| socket.is::<Socket>())
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// The sockets that data sent on `socket_id` can reach: the other end of a socket pair,
+/// or any internet socket, as the receiver may be local.
+fn receivers(socket_id: SocketID) -> Vec<SocketID> {
+    let peer = PEERS.lock().get(&socket_id).copied();
+    match peer {
+        Some(peer) => Vec::from([peer]),
+        None => inet_sockets(),
+    }
 }
 
 fn add_socket_file(socket: Arc<dyn SocketFile>) -> SocketID {
     let id = SOCKET_ID.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
     SOCKET_MAP.lock().insert(id, socket);
+    POLL_WAITERS.lock().insert(id, PollWaiters::default());
     id
 }
 
 fn remove_socket_file(socket_id: SocketID) {
-    SOCKET_MAP.lock().remove(&socket_id);
+    POLL_WAITERS.lock().remove(&socket_id);
+    let socket = SOCKET_MAP.lock().remove(&socket_id);
+    // dropping a socket pair closes the files in flight to it, which may remove sockets
+    drop(socket);
+}
+
+fn task_domain() -> AlienResult<&'static Arc<dyn TaskDomain>> {
+    TASK_DOMAIN.try_call_once(|| match basic::get_domain("task") {
+        Some(DomainType::TaskDomain(task_domain)) => Ok(task_domain),
+        _ => Err(AlienError::ENOSYS),
+    })
 }
 
 define_unwind_for_NetDomain!(NetStack);
@@ -1,55 +1,233 @@
-use alloc::{collections::VecDeque, sync::Arc};
+//! Connected pairs of `AF_UNIX` stream sockets.
+//!
+//! Each end reads from one channel and writes to the other. Control messages are kept in
+//! the channel by the stream position of the data they came with, and a read never
+//! crosses the position where the next one starts, as on Linux.
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use core::cmp;
 
 use basic::{constants::io::PollEvents, sync::Mutex, AlienError, AlienResult};
 
-use crate::SocketFile;
+use crate::{task_domain, SocketFile};
+
+/// Writes block once this many bytes are queued
+const CHANNEL_LIMIT: usize = 0x50000;
+
+/// The control data of one `sendmsg`
+#[derive(Debug, Default)]
+pub struct Ancillary {
+    /// Files in flight, as handles of the task domain
+    pub rights: Vec<usize>,
+    /// pid, uid and gid of the sender
+    pub cred: Option<(u32, u32, u32)>,
+}
+
+impl Ancillary {
+    pub fn is_empty(&self) -> bool {
+        self.rights.is_empty() && self.cred.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Channel {
+    data: VecDeque<u8>,
+    /// Control messages with the stream position of their first byte
+    control: VecDeque<(usize, Ancillary)>,
+    /// Stream position of the front of `data`
+    head: usize,
+    /// One of the ends is gone
+    closed: bool,
+}
 
 pub struct SocketPair {
-    inner: Arc<Mutex<VecDeque<u8>>>,
+    rx: Arc<Mutex<Channel>>,
+    tx: Arc<Mutex<Channel>>,
 }
 
 impl SocketPair {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self {
-            inner: Arc::new(Mutex::new(VecDeque::new())),
-        })
+    pub fn new() -> (Arc<Self>, Arc<Self>) {
+        let a = Arc::new(Mutex::new(Channel::default()));
+        let b = Arc::new(Mutex::new(Channel::default()));
+        let end1 = Arc::new(Self {
+            rx: a.clone(),
+            tx: b.clone(),
+        });
+        let end2 = Arc::new(Self { rx: b, tx: a });
+        (end1, end2)
+    }
+
+    /// Queue `buffer` for the peer, with `ancillary` attached to its first byte.
+    pub fn send(&self, buffer: &[u8], ancillary: Ancillary) -> AlienResult<usize> {
+        let mut channel = self.tx.lock();
+        if channel.closed {
+            return Err(AlienError::EPIPE);
+        }
+        if channel.data.len() > CHANNEL_LIMIT {
+            return Err(AlienError::EBLOCKING);
+        }
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        if !ancillary.is_empty() {
+            let pos = channel.head + channel.data.len();
+            channel.control.push_back((pos, ancillary));
+        }
+        channel.data.extend(buffer.iter());
+        Ok(buffer.len())
+    }
+
+    /// Read queued data up to the next control message, together with the control message
+    /// that came with it. Returns 0 once the peer is gone and all data is read.
+    pub fn recv(&self, buffer: &mut [u8]) -> AlienResult<(usize, Option<Ancillary>)> {
+        let mut channel = self.rx.lock();
+        if channel.data.is_empty() {
+            return if channel.closed {
+                Ok((0, None))
+            } else {
+                Err(AlienError::EBLOCKING)
+            };
+        }
+        let head = channel.head;
+        let ancillary = match channel.control.front() {
+            Some((pos, _)) if *pos == head => channel.control.pop_front().map(|(_, anc)| anc),
+            _ => None,
+        };
+        let mut rlen = cmp::min(channel.data.len(), buffer.len());
+        if let Some((pos, _)) = channel.control.front() {
+            rlen = cmp::min(rlen, pos - head);
+        }
+        channel.data.drain(..rlen).enumerate().for_each(|(i, x)| {
+            buffer[i] = x;
+        });
+        channel.head += rlen;
+        Ok((rlen, ancillary))
+    }
+}
+
+impl Drop for SocketPair {
+    fn drop(&mut self) {
+        self.tx.lock().closed = true;
+        let pending = {
+            let mut rx = self.rx.lock();
+            rx.closed = true;
+            core::mem::take(&mut rx.control)
+        };
+        // no one can receive the files still in flight to this end
+        let rights = pending.into_iter().flat_map(|(_, anc)| anc.rights);
+        release_rights(rights);
+    }
+}
+
+/// Close files in flight that will not be received.
+pub fn release_rights(rights: impl IntoIterator<Item = usize>) {
+    let Ok(task_domain) = task_domain() else {
+        return;
+    };
+    for id in rights {
+        let _ = task_domain.release_held_fd(id);
     }
 }
 
 impl SocketFile for SocketPair {
     fn write_at(&self, _offset: usize, buffer: &[u8]) -> AlienResult<usize> {
-        let mut queue = self.inner.lock();
-        if queue.len() > 0x50000 {
-            Err(AlienError::EBLOCKING)
-        } else {
-            let wlen = buffer.len();
-            queue.extend(buffer.iter());
-            Ok(wlen)
-        }
+        self.send(buffer, Ancillary::default())
     }
 
     fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> AlienResult<usize> {
-        let mut queue = self.inner.lock();
-        let rlen = cmp::min(queue.len(), buffer.len());
-        queue.drain(..rlen).enumerate().for_each(|(i, x)| {
-            buffer[i] = x;
-        });
-        if rlen == 0 {
-            Err(AlienError::EBLOCKING)
-        } else {
-            Ok(rlen)
+        let (rlen, ancillary) = self.recv(buffer)?;
+        // plain reads drop the control data
+        if let Some(ancillary) = ancillary {
+            release_rights(ancillary.rights);
         }
+        Ok(rlen)
     }
 
     fn poll(&self, events: PollEvents) -> AlienResult<PollEvents> {
         let mut res = PollEvents::empty();
-        if events.contains(PollEvents::EPOLLOUT) && self.inner.lock().len() <= 0x50000 {
-            res |= PollEvents::EPOLLOUT;
+        if events.contains(PollEvents::EPOLLOUT) {
+            let tx = self.tx.lock();
+            if tx.closed || tx.data.len() <= CHANNEL_LIMIT {
+                res |= PollEvents::EPOLLOUT;
+            }
         }
-        if events.contains(PollEvents::EPOLLIN) && self.inner.lock().len() > 0 {
-            res |= PollEvents::EPOLLIN;
+        if events.contains(PollEvents::EPOLLIN) {
+            let rx = self.rx.lock();
+            if rx.closed || !rx.data.is_empty() {
+                res |= PollEvents::EPOLLIN;
+            }
         }
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use basic::AlienError;
+
+    use super::{Ancillary, SocketPair, CHANNEL_LIMIT};
+
+    fn cred(pid: u32) -> Ancillary {
+        Ancillary {
+            rights: vec![],
+            cred: Some((pid, 0, 0)),
+        }
+    }
+
+    #[test]
+    fn data_keeps_its_order() {
+        let (a, b) = SocketPair::new();
+        a.send(b"hello ", Ancillary::default()).unwrap();
+        a.send(b"world", Ancillary::default()).unwrap();
+        let mut buf = [0; 8];
+        assert_eq!(b.recv(&mut buf).unwrap().0, 8);
+        assert_eq!(&buf, b"hello wo");
+        assert_eq!(b.recv(&mut buf).unwrap().0, 3);
+        assert_eq!(&buf[..3], b"rld");
+        assert!(matches!(b.recv(&mut buf), Err(AlienError::EBLOCKING)));
+    }
+
+    #[test]
+    fn reads_stop_at_control_messages() {
+        let (a, b) = SocketPair::new();
+        a.send(b"ab", Ancillary::default()).unwrap();
+        a.send(b"cd", cred(7)).unwrap();
+        a.send(b"ef", cred(8)).unwrap();
+        let mut buf = [0; 8];
+        let (len, anc) = b.recv(&mut buf).unwrap();
+        assert_eq!((&buf[..len], anc.is_none()), (&b"ab"[..], true));
+        let (len, anc) = b.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"cd");
+        assert_eq!(anc.unwrap().cred, Some((7, 0, 0)));
+        let (len, anc) = b.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ef");
+        assert_eq!(anc.unwrap().cred, Some((8, 0, 0)));
+    }
+
+    #[test]
+    fn closed_peer() {
+        let (a, b) = SocketPair::new();
+        a.send(b"last", Ancillary::default()).unwrap();
+        drop(a);
+        let mut buf = [0; 8];
+        assert_eq!(b.recv(&mut buf).unwrap().0, 4);
+        assert_eq!(b.recv(&mut buf).unwrap().0, 0);
+        assert!(matches!(
+            b.send(b"x", Ancillary::default()),
+            Err(AlienError::EPIPE)
+        ));
+    }
+
+    #[test]
+    fn full_channel_blocks() {
+        let (a, _b) = SocketPair::new();
+        let chunk = vec![0u8; CHANNEL_LIMIT + 1];
+        assert_eq!(a.send(&chunk, Ancillary::default()).unwrap(), chunk.len());
+        assert!(matches!(
+            a.send(b"x", Ancillary::default()),
+            Err(AlienError::EBLOCKING)
+        ));
+    }
+}
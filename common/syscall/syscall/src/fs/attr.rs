@@ -0,0 +1,115 @@
+use alloc::sync::Arc;
+
+use basic::{
+    constants::io::{OpenFlags, StatFlags},
+    AlienError, AlienResult,
+};
+use interface::{InodeID, TaskDomain, VfsDomain};
+use log::info;
+use shared_heap::DVec;
+
+use crate::fs::user_path_at;
+
+/// An owner argument of -1 leaves the owner unchanged
+const KEEP_ID: u32 = u32::MAX;
+
+/// Open the file at `path_ptr` relative to `dirfd` only to change its attributes.
+fn open_attr_target(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    dirfd: usize,
+    path_ptr: usize,
+    flags: StatFlags,
+) -> AlienResult<InodeID> {
+    if path_ptr == 0 {
+        return Err(AlienError::EFAULT);
+    }
+    let (tmp_buf, len) = task_domain.read_string_from_user(path_ptr, DVec::new_uninit(256))?;
+    let path = core::str::from_utf8(&tmp_buf.as_slice()[..len]).map_err(|_| AlienError::EINVAL)?;
+    info!("<open_attr_target> path: {:?}, flags: {:?}", path, flags);
+    if len == 0 && !flags.contains(StatFlags::AT_EMPTY_PATH) {
+        return Err(AlienError::ENOENT);
+    }
+    let open_flags = if flags.contains(StatFlags::AT_SYMLINK_NOFOLLOW) {
+        OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW
+    } else {
+        OpenFlags::O_PATH
+    };
+    let (_, current_root) = user_path_at(task_domain, dirfd as isize, path)?;
+    vfs.vfs_open(current_root, &tmp_buf, len, 0, open_flags.bits())
+}
+
+/// Change the attributes of the file opened as `id`, closing it afterwards.
+fn set_attr_and_close(
+    vfs: &Arc<dyn VfsDomain>,
+    id: InodeID,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> AlienResult<isize> {
+    let res = vfs.vfs_set_attr(id, mode, uid, gid);
+    vfs.vfs_close(id)?;
+    res.map(|_| 0)
+}
+
+fn owner_arg(id: usize) -> Option<u32> {
+    Some(id as u32).filter(|&id| id != KEEP_ID)
+}
+
+pub fn sys_fchmodat(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    dirfd: usize,
+    path_ptr: usize,
+    mode: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    let flags = StatFlags::from_bits_truncate(flags as u32);
+    // the mode of a symbolic link can not be changed
+    if flags.contains(StatFlags::AT_SYMLINK_NOFOLLOW) {
+        return Err(AlienError::EOPNOTSUPP);
+    }
+    let id = open_attr_target(vfs, task_domain, dirfd, path_ptr, flags)?;
+    set_attr_and_close(vfs, id, Some(mode as u32), None, None)
+}
+
+pub fn sys_fchmod(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    mode: usize,
+) -> AlienResult<isize> {
+    let file = task_domain.get_fd(fd)?;
+    vfs.vfs_set_attr(file, Some(mode as u32), None, None)?;
+    Ok(0)
+}
+
+pub fn sys_fchownat(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    dirfd: usize,
+    path_ptr: usize,
+    uid: usize,
+    gid: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    let flags = StatFlags::from_bits_truncate(flags as u32);
+    let id = open_attr_target(vfs, task_domain, dirfd, path_ptr, flags)?;
+    set_attr_and_close(vfs, id, None, owner_arg(uid), owner_arg(gid))
+}
+
+pub fn sys_fchown(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    uid: usize,
+    gid: usize,
+) -> AlienResult<isize> {
+    let file = task_domain.get_fd(fd)?;
+    vfs.vfs_set_attr(file, None, owner_arg(uid), owner_arg(gid))?;
+    Ok(0)
+}
+
+pub fn sys_umask(task_domain: &Arc<dyn TaskDomain>, mask: usize) -> AlienResult<isize> {
+    task_domain.do_umask(mask as u32).map(|old| old as isize)
+}
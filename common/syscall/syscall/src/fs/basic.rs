@@ -15,7 +15,13 @@ use pod::Pod;
 use shared_heap::{DBox, DVec};
 use vfscore::utils::{VfsFileStat, VfsPollEvents};
 
-use crate::fs::user_path_at;
+use crate::fs::{
+    rw::{copy_range, read_iovecs, read_to_user, write_from_user, write_iovecs, MAX_RW_COUNT},
+    user_path_at,
+};
+
+/// faccessat flag: check with the effective instead of the real ids
+const AT_EACCESS: u32 = 0x200;
 
 pub fn sys_openat(
     vfs: &Arc<dyn VfsDomain>,
@@ -37,7 +43,8 @@ pub fn sys_openat(
         path, flags, mode
     );
     let (_, current_root) = user_path_at(task_domain, dirfd as isize, path)?;
-    let file = vfs.vfs_open(current_root, &tmp_buf, len, mode as _, flags as _)?;
+    let mode = mode as u32 & !task_domain.current_umask()?;
+    let file = vfs.vfs_open(current_root, &tmp_buf, len, mode, flags as _)?;
     let fd = task_domain.add_fd(file)?;
     Ok(fd as isize)
 }
@@ -63,10 +70,7 @@ pub fn sys_write(
     if len == 0 {
         return Ok(0);
     }
-    let mut tmp_buf = DVec::<u8>::new_uninit(len);
-    task_domain.copy_from_user(buf as usize, tmp_buf.as_mut_slice())?;
-    let w = vfs.vfs_write(file, &tmp_buf, len);
-    w.map(|x| x as isize)
+    write_from_user(vfs, task_domain, file, buf as usize, len, None).map(|w| w as isize)
 }
 
 pub fn sys_read(
@@ -77,29 +81,11 @@ pub fn sys_read(
     len: usize,
 ) -> AlienResult<isize> {
     info!("<sys_read> fd: {:?} buf: {:#x} len: {:?}", fd, buf, len);
-    // let now = read_time_us();
     let file = task_domain.get_fd(fd)?;
     if len == 0 {
         return Ok(0);
     }
-    // let get_file_time = read_time_us();
-    // todo!(if DVec.len is 0, talc will panic)
-    let mut tmp_buf = DVec::<u8>::new_uninit(len);
-    let r;
-    (tmp_buf, r) = vfs.vfs_read(file, tmp_buf)?;
-    // let read_file_time = read_time_us();
-    task_domain.copy_to_user(buf, &tmp_buf.as_slice()[..r])?;
-    // let copy_to_user_time = read_time_us();
-    // if len == 4096 {
-    //     println_color!(
-    //         31,
-    //         "sys_read: get_file_time: {}us, read_file_time: {}us, copy_to_user_time: {}us",
-    //         get_file_time - now,
-    //         read_file_time - get_file_time,
-    //         copy_to_user_time - read_file_time
-    //     );
-    // }
-    Ok(r as isize)
+    read_to_user(vfs, task_domain, file, buf, len, None).map(|r| r as isize)
 }
 
 pub fn sys_readv(
@@ -114,23 +100,7 @@ pub fn sys_readv(
         fd, iov, iovcnt
     );
     let file = task_domain.get_fd(fd)?;
-    let mut count = 0;
-    for i in 0..iovcnt {
-        let ptr = iov + i * core::mem::size_of::<IoVec>();
-        let mut iov = IoVec::empty();
-        task_domain.copy_from_user(ptr, iov.as_bytes_mut())?;
-        let base = iov.base;
-        if base == 0 || iov.len == 0 {
-            continue;
-        }
-        let len = iov.len;
-        let mut tmp_buf = DVec::<u8>::new_uninit(len);
-        let r;
-        (tmp_buf, r) = vfs.vfs_read(file, tmp_buf)?;
-        task_domain.copy_to_user(base, &tmp_buf.as_slice()[..r])?;
-        count += r;
-    }
-    Ok(count as isize)
+    read_iovecs(vfs, task_domain, file, iov, iovcnt, None).map(|r| r as isize)
 }
 
 pub fn sys_writev(
@@ -145,22 +115,7 @@ pub fn sys_writev(
         fd, iov, iovcnt
     );
     let file = task_domain.get_fd(fd)?;
-    let mut count = 0;
-    for i in 0..iovcnt {
-        let ptr = iov + i * core::mem::size_of::<IoVec>();
-        let mut iov = IoVec::empty();
-        task_domain.copy_from_user(ptr, iov.as_bytes_mut())?;
-        let base = iov.base;
-        if base == 0 || iov.len == 0 {
-            continue;
-        }
-        let len = iov.len;
-        let mut tmp_buf = DVec::<u8>::new_uninit(len);
-        task_domain.copy_from_user(base, tmp_buf.as_mut_slice())?;
-        let w = vfs.vfs_write(file, &tmp_buf, len)?;
-        count += w;
-    }
-    Ok(count as isize)
+    write_iovecs(vfs, task_domain, file, iov, iovcnt, None).map(|w| w as isize)
 }
 
 pub fn sys_fstatat(
@@ -183,10 +138,19 @@ pub fn sys_fstatat(
         "<sys_fstatat> path_ptr: {:#x?}, path: {:?}, len:{} flags: {:?}",
         path_ptr, path, len, flag
     );
+    if len == 0 && !flag.contains(StatFlags::AT_EMPTY_PATH) {
+        return Err(AlienError::ENOENT);
+    }
+    let open_flags = if flag.contains(StatFlags::AT_SYMLINK_NOFOLLOW) {
+        // stat the link itself
+        OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW
+    } else {
+        OpenFlags::O_PATH
+    };
     let (_, current_root) = user_path_at(task_domain, dirfd as isize, path)?;
     // todo!(VfsFileStat == FileStat)
     let attr = DBox::<VfsFileStat>::new_uninit();
-    let file = vfs.vfs_open(current_root, &tmp_buf, len, 0, 0)?;
+    let file = vfs.vfs_open(current_root, &tmp_buf, len, 0, open_flags.bits())?;
     let stat = vfs.vfs_getattr(file, attr)?;
     let file_stat = FileStat::from(*stat);
     debug!("<sys_fstatat> file_stat: {:?}", file_stat);
@@ -228,9 +192,13 @@ pub fn sys_faccessat(
         path, flag, mode
     );
     let (_, current_root) = user_path_at(task_domain, dirfd as isize, path)?;
-    let id = vfs.vfs_open(current_root, &tmp_buf, len, 0, 0)?;
+    let id = vfs.vfs_open(current_root, &tmp_buf, len, 0, OpenFlags::O_PATH.bits())?;
     info!("<sys_faccessat> id: {:?}", id);
+    // access checks with the real ids unless asked for the effective ones
+    let real = flag.bits() & AT_EACCESS == 0;
+    let res = vfs.vfs_access(id, mode.bits(), real);
     vfs.vfs_close(id)?;
+    res?;
     Ok(0)
 }
 
@@ -293,7 +261,7 @@ pub fn sys_utimensat(
     let path = core::str::from_utf8(&tmp_buf.as_slice()[..len]).unwrap();
     info!("<utimensat>: path: {:?}", path);
     let (_, current_root) = user_path_at(task_domain, dirfd as isize, path)?;
-    let file_inode = vfs.vfs_open(current_root, &tmp_buf, len, 0, 0)?;
+    let file_inode = vfs.vfs_open(current_root, &tmp_buf, len, 0, OpenFlags::O_PATH.bits())?;
 
     info!("<utimensat> inode id: {:?}", file_inode);
     if times_ptr == 0 {
@@ -331,42 +299,18 @@ pub fn sys_sendfile(
     out_fd: usize,
     in_fd: usize,
     offset_ptr: usize,
-    mut count: usize,
+    count: usize,
 ) -> AlienResult<isize> {
     let in_file = task_domain.get_fd(in_fd)?;
     let out_file = task_domain.get_fd(out_fd)?;
-    const MAX_COUNT: usize = 0x7fff_f000;
-    if count > MAX_COUNT {
-        count = MAX_COUNT;
-    }
-    let mut shared_buf = DVec::new_uninit(512);
-    let mut total = 0;
-
+    let count = min(count, MAX_RW_COUNT);
     let mut offset = if offset_ptr != 0 {
         let offset = task_domain.read_val_from_user::<u64>(offset_ptr)?;
         Some(offset)
     } else {
         None
     };
-    while total < count {
-        let (buf, r) = if let Some(offset) = offset.as_mut() {
-            let (buf, r) = vfs.vfs_read_at(in_file, *offset, shared_buf)?;
-            *offset += r as u64;
-            (buf, r)
-        } else {
-            let (buf, r) = vfs.vfs_read(in_file, shared_buf)?;
-            (buf, r)
-        };
-        if r == 0 {
-            break;
-        }
-        total += r;
-        let w = vfs.vfs_write(out_file, &buf, r)?;
-        if w != r {
-            break;
-        }
-        shared_buf = buf;
-    }
+    let total = copy_range(vfs, in_file, offset.as_mut(), out_file, None, count, false)?;
     debug!("sendfile: write {} bytes,arg count: {}", total, count);
     if let Some(offset) = offset {
         task_domain.write_val_to_user(offset_ptr, &offset)?;
@@ -604,7 +548,12 @@ pub fn sys_chdir(
     let path = core::str::from_utf8(&tmp_buf.as_slice()[..len]).unwrap();
     // basic::println_color!(31,"<sys_chdir> path: {:?}", path);
     let (_, current_root) = user_path_at(task_domain, AT_FDCWD, path)?;
-    let id = vfs.vfs_open(current_root, &tmp_buf, len, 0, 0)?;
+    let id = vfs.vfs_open(current_root, &tmp_buf, len, 0, OpenFlags::O_PATH.bits())?;
+    // the new working directory must be searchable
+    if let Err(e) = vfs.vfs_access(id, FaccessatMode::X_OK.bits(), false) {
+        vfs.vfs_close(id)?;
+        return Err(e);
+    }
     task_domain.set_cwd(id)?;
     Ok(0)
 }
@@ -641,7 +590,8 @@ pub fn sys_mkdirat(
 ) -> AlienResult<isize> {
     let tmp_buf = DVec::<u8>::new_uninit(256);
     let (tmp_buf, len) = task_domain.read_string_from_user(path_ptr, tmp_buf)?;
-    let mut mode = InodeMode::from_bits_truncate(mode as u32);
+    let mode = mode as u32 & !task_domain.current_umask()?;
+    let mut mode = InodeMode::from_bits_truncate(mode);
     let path = core::str::from_utf8(&tmp_buf.as_slice()[..len]).unwrap();
     mode |= InodeMode::DIR;
     info!("<sys_mkdirat> path: {:?},  mode: {:?}", path, mode);
@@ -0,0 +1,87 @@
+//! Shared heap buffers for file I/O.
+//!
+//! The VFS domain reads into and writes from `DVec`s, which live on the shared heap and are
+//! costly to allocate. Small reads and writes take their buffers from [`IO_BUFFERS`] and
+//! return them when done, so the common sizes are allocated once. A buffer serves any
+//! transfer that fits in it: the VFS domain gets a vector that aliases the first bytes of
+//! it. Copies between files are split into [`IO_CHUNK`] pieces and keep reusing the same
+//! buffer.
+
+use alloc::vec::Vec;
+
+use basic::sync::Mutex;
+use shared_heap::DVec;
+
+/// The largest piece a copy between files moves at once
+pub const IO_CHUNK: usize = 64 * 1024;
+/// Larger buffers are not kept
+const MAX_POOLED_LEN: usize = IO_CHUNK;
+const MAX_POOLED: usize = 8;
+
+pub static IO_BUFFERS: BufferPool = BufferPool::new();
+
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<DVec<u8>>>,
+}
+
+/// A buffer from [`BufferPool::take`], which may be larger than asked for
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buf: DVec<u8>,
+}
+
+impl PooledBuffer {
+    /// The first `len` bytes of the buffer, as a vector that aliases them.
+    pub fn part(&mut self, len: usize) -> DVec<u8> {
+        DVec::from_other_rvec_slice(&mut self.buf.as_mut_slice()[..len])
+    }
+}
+
+impl BufferPool {
+    pub const fn new() -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A buffer of at least `len` bytes, the smallest in the pool that is large enough. Its
+    /// contents are undefined.
+    ///
+    /// `len` must not be 0, the shared heap cannot allocate empty buffers.
+    pub fn take(&self, len: usize) -> PooledBuffer {
+        assert_ne!(len, 0);
+        let mut buffers = self.buffers.lock();
+        let fit = buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buf)| buf.len() >= len)
+            .min_by_key(|(_, buf)| buf.len())
+            .map(|(index, _)| index);
+        let buf = match fit {
+            Some(index) => buffers.remove(index),
+            None => {
+                drop(buffers);
+                // round up so that the buffer also serves somewhat larger transfers later
+                DVec::new_uninit(if len <= MAX_POOLED_LEN {
+                    len.next_power_of_two()
+                } else {
+                    len
+                })
+            }
+        };
+        PooledBuffer { buf }
+    }
+
+    /// Give `buf` back for later calls. The oldest buffer is dropped if the pool is full.
+    pub fn put(&self, buf: PooledBuffer) {
+        if buf.buf.len() > MAX_POOLED_LEN {
+            return;
+        }
+        let mut buffers = self.buffers.lock();
+        if buffers.len() == MAX_POOLED {
+            buffers.remove(0);
+        }
+        buffers.push(buf.buf);
+    }
+}
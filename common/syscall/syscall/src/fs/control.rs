@@ -1,9 +1,6 @@
 use alloc::sync::Arc;
 
-use basic::{
-    constants::io::{Fcntl64Cmd, TeletypeCommand},
-    AlienError, AlienResult,
-};
+use basic::{constants::io::Fcntl64Cmd, AlienError, AlienResult};
 use interface::{TaskDomain, VfsDomain};
 use log::{debug, info};
 
@@ -15,9 +12,8 @@ pub fn sys_ioctl(
     argp: usize,
 ) -> AlienResult<isize> {
     let file = task_domain.get_fd(fd)?;
-    let _cmd = TeletypeCommand::try_from(request as u32).map_err(|_| AlienError::EINVAL)?;
     info!(
-        "<sys_ioctl> fd:{:?} request:{:?} argp:{:?}",
+        "<sys_ioctl> fd:{:?} request:{:#x} argp:{:#x}",
         fd, request, argp
     );
     let res = vfs.vfs_ioctl(file, request as u32, argp);
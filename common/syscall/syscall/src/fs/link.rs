@@ -0,0 +1,116 @@
+use alloc::sync::Arc;
+use core::cmp::min;
+
+use basic::{constants::io::StatFlags, AlienError, AlienResult};
+use interface::{TaskDomain, VfsDomain};
+use log::info;
+use shared_heap::DVec;
+
+use crate::fs::user_path_at;
+
+/// `linkat` flag: dereference `oldpath` if it is a symbolic link
+const AT_SYMLINK_FOLLOW: usize = 0x400;
+const PATH_MAX: usize = 4096;
+
+pub fn sys_symlinkat(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    target: *const u8,
+    new_dirfd: usize,
+    link_path: *const u8,
+) -> AlienResult<isize> {
+    if target.is_null() || link_path.is_null() {
+        return Err(AlienError::EFAULT);
+    }
+    let (target_buf, target_len) =
+        task_domain.read_string_from_user(target as usize, DVec::new_uninit(256))?;
+    let (path_buf, path_len) =
+        task_domain.read_string_from_user(link_path as usize, DVec::new_uninit(256))?;
+    if target_len == 0 || path_len == 0 {
+        return Err(AlienError::ENOENT);
+    }
+    let path = core::str::from_utf8(&path_buf.as_slice()[..path_len]).unwrap();
+    info!(
+        "<sys_symlinkat> target: {:?}, new_dirfd: {}, link_path: {:?}",
+        core::str::from_utf8(&target_buf.as_slice()[..target_len]),
+        new_dirfd as isize,
+        path
+    );
+    let (_, current_root) = user_path_at(task_domain, new_dirfd as isize, path)?;
+    vfs.vfs_symlink(current_root, &target_buf, target_len, &path_buf, path_len)?;
+    Ok(0)
+}
+
+pub fn sys_linkat(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    old_dirfd: usize,
+    old_path: *const u8,
+    new_dirfd: usize,
+    new_path: *const u8,
+    flags: usize,
+) -> AlienResult<isize> {
+    if old_path.is_null() || new_path.is_null() {
+        return Err(AlienError::EFAULT);
+    }
+    let empty_path = StatFlags::AT_EMPTY_PATH.bits() as usize;
+    if flags & !(AT_SYMLINK_FOLLOW | empty_path) != 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let (old_buf, old_len) =
+        task_domain.read_string_from_user(old_path as usize, DVec::new_uninit(256))?;
+    let (new_buf, new_len) =
+        task_domain.read_string_from_user(new_path as usize, DVec::new_uninit(256))?;
+    // an empty `old_path` refers to `old_dirfd` itself
+    if (old_len == 0 && flags & empty_path == 0) || new_len == 0 {
+        return Err(AlienError::ENOENT);
+    }
+    let old = core::str::from_utf8(&old_buf.as_slice()[..old_len]).unwrap();
+    let new = core::str::from_utf8(&new_buf.as_slice()[..new_len]).unwrap();
+    info!(
+        "<sys_linkat> old_dirfd: {}, old_path: {:?}, new_dirfd: {}, new_path: {:?}, flags: {:#x}",
+        old_dirfd as isize, old, new_dirfd as isize, new, flags
+    );
+    let (_, old_root) = user_path_at(task_domain, old_dirfd as isize, old)?;
+    let (_, new_root) = user_path_at(task_domain, new_dirfd as isize, new)?;
+    vfs.vfs_link(
+        old_root,
+        &old_buf,
+        old_len,
+        new_root,
+        &new_buf,
+        new_len,
+        flags & AT_SYMLINK_FOLLOW != 0,
+    )?;
+    Ok(0)
+}
+
+pub fn sys_readlinkat(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    dirfd: usize,
+    path: *const u8,
+    buf: usize,
+    size: usize,
+) -> AlienResult<isize> {
+    if path.is_null() {
+        return Err(AlienError::EFAULT);
+    }
+    if size as isize <= 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let (path_buf, path_len) =
+        task_domain.read_string_from_user(path as usize, DVec::new_uninit(256))?;
+    let path = core::str::from_utf8(&path_buf.as_slice()[..path_len]).unwrap();
+    info!(
+        "<sys_readlinkat> dirfd: {}, path: {:?}, size: {}",
+        dirfd as isize, path, size
+    );
+    // an empty path refers to `dirfd` itself, which must then be a link opened with `O_PATH`
+    let (_, current_root) = user_path_at(task_domain, dirfd as isize, path)?;
+    let link_buf = DVec::new_uninit(min(size, PATH_MAX));
+    let (link, len) = vfs.vfs_readlink(current_root, &path_buf, path_len, link_buf)?;
+    // the link is not NUL-terminated
+    task_domain.copy_to_user(buf, &link.as_slice()[..len])?;
+    Ok(len as isize)
+}
@@ -1,15 +1,24 @@
+mod attr;
 mod basic;
+mod buffer;
 mod control;
+mod link;
+mod mount;
 mod poll;
+mod rw;
 
 use alloc::sync::Arc;
 
 use ::basic::{constants::AT_FDCWD, AlienResult};
+pub use attr::*;
 pub use basic::*;
 pub use control::*;
 use interface::{InodeID, TaskDomain, VFS_ROOT_ID};
+pub use link::*;
 use log::info;
+pub use mount::*;
 pub use poll::*;
+pub use rw::*;
 
 fn user_path_at(
     task_domain: &Arc<dyn TaskDomain>,
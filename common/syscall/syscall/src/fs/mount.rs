@@ -0,0 +1,160 @@
+use alloc::sync::Arc;
+
+use basic::{
+    constants::{io::OpenFlags, AT_FDCWD},
+    AlienError, AlienResult,
+};
+use interface::{InodeID, TaskDomain, VfsDomain};
+use log::info;
+use pod::Pod;
+use shared_heap::{DBox, DVec};
+use vfscore::utils::VfsFsStat;
+
+use crate::fs::user_path_at;
+
+/// `struct statfs` of Linux
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod)]
+pub struct StatFs {
+    pub f_type: i64,
+    pub f_bsize: i64,
+    pub f_blocks: u64,
+    pub f_bfree: u64,
+    pub f_bavail: u64,
+    pub f_files: u64,
+    pub f_ffree: u64,
+    pub f_fsid: [i32; 2],
+    pub f_namelen: i64,
+    pub f_frsize: i64,
+    pub f_flags: i64,
+    pub f_spare: [i64; 4],
+}
+
+impl From<VfsFsStat> for StatFs {
+    fn from(value: VfsFsStat) -> Self {
+        Self {
+            f_type: value.f_type,
+            f_bsize: value.f_bsize,
+            f_blocks: value.f_blocks,
+            f_bfree: value.f_bfree,
+            f_bavail: value.f_bavail,
+            f_files: value.f_files,
+            f_ffree: value.f_ffree,
+            f_fsid: value.f_fsid,
+            f_namelen: value.f_namelen as i64,
+            f_frsize: value.f_frsize as i64,
+            f_flags: value.f_flags as i64,
+            f_spare: [0; 4],
+        }
+    }
+}
+
+fn read_user_string(
+    task_domain: &Arc<dyn TaskDomain>,
+    ptr: usize,
+) -> AlienResult<(DVec<u8>, usize)> {
+    if ptr == 0 {
+        return Err(AlienError::EFAULT);
+    }
+    let tmp_buf = DVec::<u8>::new_uninit(256);
+    task_domain.read_string_from_user(ptr, tmp_buf)
+}
+
+/// `mount(source, target, fstype, flags, data)`
+///
+/// `data` is not supported and ignored.
+pub fn sys_mount(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    source: usize,
+    target: usize,
+    fs_type: usize,
+    flags: usize,
+    _data: usize,
+) -> AlienResult<isize> {
+    let (source, source_len) = read_user_string(task_domain, source)?;
+    let (target, target_len) = read_user_string(task_domain, target)?;
+    let (fs_type, fs_type_len) = read_user_string(task_domain, fs_type)?;
+    let target_path =
+        core::str::from_utf8(&target.as_slice()[..target_len]).map_err(|_| AlienError::EINVAL)?;
+    info!(
+        "<sys_mount> source: {:?}, target: {:?}, fs_type: {:?}, flags: {:#x}",
+        core::str::from_utf8(&source.as_slice()[..source_len]),
+        target_path,
+        core::str::from_utf8(&fs_type.as_slice()[..fs_type_len]),
+        flags
+    );
+    let (_, current_root) = user_path_at(task_domain, AT_FDCWD, target_path)?;
+    vfs.vfs_mount(
+        current_root,
+        &source,
+        source_len,
+        &target,
+        target_len,
+        &fs_type,
+        fs_type_len,
+        flags as u32,
+    )?;
+    Ok(0)
+}
+
+/// `umount2(target, flags)`
+pub fn sys_umount2(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    target: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    let (target, target_len) = read_user_string(task_domain, target)?;
+    let target_path =
+        core::str::from_utf8(&target.as_slice()[..target_len]).map_err(|_| AlienError::EINVAL)?;
+    info!(
+        "<sys_umount2> target: {:?}, flags: {:#x}",
+        target_path, flags
+    );
+    let (_, current_root) = user_path_at(task_domain, AT_FDCWD, target_path)?;
+    vfs.vfs_umount(current_root, &target, target_len, flags as u32)?;
+    Ok(0)
+}
+
+fn copy_statfs(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    buf: usize,
+) -> AlienResult<()> {
+    let fs_stat = vfs.vfs_statfs(file, DBox::<VfsFsStat>::new_uninit())?;
+    let stat = StatFs::from(*fs_stat);
+    task_domain.copy_to_user(buf, stat.as_bytes())?;
+    Ok(())
+}
+
+/// `statfs(path, buf)`
+pub fn sys_statfs(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    path: usize,
+    buf: usize,
+) -> AlienResult<isize> {
+    let (tmp_buf, len) = read_user_string(task_domain, path)?;
+    let path = core::str::from_utf8(&tmp_buf.as_slice()[..len]).map_err(|_| AlienError::EINVAL)?;
+    info!("<sys_statfs> path: {:?}, buf: {:#x}", path, buf);
+    let (_, current_root) = user_path_at(task_domain, AT_FDCWD, path)?;
+    let file = vfs.vfs_open(current_root, &tmp_buf, len, 0, OpenFlags::O_PATH.bits())?;
+    let res = copy_statfs(vfs, task_domain, file, buf);
+    vfs.vfs_close(file)?;
+    res.map(|_| 0)
+}
+
+/// `fstatfs(fd, buf)`
+pub fn sys_fstatfs(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    buf: usize,
+) -> AlienResult<isize> {
+    info!("<sys_fstatfs> fd: {}, buf: {:#x}", fd, buf);
+    let file = task_domain.get_fd(fd)?;
+    copy_statfs(vfs, task_domain, file, buf)?;
+    Ok(0)
+}
@@ -1,15 +1,21 @@
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
+use core::cmp::min;
 
 use basic::{
     constants::{
         epoll::{EpollEvent, EpollEventType},
         io::OpenFlags,
+        time::TimeSpec,
     },
-    AlienResult,
+    AlienError, AlienResult,
 };
 use interface::{TaskDomain, VfsDomain};
+use log::debug;
 use pod::Pod;
-use shared_heap::DBox;
+use shared_heap::{DBox, DVec};
+
+/// The most events one `epoll_pwait` returns
+const MAX_EPOLL_EVENTS: usize = 1024;
 
 /// See https://man7.org/linux/man-pages/man2/epoll_create1.2.html
 pub fn sys_poll_createl(
@@ -49,6 +55,104 @@ pub fn sys_poll_ctl(
     Ok(0)
 }
 
+/// See https://man7.org/linux/man-pages/man2/epoll_wait.2.html
+///
+/// A negative `timeout` in milliseconds waits forever.
+pub fn sys_epoll_pwait(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    epfd: usize,
+    events_ptr: usize,
+    max_events: usize,
+    timeout: usize,
+    sigmask: usize,
+) -> AlienResult<isize> {
+    let timeout = timeout as isize;
+    let timeout = if timeout < 0 {
+        None
+    } else {
+        let ms = timeout as usize;
+        Some(TimeSpec::new(ms / 1000, (ms % 1000) * 1_000_000))
+    };
+    epoll_wait(
+        vfs_domain,
+        task_domain,
+        epfd,
+        events_ptr,
+        max_events,
+        timeout,
+        sigmask,
+    )
+}
+
+/// Like [`sys_epoll_pwait`], but with a `timespec` timeout that waits forever if it is NULL.
+pub fn sys_epoll_pwait2(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    epfd: usize,
+    events_ptr: usize,
+    max_events: usize,
+    timeout_ptr: usize,
+    sigmask: usize,
+) -> AlienResult<isize> {
+    let timeout = if timeout_ptr == 0 {
+        None
+    } else {
+        Some(task_domain.read_val_from_user::<TimeSpec>(timeout_ptr)?)
+    };
+    epoll_wait(
+        vfs_domain,
+        task_domain,
+        epfd,
+        events_ptr,
+        max_events,
+        timeout,
+        sigmask,
+    )
+}
+
+fn epoll_wait(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    epfd: usize,
+    events_ptr: usize,
+    max_events: usize,
+    timeout: Option<TimeSpec>,
+    sigmask: usize,
+) -> AlienResult<isize> {
+    if max_events as isize <= 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let inode = task_domain.get_fd(epfd)?;
+    debug!(
+        "<epoll_wait> epfd: {}, max_events: {}, timeout: {:?}, sigmask: {:#x}",
+        epfd, max_events, timeout, sigmask
+    );
+    // the mask only applies while waiting
+    let old_mask = if sigmask != 0 {
+        let mask = task_domain.read_val_from_user::<usize>(sigmask)?;
+        Some(task_domain.do_set_sigmask(mask)?)
+    } else {
+        None
+    };
+    let events = DVec::<EpollEvent>::new_uninit(min(max_events, MAX_EPOLL_EVENTS));
+    let res = vfs_domain.do_poll_wait(inode, events, timeout);
+    if let Some(old_mask) = old_mask {
+        task_domain.do_set_sigmask(old_mask)?;
+    }
+    let (events, count) = res?;
+    let mut buf = Vec::with_capacity(count * core::mem::size_of::<EpollEventTmp>());
+    for event in &events.as_slice()[..count] {
+        let event = EpollEventTmp {
+            events: event.events,
+            data: event.data,
+        };
+        buf.extend_from_slice(event.as_bytes());
+    }
+    task_domain.copy_to_user(events_ptr, &buf)?;
+    Ok(count as isize)
+}
+
 pub fn sys_eventfd2(
     vfs_domain: &Arc<dyn VfsDomain>,
     task_domain: &Arc<dyn TaskDomain>,
@@ -0,0 +1,628 @@
+//! Positional and vectored reads and writes, and copies between files that do not pass
+//! through user memory.
+//!
+//! Small transfers are copied through buffers from [`IO_BUFFERS`]. Large ones pin the user
+//! buffer and hand the VFS domain its pages, so the data is not copied in this domain. Pipes
+//! and the other files without a position may block, so their buffers are never pinned.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::cmp::min;
+
+use basic::{config::FRAME_SIZE, constants::io::*, AlienError, AlienResult};
+use interface::{InodeID, TaskDomain, VfsDomain};
+use log::{debug, error, info};
+use pod::Pod;
+use shared_heap::{DBox, DVec};
+use vfscore::utils::{VfsNodeType, VfsPollEvents};
+
+use crate::fs::buffer::{IO_BUFFERS, IO_CHUNK};
+
+/// The most a single read or write transfers, as on Linux
+pub(crate) const MAX_RW_COUNT: usize = 0x7fff_f000;
+const IOV_MAX: usize = 1024;
+/// Reads and writes of seekable files from this size on pin the user buffer instead of
+/// copying it here
+const PIN_MIN_LEN: usize = IO_CHUNK;
+
+/// High priority request, accepted and ignored
+const RWF_HIPRI: usize = 0x1;
+/// Per-write O_DSYNC
+const RWF_DSYNC: usize = 0x2;
+/// Per-write O_SYNC
+const RWF_SYNC: usize = 0x4;
+/// Fail with EAGAIN instead of waiting for data
+const RWF_NOWAIT: usize = 0x8;
+/// Per-write O_APPEND
+const RWF_APPEND: usize = 0x10;
+const RWF_SUPPORTED: usize = RWF_HIPRI | RWF_DSYNC | RWF_SYNC | RWF_NOWAIT | RWF_APPEND;
+
+const SPLICE_F_NONBLOCK: usize = 0x2;
+
+/// Pipes, sockets and the other special files have no file position.
+fn is_seekable(vfs: &Arc<dyn VfsDomain>, file: InodeID) -> bool {
+    vfs.vfs_lseek(file, SeekFrom::Current(0)).is_ok()
+}
+
+fn is_pipe(vfs: &Arc<dyn VfsDomain>, file: InodeID) -> AlienResult<bool> {
+    Ok(vfs.vfs_inode_type(file)? == VfsNodeType::Fifo)
+}
+
+/// A transfer that already moved some bytes reports them instead of the error.
+fn partial(total: usize, err: AlienError) -> AlienResult<usize> {
+    if total > 0 {
+        Ok(total)
+    } else {
+        Err(err)
+    }
+}
+
+/// Whether `file` reports `event` right now.
+fn is_ready(vfs: &Arc<dyn VfsDomain>, file: InodeID, event: VfsPollEvents) -> AlienResult<bool> {
+    Ok(vfs.vfs_poll(file, event)?.contains(event))
+}
+
+/// A pinned user buffer, unpinned when the guard is dropped, also if the VFS domain
+/// panics during the transfer.
+struct PinGuard<'a> {
+    task_domain: &'a Arc<dyn TaskDomain>,
+    id: usize,
+    /// The physical addresses of the pages of the buffer
+    pages: DVec<usize>,
+    /// Where the buffer starts in its first page
+    start: usize,
+}
+
+impl<'a> PinGuard<'a> {
+    fn new(
+        task_domain: &'a Arc<dyn TaskDomain>,
+        buf: usize,
+        len: usize,
+        writable: bool,
+    ) -> AlienResult<Self> {
+        let id = task_domain.pin_user_buffer(buf, len, writable)?;
+        let start = buf % FRAME_SIZE;
+        // unpins the buffer if the pages cannot be had
+        let mut guard = Self {
+            task_domain,
+            id,
+            pages: DVec::new(0, 0),
+            start,
+        };
+        let pages = DVec::new(0, (start + len).div_ceil(FRAME_SIZE));
+        guard.pages = task_domain.pinned_pages(id, pages)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for PinGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.task_domain.unpin_user_buffer(self.id) {
+            error!("failed to unpin user buffer {}: {:?}", self.id, e);
+        }
+    }
+}
+
+fn read_pinned(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    buf: usize,
+    len: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    let pin = PinGuard::new(task_domain, buf, len, true)?;
+    vfs.vfs_read_user(file, offset, &pin.pages, pin.start, len)
+}
+
+fn write_pinned(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    buf: usize,
+    len: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    let pin = PinGuard::new(task_domain, buf, len, false)?;
+    vfs.vfs_write_user(file, offset, &pin.pages, pin.start, len)
+}
+
+/// Read up to `len` bytes from `file` into the user buffer `buf`, at `offset` or at the
+/// file position.
+pub(super) fn read_to_user(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    buf: usize,
+    len: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    if len >= PIN_MIN_LEN && is_seekable(vfs, file) {
+        return read_pinned(vfs, task_domain, file, buf, len, offset);
+    }
+    // pipes and terminals may wait for data, which must not keep the buffer pinned, and
+    // return what they have in one piece anyway
+    let len = min(len, IO_CHUNK);
+    let mut tmp_buf = IO_BUFFERS.take(len);
+    let res = match offset {
+        Some(offset) => vfs.vfs_read_at(file, offset, tmp_buf.part(len)),
+        None => vfs.vfs_read(file, tmp_buf.part(len)),
+    };
+    let res = res.and_then(|(part, r)| {
+        task_domain
+            .copy_to_user(buf, &part.as_slice()[..r])
+            .map(|_| r)
+    });
+    IO_BUFFERS.put(tmp_buf);
+    res
+}
+
+/// Write up to `len` bytes from the user buffer `buf` to `file`, at `offset` or at the
+/// file position.
+pub(super) fn write_from_user(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    buf: usize,
+    len: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    if len < PIN_MIN_LEN {
+        return write_copied(vfs, task_domain, file, buf, len, offset);
+    }
+    if is_seekable(vfs, file) {
+        return write_pinned(vfs, task_domain, file, buf, len, offset);
+    }
+    // pipes and terminals may wait for room, which must not keep the buffer pinned
+    let mut total = 0;
+    while total < len {
+        let chunk = min(len - total, IO_CHUNK);
+        let offset = offset.map(|offset| offset + total as u64);
+        let w = match write_copied(vfs, task_domain, file, buf + total, chunk, offset) {
+            Ok(w) => w,
+            Err(e) => return partial(total, e),
+        };
+        total += w;
+        if w < chunk {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Write `len` bytes, at most [`IO_CHUNK`], from the user buffer `buf` to `file` through a
+/// pooled buffer.
+fn write_copied(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    buf: usize,
+    len: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    let mut tmp_buf = IO_BUFFERS.take(len);
+    let mut part = tmp_buf.part(len);
+    let res = task_domain
+        .copy_from_user(buf, part.as_mut_slice())
+        .and_then(|_| match offset {
+            Some(offset) => vfs.vfs_write_at(file, offset, &part, len),
+            None => vfs.vfs_write(file, &part, len),
+        });
+    IO_BUFFERS.put(tmp_buf);
+    res
+}
+
+pub(crate) fn user_iovecs(
+    task_domain: &Arc<dyn TaskDomain>,
+    iov: usize,
+    iovcnt: usize,
+) -> AlienResult<Vec<IoVec>> {
+    if iovcnt > IOV_MAX {
+        return Err(AlienError::EINVAL);
+    }
+    let mut iovecs = Vec::with_capacity(iovcnt);
+    for i in 0..iovcnt {
+        let ptr = iov + i * core::mem::size_of::<IoVec>();
+        let mut iov = IoVec::empty();
+        task_domain.copy_from_user(ptr, iov.as_bytes_mut())?;
+        if iov.base == 0 || iov.len == 0 {
+            continue;
+        }
+        iovecs.push(iov);
+    }
+    Ok(iovecs)
+}
+
+/// Fill the user iovecs in order, starting at `offset` or at the file position.
+pub(super) fn read_iovecs(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    iov: usize,
+    iovcnt: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    let mut total = 0;
+    for iov in user_iovecs(task_domain, iov, iovcnt)? {
+        let offset = offset.map(|offset| offset + total as u64);
+        let r = match read_to_user(vfs, task_domain, file, iov.base, iov.len, offset) {
+            Ok(r) => r,
+            Err(e) => return partial(total, e),
+        };
+        total += r;
+        if r < iov.len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Write the user iovecs in order, starting at `offset` or at the file position.
+pub(super) fn write_iovecs(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    file: InodeID,
+    iov: usize,
+    iovcnt: usize,
+    offset: Option<u64>,
+) -> AlienResult<usize> {
+    let mut total = 0;
+    for iov in user_iovecs(task_domain, iov, iovcnt)? {
+        let offset = offset.map(|offset| offset + total as u64);
+        let w = match write_from_user(vfs, task_domain, file, iov.base, iov.len, offset) {
+            Ok(w) => w,
+            Err(e) => return partial(total, e),
+        };
+        total += w;
+        if w < iov.len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Copy up to `count` bytes from `input` to `output` inside the kernel.
+///
+/// Each side uses its offset when given, which is advanced by the bytes moved, or else its
+/// file position. With `once` the copy stops after the first read that returned data, so a
+/// pipe or socket input does not wait for more.
+pub(super) fn copy_range(
+    vfs: &Arc<dyn VfsDomain>,
+    input: InodeID,
+    mut in_offset: Option<&mut u64>,
+    output: InodeID,
+    mut out_offset: Option<&mut u64>,
+    count: usize,
+    once: bool,
+) -> AlienResult<usize> {
+    let mut total = 0;
+    while total < count {
+        let chunk = min(count - total, IO_CHUNK);
+        let mut buf = IO_BUFFERS.take(chunk);
+        let res = match in_offset.as_deref() {
+            Some(offset) => vfs.vfs_read_at(input, *offset, buf.part(chunk)),
+            None => vfs.vfs_read(input, buf.part(chunk)),
+        };
+        let r = match res {
+            Ok((_, r)) => r,
+            Err(e) => {
+                IO_BUFFERS.put(buf);
+                return partial(total, e);
+            }
+        };
+        if r == 0 {
+            IO_BUFFERS.put(buf);
+            break;
+        }
+        // positional writes take the whole vector
+        let part = buf.part(r);
+        let res = match out_offset.as_deref() {
+            Some(offset) => vfs.vfs_write_at(output, *offset, &part, r),
+            None => vfs.vfs_write(output, &part, r),
+        };
+        IO_BUFFERS.put(buf);
+        let w = match res {
+            Ok(w) => w,
+            Err(e) => return partial(total, e),
+        };
+        match in_offset.as_deref_mut() {
+            Some(offset) => *offset += w as u64,
+            None if w < r => {
+                // give the bytes that were not written back to a seekable input
+                let _ = vfs.vfs_lseek(input, SeekFrom::Current(-((r - w) as i64)));
+            }
+            None => {}
+        }
+        if let Some(offset) = out_offset.as_deref_mut() {
+            *offset += w as u64;
+        }
+        total += w;
+        if w < r || once {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn check_rw_flags(flags: usize) -> AlienResult<()> {
+    if flags & !RWF_SUPPORTED != 0 {
+        return Err(AlienError::EOPNOTSUPP);
+    }
+    Ok(())
+}
+
+/// An offset of -1 selects the file position.
+fn rw_offset(offset: isize, allow_current: bool) -> AlienResult<Option<u64>> {
+    match offset {
+        -1 if allow_current => Ok(None),
+        offset if offset < 0 => Err(AlienError::EINVAL),
+        offset => Ok(Some(offset as u64)),
+    }
+}
+
+fn read_user_offset(task_domain: &Arc<dyn TaskDomain>, ptr: usize) -> AlienResult<Option<u64>> {
+    if ptr == 0 {
+        return Ok(None);
+    }
+    let offset = task_domain.read_val_from_user::<u64>(ptr)?;
+    if (offset as i64) < 0 {
+        return Err(AlienError::EINVAL);
+    }
+    Ok(Some(offset))
+}
+
+fn write_user_offset(
+    task_domain: &Arc<dyn TaskDomain>,
+    ptr: usize,
+    offset: Option<u64>,
+) -> AlienResult<()> {
+    if let Some(offset) = offset {
+        task_domain.write_val_to_user(ptr, &offset)?;
+    }
+    Ok(())
+}
+
+pub fn sys_pread64(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    buf: usize,
+    count: usize,
+    offset: isize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_pread64> fd: {:?} buf: {:#x} count: {:?} offset: {:?}",
+        fd, buf, count, offset
+    );
+    let file = task_domain.get_fd(fd)?;
+    let offset = rw_offset(offset, false)?;
+    if count == 0 {
+        return Ok(0);
+    }
+    let count = min(count, MAX_RW_COUNT);
+    read_to_user(vfs, task_domain, file, buf, count, offset).map(|r| r as isize)
+}
+
+pub fn sys_pwrite64(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    buf: usize,
+    count: usize,
+    offset: isize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_pwrite64> fd: {:?} buf: {:#x} count: {:?} offset: {:?}",
+        fd, buf, count, offset
+    );
+    let file = task_domain.get_fd(fd)?;
+    let offset = rw_offset(offset, false)?;
+    if count == 0 {
+        return Ok(0);
+    }
+    let count = min(count, MAX_RW_COUNT);
+    write_from_user(vfs, task_domain, file, buf, count, offset).map(|w| w as isize)
+}
+
+pub fn sys_preadv(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    iov: usize,
+    iovcnt: usize,
+    offset: isize,
+) -> AlienResult<isize> {
+    sys_preadv2(vfs, task_domain, fd, iov, iovcnt, offset, 0)
+}
+
+pub fn sys_pwritev(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    iov: usize,
+    iovcnt: usize,
+    offset: isize,
+) -> AlienResult<isize> {
+    sys_pwritev2(vfs, task_domain, fd, iov, iovcnt, offset, 0)
+}
+
+pub fn sys_preadv2(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    iov: usize,
+    iovcnt: usize,
+    offset: isize,
+    flags: usize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_preadv2> fd: {:?} iov: {:#x} iovcnt: {:?} offset: {:?} flags: {:#x}",
+        fd, iov, iovcnt, offset, flags
+    );
+    check_rw_flags(flags)?;
+    let file = task_domain.get_fd(fd)?;
+    let offset = rw_offset(offset, true)?;
+    if flags & RWF_NOWAIT != 0 && !is_ready(vfs, file, VfsPollEvents::IN)? {
+        return Err(AlienError::EAGAIN);
+    }
+    read_iovecs(vfs, task_domain, file, iov, iovcnt, offset).map(|r| r as isize)
+}
+
+pub fn sys_pwritev2(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    iov: usize,
+    iovcnt: usize,
+    offset: isize,
+    flags: usize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_pwritev2> fd: {:?} iov: {:#x} iovcnt: {:?} offset: {:?} flags: {:#x}",
+        fd, iov, iovcnt, offset, flags
+    );
+    check_rw_flags(flags)?;
+    let file = task_domain.get_fd(fd)?;
+    let mut offset = rw_offset(offset, true)?;
+    if flags & RWF_NOWAIT != 0 && !is_ready(vfs, file, VfsPollEvents::OUT)? {
+        return Err(AlienError::EAGAIN);
+    }
+    if flags & RWF_APPEND != 0 {
+        offset = match offset {
+            // an explicit offset leaves the file position alone
+            Some(_) => Some(vfs.vfs_getattr(file, DBox::new_uninit())?.st_size),
+            None => {
+                vfs.vfs_lseek(file, SeekFrom::End(0))?;
+                None
+            }
+        };
+    }
+    let w = write_iovecs(vfs, task_domain, file, iov, iovcnt, offset)?;
+    if w > 0 && flags & (RWF_SYNC | RWF_DSYNC) != 0 {
+        vfs.vfs_fsync(file)?;
+    }
+    Ok(w as isize)
+}
+
+pub fn sys_copy_file_range(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd_in: usize,
+    off_in: usize,
+    fd_out: usize,
+    off_out: usize,
+    len: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_copy_file_range> fd_in: {:?} off_in: {:#x} fd_out: {:?} off_out: {:#x} len: {:?}",
+        fd_in, off_in, fd_out, off_out, len
+    );
+    if flags != 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let input = task_domain.get_fd(fd_in)?;
+    let output = task_domain.get_fd(fd_out)?;
+    if !is_seekable(vfs, input) || !is_seekable(vfs, output) {
+        return Err(AlienError::EINVAL);
+    }
+    let mut in_offset = read_user_offset(task_domain, off_in)?;
+    let mut out_offset = read_user_offset(task_domain, off_out)?;
+    let len = min(len, MAX_RW_COUNT);
+    if len == 0 {
+        return Ok(0);
+    }
+    if input == output {
+        let pos = vfs.vfs_lseek(input, SeekFrom::Current(0))?;
+        let from = in_offset.unwrap_or(pos);
+        let to = out_offset.unwrap_or(pos);
+        if from < to + len as u64 && to < from + len as u64 {
+            return Err(AlienError::EINVAL);
+        }
+    }
+    let total = copy_range(
+        vfs,
+        input,
+        in_offset.as_mut(),
+        output,
+        out_offset.as_mut(),
+        len,
+        false,
+    )?;
+    write_user_offset(task_domain, off_in, in_offset)?;
+    write_user_offset(task_domain, off_out, out_offset)?;
+    Ok(total as isize)
+}
+
+pub fn sys_splice(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd_in: usize,
+    off_in: usize,
+    fd_out: usize,
+    off_out: usize,
+    len: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_splice> fd_in: {:?} off_in: {:#x} fd_out: {:?} off_out: {:#x} len: {:?} flags: {:#x}",
+        fd_in, off_in, fd_out, off_out, len, flags
+    );
+    let input = task_domain.get_fd(fd_in)?;
+    let output = task_domain.get_fd(fd_out)?;
+    if !is_pipe(vfs, input)? && !is_pipe(vfs, output)? {
+        return Err(AlienError::EINVAL);
+    }
+    let in_seekable = is_seekable(vfs, input);
+    let out_seekable = is_seekable(vfs, output);
+    if (!in_seekable && off_in != 0) || (!out_seekable && off_out != 0) {
+        return Err(AlienError::ESPIPE);
+    }
+    let mut in_offset = read_user_offset(task_domain, off_in)?;
+    let mut out_offset = read_user_offset(task_domain, off_out)?;
+    let len = min(len, MAX_RW_COUNT);
+    if len == 0 {
+        return Ok(0);
+    }
+    if flags & SPLICE_F_NONBLOCK != 0 {
+        let in_ready = in_seekable || is_ready(vfs, input, VfsPollEvents::IN)?;
+        let out_ready = out_seekable || is_ready(vfs, output, VfsPollEvents::OUT)?;
+        if !in_ready || !out_ready {
+            return Err(AlienError::EAGAIN);
+        }
+    }
+    let total = copy_range(
+        vfs,
+        input,
+        in_offset.as_mut(),
+        output,
+        out_offset.as_mut(),
+        len,
+        !in_seekable,
+    )?;
+    debug!("<sys_splice> moved {} bytes", total);
+    write_user_offset(task_domain, off_in, in_offset)?;
+    write_user_offset(task_domain, off_out, out_offset)?;
+    Ok(total as isize)
+}
+
+pub fn sys_tee(
+    vfs: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd_in: usize,
+    fd_out: usize,
+    len: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    info!(
+        "<sys_tee> fd_in: {:?} fd_out: {:?} len: {:?} flags: {:#x}",
+        fd_in, fd_out, len, flags
+    );
+    let input = task_domain.get_fd(fd_in)?;
+    let output = task_domain.get_fd(fd_out)?;
+    if len == 0 {
+        return Ok(0);
+    }
+    let nonblock = flags & SPLICE_F_NONBLOCK != 0;
+    vfs.do_tee(input, output, min(len, MAX_RW_COUNT), nonblock)
+        .map(|r| r as isize)
+}
@@ -9,17 +9,29 @@ mod socket;
 mod system;
 mod task;
 mod time;
+mod trace;
 
 extern crate alloc;
 extern crate log;
 
 use alloc::{boxed::Box, format, sync::Arc, vec, vec::Vec};
 
-use basic::{constants::*, println, AlienResult};
+use basic::{constants::*, println, AlienError, AlienResult};
 use interface::*;
 use shared_heap::DVec;
 
-use crate::{domain::*, fs::*, gui::*, mm::*, signal::*, socket::*, system::*, task::*, time::*};
+use crate::{
+    domain::*,
+    fs::*,
+    gui::*,
+    mm::*,
+    signal::*,
+    socket::*,
+    system::*,
+    task::*,
+    time::*,
+    trace::{Tracer, SYSCALL_TRACE},
+};
 
 #[derive(Debug)]
 struct SysCallDomainImpl {
@@ -29,6 +41,7 @@ struct SysCallDomainImpl {
     net_stack_domain: Arc<dyn NetDomain>,
     gpu_domain: Option<Arc<dyn GpuDomain>>,
     input_domain: Vec<Arc<dyn BufInputDomain>>,
+    tracer: Tracer,
 }
 
 impl SysCallDomainImpl {
@@ -43,6 +56,7 @@ impl SysCallDomainImpl {
         Self {
             vfs_domain,
             task_domain,
+            tracer: Tracer::new(logger.clone()),
             logger,
             net_stack_domain,
             gpu_domain,
@@ -64,26 +78,49 @@ impl SysCallDomain for SysCallDomainImpl {
             interface::Level::Info,
             &DVec::from_slice(log_info.as_bytes()),
         )?;
+        time::init_realtime();
         println!("syscall domain init");
         Ok(())
     }
 
     fn call(&self, syscall_id: usize, args: [usize; 6]) -> AlienResult<isize> {
-        let syscall_name = syscall_name(syscall_id);
-        // let pid = self.task_domain.current_pid().unwrap();
         let tid = basic::current_tid()?;
-        // if syscall_id != SYSCALL_YIELD &&  syscall_id != SYSCALL_WAIT4{
-        //     println!("[tid:{:?}] syscall: {}",tid, syscall_name,);
-        // }
-        if syscall_id == 2003 {
-            let log_info = format!("[tid:{:?}] syscall: 2003", tid);
-            self.logger.log(
-                interface::Level::Info,
-                &DVec::from_slice(log_info.as_bytes()),
-            )?;
-            return Ok(0);
+        self.task_domain.do_syscall_account(true)?;
+        let trace = self
+            .tracer
+            .is_traced(tid, syscall_id)
+            .then(|| self.tracer.start(&self.task_domain, tid, syscall_id, &args));
+        let result = self.dispatch(tid, syscall_id, args);
+        if let Some(trace) = trace {
+            self.tracer.finish(trace, &result)?;
         }
+        self.task_domain.do_syscall_account(false)?;
+        // a handler gets the signal number in `a0`, its frame keeps the result
+        let delivered = self
+            .task_domain
+            .do_deliver_signal(user_return_value(&result))?;
+        match delivered {
+            Some(signum) => Ok(signum as isize),
+            None => result,
+        }
+    }
+}
+
+/// The `a0` user space sees for `result`. Errors are negative errno values.
+fn user_return_value(result: &AlienResult<isize>) -> isize {
+    match result {
+        Ok(value) => *value,
+        Err(e) => *e as isize,
+    }
+}
 
+impl SysCallDomainImpl {
+    fn dispatch(
+        &self,
+        tid: Option<usize>,
+        syscall_id: usize,
+        args: [usize; 6],
+    ) -> AlienResult<isize> {
         match syscall_id {
             19 => sys_eventfd2(&self.vfs_domain, &self.task_domain, args[0], args[1]),
             20 => sys_poll_createl(&self.vfs_domain, &self.task_domain, args[0]),
@@ -95,6 +132,24 @@ impl SysCallDomain for SysCallDomainImpl {
                 args[2],
                 args[3],
             ),
+            22 => sys_epoll_pwait(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+            ),
+            441 => sys_epoll_pwait2(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+            ),
             SYSCALL_GETCWD => sys_getcwd(&self.vfs_domain, &self.task_domain, args[0], args[1]),
             SYSCALL_DUP => sys_dup(&self.task_domain, args[0]),
             SYSCALL_DUP3 => sys_dup2(&self.task_domain, args[0], args[1]),
@@ -198,6 +253,84 @@ impl SysCallDomain for SysCallDomainImpl {
                 args[2],
                 args[3],
             ),
+            67 => sys_pread64(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3] as isize,
+            ),
+            68 => sys_pwrite64(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3] as isize,
+            ),
+            69 => sys_preadv(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3] as isize,
+            ),
+            70 => sys_pwritev(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3] as isize,
+            ),
+            76 => sys_splice(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+                args[5],
+            ),
+            77 => sys_tee(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+            ),
+            285 => sys_copy_file_range(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+                args[5],
+            ),
+            286 => sys_preadv2(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3] as isize,
+                args[5],
+            ),
+            287 => sys_pwritev2(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3] as isize,
+                args[5],
+            ),
             SYSCALL_PSELECT6 => sys_pselect6(
                 &self.vfs_domain,
                 &self.task_domain,
@@ -236,6 +369,68 @@ impl SysCallDomain for SysCallDomainImpl {
                 args[2],
                 args[3],
             ),
+            52 => sys_fchmod(&self.vfs_domain, &self.task_domain, args[0], args[1]),
+            53 => sys_fchmodat(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+            ),
+            54 => sys_fchownat(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+            ),
+            55 => sys_fchown(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+            ),
+            166 => sys_umask(&self.task_domain, args[0]),
+            SYSCALL_SYMLINKAT => sys_symlinkat(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0] as _,
+                args[1],
+                args[2] as _,
+            ),
+            SYSCALL_LINKAT => sys_linkat(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1] as _,
+                args[2],
+                args[3] as _,
+                args[4],
+            ),
+            SYSCALL_READLINKAT => sys_readlinkat(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1] as _,
+                args[2],
+                args[3],
+            ),
+            SYSCALL_MOUNT => sys_mount(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+                args[4],
+            ),
+            SYSCALL_UMOUNT2 => sys_umount2(&self.vfs_domain, &self.task_domain, args[0], args[1]),
+            SYSCALL_STATFS => sys_statfs(&self.vfs_domain, &self.task_domain, args[0], args[1]),
+            SYSCALL_FSTATFS => sys_fstatfs(&self.vfs_domain, &self.task_domain, args[0], args[1]),
             SYSCALL_EXIT => sys_exit(&self.task_domain, args[0]),
             SYSCALL_EXIT_GROUP => sys_exit_group(&self.task_domain, args[0]),
             SYSCALL_SET_TID_ADDRESS => sys_set_tid_address(&self.task_domain, args[0]),
@@ -250,17 +445,44 @@ impl SysCallDomain for SysCallDomainImpl {
                 args[4],
                 args[5],
             ),
+            129 => sys_kill(&self.task_domain, args[0], args[1]),
+            130 => sys_tkill(&self.task_domain, args[0], args[1]),
+            131 => sys_tgkill(&self.task_domain, args[0], args[1], args[2]),
             132 => sys_sigaltstack(&self.task_domain, args[0], args[1]),
+            133 => sys_rt_sigsuspend(&self.task_domain, args[0]),
             SYSCALL_SIGACTION => sys_sigaction(&self.task_domain, args[0], args[1], args[2]),
             SYSCALL_SIGPROCMASK => {
                 sys_sigprocmask(&self.task_domain, args[0], args[1], args[2], args[3])
             }
+            137 => sys_rt_sigtimedwait(&self.task_domain, args[0], args[1], args[2]),
+            138 => sys_rt_sigqueueinfo(&self.task_domain, args[0], args[1], args[2]),
+            139 => sys_rt_sigreturn(&self.task_domain),
             140 => sys_set_priority(&self.task_domain, args[0], args[1], args[2]),
             141 => sys_get_priority(&self.task_domain, args[0], args[1]),
-            SYSCALL_SETPGID => sys_set_pgid(&self.task_domain),
-            SYSCALL_GETPGID => sys_get_pgid(&self.task_domain),
+            SYSCALL_SETPGID => sys_set_pgid(&self.task_domain, args[0], args[1]),
+            SYSCALL_GETPGID => sys_get_pgid(&self.task_domain, args[0]),
+            156 => sys_get_sid(&self.task_domain, args[0]),
             SYSCALL_SETSID => sys_set_sid(&self.task_domain),
             SYSCALL_UNAME => sys_uname(&self.task_domain, args[0]),
+            85 => sys_timerfd_create(&self.vfs_domain, &self.task_domain, args[0], args[1]),
+            86 => sys_timerfd_settime(
+                &self.vfs_domain,
+                &self.task_domain,
+                args[0],
+                args[1],
+                args[2],
+                args[3],
+            ),
+            87 => sys_timerfd_gettime(&self.vfs_domain, &self.task_domain, args[0], args[1]),
+            101 => sys_nanosleep(&self.task_domain, args[0], args[1]),
+            102 => sys_getitimer(&self.task_domain, args[0], args[1]),
+            103 => sys_setitimer(&self.task_domain, args[0], args[1], args[2]),
+            112 => sys_clock_settime(&self.task_domain, args[0], args[1]),
+            114 => sys_clock_getres(&self.task_domain, args[0], args[1]),
+            115 => sys_clock_nanosleep(&self.task_domain, args[0], args[1], args[2], args[3]),
+            153 => sys_times(&self.task_domain, args[0]),
+            165 => sys_getrusage(&self.task_domain, args[0], args[1]),
+            170 => sys_set_time_of_day(&self.task_domain, args[0]),
             SYSCALL_GET_TIME_OF_DAY => sys_get_time_of_day(&self.task_domain, args[0]),
             SYSCALL_GETPID => sys_get_pid(&self.task_domain),
             SYSCALL_GETPPID => sys_get_ppid(&self.task_domain),
@@ -268,6 +490,16 @@ impl SysCallDomain for SysCallDomainImpl {
             SYSCALL_GETEUID => sys_get_euid(&self.task_domain),
             SYSCALL_GETGID => sys_get_gid(&self.task_domain),
             SYSCALL_GETEGID => sys_get_egid(&self.task_domain),
+            143 => sys_setregid(&self.task_domain, args[0], args[1]),
+            144 => sys_setgid(&self.task_domain, args[0]),
+            145 => sys_setreuid(&self.task_domain, args[0], args[1]),
+            146 => sys_setuid(&self.task_domain, args[0]),
+            147 => sys_setresuid(&self.task_domain, args[0], args[1], args[2]),
+            148 => sys_getresuid(&self.task_domain, args[0], args[1], args[2]),
+            149 => sys_setresgid(&self.task_domain, args[0], args[1], args[2]),
+            150 => sys_getresgid(&self.task_domain, args[0], args[1], args[2]),
+            158 => sys_getgroups(&self.task_domain, args[0], args[1]),
+            159 => sys_setgroups(&self.task_domain, args[0], args[1]),
             SYSCALL_GETTID => sys_get_tid(),
             SYSCALL_SOCKET => sys_socket(
                 &self.task_domain,
@@ -309,6 +541,12 @@ impl SysCallDomain for SysCallDomainImpl {
                 args[1],
                 args[2],
             ),
+            242 => sys_accept4(
+                &self.task_domain,
+                &self.vfs_domain,
+                &self.net_stack_domain,
+                [args[0], args[1], args[2], args[3]],
+            ),
             SYSCALL_CONNECT => sys_connect(
                 &self.task_domain,
                 &self.vfs_domain,
@@ -345,6 +583,34 @@ impl SysCallDomain for SysCallDomainImpl {
                 &self.net_stack_domain,
                 [args[0], args[1], args[2], args[3], args[4], args[5]],
             ),
+            211 => sys_sendmsg(
+                &self.task_domain,
+                &self.vfs_domain,
+                &self.net_stack_domain,
+                args[0],
+                args[1],
+                args[2],
+            ),
+            212 => sys_recvmsg(
+                &self.task_domain,
+                &self.vfs_domain,
+                &self.net_stack_domain,
+                args[0],
+                args[1],
+                args[2],
+            ),
+            269 => sys_sendmmsg(
+                &self.task_domain,
+                &self.vfs_domain,
+                &self.net_stack_domain,
+                [args[0], args[1], args[2], args[3]],
+            ),
+            243 => sys_recvmmsg(
+                &self.task_domain,
+                &self.vfs_domain,
+                &self.net_stack_domain,
+                [args[0], args[1], args[2], args[3], args[4]],
+            ),
             SYSCALL_SETSOCKOPT => sys_set_socket_opt(
                 &self.task_domain,
                 &self.vfs_domain,
@@ -422,7 +688,11 @@ impl SysCallDomain for SysCallDomainImpl {
                 args[0],
                 args[1],
             ),
-            _ => panic!("syscall [{}: {}] not found", syscall_id, syscall_name),
+            SYSCALL_TRACE => self.tracer.control(&self.task_domain, args[0], args[1]),
+            _ => {
+                self.tracer.unknown(tid, syscall_id)?;
+                Err(AlienError::ENOSYS)
+            }
         }
     }
 }
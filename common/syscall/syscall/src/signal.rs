@@ -1,12 +1,18 @@
 use alloc::sync::Arc;
 
 use basic::{
-    constants::signal::{SigProcMaskHow, SignalNumber},
+    constants::{
+        signal::{SigProcMaskHow, SignalNumber},
+        time::TimeSpec,
+    },
+    time::read_timer,
     AlienError, AlienResult,
 };
 use interface::TaskDomain;
 use log::info;
 
+use crate::time::timespec_to_clock;
+
 pub fn sys_sigaction(
     task_domain: &Arc<dyn TaskDomain>,
     sig: usize,
@@ -38,3 +44,70 @@ pub fn sys_sigprocmask(
     let how = SigProcMaskHow::try_from(how).map_err(|_| AlienError::EINVAL)?;
     task_domain.do_sigprocmask(how as _, set, oldset)
 }
+
+/// See https://man7.org/linux/man-pages/man2/kill.2.html
+pub fn sys_kill(task_domain: &Arc<dyn TaskDomain>, pid: usize, sig: usize) -> AlienResult<isize> {
+    task_domain.do_kill(pid as isize, sig)
+}
+
+pub fn sys_tkill(task_domain: &Arc<dyn TaskDomain>, tid: usize, sig: usize) -> AlienResult<isize> {
+    if tid as isize <= 0 {
+        return Err(AlienError::EINVAL);
+    }
+    task_domain.do_tgkill(None, tid, sig)
+}
+
+/// See https://man7.org/linux/man-pages/man2/tgkill.2.html
+pub fn sys_tgkill(
+    task_domain: &Arc<dyn TaskDomain>,
+    tgid: usize,
+    tid: usize,
+    sig: usize,
+) -> AlienResult<isize> {
+    if tgid as isize <= 0 || tid as isize <= 0 {
+        return Err(AlienError::EINVAL);
+    }
+    task_domain.do_tgkill(Some(tgid), tid, sig)
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigqueueinfo.2.html
+pub fn sys_rt_sigqueueinfo(
+    task_domain: &Arc<dyn TaskDomain>,
+    tgid: usize,
+    sig: usize,
+    info: usize,
+) -> AlienResult<isize> {
+    if info == 0 {
+        return Err(AlienError::EFAULT);
+    }
+    task_domain.do_sigqueueinfo(tgid, sig, info)
+}
+
+pub fn sys_rt_sigreturn(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+    task_domain.do_sigreturn()
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigtimedwait.2.html
+///
+/// A NULL `timeout` waits forever.
+pub fn sys_rt_sigtimedwait(
+    task_domain: &Arc<dyn TaskDomain>,
+    set: usize,
+    info: usize,
+    timeout: usize,
+) -> AlienResult<isize> {
+    let set = task_domain.read_val_from_user::<usize>(set)?;
+    let deadline = if timeout == 0 {
+        None
+    } else {
+        let timeout = task_domain.read_val_from_user::<TimeSpec>(timeout)?;
+        Some(read_timer() + timespec_to_clock(&timeout)?)
+    };
+    task_domain.do_sigtimedwait(set, info, deadline)
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigsuspend.2.html
+pub fn sys_rt_sigsuspend(task_domain: &Arc<dyn TaskDomain>, mask: usize) -> AlienResult<isize> {
+    let mask = task_domain.read_val_from_user::<usize>(mask)?;
+    task_domain.do_sigsuspend(mask)
+}
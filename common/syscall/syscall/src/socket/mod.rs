@@ -1,17 +1,72 @@
+mod msg;
+
 use alloc::sync::Arc;
-use core::net::{Ipv4Addr, SocketAddrV4};
+use core::{
+    cmp::min,
+    net::{Ipv4Addr, SocketAddrV4},
+};
 
 use basic::{
-    constants::net::{
-        Domain, ShutdownFlag, SocketAddrIn, SocketAddrInRaw, SocketLevel, SocketOption, SocketType,
-        TcpSocketOption, SOCKET_TYPE_MASK,
+    constants::{
+        io::{Fcntl64Cmd, OpenFlags},
+        net::{
+            Domain, ShutdownFlag, SocketAddrIn, SocketAddrInRaw, SocketLevel, SocketOption,
+            SocketType, TcpSocketOption, SOCKET_TYPE_MASK,
+        },
     },
     AlienError, AlienResult,
 };
-use interface::{NetDomain, SocketArgTuple, TaskDomain, VfsDomain};
+use interface::{InodeID, NetDomain, SocketControl, TaskDomain, VfsDomain};
 use log::error;
+pub use msg::*;
 use shared_heap::{DBox, DVec};
 
+/// `socket`, `socketpair` and `accept4` flag: open the socket with O_NONBLOCK
+const SOCK_NONBLOCK: usize = 0o4000;
+/// `socket`, `socketpair` and `accept4` flag: close the socket on exec
+const SOCK_CLOEXEC: usize = 0o2000000;
+const FD_CLOEXEC: usize = 1;
+
+/// Set the file flags that `SOCK_NONBLOCK` and `SOCK_CLOEXEC` in `flags` ask for.
+fn apply_socket_flags(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    inode_id: InodeID,
+    flags: usize,
+) -> AlienResult<()> {
+    if flags & SOCK_NONBLOCK != 0 {
+        let open_flags = vfs_domain.do_fcntl(inode_id, Fcntl64Cmd::F_GETFL as usize, 0)?;
+        let open_flags = open_flags as usize | OpenFlags::O_NONBLOCK.bits();
+        vfs_domain.do_fcntl(inode_id, Fcntl64Cmd::F_SETFL as usize, open_flags)?;
+    }
+    if flags & SOCK_CLOEXEC != 0 {
+        vfs_domain.do_fcntl(inode_id, Fcntl64Cmd::F_SETFD as usize, FD_CLOEXEC)?;
+    }
+    Ok(())
+}
+
+fn is_nonblocking(vfs_domain: &Arc<dyn VfsDomain>, inode_id: InodeID) -> AlienResult<bool> {
+    let open_flags = vfs_domain.do_fcntl(inode_id, Fcntl64Cmd::F_GETFL as usize, 0)?;
+    Ok(OpenFlags::from_bits_truncate(open_flags as usize).contains(OpenFlags::O_NONBLOCK))
+}
+
+/// The address a message is sent to.
+fn read_remote_addr(task_domain: &Arc<dyn TaskDomain>, addr: usize) -> AlienResult<SocketAddrV4> {
+    let addr_raw = task_domain.read_val_from_user::<SocketAddrInRaw>(addr)?;
+    Ok(SocketAddrV4::new(
+        Ipv4Addr::from(addr_raw.addr),
+        addr_raw.in_port.to_be(),
+    ))
+}
+
+/// Let other tasks run while a socket is not ready, failing with EINTR if a signal
+/// arrived.
+fn wait_socket(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<()> {
+    if task_domain.do_has_pending_signal()? {
+        return Err(AlienError::EINTR);
+    }
+    basic::yield_now()
+}
+
 pub fn sys_socket(
     task_domain: &Arc<dyn TaskDomain>,
     vfs_domain: &Arc<dyn VfsDomain>,
@@ -29,6 +84,7 @@ pub fn sys_socket(
     );
     let socket_id = net_stack_domain.socket(domain, socket_type, protocol)?;
     let inode_id = vfs_domain.do_socket(socket_id)?;
+    apply_socket_flags(vfs_domain, inode_id, s_type)?;
     let fd = task_domain.add_fd(inode_id)?;
     Ok(fd as isize)
 }
@@ -55,6 +111,8 @@ pub fn sys_socket_pair(
     let (id1, id2) = net_stack_domain.socket_pair(domain, socket_type)?;
     let inode_id1 = vfs_domain.do_socket(id1)?;
     let inode_id2 = vfs_domain.do_socket(id2)?;
+    apply_socket_flags(vfs_domain, inode_id1, s_type)?;
+    apply_socket_flags(vfs_domain, inode_id2, s_type)?;
     let fd1 = task_domain.add_fd(inode_id1)?;
     let fd2 = task_domain.add_fd(inode_id2)?;
     task_domain.write_val_to_user(sv, &[fd1 as u32, fd2 as u32])?;
@@ -111,33 +169,68 @@ pub fn sys_accept(
     addr: usize,
     addr_len: usize,
 ) -> AlienResult<isize> {
+    sys_accept4(
+        task_domain,
+        vfs_domain,
+        net_stack_domain,
+        [fd, addr, addr_len, 0],
+    )
+}
+
+/// See https://man7.org/linux/man-pages/man2/accept4.2.html
+pub fn sys_accept4(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    args: [usize; 4],
+) -> AlienResult<isize> {
+    let (fd, addr, addr_len, flags) = (args[0], args[1], args[2], args[3]);
     error!(
-        "<sys_accept> fd: {}, addr: {:#x}, addr_len: {}",
-        fd, addr, addr_len
+        "<sys_accept4> fd: {}, addr: {:#x}, addr_len: {}, flags: {:#x}",
+        fd, addr, addr_len, flags
     );
+    if flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+        return Err(AlienError::EINVAL);
+    }
     let inode_id = task_domain.get_fd(fd)?;
     let socket_id = vfs_domain.socket_id(inode_id)?;
-    loop {
-        let new_socket_id = net_stack_domain.accept(socket_id);
-        match new_socket_id {
-            Ok(new_socket_id) => {
-                if addr != 0 {
-                    let remote_addr = DBox::new(SocketAddrIn::default());
-                    let remote_addr = net_stack_domain.remote_addr(new_socket_id, remote_addr)?;
-                    let raw = SocketAddrInRaw::from(*remote_addr);
-                    task_domain.write_val_to_user(addr, &raw)?;
-                    let len = core::mem::size_of::<SocketAddrInRaw>();
-                    task_domain.write_val_to_user(addr_len, &len)?;
-                }
-                let inode_id = vfs_domain.do_socket(new_socket_id)?;
-                let fd = task_domain.add_fd(inode_id)?;
-                return Ok(fd as isize);
-            }
-            Err(AlienError::EBLOCKING) => basic::yield_now()?,
+    let nonblock = is_nonblocking(vfs_domain, inode_id)?;
+    let new_socket_id = loop {
+        match net_stack_domain.accept(socket_id) {
+            Ok(new_socket_id) => break new_socket_id,
+            Err(AlienError::EBLOCKING) if nonblock => return Err(AlienError::EAGAIN),
+            Err(AlienError::EBLOCKING) => wait_socket(task_domain)?,
             Err(err) => return Err(err),
         }
-        // check if there is a EINTR signal
+    };
+    // the accepted socket is closed with its file if anything below fails
+    let new_inode_id = vfs_domain.do_socket(new_socket_id)?;
+    let res = accept_address(task_domain, net_stack_domain, new_socket_id, addr, addr_len)
+        .and_then(|_| apply_socket_flags(vfs_domain, new_inode_id, flags));
+    if let Err(err) = res {
+        vfs_domain.vfs_close(new_inode_id)?;
+        return Err(err);
     }
+    let fd = task_domain.add_fd(new_inode_id)?;
+    Ok(fd as isize)
+}
+
+fn accept_address(
+    task_domain: &Arc<dyn TaskDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    socket_id: usize,
+    addr: usize,
+    addr_len: usize,
+) -> AlienResult<()> {
+    if addr == 0 {
+        return Ok(());
+    }
+    let remote_addr = DBox::new(SocketAddrIn::default());
+    let remote_addr = net_stack_domain.remote_addr(socket_id, remote_addr)?;
+    let raw = SocketAddrInRaw::from(*remote_addr);
+    task_domain.write_val_to_user(addr, &raw)?;
+    let len = core::mem::size_of::<SocketAddrInRaw>() as u32;
+    task_domain.write_val_to_user(addr_len, &len)
 }
 
 pub fn sys_connect(
@@ -189,44 +282,39 @@ pub fn sys_recvfrom(
     );
     let inode_id = task_domain.get_fd(fd)?;
     let socket_id = vfs_domain.socket_id(inode_id)?;
-
-    // todo! DBox::new(SocketArgTuple {} may leak memory
-    let remote_addr = DBox::new(SocketAddrIn::default());
-    let mut tmp_arg_tuple = DBox::new(SocketArgTuple {
-        buf: DVec::new(0, len),
-        addr: remote_addr,
-        len: 0,
-    });
-    loop {
-        let res = net_stack_domain.recv_from(socket_id, tmp_arg_tuple);
-        match res {
-            Ok(arg_tuple) => {
-                if arg_tuple.len != 0 {
-                    task_domain.copy_to_user(buf, &arg_tuple.buf.as_slice()[..arg_tuple.len])?;
-                    if addr != 0 {
-                        let raw = SocketAddrInRaw::from(*arg_tuple.addr);
-                        task_domain.write_val_to_user(addr, &raw)?;
-                        let len = core::mem::size_of::<SocketAddrInRaw>() as u32;
-                        task_domain.write_val_to_user(addr_len, &len)?;
-                    }
-                    return Ok(arg_tuple.len as isize);
-                } else {
-                    tmp_arg_tuple = arg_tuple;
-                    basic::yield_now()?
-                }
-            }
-            // Err(AlienError::EBLOCKING) => {
-            //     // check if it is a non-blocking socket
-            //     // if file.flags.lock().contains(OpenFlags::O_NONBLOCK) {
-            //     //     return Err(LinuxError::EAGAIN);
-            //     // }
-            //     scheduler_domain.yield_now()?
-            // }
-            Err(err) => return Err(err),
-        }
-        // check if there is a EINTR signal
+    let nonblock = flags & MSG_DONTWAIT != 0 || is_nonblocking(vfs_domain, inode_id)?;
+    let control = DBox::new(SocketControl::default());
+    let (data, rlen, control) = recv_blocking(
+        task_domain,
+        net_stack_domain,
+        socket_id,
+        DVec::new(0, len),
+        control,
+        nonblock,
+    )?;
+    // nothing takes the files passed along with the data
+    release_rights(task_domain, &control);
+    // the rest of a datagram that does not fit is lost
+    let copied = min(rlen, data.len());
+    task_domain.copy_to_user(buf, &data.as_slice()[..copied])?;
+    if addr != 0 {
+        // unnamed sockets have an empty address
+        let len = if control.addr.family == 0 {
+            0
+        } else {
+            let raw = SocketAddrInRaw::from(control.addr);
+            task_domain.write_val_to_user(addr, &raw)?;
+            core::mem::size_of::<SocketAddrInRaw>() as u32
+        };
+        task_domain.write_val_to_user(addr_len, &len)?;
+    }
+    if flags & MSG_TRUNC as usize != 0 {
+        Ok(rlen as isize)
+    } else {
+        Ok(copied as isize)
     }
 }
+
 pub fn sys_sendto(
     task_domain: &Arc<dyn TaskDomain>,
     vfs_domain: &Arc<dyn VfsDomain>,
@@ -245,9 +333,7 @@ pub fn sys_sendto(
     task_domain.copy_from_user(buf, data.as_mut_slice())?;
 
     let remote_addr = if addr != 0 {
-        let addr_raw = task_domain.read_val_from_user::<SocketAddrInRaw>(addr)?;
-        let socket_addr =
-            SocketAddrV4::new(Ipv4Addr::from(addr_raw.addr), addr_raw.in_port.to_be());
+        let socket_addr = read_remote_addr(task_domain, addr)?;
         error!("<sys_sendto> remote_addr: {:?}", socket_addr);
         let socket_addr = DBox::new(socket_addr);
         Some(socket_addr)
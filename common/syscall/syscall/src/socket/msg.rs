@@ -0,0 +1,519 @@
+//! `sendmsg`, `recvmsg` and their batched forms, with control messages.
+//!
+//! Files sent with `SCM_RIGHTS` are held by the task domain while in flight, and the
+//! socket carries only their handles. The receiver gets new fds for them, and handles
+//! that are never received are released so that the files get closed.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{cmp::min, mem::size_of};
+
+use basic::{
+    constants::{
+        io::{Fcntl64Cmd, IoVec},
+        net::{SocketAddrIn, SocketAddrInRaw},
+        time::TimeSpec,
+    },
+    time::read_timer,
+    AlienError, AlienResult,
+};
+use interface::{NetDomain, SocketControl, SocketID, TaskDomain, VfsDomain, SCM_MAX_FD};
+use log::error;
+use pod::Pod;
+use shared_heap::{DBox, DVec};
+
+use super::{is_nonblocking, read_remote_addr, wait_socket, FD_CLOEXEC};
+use crate::{
+    fs::{user_iovecs, MAX_RW_COUNT},
+    time::timespec_to_clock,
+};
+
+/// Control data was cut off for lack of room
+const MSG_CTRUNC: u32 = 0x8;
+/// The datagram was longer than the buffer. As a receive flag: return its whole length.
+pub(super) const MSG_TRUNC: u32 = 0x20;
+/// Fail with EAGAIN instead of waiting
+pub(super) const MSG_DONTWAIT: usize = 0x40;
+/// `recvmmsg`: wait only for the first message
+const MSG_WAITFORONE: usize = 0x10000;
+/// Open received files with close-on-exec
+const MSG_CMSG_CLOEXEC: usize = 0x40000000;
+
+const SOL_SOCKET: i32 = 1;
+const SCM_RIGHTS: i32 = 1;
+const SCM_CREDENTIALS: i32 = 2;
+
+/// The most messages one `sendmmsg` or `recvmmsg` handles, as on Linux
+const UIO_MAXIOV: usize = 1024;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod)]
+struct MsgHdr {
+    name: usize,
+    name_len: u32,
+    _pad0: u32,
+    iov: usize,
+    iov_len: usize,
+    control: usize,
+    control_len: usize,
+    flags: u32,
+    _pad1: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod)]
+struct CMsgHdr {
+    len: usize,
+    level: i32,
+    ty: i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod)]
+struct UCred {
+    pid: i32,
+    uid: u32,
+    gid: u32,
+}
+
+const CMSG_HDR_LEN: usize = size_of::<CMsgHdr>();
+/// `msg_len` follows the `msghdr` in a `mmsghdr`
+const MMSG_LEN_OFFSET: usize = size_of::<MsgHdr>();
+const MMSG_HDR_LEN: usize = MMSG_LEN_OFFSET + 8;
+
+const fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+/// Close the files in flight in `control`.
+pub(super) fn release_rights(task_domain: &Arc<dyn TaskDomain>, control: &SocketControl) {
+    for id in &control.rights[..control.rights_len] {
+        let _ = task_domain.release_held_fd(*id);
+    }
+}
+
+/// Receive from `socket_id` into `buf`, waiting for data unless `nonblock`.
+pub(super) fn recv_blocking(
+    task_domain: &Arc<dyn TaskDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    socket_id: SocketID,
+    mut buf: DVec<u8>,
+    mut control: DBox<SocketControl>,
+    nonblock: bool,
+) -> AlienResult<(DVec<u8>, usize, DBox<SocketControl>)> {
+    let len = buf.len();
+    loop {
+        match net_stack_domain.recv_msg(socket_id, buf, control) {
+            Ok(res) => return Ok(res),
+            Err(AlienError::EBLOCKING) if nonblock => return Err(AlienError::EAGAIN),
+            Err(AlienError::EBLOCKING) => wait_socket(task_domain)?,
+            Err(err) => return Err(err),
+        }
+        // the buffers went to the net stack domain with the failed call
+        buf = DVec::new(0, len);
+        control = DBox::new(SocketControl::default());
+    }
+}
+
+/// Copy the data in the iovecs of `msg` into one buffer.
+fn gather(task_domain: &Arc<dyn TaskDomain>, msg: &MsgHdr) -> AlienResult<DVec<u8>> {
+    let iovecs = user_iovecs(task_domain, msg.iov, msg.iov_len)?;
+    let total = iovecs
+        .iter()
+        .fold(0, |sum, iov| sum.saturating_add(iov.len));
+    let mut data = DVec::new_uninit(min(total, MAX_RW_COUNT));
+    let mut offset = 0;
+    for iov in iovecs {
+        let len = min(iov.len, data.len() - offset);
+        if len == 0 {
+            break;
+        }
+        task_domain.copy_from_user(iov.base, &mut data.as_mut_slice()[offset..offset + len])?;
+        offset += len;
+    }
+    Ok(data)
+}
+
+/// Copy `data` to the iovecs in order.
+fn scatter(task_domain: &Arc<dyn TaskDomain>, iovecs: &[IoVec], data: &[u8]) -> AlienResult<()> {
+    let mut offset = 0;
+    for iov in iovecs {
+        let len = min(iov.len, data.len() - offset);
+        if len == 0 {
+            break;
+        }
+        task_domain.copy_to_user(iov.base, &data[offset..offset + len])?;
+        offset += len;
+    }
+    Ok(())
+}
+
+/// Senders may only claim their own pid and ids, unless they are root.
+fn check_cred(task_domain: &Arc<dyn TaskDomain>, cred: &UCred) -> AlienResult<()> {
+    let (ruid, euid, suid) = task_domain.do_getresuid()?;
+    let (rgid, egid, sgid) = task_domain.do_getresgid()?;
+    if euid == 0 {
+        return Ok(());
+    }
+    let pid_ok = cred.pid as usize == task_domain.current_pid()?;
+    let uid_ok = [ruid, euid, suid].contains(&cred.uid);
+    let gid_ok = [rgid, egid, sgid].contains(&cred.gid);
+    if pid_ok && uid_ok && gid_ok {
+        Ok(())
+    } else {
+        Err(AlienError::EPERM)
+    }
+}
+
+/// Read the control messages of `msg` into `control`, holding the files of `SCM_RIGHTS`
+/// until they are received.
+fn read_control(
+    task_domain: &Arc<dyn TaskDomain>,
+    msg: &MsgHdr,
+    control: &mut SocketControl,
+) -> AlienResult<()> {
+    let mut offset = 0;
+    while msg.control != 0 && offset + CMSG_HDR_LEN <= msg.control_len {
+        let hdr = task_domain.read_val_from_user::<CMsgHdr>(msg.control + offset)?;
+        if hdr.len < CMSG_HDR_LEN || hdr.len > msg.control_len - offset {
+            return Err(AlienError::EINVAL);
+        }
+        let data = msg.control + offset + CMSG_HDR_LEN;
+        let data_len = hdr.len - CMSG_HDR_LEN;
+        match (hdr.level, hdr.ty) {
+            (SOL_SOCKET, SCM_RIGHTS) => {
+                for i in 0..data_len / size_of::<i32>() {
+                    if control.rights_len == SCM_MAX_FD {
+                        return Err(AlienError::EINVAL);
+                    }
+                    let fd = task_domain.read_val_from_user::<i32>(data + i * size_of::<i32>())?;
+                    let fd = usize::try_from(fd).map_err(|_| AlienError::EBADF)?;
+                    control.rights[control.rights_len] = task_domain.hold_fd(fd)?;
+                    control.rights_len += 1;
+                }
+            }
+            (SOL_SOCKET, SCM_CREDENTIALS) => {
+                if data_len != size_of::<UCred>() {
+                    return Err(AlienError::EINVAL);
+                }
+                let cred = task_domain.read_val_from_user::<UCred>(data)?;
+                check_cred(task_domain, &cred)?;
+                control.cred = Some((cred.pid as u32, cred.uid, cred.gid));
+            }
+            (SOL_SOCKET, _) => return Err(AlienError::EINVAL),
+            // other levels belong to protocols that have no control messages here
+            _ => {}
+        }
+        offset += cmsg_align(hdr.len);
+    }
+    Ok(())
+}
+
+/// Append a control message with `data` to `buf` if it fits in `space`.
+fn push_cmsg(buf: &mut Vec<u8>, space: usize, ty: i32, data: &[u8]) -> bool {
+    let len = CMSG_HDR_LEN + data.len();
+    if buf.len() + len > space {
+        return false;
+    }
+    let hdr = CMsgHdr {
+        len,
+        level: SOL_SOCKET,
+        ty,
+    };
+    buf.extend_from_slice(hdr.as_bytes());
+    buf.extend_from_slice(data);
+    // the padding of the last message only as far as there is room
+    buf.resize(min(cmsg_align(buf.len()), space), 0);
+    true
+}
+
+fn install_right(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    id: usize,
+    cloexec: bool,
+) -> AlienResult<i32> {
+    let fd = task_domain.install_held_fd(id)?;
+    if cloexec {
+        let res = task_domain.get_fd(fd).and_then(|inode_id| {
+            vfs_domain.do_fcntl(inode_id, Fcntl64Cmd::F_SETFD as usize, FD_CLOEXEC)
+        });
+        if let Err(err) = res {
+            let _ = task_domain.remove_fd(fd);
+            return Err(err);
+        }
+    }
+    Ok(fd as i32)
+}
+
+/// Close the fds of received files again when they cannot be reported.
+fn close_rights(task_domain: &Arc<dyn TaskDomain>, fds: &[i32]) {
+    for fd in fds {
+        let _ = task_domain.remove_fd(*fd as usize);
+    }
+}
+
+/// Write the control messages for `control` to the control buffer of `msg` and update
+/// its length and flags. Files in flight get new fds, those that do not fit are closed.
+/// On failure no new fd stays open.
+fn write_control(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    msg: &mut MsgHdr,
+    control: &SocketControl,
+    flags: usize,
+) -> AlienResult<()> {
+    let space = if msg.control == 0 { 0 } else { msg.control_len };
+    let mut buf = Vec::new();
+    msg.flags = 0;
+    if let Some((pid, uid, gid)) = control.cred {
+        let cred = UCred {
+            pid: pid as i32,
+            uid,
+            gid,
+        };
+        if !push_cmsg(&mut buf, space, SCM_CREDENTIALS, cred.as_bytes()) {
+            msg.flags |= MSG_CTRUNC;
+        }
+    }
+    let rights = &control.rights[..control.rights_len];
+    let room = space.saturating_sub(buf.len() + CMSG_HDR_LEN) / size_of::<i32>();
+    let (rights, dropped) = rights.split_at(min(rights.len(), room));
+    if !dropped.is_empty() {
+        msg.flags |= MSG_CTRUNC;
+    }
+    for id in dropped {
+        let _ = task_domain.release_held_fd(*id);
+    }
+    let cloexec = flags & MSG_CMSG_CLOEXEC != 0;
+    let mut fds = Vec::with_capacity(rights.len());
+    let mut pending = rights.iter();
+    while let Some(id) = pending.next() {
+        match install_right(task_domain, vfs_domain, *id, cloexec) {
+            Ok(fd) => fds.push(fd),
+            Err(err) => {
+                pending.for_each(|id| {
+                    let _ = task_domain.release_held_fd(*id);
+                });
+                close_rights(task_domain, &fds);
+                return Err(err);
+            }
+        }
+    }
+    if !fds.is_empty() {
+        let data = fds
+            .iter()
+            .flat_map(|fd| fd.to_ne_bytes())
+            .collect::<Vec<u8>>();
+        push_cmsg(&mut buf, space, SCM_RIGHTS, &data);
+    }
+    if let Err(err) = task_domain.copy_to_user(msg.control, &buf) {
+        close_rights(task_domain, &fds);
+        return Err(err);
+    }
+    msg.control_len = buf.len();
+    Ok(())
+}
+
+/// Write the address of the sender to the name buffer of `msg`, cut to its length.
+/// Unnamed sockets have an empty address.
+fn write_name(
+    task_domain: &Arc<dyn TaskDomain>,
+    msg: &mut MsgHdr,
+    addr: &SocketAddrIn,
+) -> AlienResult<()> {
+    if msg.name == 0 {
+        return Ok(());
+    }
+    if addr.family == 0 {
+        msg.name_len = 0;
+        return Ok(());
+    }
+    let raw = SocketAddrInRaw::from(*addr);
+    let len = min(msg.name_len as usize, size_of::<SocketAddrInRaw>());
+    task_domain.copy_to_user(msg.name, &raw.as_bytes()[..len])?;
+    msg.name_len = size_of::<SocketAddrInRaw>() as u32;
+    Ok(())
+}
+
+fn send_one(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    fd: usize,
+    msg_ptr: usize,
+    flags: usize,
+) -> AlienResult<usize> {
+    let inode_id = task_domain.get_fd(fd)?;
+    let socket_id = vfs_domain.socket_id(inode_id)?;
+    let nonblock = flags & MSG_DONTWAIT != 0 || is_nonblocking(vfs_domain, inode_id)?;
+    let msg = task_domain.read_val_from_user::<MsgHdr>(msg_ptr)?;
+    let data = gather(task_domain, &msg)?;
+    let remote_addr = match msg.name {
+        0 => None,
+        name => Some(DBox::new(read_remote_addr(task_domain, name)?)),
+    };
+    let mut control = DBox::new(SocketControl::default());
+    if let Err(err) = read_control(task_domain, &msg, &mut control) {
+        release_rights(task_domain, &control);
+        return Err(err);
+    }
+    let res = loop {
+        match net_stack_domain.send_msg(socket_id, &data, remote_addr.as_ref(), &control) {
+            Err(AlienError::EBLOCKING) if nonblock => break Err(AlienError::EAGAIN),
+            Err(AlienError::EBLOCKING) => {
+                if let Err(err) = wait_socket(task_domain) {
+                    break Err(err);
+                }
+            }
+            res => break res,
+        }
+    };
+    // the socket keeps the files only along with some data
+    if !matches!(res, Ok(len) if len > 0) {
+        release_rights(task_domain, &control);
+    }
+    res
+}
+
+fn recv_one(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    fd: usize,
+    msg_ptr: usize,
+    flags: usize,
+) -> AlienResult<usize> {
+    let inode_id = task_domain.get_fd(fd)?;
+    let socket_id = vfs_domain.socket_id(inode_id)?;
+    let nonblock = flags & MSG_DONTWAIT != 0 || is_nonblocking(vfs_domain, inode_id)?;
+    let mut msg = task_domain.read_val_from_user::<MsgHdr>(msg_ptr)?;
+    let iovecs = user_iovecs(task_domain, msg.iov, msg.iov_len)?;
+    let total = iovecs
+        .iter()
+        .fold(0, |sum, iov| sum.saturating_add(iov.len));
+    let (data, rlen, control) = recv_blocking(
+        task_domain,
+        net_stack_domain,
+        socket_id,
+        DVec::new(0, min(total, MAX_RW_COUNT)),
+        DBox::new(SocketControl::default()),
+        nonblock,
+    )?;
+    // the rest of a datagram that does not fit is lost
+    let copied = min(rlen, data.len());
+    if let Err(err) = scatter(task_domain, &iovecs, &data.as_slice()[..copied]) {
+        release_rights(task_domain, &control);
+        return Err(err);
+    }
+    write_control(task_domain, vfs_domain, &mut msg, &control, flags)?;
+    if rlen > copied {
+        msg.flags |= MSG_TRUNC;
+    }
+    write_name(task_domain, &mut msg, &control.addr)?;
+    task_domain.write_val_to_user(msg_ptr, &msg)?;
+    Ok(if flags & MSG_TRUNC as usize != 0 {
+        rlen
+    } else {
+        copied
+    })
+}
+
+/// See https://man7.org/linux/man-pages/man2/sendmsg.2.html
+pub fn sys_sendmsg(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    fd: usize,
+    msg: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    error!(
+        "<sys_sendmsg> fd: {}, msg: {:#x}, flags: {:#x}",
+        fd, msg, flags
+    );
+    send_one(task_domain, vfs_domain, net_stack_domain, fd, msg, flags).map(|len| len as isize)
+}
+
+/// See https://man7.org/linux/man-pages/man2/recvmsg.2.html
+pub fn sys_recvmsg(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    fd: usize,
+    msg: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    error!(
+        "<sys_recvmsg> fd: {}, msg: {:#x}, flags: {:#x}",
+        fd, msg, flags
+    );
+    recv_one(task_domain, vfs_domain, net_stack_domain, fd, msg, flags).map(|len| len as isize)
+}
+
+/// See https://man7.org/linux/man-pages/man2/sendmmsg.2.html
+///
+/// Stops at the first message that fails, which only fails the call if it is the first.
+pub fn sys_sendmmsg(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    args: [usize; 4],
+) -> AlienResult<isize> {
+    let (fd, msgvec, vlen, flags) = (args[0], args[1], args[2], args[3]);
+    error!(
+        "<sys_sendmmsg> fd: {}, msgvec: {:#x}, vlen: {}, flags: {:#x}",
+        fd, msgvec, vlen, flags
+    );
+    let vlen = min(vlen, UIO_MAXIOV);
+    let mut sent = 0;
+    while sent < vlen {
+        let hdr = msgvec + sent * MMSG_HDR_LEN;
+        match send_one(task_domain, vfs_domain, net_stack_domain, fd, hdr, flags) {
+            Ok(len) => task_domain.write_val_to_user(hdr + MMSG_LEN_OFFSET, &(len as u32))?,
+            Err(err) if sent == 0 => return Err(err),
+            Err(_) => break,
+        }
+        sent += 1;
+    }
+    Ok(sent as isize)
+}
+
+/// See https://man7.org/linux/man-pages/man2/recvmmsg.2.html
+///
+/// As on Linux, the timeout is only checked after each message is received.
+pub fn sys_recvmmsg(
+    task_domain: &Arc<dyn TaskDomain>,
+    vfs_domain: &Arc<dyn VfsDomain>,
+    net_stack_domain: &Arc<dyn NetDomain>,
+    args: [usize; 5],
+) -> AlienResult<isize> {
+    let (fd, msgvec, vlen, mut flags, timeout) = (args[0], args[1], args[2], args[3], args[4]);
+    error!(
+        "<sys_recvmmsg> fd: {}, msgvec: {:#x}, vlen: {}, flags: {:#x}, timeout: {:#x}",
+        fd, msgvec, vlen, flags, timeout
+    );
+    let deadline = if timeout == 0 {
+        None
+    } else {
+        let timeout = task_domain.read_val_from_user::<TimeSpec>(timeout)?;
+        Some(read_timer() + timespec_to_clock(&timeout)?)
+    };
+    let vlen = min(vlen, UIO_MAXIOV);
+    let mut received = 0;
+    while received < vlen {
+        let hdr = msgvec + received * MMSG_HDR_LEN;
+        match recv_one(task_domain, vfs_domain, net_stack_domain, fd, hdr, flags) {
+            Ok(len) => task_domain.write_val_to_user(hdr + MMSG_LEN_OFFSET, &(len as u32))?,
+            Err(err) if received == 0 => return Err(err),
+            Err(_) => break,
+        }
+        received += 1;
+        if flags & MSG_WAITFORONE != 0 {
+            flags |= MSG_DONTWAIT;
+        }
+        if deadline.is_some_and(|deadline| read_timer() >= deadline) {
+            break;
+        }
+    }
+    Ok(received as isize)
+}
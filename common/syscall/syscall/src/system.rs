@@ -1,8 +1,10 @@
-use alloc::{sync::Arc, vec};
+use alloc::sync::Arc;
+use core::cmp::min;
 
-use basic::AlienResult;
-use interface::TaskDomain;
+use basic::{AlienError, AlienResult};
+use interface::{DomainType, TaskDomain};
 use pod::Pod;
+use shared_heap::DVec;
 
 pub fn sys_uname(task_domain: &Arc<dyn TaskDomain>, utsname: usize) -> AlienResult<isize> {
     let info = system_info();
@@ -49,17 +51,36 @@ fn system_info() -> Utsname {
     name
 }
 
+/// `getrandom` flag: do not block. Accepted, the generator never blocks
+const GRND_NONBLOCK: usize = 0x1;
+/// `getrandom` flag: read from the `/dev/random` source
+const GRND_RANDOM: usize = 0x2;
+/// The size of the buffer the random domain fills per call
+const RANDOM_CHUNK: usize = 4096;
+
+/// See https://man7.org/linux/man-pages/man2/getrandom.2.html
+///
+/// As on Linux since 5.6, `/dev/random` and `/dev/urandom` are the same generator. It is
+/// seeded when the random domain starts, so neither flag changes how it is read. Images
+/// without a random domain fail with ENOSYS.
 pub fn sys_random(
     task_domain: &Arc<dyn TaskDomain>,
     buf: usize,
     len: usize,
-    _flags: usize,
+    flags: usize,
 ) -> AlienResult<isize> {
-    let mut random_buf = vec![0; len];
-    for v in random_buf.iter_mut() {
-        let time_ms = basic::time::read_time_ms();
-        *v = (time_ms & 0xff) as u8;
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM) != 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let Some(DomainType::EmptyDeviceDomain(random_domain)) = basic::get_domain("random") else {
+        return Err(AlienError::ENOSYS);
+    };
+    let mut written = 0;
+    while written < len {
+        let random_buf = DVec::new_uninit(min(len - written, RANDOM_CHUNK));
+        let random_buf = random_domain.read(random_buf)?;
+        task_domain.copy_to_user(buf + written, random_buf.as_slice())?;
+        written += random_buf.len();
     }
-    task_domain.copy_to_user(buf, &random_buf)?;
     Ok(len as isize)
 }
@@ -0,0 +1,116 @@
+use alloc::sync::Arc;
+
+use basic::AlienResult;
+use interface::TaskDomain;
+
+pub fn sys_getuid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+    task_domain.do_getresuid().map(|(ruid, _, _)| ruid as isize)
+}
+
+pub fn sys_get_euid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+    task_domain.do_getresuid().map(|(_, euid, _)| euid as isize)
+}
+
+pub fn sys_get_gid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+    task_domain.do_getresgid().map(|(rgid, _, _)| rgid as isize)
+}
+
+pub fn sys_get_egid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+    task_domain.do_getresgid().map(|(_, egid, _)| egid as isize)
+}
+
+pub fn sys_setuid(task_domain: &Arc<dyn TaskDomain>, uid: usize) -> AlienResult<isize> {
+    task_domain.do_setuid(uid as u32)?;
+    Ok(0)
+}
+
+pub fn sys_setgid(task_domain: &Arc<dyn TaskDomain>, gid: usize) -> AlienResult<isize> {
+    task_domain.do_setgid(gid as u32)?;
+    Ok(0)
+}
+
+pub fn sys_setreuid(
+    task_domain: &Arc<dyn TaskDomain>,
+    ruid: usize,
+    euid: usize,
+) -> AlienResult<isize> {
+    task_domain.do_setreuid(ruid as u32, euid as u32)?;
+    Ok(0)
+}
+
+pub fn sys_setregid(
+    task_domain: &Arc<dyn TaskDomain>,
+    rgid: usize,
+    egid: usize,
+) -> AlienResult<isize> {
+    task_domain.do_setregid(rgid as u32, egid as u32)?;
+    Ok(0)
+}
+
+pub fn sys_setresuid(
+    task_domain: &Arc<dyn TaskDomain>,
+    ruid: usize,
+    euid: usize,
+    suid: usize,
+) -> AlienResult<isize> {
+    task_domain.do_setresuid(ruid as u32, euid as u32, suid as u32)?;
+    Ok(0)
+}
+
+pub fn sys_setresgid(
+    task_domain: &Arc<dyn TaskDomain>,
+    rgid: usize,
+    egid: usize,
+    sgid: usize,
+) -> AlienResult<isize> {
+    task_domain.do_setresgid(rgid as u32, egid as u32, sgid as u32)?;
+    Ok(0)
+}
+
+/// Write the real, effective and saved id to the three user pointers.
+fn write_ids(
+    task_domain: &Arc<dyn TaskDomain>,
+    ids: (u32, u32, u32),
+    ptrs: [usize; 3],
+) -> AlienResult<isize> {
+    for (id, ptr) in [ids.0, ids.1, ids.2].into_iter().zip(ptrs) {
+        task_domain.copy_to_user(ptr, &id.to_ne_bytes())?;
+    }
+    Ok(0)
+}
+
+pub fn sys_getresuid(
+    task_domain: &Arc<dyn TaskDomain>,
+    ruid: usize,
+    euid: usize,
+    suid: usize,
+) -> AlienResult<isize> {
+    let ids = task_domain.do_getresuid()?;
+    write_ids(task_domain, ids, [ruid, euid, suid])
+}
+
+pub fn sys_getresgid(
+    task_domain: &Arc<dyn TaskDomain>,
+    rgid: usize,
+    egid: usize,
+    sgid: usize,
+) -> AlienResult<isize> {
+    let ids = task_domain.do_getresgid()?;
+    write_ids(task_domain, ids, [rgid, egid, sgid])
+}
+
+pub fn sys_getgroups(
+    task_domain: &Arc<dyn TaskDomain>,
+    size: usize,
+    list: usize,
+) -> AlienResult<isize> {
+    task_domain.do_getgroups(size, list)
+}
+
+pub fn sys_setgroups(
+    task_domain: &Arc<dyn TaskDomain>,
+    size: usize,
+    list: usize,
+) -> AlienResult<isize> {
+    task_domain.do_setgroups(size, list)
+}
@@ -1,9 +1,11 @@
+mod cred;
 mod ipc;
 mod resource;
 
 use alloc::sync::Arc;
 
-use basic::AlienResult;
+use basic::{AlienError, AlienResult};
+pub use cred::*;
 use interface::TaskDomain;
 pub use ipc::*;
 use log::info;
@@ -48,20 +50,28 @@ pub fn sys_set_tid_address(task_domain: &Arc<dyn TaskDomain>, tidptr: usize) ->
     task_domain.do_set_tid_address(tidptr)
 }
 
-pub fn sys_getuid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+pub fn sys_set_pgid(
+    task_domain: &Arc<dyn TaskDomain>,
+    pid: usize,
+    pgid: usize,
+) -> AlienResult<isize> {
+    if (pid as isize) < 0 || (pgid as isize) < 0 {
+        return Err(AlienError::EINVAL);
+    }
+    task_domain.do_setpgid(pid, pgid)?;
     Ok(0)
 }
 
-pub fn sys_set_pgid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
-    Ok(0)
+pub fn sys_get_pgid(task_domain: &Arc<dyn TaskDomain>, pid: usize) -> AlienResult<isize> {
+    task_domain.do_getpgid(pid).map(|pgid| pgid as isize)
 }
 
-pub fn sys_get_pgid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
-    Ok(0)
+pub fn sys_set_sid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
+    task_domain.do_setsid().map(|sid| sid as isize)
 }
 
-pub fn sys_set_sid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
-    Ok(0)
+pub fn sys_get_sid(task_domain: &Arc<dyn TaskDomain>, pid: usize) -> AlienResult<isize> {
+    task_domain.do_getsid(pid).map(|sid| sid as isize)
 }
 
 pub fn sys_get_pid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
@@ -72,18 +82,6 @@ pub fn sys_get_ppid(task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
     task_domain.current_ppid().map(|ppid| ppid as isize)
 }
 
-pub fn sys_get_euid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
-    Ok(0)
-}
-
-pub fn sys_get_gid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
-    Ok(0)
-}
-
-pub fn sys_get_egid(_task_domain: &Arc<dyn TaskDomain>) -> AlienResult<isize> {
-    Ok(0)
-}
-
 pub fn sys_get_tid() -> AlienResult<isize> {
     basic::current_tid().map(|tid| tid.unwrap() as isize)
 }
@@ -1,38 +1,306 @@
+mod timer;
+
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicIsize, Ordering};
 
 use basic::{
     config::CLOCK_FREQ,
-    constants::time::{ClockId, TimeSpec, TimeVal},
-    time::{read_timer, TimeNow},
+    constants::{
+        io::RtcTime,
+        time::{ClockId, TimeSpec, TimeVal},
+    },
+    println,
+    time::read_timer,
     AlienError, AlienResult,
 };
-use interface::TaskDomain;
+use interface::{DomainType, TaskDomain};
 use pod::Pod;
+use shared_heap::DBox;
+pub use timer::*;
+
+/// `clock_nanosleep` flag: `request` is an absolute time of the clock
+const TIMER_ABSTIME: usize = 1;
+const NANOS_PER_SEC: usize = 1_000_000_000;
+const MICROS_PER_SEC: usize = 1_000_000;
+/// The unit of `times`, `sysconf(_SC_CLK_TCK)`
+const CLK_TCK: usize = 100;
+/// `getrusage` targets
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
+/// `CLOCK_REALTIME` minus the timer, in timer cycles
+static REALTIME_OFFSET: AtomicIsize = AtomicIsize::new(0);
+
+/// Anchor `CLOCK_REALTIME` to the time of the RTC. Without an RTC it starts at boot.
+pub fn init_realtime() {
+    // unique name
+    let Some(DomainType::RtcDomain(rtc)) = basic::get_domain("goldfish") else {
+        println!("rtc domain not found, realtime starts at boot");
+        return;
+    };
+    match rtc.read_time(DBox::new(RtcTime::default())) {
+        Ok(time) => set_realtime(unix_time(&time) * CLOCK_FREQ),
+        Err(e) => println!("read rtc failed: {:?}", e),
+    }
+}
+
+/// Seconds since the epoch of the date of the RTC, which has the full year and 1-based
+/// months.
+fn unix_time(time: &RtcTime) -> usize {
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let (year, month, day) = (time.year as i64, time.mon as i64, time.mday as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let secs = days * 86400 + time.hour as i64 * 3600 + time.min as i64 * 60 + time.sec as i64;
+    secs.max(0) as usize
+}
+
+/// `CLOCK_REALTIME` in timer cycles
+fn realtime() -> usize {
+    (read_timer() as isize).wrapping_add(REALTIME_OFFSET.load(Ordering::Relaxed)) as usize
+}
+
+fn set_realtime(clock: usize) {
+    let offset = (clock as isize).wrapping_sub(read_timer() as isize);
+    REALTIME_OFFSET.store(offset, Ordering::Relaxed);
+}
+
+/// Convert a deadline of `id` to the timer.
+fn deadline_to_timer(id: ClockId, deadline: usize) -> usize {
+    match id {
+        ClockId::Realtime | ClockId::RealtimeCoarse => {
+            let offset = REALTIME_OFFSET.load(Ordering::Relaxed);
+            (deadline as isize).wrapping_sub(offset).max(0) as usize
+        }
+        _ => deadline,
+    }
+}
+
+/// Convert timer cycles to a `TimeSpec`.
+fn clock_to_timespec(clock: usize) -> TimeSpec {
+    TimeSpec {
+        tv_sec: clock / CLOCK_FREQ,
+        tv_nsec: (clock % CLOCK_FREQ) * NANOS_PER_SEC / CLOCK_FREQ,
+    }
+}
+
+/// Convert a `TimeSpec` from user space to timer cycles.
+pub(crate) fn timespec_to_clock(time: &TimeSpec) -> AlienResult<usize> {
+    if time.tv_nsec >= NANOS_PER_SEC {
+        return Err(AlienError::EINVAL);
+    }
+    Ok(time.tv_sec * CLOCK_FREQ + time.tv_nsec * CLOCK_FREQ / NANOS_PER_SEC)
+}
+
+fn clock_to_timeval(clock: usize) -> TimeVal {
+    TimeVal {
+        tv_sec: clock / CLOCK_FREQ,
+        tv_usec: (clock % CLOCK_FREQ) * MICROS_PER_SEC / CLOCK_FREQ,
+    }
+}
+
+fn timeval_to_clock(time: &TimeVal) -> AlienResult<usize> {
+    if time.tv_usec >= MICROS_PER_SEC {
+        return Err(AlienError::EINVAL);
+    }
+    Ok(time.tv_sec * CLOCK_FREQ + time.tv_usec * CLOCK_FREQ / MICROS_PER_SEC)
+}
+
+fn clock_to_ticks(clock: usize) -> usize {
+    clock / (CLOCK_FREQ / CLK_TCK)
+}
+
+/// The clocks that can be read
+fn clock_id(clk_id: usize) -> AlienResult<ClockId> {
+    let id = ClockId::try_from(clk_id).map_err(|_| AlienError::EINVAL)?;
+    match id {
+        ClockId::Realtime
+        | ClockId::Monotonic
+        | ClockId::ProcessCputimeId
+        | ClockId::ThreadCputimeId
+        | ClockId::MonotonicRaw
+        | ClockId::RealtimeCoarse
+        | ClockId::MonotonicCoarse
+        | ClockId::Boottime => Ok(id),
+        _ => Err(AlienError::EINVAL),
+    }
+}
 
 pub fn sys_clock_gettime(
     task_domain: &Arc<dyn TaskDomain>,
     clk_id: usize,
     tp: usize,
 ) -> AlienResult<isize> {
-    let id = ClockId::try_from(clk_id).map_err(|_| AlienError::EINVAL)?;
-    match id {
-        ClockId::Monotonic | ClockId::Realtime | ClockId::ProcessCputimeId => {
-            let time = read_timer();
-            let time = TimeSpec {
-                tv_sec: time / CLOCK_FREQ,
-                tv_nsec: (time % CLOCK_FREQ) * 1000_000_000 / CLOCK_FREQ,
-            };
-            task_domain.copy_to_user(tp, time.as_bytes())?;
-            Ok(0)
+    let time = match clock_id(clk_id)? {
+        ClockId::Realtime | ClockId::RealtimeCoarse => realtime(),
+        ClockId::ProcessCputimeId => {
+            let (utime, stime) = task_domain.do_cpu_times(RUSAGE_SELF)?;
+            utime + stime
         }
-        _ => {
-            panic!("clock_get_time: clock_id {:?} not supported", id);
+        ClockId::ThreadCputimeId => {
+            let (utime, stime) = task_domain.do_cpu_times(RUSAGE_THREAD)?;
+            utime + stime
+        }
+        _ => read_timer(),
+    };
+    let time = clock_to_timespec(time);
+    task_domain.copy_to_user(tp, time.as_bytes())?;
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/clock_settime.2.html
+///
+/// Only `CLOCK_REALTIME` can be set.
+pub fn sys_clock_settime(
+    task_domain: &Arc<dyn TaskDomain>,
+    clk_id: usize,
+    tp: usize,
+) -> AlienResult<isize> {
+    match clock_id(clk_id)? {
+        ClockId::Realtime => {}
+        _ => return Err(AlienError::EINVAL),
+    }
+    let time = task_domain.read_val_from_user::<TimeSpec>(tp)?;
+    set_realtime(timespec_to_clock(&time)?);
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/clock_getres.2.html
+pub fn sys_clock_getres(
+    task_domain: &Arc<dyn TaskDomain>,
+    clk_id: usize,
+    res: usize,
+) -> AlienResult<isize> {
+    clock_id(clk_id)?;
+    if res != 0 {
+        let time = TimeSpec {
+            tv_sec: 0,
+            tv_nsec: (NANOS_PER_SEC / CLOCK_FREQ).max(1),
+        };
+        task_domain.copy_to_user(res, time.as_bytes())?;
+    }
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/nanosleep.2.html
+pub fn sys_nanosleep(
+    task_domain: &Arc<dyn TaskDomain>,
+    req: usize,
+    rem: usize,
+) -> AlienResult<isize> {
+    let req = task_domain.read_val_from_user::<TimeSpec>(req)?;
+    let deadline = read_timer() + timespec_to_clock(&req)?;
+    sleep_until(task_domain, deadline, rem)
+}
+
+/// See https://man7.org/linux/man-pages/man2/clock_nanosleep.2.html
+pub fn sys_clock_nanosleep(
+    task_domain: &Arc<dyn TaskDomain>,
+    clk_id: usize,
+    flags: usize,
+    req: usize,
+    rem: usize,
+) -> AlienResult<isize> {
+    let id = clock_id(clk_id)?;
+    if let ClockId::ProcessCputimeId | ClockId::ThreadCputimeId = id {
+        return Err(AlienError::EINVAL);
+    }
+    let req = task_domain.read_val_from_user::<TimeSpec>(req)?;
+    let req = timespec_to_clock(&req)?;
+    if flags & TIMER_ABSTIME != 0 {
+        // the remaining time is not reported for an absolute sleep
+        sleep_until(task_domain, deadline_to_timer(id, req), 0)
+    } else {
+        sleep_until(task_domain, read_timer() + req, rem)
+    }
+}
+
+/// Sleep until `deadline` and store the time left in `rem` if the sleep is interrupted.
+fn sleep_until(
+    task_domain: &Arc<dyn TaskDomain>,
+    deadline: usize,
+    rem: usize,
+) -> AlienResult<isize> {
+    match task_domain.do_sleep_until(deadline) {
+        Err(AlienError::EINTR) if rem != 0 => {
+            let left = clock_to_timespec(deadline.saturating_sub(read_timer()));
+            task_domain.copy_to_user(rem, left.as_bytes())?;
+            Err(AlienError::EINTR)
         }
+        res => res.map(|_| 0),
     }
 }
 
 pub fn sys_get_time_of_day(task_domain: &Arc<dyn TaskDomain>, tv: usize) -> AlienResult<isize> {
-    let time = TimeVal::now();
+    let time = clock_to_timeval(realtime());
     task_domain.write_val_to_user(tv, &time)?;
     Ok(0)
 }
+
+/// See https://man7.org/linux/man-pages/man2/settimeofday.2.html
+///
+/// The timezone is ignored, like on Linux.
+pub fn sys_set_time_of_day(task_domain: &Arc<dyn TaskDomain>, tv: usize) -> AlienResult<isize> {
+    if tv != 0 {
+        let time = task_domain.read_val_from_user::<TimeVal>(tv)?;
+        set_realtime(timeval_to_clock(&time)?);
+    }
+    Ok(0)
+}
+
+#[derive(Pod, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct Tms {
+    pub tms_utime: usize,
+    pub tms_stime: usize,
+    pub tms_cutime: usize,
+    pub tms_cstime: usize,
+}
+
+/// See https://man7.org/linux/man-pages/man2/times.2.html
+pub fn sys_times(task_domain: &Arc<dyn TaskDomain>, buf: usize) -> AlienResult<isize> {
+    if buf != 0 {
+        let (utime, stime) = task_domain.do_cpu_times(RUSAGE_SELF)?;
+        let (cutime, cstime) = task_domain.do_cpu_times(RUSAGE_CHILDREN)?;
+        let tms = Tms {
+            tms_utime: clock_to_ticks(utime),
+            tms_stime: clock_to_ticks(stime),
+            tms_cutime: clock_to_ticks(cutime),
+            tms_cstime: clock_to_ticks(cstime),
+        };
+        task_domain.copy_to_user(buf, tms.as_bytes())?;
+    }
+    Ok(clock_to_ticks(read_timer()) as isize)
+}
+
+#[derive(Pod, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct RUsage {
+    pub ru_utime: TimeVal,
+    pub ru_stime: TimeVal,
+    /// The other fields, which are not tracked
+    pub ru_other: [usize; 14],
+}
+
+/// See https://man7.org/linux/man-pages/man2/getrusage.2.html
+///
+/// Only the user and system time are filled in.
+pub fn sys_getrusage(
+    task_domain: &Arc<dyn TaskDomain>,
+    who: usize,
+    usage: usize,
+) -> AlienResult<isize> {
+    let (utime, stime) = task_domain.do_cpu_times(who as isize)?;
+    let usage_val = RUsage {
+        ru_utime: clock_to_timeval(utime),
+        ru_stime: clock_to_timeval(stime),
+        ru_other: [0; 14],
+    };
+    task_domain.copy_to_user(usage, usage_val.as_bytes())?;
+    Ok(0)
+}
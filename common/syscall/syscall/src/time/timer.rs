@@ -0,0 +1,154 @@
+use alloc::sync::Arc;
+
+use basic::{
+    config::CLOCK_FREQ,
+    constants::{
+        io::OpenFlags,
+        time::{ClockId, TimeSpec, TimeVal},
+    },
+    time::read_timer,
+    AlienError, AlienResult,
+};
+use interface::{TaskDomain, VfsDomain};
+use pod::Pod;
+
+use super::{
+    clock_id, clock_to_timespec, clock_to_timeval, deadline_to_timer, timespec_to_clock,
+    timeval_to_clock,
+};
+
+/// `timerfd_settime` flag: `it_value` is an absolute time of the clock
+const TFD_TIMER_ABSTIME: usize = 1;
+/// `timerfd_settime` flag: accepted, but the clocks cannot be set
+const TFD_TIMER_CANCEL_ON_SET: usize = 2;
+
+#[derive(Pod, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+#[derive(Pod, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct ITimerSpec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+fn itimerval(interval: usize, value: usize) -> ITimerVal {
+    ITimerVal {
+        it_interval: clock_to_timeval(interval),
+        it_value: clock_to_timeval(value),
+    }
+}
+
+fn itimerspec(interval: usize, value: usize) -> ITimerSpec {
+    ITimerSpec {
+        it_interval: clock_to_timespec(interval),
+        it_value: clock_to_timespec(value),
+    }
+}
+
+/// See https://man7.org/linux/man-pages/man2/setitimer.2.html
+///
+/// Only `ITIMER_REAL` is supported. A NULL `new_value` disarms the timer, like on Linux.
+pub fn sys_setitimer(
+    task_domain: &Arc<dyn TaskDomain>,
+    which: usize,
+    new_value: usize,
+    old_value: usize,
+) -> AlienResult<isize> {
+    let (interval, value) = if new_value == 0 {
+        (0, 0)
+    } else {
+        let new = task_domain.read_val_from_user::<ITimerVal>(new_value)?;
+        (
+            timeval_to_clock(&new.it_interval)?,
+            timeval_to_clock(&new.it_value)?,
+        )
+    };
+    let (old_interval, old_value_left) = task_domain.do_setitimer(which, interval, value)?;
+    if old_value != 0 {
+        let old = itimerval(old_interval, old_value_left);
+        task_domain.copy_to_user(old_value, old.as_bytes())?;
+    }
+    Ok(0)
+}
+
+pub fn sys_getitimer(
+    task_domain: &Arc<dyn TaskDomain>,
+    which: usize,
+    curr_value: usize,
+) -> AlienResult<isize> {
+    let (interval, value) = task_domain.do_getitimer(which)?;
+    let curr = itimerval(interval, value);
+    task_domain.copy_to_user(curr_value, curr.as_bytes())?;
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/timerfd_create.2.html
+pub fn sys_timerfd_create(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    clk_id: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    match clock_id(clk_id)? {
+        ClockId::Realtime | ClockId::Monotonic | ClockId::Boottime => {}
+        _ => return Err(AlienError::EINVAL),
+    }
+    let valid = OpenFlags::O_NONBLOCK | OpenFlags::O_CLOEXEC;
+    if flags & !valid.bits() != 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let timerfd_file = vfs_domain.do_timerfd_create(clk_id, flags as u32)?;
+    let fd = task_domain.add_fd(timerfd_file)?;
+    Ok(fd as isize)
+}
+
+pub fn sys_timerfd_settime(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    flags: usize,
+    new_value: usize,
+    old_value: usize,
+) -> AlienResult<isize> {
+    if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let inode = task_domain.get_fd(fd)?;
+    let new = task_domain.read_val_from_user::<ITimerSpec>(new_value)?;
+    let interval = timespec_to_clock(&new.it_interval)?;
+    let value = timespec_to_clock(&new.it_value)?;
+    let deadline = match value {
+        0 => 0,
+        value if flags & TFD_TIMER_ABSTIME != 0 => {
+            let id = ClockId::try_from(vfs_domain.do_timerfd_clock(inode)?)
+                .map_err(|_| AlienError::EINVAL)?;
+            deadline_to_timer(id, value).max(1)
+        }
+        value => read_timer() + value,
+    };
+    let (old_interval, old_value_left) =
+        vfs_domain.do_timerfd_settime(inode, interval, deadline)?;
+    if old_value != 0 {
+        let old = itimerspec(old_interval, old_value_left);
+        task_domain.copy_to_user(old_value, old.as_bytes())?;
+    }
+    Ok(0)
+}
+
+pub fn sys_timerfd_gettime(
+    vfs_domain: &Arc<dyn VfsDomain>,
+    task_domain: &Arc<dyn TaskDomain>,
+    fd: usize,
+    curr_value: usize,
+) -> AlienResult<isize> {
+    let inode = task_domain.get_fd(fd)?;
+    let (interval, value) = vfs_domain.do_timerfd_gettime(inode)?;
+    let curr = itimerspec(interval, value);
+    task_domain.copy_to_user(curr_value, curr.as_bytes())?;
+    Ok(0)
+}
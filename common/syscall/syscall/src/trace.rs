@@ -0,0 +1,278 @@
+//! A strace-like tracer for the syscall domain.
+//!
+//! Tracing is controlled at runtime through [`SYSCALL_TRACE`]. When it is on, every
+//! syscall matching the tid and syscall filters is logged through the logger domain
+//! with its decoded arguments and its return value or error. Arguments are decoded
+//! before the syscall runs, a user string that cannot be read is shown as its address.
+//! Syscalls the domain does not implement are counted per number whether tracing is on or
+//! not.
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    sync::Arc,
+};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use basic::{constants::*, sync::Mutex, AlienError, AlienResult};
+use interface::{Level, LogDomain, TaskDomain};
+use shared_heap::DVec;
+
+/// `trace(op, arg)`: control the tracer, fails with EPERM unless the effective uid is 0.
+pub const SYSCALL_TRACE: usize = 2003;
+
+/// Stop tracing and clear the filters.
+pub const TRACE_OFF: usize = 0;
+/// Start tracing the syscalls that match the filters, all syscalls if there are none.
+pub const TRACE_ON: usize = 1;
+/// Only trace the syscalls of task `arg`.
+pub const TRACE_ADD_TID: usize = 2;
+pub const TRACE_REMOVE_TID: usize = 3;
+/// Only trace syscall number `arg`.
+pub const TRACE_ADD_SYSCALL: usize = 4;
+pub const TRACE_REMOVE_SYSCALL: usize = 5;
+/// Log how often every unknown syscall was called.
+pub const TRACE_DUMP_UNKNOWN: usize = 6;
+
+#[derive(Debug, Default)]
+struct TraceState {
+    tids: BTreeSet<usize>,
+    syscalls: BTreeSet<usize>,
+    /// Calls of unknown syscalls, keyed by syscall number
+    unknown: BTreeMap<usize, usize>,
+}
+
+#[derive(Debug)]
+pub struct Tracer {
+    logger: Arc<dyn LogDomain>,
+    /// Checked before `state` so that syscalls do not take the lock while tracing is off
+    enabled: AtomicBool,
+    state: Mutex<TraceState>,
+}
+
+impl Tracer {
+    pub fn new(logger: Arc<dyn LogDomain>) -> Self {
+        Self {
+            logger,
+            enabled: AtomicBool::new(false),
+            state: Mutex::new(TraceState::default()),
+        }
+    }
+
+    fn log(&self, level: Level, msg: &str) -> AlienResult<()> {
+        self.logger.log(level, &DVec::from_slice(msg.as_bytes()))
+    }
+
+    /// Handle [`SYSCALL_TRACE`]. Traces show the arguments of every task, so only root may
+    /// control the tracer.
+    pub fn control(
+        &self,
+        task_domain: &Arc<dyn TaskDomain>,
+        op: usize,
+        arg: usize,
+    ) -> AlienResult<isize> {
+        let (_, euid, _) = task_domain.do_getresuid()?;
+        if euid != 0 {
+            return Err(AlienError::EPERM);
+        }
+        let mut state = self.state.lock();
+        match op {
+            TRACE_OFF => {
+                self.enabled.store(false, Ordering::Release);
+                state.tids.clear();
+                state.syscalls.clear();
+            }
+            TRACE_ON => self.enabled.store(true, Ordering::Release),
+            TRACE_ADD_TID => {
+                state.tids.insert(arg);
+            }
+            TRACE_REMOVE_TID => {
+                state.tids.remove(&arg);
+            }
+            TRACE_ADD_SYSCALL => {
+                state.syscalls.insert(arg);
+            }
+            TRACE_REMOVE_SYSCALL => {
+                state.syscalls.remove(&arg);
+            }
+            TRACE_DUMP_UNKNOWN => {
+                let mut msg = String::from("unknown syscalls:");
+                for (id, count) in state.unknown.iter() {
+                    let _ = write!(msg, " [{}: {}] x{}", id, syscall_name(*id), count);
+                }
+                drop(state);
+                self.log(Level::Info, &msg)?;
+            }
+            _ => return Err(AlienError::EINVAL),
+        }
+        Ok(0)
+    }
+
+    /// Whether the call of `syscall_id` by `tid` should be logged.
+    pub fn is_traced(&self, tid: Option<usize>, syscall_id: usize) -> bool {
+        if !self.enabled.load(Ordering::Acquire) || syscall_id == SYSCALL_TRACE {
+            return false;
+        }
+        let state = self.state.lock();
+        let tid_match = state.tids.is_empty() || tid.is_some_and(|tid| state.tids.contains(&tid));
+        let syscall_match = state.syscalls.is_empty() || state.syscalls.contains(&syscall_id);
+        tid_match && syscall_match
+    }
+
+    /// Count a call of an unsupported syscall, logging it the first time.
+    pub fn unknown(&self, tid: Option<usize>, syscall_id: usize) -> AlienResult<()> {
+        let first = {
+            let mut state = self.state.lock();
+            let count = state.unknown.entry(syscall_id).or_insert(0);
+            *count += 1;
+            *count == 1
+        };
+        if first {
+            let msg = format!(
+                "[tid:{:?}] syscall [{}: {}] not implemented, returning ENOSYS",
+                tid,
+                syscall_id,
+                syscall_name(syscall_id)
+            );
+            self.log(Level::Warn, &msg)?;
+        }
+        Ok(())
+    }
+
+    /// Decode the arguments of a syscall that is about to run. Strings are read now,
+    /// before the call can change or replace the memory they are in.
+    pub fn start(
+        &self,
+        task_domain: &Arc<dyn TaskDomain>,
+        tid: Option<usize>,
+        syscall_id: usize,
+        args: &[usize; 6],
+    ) -> String {
+        let mut msg = format!("[tid:{:?}] {}(", tid, syscall_name(syscall_id));
+        for (i, kind) in arg_kinds(syscall_id).iter().enumerate() {
+            if i > 0 {
+                msg.push_str(", ");
+            }
+            decode_arg(&mut msg, task_domain, *kind, args[i]);
+        }
+        msg
+    }
+
+    /// Log a finished syscall, `msg` is what [`Tracer::start`] returned for it.
+    pub fn finish(&self, mut msg: String, result: &AlienResult<isize>) -> AlienResult<()> {
+        match result {
+            Ok(ret) => {
+                let _ = write!(msg, ") = {}", ret);
+            }
+            Err(e) => {
+                let _ = write!(msg, ") = {:?}", e);
+            }
+        }
+        self.log(Level::Info, &msg)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum ArgKind {
+    Dec,
+    Hex,
+    Fd,
+    /// A pointer to a NUL-terminated string in user memory
+    Path,
+}
+
+/// How the arguments of a syscall are printed. Unlisted syscalls show six hex values.
+fn arg_kinds(syscall_id: usize) -> &'static [ArgKind] {
+    match syscall_id {
+        SYSCALL_OPENAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_MKDIRAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex],
+        SYSCALL_UNLINKAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex],
+        SYSCALL_FACCESSAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_FSTATAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_UTIMENSAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_RENAMEAT2 => &[
+            ArgKind::Fd,
+            ArgKind::Path,
+            ArgKind::Fd,
+            ArgKind::Path,
+            ArgKind::Hex,
+        ],
+        SYSCALL_SYMLINKAT => &[ArgKind::Path, ArgKind::Fd, ArgKind::Path],
+        SYSCALL_LINKAT => &[
+            ArgKind::Fd,
+            ArgKind::Path,
+            ArgKind::Fd,
+            ArgKind::Path,
+            ArgKind::Hex,
+        ],
+        SYSCALL_READLINKAT => &[ArgKind::Fd, ArgKind::Path, ArgKind::Hex, ArgKind::Dec],
+        SYSCALL_CHDIR => &[ArgKind::Path],
+        SYSCALL_MOUNT => &[
+            ArgKind::Path,
+            ArgKind::Path,
+            ArgKind::Path,
+            ArgKind::Hex,
+            ArgKind::Hex,
+        ],
+        SYSCALL_UMOUNT2 => &[ArgKind::Path, ArgKind::Hex],
+        SYSCALL_STATFS => &[ArgKind::Path, ArgKind::Hex],
+        SYSCALL_FSTATFS => &[ArgKind::Fd, ArgKind::Hex],
+        // epoll_pwait and epoll_pwait2
+        22 | 441 => &[
+            ArgKind::Fd,
+            ArgKind::Hex,
+            ArgKind::Dec,
+            ArgKind::Hex,
+            ArgKind::Hex,
+        ],
+        SYSCALL_EXECVE => &[ArgKind::Path, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_CLOSE | SYSCALL_DUP | SYSCALL_FSYNC => &[ArgKind::Fd],
+        SYSCALL_DUP3 => &[ArgKind::Fd, ArgKind::Fd, ArgKind::Hex],
+        SYSCALL_READ | SYSCALL_WRITE | SYSCALL_READV | SYSCALL_WRITEV => {
+            &[ArgKind::Fd, ArgKind::Hex, ArgKind::Dec]
+        }
+        SYSCALL_LSEEK => &[ArgKind::Fd, ArgKind::Dec, ArgKind::Dec],
+        SYSCALL_FSTAT => &[ArgKind::Fd, ArgKind::Hex],
+        SYSCALL_FTRUNCATE => &[ArgKind::Fd, ArgKind::Dec],
+        SYSCALL_FCNTL | SYSCALL_IOCTL => &[ArgKind::Fd, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_GETDENTS64 => &[ArgKind::Fd, ArgKind::Hex, ArgKind::Dec],
+        SYSCALL_SENDFILE => &[ArgKind::Fd, ArgKind::Fd, ArgKind::Hex, ArgKind::Dec],
+        SYSCALL_EXIT | SYSCALL_EXIT_GROUP => &[ArgKind::Dec],
+        SYSCALL_WAIT4 => &[ArgKind::Dec, ArgKind::Hex, ArgKind::Hex, ArgKind::Hex],
+        SYSCALL_GETPID | SYSCALL_GETPPID | SYSCALL_GETTID | SYSCALL_YIELD => &[],
+        // kill, tkill and tgkill
+        129 | 130 => &[ArgKind::Dec, ArgKind::Dec],
+        131 => &[ArgKind::Dec, ArgKind::Dec, ArgKind::Dec],
+        _ => &[
+            ArgKind::Hex,
+            ArgKind::Hex,
+            ArgKind::Hex,
+            ArgKind::Hex,
+            ArgKind::Hex,
+            ArgKind::Hex,
+        ],
+    }
+}
+
+fn decode_arg(msg: &mut String, task_domain: &Arc<dyn TaskDomain>, kind: ArgKind, arg: usize) {
+    let _ = match kind {
+        ArgKind::Dec => write!(msg, "{}", arg as isize),
+        ArgKind::Hex => write!(msg, "{:#x}", arg),
+        ArgKind::Fd if arg as isize == AT_FDCWD => write!(msg, "AT_FDCWD"),
+        ArgKind::Fd => write!(msg, "{}", arg as isize),
+        ArgKind::Path if arg == 0 => write!(msg, "NULL"),
+        ArgKind::Path => {
+            let buf = DVec::<u8>::new_uninit(256);
+            match task_domain.read_string_from_user(arg, buf) {
+                Ok((buf, len)) => {
+                    let path = core::str::from_utf8(&buf.as_slice()[..len]).unwrap_or("<invalid>");
+                    write!(msg, "{:?}", path)
+                }
+                Err(_) => write!(msg, "{:#x}", arg),
+            }
+        }
+    };
+}
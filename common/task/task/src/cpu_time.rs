@@ -0,0 +1,153 @@
+//! Per-task accounting of user and system time.
+//!
+//! Time is split at the syscall boundary: from returning to user space until the next
+//! syscall counts as user time, the syscall itself as system time. Time a task spends
+//! parked in the task domain, in sleeps, futex waits and `wait4`, is not counted.
+
+use core::ops::AddAssign;
+
+use basic::{time::read_timer, AlienError, AlienResult};
+
+use crate::processor::{current_task, find_task, find_threads};
+
+/// `getrusage` targets
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
+/// User and system time, in timer cycles
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CpuTimes {
+    pub utime: usize,
+    pub stime: usize,
+}
+
+impl AddAssign for CpuTimes {
+    fn add_assign(&mut self, rhs: Self) {
+        self.utime += rhs.utime;
+        self.stime += rhs.stime;
+    }
+}
+
+#[derive(Debug)]
+pub struct CpuTime {
+    /// The time of this task
+    pub own: CpuTimes,
+    /// The time of the exited threads, kept by the thread group leader
+    pub exited_threads: CpuTimes,
+    /// The time of the children that were waited for, and of their children
+    pub children: CpuTimes,
+    /// The start of the time that is not accounted yet
+    mark: usize,
+}
+
+impl CpuTime {
+    pub fn new() -> Self {
+        Self {
+            own: CpuTimes::default(),
+            exited_threads: CpuTimes::default(),
+            children: CpuTimes::default(),
+            mark: read_timer(),
+        }
+    }
+
+    /// The time of an exited process: its own, that of its threads and of its children.
+    pub fn total(&self) -> CpuTimes {
+        let mut times = self.own;
+        times += self.exited_threads;
+        times += self.children;
+        times
+    }
+
+    /// Count the time until `now` as user time.
+    pub fn user_until(&mut self, now: usize) {
+        self.own.utime += now.saturating_sub(self.mark);
+        self.mark = now;
+    }
+
+    /// Count the time until `now` as system time.
+    pub fn system_until(&mut self, now: usize) {
+        self.own.stime += now.saturating_sub(self.mark);
+        self.mark = now;
+    }
+
+    /// Do not count the time until `now`.
+    pub fn skip_until(&mut self, now: usize) {
+        self.mark = now;
+    }
+}
+
+/// Account the time of the current task at the entry or the exit of a syscall.
+pub fn syscall_boundary(enter: bool) {
+    let Some(task) = current_task() else {
+        return;
+    };
+    let now = read_timer();
+    let mut inner = task.inner();
+    if enter {
+        inner.cpu_time.user_until(now);
+    } else {
+        inner.cpu_time.system_until(now);
+    }
+}
+
+/// Run `park`, which blocks the current task, without counting the time it is blocked.
+pub fn parked<T>(park: impl FnOnce() -> T) -> T {
+    let task = current_task();
+    if let Some(task) = &task {
+        task.inner().cpu_time.system_until(read_timer());
+    }
+    let res = park();
+    if let Some(task) = &task {
+        task.inner().cpu_time.skip_until(read_timer());
+    }
+    res
+}
+
+/// The `(utime, stime)` of the current process, of its waited-for children or of the
+/// current thread, selected like the `who` of `getrusage`.
+pub fn do_cpu_times(who: isize) -> AlienResult<(usize, usize)> {
+    let task = current_task().unwrap();
+    task.inner().cpu_time.system_until(read_timer());
+    let times = match who {
+        RUSAGE_SELF => process_times(task.pid()),
+        RUSAGE_CHILDREN => {
+            let mut times = CpuTimes::default();
+            for thread in find_threads(task.pid()) {
+                times += thread.inner().cpu_time.children;
+            }
+            times
+        }
+        RUSAGE_THREAD => task.inner().cpu_time.own,
+        _ => return Err(AlienError::EINVAL),
+    };
+    Ok((times.utime, times.stime))
+}
+
+/// The time of all threads of process `pid`, including the exited ones.
+pub fn process_times(pid: usize) -> CpuTimes {
+    let mut times = CpuTimes::default();
+    for thread in find_threads(pid) {
+        let inner = thread.inner();
+        times += inner.cpu_time.own;
+        if thread.tid() == pid {
+            times += inner.cpu_time.exited_threads;
+        }
+    }
+    times
+}
+
+/// Hand the time of the exiting current thread to its thread group leader.
+pub fn thread_exit() {
+    let task = current_task().unwrap();
+    let times = {
+        let mut inner = task.inner();
+        inner.cpu_time.system_until(read_timer());
+        inner.cpu_time.own
+    };
+    if task.pid() != task.tid() {
+        if let Some(leader) = find_task(task.pid()) {
+            leader.inner().cpu_time.exited_threads += times;
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Process credentials.
+//!
+//! User and group ids each come as an [`IdSet`] like on Linux: the real id, the effective id
+//! that permission checks use, the saved id an unprivileged process may switch back to, and
+//! the filesystem id that follows the effective id. The threads of a process share one set of
+//! credentials. Effective user id 0 holds every capability.
+
+use alloc::vec::Vec;
+
+use basic::{AlienError, AlienResult};
+
+/// The most supplementary groups a process may have
+pub const NGROUPS_MAX: usize = 65536;
+/// An id argument of -1 leaves the id unchanged
+const KEEP: u32 = u32::MAX;
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdSet {
+    pub real: u32,
+    pub effective: u32,
+    pub saved: u32,
+    pub fs: u32,
+}
+
+impl IdSet {
+    fn is_one_of(&self, id: u32) -> bool {
+        id == self.real || id == self.effective || id == self.saved
+    }
+
+    /// setuid: a privileged caller sets all ids, others only the effective id, to the real
+    /// or saved id.
+    fn set(&mut self, id: u32, privileged: bool) -> AlienResult<()> {
+        if privileged {
+            self.real = id;
+            self.saved = id;
+        } else if id != self.real && id != self.saved {
+            return Err(AlienError::EPERM);
+        }
+        self.effective = id;
+        self.fs = id;
+        Ok(())
+    }
+
+    /// setreuid: the saved id follows the new effective id once the real id is set or the
+    /// effective id differs from the old real id.
+    fn set_re(&mut self, real: u32, effective: u32, privileged: bool) -> AlienResult<()> {
+        if !privileged {
+            if real != KEEP && real != self.real && real != self.effective {
+                return Err(AlienError::EPERM);
+            }
+            if effective != KEEP && !self.is_one_of(effective) {
+                return Err(AlienError::EPERM);
+            }
+        }
+        let old_real = self.real;
+        if real != KEEP {
+            self.real = real;
+        }
+        if effective != KEEP {
+            self.effective = effective;
+        }
+        if real != KEEP || (effective != KEEP && effective != old_real) {
+            self.saved = self.effective;
+        }
+        self.fs = self.effective;
+        Ok(())
+    }
+
+    /// setresuid: an unprivileged caller may only pick among its current ids.
+    fn set_res(
+        &mut self,
+        real: u32,
+        effective: u32,
+        saved: u32,
+        privileged: bool,
+    ) -> AlienResult<()> {
+        if !privileged
+            && [real, effective, saved]
+                .iter()
+                .any(|&id| id != KEEP && !self.is_one_of(id))
+        {
+            return Err(AlienError::EPERM);
+        }
+        if real != KEEP {
+            self.real = real;
+        }
+        if effective != KEEP {
+            self.effective = effective;
+        }
+        if saved != KEEP {
+            self.saved = saved;
+        }
+        self.fs = self.effective;
+        Ok(())
+    }
+
+    /// The real, effective and saved ids
+    pub fn res(&self) -> (u32, u32, u32) {
+        (self.real, self.effective, self.saved)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub uid: IdSet,
+    pub gid: IdSet,
+    /// Supplementary groups
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// The credentials of init and the kernel threads
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    fn privileged(&self) -> bool {
+        self.uid.effective == 0
+    }
+
+    pub fn set_uid(&mut self, uid: u32) -> AlienResult<()> {
+        let privileged = self.privileged();
+        self.uid.set(uid, privileged)
+    }
+
+    pub fn set_gid(&mut self, gid: u32) -> AlienResult<()> {
+        let privileged = self.privileged();
+        self.gid.set(gid, privileged)
+    }
+
+    pub fn set_reuid(&mut self, ruid: u32, euid: u32) -> AlienResult<()> {
+        let privileged = self.privileged();
+        self.uid.set_re(ruid, euid, privileged)
+    }
+
+    pub fn set_regid(&mut self, rgid: u32, egid: u32) -> AlienResult<()> {
+        let privileged = self.privileged();
+        self.gid.set_re(rgid, egid, privileged)
+    }
+
+    pub fn set_resuid(&mut self, ruid: u32, euid: u32, suid: u32) -> AlienResult<()> {
+        let privileged = self.privileged();
+        self.uid.set_res(ruid, euid, suid, privileged)
+    }
+
+    pub fn set_resgid(&mut self, rgid: u32, egid: u32, sgid: u32) -> AlienResult<()> {
+        let privileged = self.privileged();
+        self.gid.set_res(rgid, egid, sgid, privileged)
+    }
+
+    pub fn set_groups(&mut self, groups: Vec<u32>) -> AlienResult<()> {
+        if !self.privileged() {
+            return Err(AlienError::EPERM);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// Whether `gid` is one of the supplementary groups
+    pub fn in_groups(&self, gid: u32) -> bool {
+        self.groups.contains(&gid)
+    }
+
+    /// Take the owner of a program with the set-user-ID or set-group-ID bit in `mode` as
+    /// the effective id. The saved ids always become the effective ids on exec.
+    pub fn exec(&mut self, mode: u32, uid: u32, gid: u32) {
+        if mode & S_ISUID != 0 {
+            self.uid.effective = uid;
+        }
+        if mode & S_ISGID != 0 {
+            self.gid.effective = gid;
+        }
+        self.uid.saved = self.uid.effective;
+        self.uid.fs = self.uid.effective;
+        self.gid.saved = self.gid.effective;
+        self.gid.fs = self.gid.effective;
+    }
+}
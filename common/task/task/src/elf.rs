@@ -21,7 +21,10 @@ use xmas_elf::{
     ElfFile,
 };
 
-use crate::vfs_shim;
+use crate::{
+    signal::{SIGRETURN_CODE, SIGRETURN_TRAMPOLINE},
+    vfs_shim,
+};
 
 #[derive(Debug)]
 pub struct FrameTrackerWrapper(pub(crate) FrameTracker);
@@ -392,6 +395,8 @@ pub fn build_vm_space(elf: &[u8], args: &mut Vec<String>, name: &str) -> AlienRe
         .map(VmAreaType::VmArea(trap_context_area))
         .unwrap();
 
+    map_sigreturn_trampoline(&mut address_space);
+
     // todo!(how to solve trampoline)
     let trampoline_frame = FrameTracker::create_trampoline();
 
@@ -464,6 +469,24 @@ pub fn build_vm_space(elf: &[u8], args: &mut Vec<String>, name: &str) -> AlienRe
     })
 }
 
+/// Map the page handlers return through when they have no `sa_restorer`.
+fn map_sigreturn_trampoline(space: &mut VmSpace<VmmPageAllocator>) {
+    let frame = FrameTracker::new(1);
+    let area = VmArea::new(
+        SIGRETURN_TRAMPOLINE..(SIGRETURN_TRAMPOLINE + FRAME_SIZE),
+        MappingFlags::USER | MappingFlags::READ | MappingFlags::EXECUTE,
+        vec![Box::new(FrameTrackerWrapper(frame))],
+    );
+    space.map(VmAreaType::VmArea(area)).unwrap();
+    let code: Vec<u8> = SIGRETURN_CODE
+        .iter()
+        .flat_map(|inst| inst.to_le_bytes())
+        .collect();
+    space
+        .write_bytes(VirtAddr::from(SIGRETURN_TRAMPOLINE), &code)
+        .unwrap();
+}
+
 pub fn clone_vm_space(vm_space: &VmSpace<VmmPageAllocator>) -> VmSpace<VmmPageAllocator> {
     let mut space = VmSpace::new();
     let trampoline_frame = FrameTracker::create_trampoline();
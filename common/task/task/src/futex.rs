@@ -35,6 +35,21 @@ impl FutexWaiter {
     pub fn wake(&mut self) -> usize {
         self.task.take().unwrap()
     }
+
+    pub fn tid(&self) -> Option<usize> {
+        self.task
+    }
+
+    /// If the wait time has passed at `now`, mark the waiter as timed out and return the tid
+    pub fn check_timeout(&mut self, now: usize) -> Option<usize> {
+        match self.wait_time {
+            Some(wait_time) if wait_time <= now && self.task.is_some() => {
+                *self.timeout_flag.lock() = true;
+                self.task.take()
+            }
+            _ => None,
+        }
+    }
 }
 
 /// 用于管理 futex 等待队列的数据结构
@@ -75,6 +90,31 @@ impl FutexWaitManager {
         }
     }
 
+    /// 唤醒等待超时的进程
+    pub fn check_timeout(&mut self, now: usize) -> AlienResult<()> {
+        let mut woken = Vec::new();
+        for waiters in self.map.values_mut() {
+            waiters.retain_mut(|waiter| match waiter.check_timeout(now) {
+                Some(tid) => {
+                    woken.push(tid);
+                    false
+                }
+                None => true,
+            });
+        }
+        for tid in woken {
+            basic::wake_up_wait_task(tid)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the waiter of `tid` without waking it, when a signal interrupts its wait.
+    pub fn cancel(&mut self, tid: usize) {
+        for waiters in self.map.values_mut() {
+            waiters.retain(|waiter| waiter.tid() != Some(tid));
+        }
+    }
+
     /// 将原来等待在 old_futex 上至多 num 个进程转移到 requeue_futex 上等待，返回转移的进程数
     pub fn requeue(
         &mut self,
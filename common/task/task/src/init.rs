@@ -29,6 +29,7 @@ fn kthread_init() {
             // println!("kthread_init tick at {}", now);
             time = now;
         }
+        crate::timer::check_timers().unwrap();
         basic::yield_now().unwrap();
     }
     // kthread::ktrhead_exit();
@@ -14,9 +14,13 @@ use small_index::IndexAllocator;
 use task_meta::{TaskBasicInfo, TaskMeta, TaskSchedulingInfo, TaskStatus};
 
 use crate::{
+    cpu_time::CpuTime,
+    cred::Credentials,
     elf::VmmPageAllocator,
+    pin::PinnedBuffers,
     processor::add_task,
     resource::{FdManager, HeapInfo, MMapInfo, ResourceLimits, TidHandle},
+    signal::PendingSignals,
     task::{FsContext, Task, TaskInner},
     vfs_shim::{STDIN, STDOUT},
 };
@@ -54,6 +58,8 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             status: TaskStatus::Ready,
             parent: None,
             children: BTreeMap::new(),
+            pgid: 0,
+            sid: 0,
             fs_info: FsContext::new(VFS_ROOT_ID, VFS_ROOT_ID),
             exit_code: 0,
             clear_child_tid: 0,
@@ -65,11 +71,15 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
                 ss_flags: 0x2,
                 ss_size: 0,
             },
+            cpu_time: CpuTime::new(),
         }),
         send_sigchld_when_exit: false,
         mmap: Arc::new(Mutex::new(MMapInfo::new())),
+        cred: Arc::new(Mutex::new(Credentials::root())),
         signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
         signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
+        pending_signals: Mutex::new(PendingSignals::new()),
+        pinned_buffers: Mutex::new(PinnedBuffers::new()),
     };
     let task = Arc::new(task);
     add_task(task);
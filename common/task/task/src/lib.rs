@@ -4,14 +4,19 @@
 extern crate alloc;
 #[macro_use]
 extern crate log;
+mod cpu_time;
+mod cred;
 mod elf;
 mod futex;
 mod init;
 mod kthread;
+mod pin;
 mod processor;
 mod resource;
+mod signal;
 mod syscall;
 mod task;
+mod timer;
 mod utils;
 mod vfs_shim;
 
@@ -20,7 +25,7 @@ use core::ops::Range;
 
 use basic::{println, AlienError, AlienResult};
 use interface::{
-    define_unwind_for_TaskDomain, Basic, DomainType, InodeID, TaskDomain, TmpHeapInfo,
+    define_unwind_for_TaskDomain, Basic, DomainType, InodeID, TaskDomain, TmpHeapInfo, VFS_ROOT_ID,
 };
 use memory_addr::VirtAddr;
 use shared_heap::{DBox, DVec};
@@ -103,7 +108,10 @@ impl TaskDomain for TaskDomainImpl {
     }
 
     fn fs_info(&self) -> AlienResult<(InodeID, InodeID)> {
-        let task = current_task().unwrap();
+        // work done outside a task, like loading init, runs in the system root
+        let Some(task) = current_task() else {
+            return Ok((VFS_ROOT_ID, VFS_ROOT_ID));
+        };
         let fs_info = task.inner().fs_info.clone();
         Ok((fs_info.root.inode_id(), fs_info.cwd.inode_id()))
     }
@@ -113,6 +121,18 @@ impl TaskDomain for TaskDomainImpl {
         task.inner().fs_info.cwd = Arc::new(ShimFile::new(inode));
         Ok(())
     }
+    fn current_umask(&self) -> AlienResult<u32> {
+        let task = current_task().unwrap();
+        let umask = task.inner().fs_info.umask;
+        Ok(umask)
+    }
+
+    fn do_umask(&self, mask: u32) -> AlienResult<u32> {
+        let task = current_task().unwrap();
+        let old = core::mem::replace(&mut task.inner().fs_info.umask, mask & 0o777);
+        Ok(old)
+    }
+
     fn copy_to_user(&self, dst: usize, buf: &[u8]) -> AlienResult<()> {
         let task = current_task().unwrap();
         task.write_bytes_to_user(VirtAddr::from(dst), buf)
@@ -217,6 +237,9 @@ impl TaskDomain for TaskDomainImpl {
     fn do_sigprocmask(&self, how: usize, set: usize, oldset: usize) -> AlienResult<isize> {
         syscall::signal::do_sigprocmask(how, set, oldset)
     }
+    fn do_set_sigmask(&self, mask: usize) -> AlienResult<usize> {
+        syscall::signal::do_set_sigmask(mask)
+    }
     fn do_fcntl(&self, fd: usize, cmd: usize) -> AlienResult<(InodeID, usize)> {
         syscall::fs::do_fcntl(fd, cmd)
     }
@@ -237,6 +260,18 @@ impl TaskDomain for TaskDomainImpl {
         syscall::fs::do_pipe2(r, w, pipe)
     }
 
+    fn hold_fd(&self, fd: usize) -> AlienResult<usize> {
+        syscall::fs::hold_fd(fd)
+    }
+
+    fn install_held_fd(&self, id: usize) -> AlienResult<usize> {
+        syscall::fs::install_held_fd(id)
+    }
+
+    fn release_held_fd(&self, id: usize) -> AlienResult<()> {
+        syscall::fs::release_held_fd(id)
+    }
+
     fn do_exit(&self, exit_code: isize) -> AlienResult<isize> {
         syscall::exit::do_exit(exit_code as i32)
     }
@@ -270,6 +305,129 @@ impl TaskDomain for TaskDomainImpl {
     ) -> AlienResult<isize> {
         syscall::futex::futex(uaddr, futex_op, val, timeout, uaddr2, val3)
     }
+    fn do_sleep_until(&self, deadline: usize) -> AlienResult<()> {
+        timer::sleep_until(deadline)
+    }
+    fn do_park_until(&self, deadline: usize) -> AlienResult<()> {
+        timer::park_until(deadline)
+    }
+    fn do_setitimer(
+        &self,
+        which: usize,
+        interval: usize,
+        value: usize,
+    ) -> AlienResult<(usize, usize)> {
+        syscall::timer::do_setitimer(which, interval, value)
+    }
+    fn do_getitimer(&self, which: usize) -> AlienResult<(usize, usize)> {
+        syscall::timer::do_getitimer(which)
+    }
+    fn do_syscall_account(&self, enter: bool) -> AlienResult<()> {
+        cpu_time::syscall_boundary(enter);
+        Ok(())
+    }
+    fn do_cpu_times(&self, who: isize) -> AlienResult<(usize, usize)> {
+        cpu_time::do_cpu_times(who)
+    }
+    fn do_kill(&self, pid: isize, signum: usize) -> AlienResult<isize> {
+        syscall::signal::do_kill(pid, signum)
+    }
+    fn do_tgkill(&self, tgid: Option<usize>, tid: usize, signum: usize) -> AlienResult<isize> {
+        syscall::signal::do_tgkill(tgid, tid, signum)
+    }
+    fn do_sigqueueinfo(&self, pid: usize, signum: usize, info: usize) -> AlienResult<isize> {
+        syscall::signal::do_sigqueueinfo(pid, signum, info)
+    }
+    fn do_sigreturn(&self) -> AlienResult<isize> {
+        syscall::signal::do_sigreturn()
+    }
+    fn do_sigtimedwait(
+        &self,
+        set: usize,
+        info: usize,
+        deadline: Option<usize>,
+    ) -> AlienResult<isize> {
+        syscall::signal::do_sigtimedwait(set, info, deadline)
+    }
+    fn do_sigsuspend(&self, mask: usize) -> AlienResult<isize> {
+        syscall::signal::do_sigsuspend(mask)
+    }
+    fn do_deliver_signal(&self, result: isize) -> AlienResult<Option<usize>> {
+        signal::deliver(result)
+    }
+    fn do_has_pending_signal(&self) -> AlienResult<bool> {
+        let task = current_task().unwrap();
+        Ok(signal::has_pending(&task))
+    }
+    fn do_setpgid(&self, pid: usize, pgid: usize) -> AlienResult<()> {
+        syscall::pgrp::do_setpgid(pid, pgid)
+    }
+    fn do_getpgid(&self, pid: usize) -> AlienResult<usize> {
+        syscall::pgrp::do_getpgid(pid)
+    }
+    fn do_getsid(&self, pid: usize) -> AlienResult<usize> {
+        syscall::pgrp::do_getsid(pid)
+    }
+    fn do_setsid(&self) -> AlienResult<usize> {
+        syscall::pgrp::do_setsid()
+    }
+    fn job_control_ids(&self) -> AlienResult<(usize, usize, usize)> {
+        syscall::pgrp::job_control_ids()
+    }
+    fn pgrp_in_session(&self, pgid: usize, sid: usize) -> AlienResult<bool> {
+        Ok(syscall::pgrp::pgrp_in_session(pgid, sid))
+    }
+    fn do_kill_pgrp(&self, pgid: usize, signum: usize) -> AlienResult<()> {
+        syscall::pgrp::do_kill_pgrp(pgid, signum)
+    }
+    fn do_tty_job_control(&self, tty_sid: usize, fg_pgid: usize, signum: usize) -> AlienResult<()> {
+        syscall::pgrp::do_tty_job_control(tty_sid, fg_pgid, signum)
+    }
+    fn do_getresuid(&self) -> AlienResult<(u32, u32, u32)> {
+        syscall::cred::do_getresuid()
+    }
+    fn do_getresgid(&self) -> AlienResult<(u32, u32, u32)> {
+        syscall::cred::do_getresgid()
+    }
+    fn do_setuid(&self, uid: u32) -> AlienResult<()> {
+        syscall::cred::do_setuid(uid)
+    }
+    fn do_setgid(&self, gid: u32) -> AlienResult<()> {
+        syscall::cred::do_setgid(gid)
+    }
+    fn do_setreuid(&self, ruid: u32, euid: u32) -> AlienResult<()> {
+        syscall::cred::do_setreuid(ruid, euid)
+    }
+    fn do_setregid(&self, rgid: u32, egid: u32) -> AlienResult<()> {
+        syscall::cred::do_setregid(rgid, egid)
+    }
+    fn do_setresuid(&self, ruid: u32, euid: u32, suid: u32) -> AlienResult<()> {
+        syscall::cred::do_setresuid(ruid, euid, suid)
+    }
+    fn do_setresgid(&self, rgid: u32, egid: u32, sgid: u32) -> AlienResult<()> {
+        syscall::cred::do_setresgid(rgid, egid, sgid)
+    }
+    fn do_getgroups(&self, size: usize, list: usize) -> AlienResult<isize> {
+        syscall::cred::do_getgroups(size, list)
+    }
+    fn do_setgroups(&self, size: usize, list: usize) -> AlienResult<isize> {
+        syscall::cred::do_setgroups(size, list)
+    }
+    fn do_access_ids(&self, real: bool) -> AlienResult<(u32, u32)> {
+        syscall::cred::do_access_ids(real)
+    }
+    fn do_in_group(&self, gid: u32) -> AlienResult<bool> {
+        syscall::cred::do_in_group(gid)
+    }
+    fn pin_user_buffer(&self, addr: usize, len: usize, writable: bool) -> AlienResult<usize> {
+        pin::pin_user_buffer(addr, len, writable)
+    }
+    fn unpin_user_buffer(&self, id: usize) -> AlienResult<()> {
+        pin::unpin_user_buffer(id)
+    }
+    fn pinned_pages(&self, id: usize, pages: DVec<usize>) -> AlienResult<DVec<usize>> {
+        pin::pinned_pages(id, pages)
+    }
 }
 define_unwind_for_TaskDomain!(TaskDomainImpl);
 pub fn main() -> Box<dyn TaskDomain> {
@@ -0,0 +1,150 @@
+//! User buffers pinned for the length of one large transfer.
+//!
+//! Pinning checks once, page by page, that the whole buffer is mapped and allows the
+//! access, looks up the physical pages behind it and marks the mmap regions it overlaps so
+//! that munmap and mprotect fail with EBUSY until the buffer is unpinned. While another
+//! thread has a buffer pinned, the break does not move down and exec waits. Other domains
+//! get the pages with [`pinned_pages`] and move data to or from them directly, without
+//! walking the page table again.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::ops::Range;
+
+use basic::{config::FRAME_SIZE, AlienError, AlienResult};
+use memory_addr::align_down_4k;
+use page_table::MappingFlags;
+use shared_heap::DVec;
+
+use crate::{
+    processor::{current_task, find_threads},
+    syscall::mmap::from_prot,
+    task::Task,
+};
+
+#[derive(Debug)]
+struct PinnedBuffer {
+    range: Range<usize>,
+    /// The physical address of every page of the buffer
+    pages: Vec<usize>,
+}
+
+/// The buffers a thread has pinned, by id
+#[derive(Debug, Default)]
+pub struct PinnedBuffers {
+    next_id: usize,
+    buffers: BTreeMap<usize, PinnedBuffer>,
+}
+
+impl PinnedBuffers {
+    pub const fn new() -> Self {
+        Self {
+            next_id: 0,
+            buffers: BTreeMap::new(),
+        }
+    }
+}
+
+/// The physical address of the page at `page`, if the kernel may access it on behalf of
+/// the user.
+fn user_page(task: &Task, page: usize, writable: bool) -> Option<usize> {
+    let need = if writable {
+        MappingFlags::USER | MappingFlags::WRITE
+    } else {
+        MappingFlags::USER | MappingFlags::READ
+    };
+    let (phys, flags, _) = task.address_space.lock().query(page).ok()?;
+    if flags.contains(need) {
+        return Some(phys.as_usize());
+    }
+    // mmap pages get their permissions on the first access, fault this one in as that
+    // access would and look again
+    let prot = task.mmap.lock().get_region(page)?.prot;
+    task.address_space
+        .lock()
+        .protect(page..page + FRAME_SIZE, from_prot(prot))
+        .ok()?;
+    let (phys, flags, _) = task.address_space.lock().query(page).ok()?;
+    flags.contains(need).then_some(phys.as_usize())
+}
+
+/// Count a pin of `range` in, or out of, every mmap region it overlaps.
+fn mark_regions(task: &Task, range: &Range<usize>, pin: bool) {
+    let mut mmap = task.mmap.lock();
+    let mut page = align_down_4k(range.start);
+    while page < range.end {
+        let next = match mmap.get_region_mut(page) {
+            Some(region) => {
+                if pin {
+                    region.pins += 1;
+                } else {
+                    region.pins = region.pins.saturating_sub(1);
+                }
+                region.start + region.len
+            }
+            None => page + FRAME_SIZE,
+        };
+        page = next;
+    }
+}
+
+/// Pin the user buffer `addr..addr + len`, `writable` if the kernel is going to fill it.
+pub fn pin_user_buffer(addr: usize, len: usize, writable: bool) -> AlienResult<usize> {
+    if len == 0 {
+        return Err(AlienError::EINVAL);
+    }
+    let end = addr.checked_add(len).ok_or(AlienError::EFAULT)?;
+    let task = current_task().unwrap();
+    let range = addr..end;
+    let pages = (align_down_4k(addr)..end)
+        .step_by(FRAME_SIZE)
+        .map(|page| user_page(&task, page, writable).ok_or(AlienError::EFAULT))
+        .collect::<AlienResult<Vec<_>>>()?;
+    mark_regions(&task, &range, true);
+    let mut pinned = task.pinned_buffers.lock();
+    let id = pinned.next_id;
+    pinned.next_id += 1;
+    pinned.buffers.insert(id, PinnedBuffer { range, pages });
+    Ok(id)
+}
+
+/// Whether a thread other than `task` has a buffer pinned in the address space of `task`.
+pub fn pinned_by_others(task: &Task) -> bool {
+    find_threads(task.pid()).iter().any(|thread| {
+        thread.tid() != task.tid()
+            && Arc::ptr_eq(&thread.address_space, &task.address_space)
+            && !thread.pinned_buffers.lock().buffers.is_empty()
+    })
+}
+
+/// Unpin every buffer `task` still has pinned, when it exits.
+pub fn unpin_all(task: &Task) {
+    let buffers = core::mem::take(&mut task.pinned_buffers.lock().buffers);
+    for buffer in buffers.values() {
+        mark_regions(task, &buffer.range, false);
+    }
+}
+
+pub fn unpin_user_buffer(id: usize) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let buffer = task
+        .pinned_buffers
+        .lock()
+        .buffers
+        .remove(&id)
+        .ok_or(AlienError::EINVAL)?;
+    mark_regions(&task, &buffer.range, false);
+    Ok(())
+}
+
+/// Fill `pages` with the physical addresses of the pages of the pinned buffer `id`, from
+/// the page of its first byte on. `pages` has to have room for exactly all of them.
+pub fn pinned_pages(id: usize, mut pages: DVec<usize>) -> AlienResult<DVec<usize>> {
+    let task = current_task().unwrap();
+    let pinned = task.pinned_buffers.lock();
+    let buffer = pinned.buffers.get(&id).ok_or(AlienError::EINVAL)?;
+    if pages.len() != buffer.pages.len() {
+        return Err(AlienError::EINVAL);
+    }
+    pages.as_mut_slice().copy_from_slice(&buffer.pages);
+    Ok(pages)
+}
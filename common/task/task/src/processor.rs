@@ -1,4 +1,8 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
 
 use basic::{sync::Mutex, wake_up_wait_task};
 
@@ -25,3 +29,24 @@ pub fn remove_task(tid: usize) {
 pub fn find_task(tid: usize) -> Option<Arc<Task>> {
     GLOBAL_TASK_MANAGER.lock().get(&tid).map(Arc::clone)
 }
+
+/// The live threads of process `pid`
+pub fn find_threads(pid: usize) -> Vec<Arc<Task>> {
+    GLOBAL_TASK_MANAGER
+        .lock()
+        .values()
+        .filter(|task| task.pid() == pid)
+        .cloned()
+        .collect()
+}
+
+/// The pids of the processes with a live thread that matches `filter`
+pub fn find_processes(filter: impl Fn(&Task) -> bool) -> BTreeSet<usize> {
+    // the filter may lock the tasks, so it runs without the manager locked
+    let tasks: Vec<Arc<Task>> = GLOBAL_TASK_MANAGER.lock().values().cloned().collect();
+    tasks
+        .iter()
+        .filter(|task| filter(task))
+        .map(|task| task.pid())
+        .collect()
+}
@@ -328,6 +328,8 @@ pub struct MMapRegion {
     pub fd: Option<Arc<ShimFile>>,
     /// The offset in the file to start from
     pub offset: usize,
+    /// Pinned user buffers overlapping the mapping, which keep it from being unmapped
+    pub pins: usize,
 }
 
 impl MMapInfo {
@@ -392,6 +394,7 @@ impl MMapRegion {
             flags,
             fd,
             offset,
+            pins: 0,
         }
     }
     // [a-b]
@@ -450,3 +453,77 @@ impl Default for ResourceLimits {
         rlimits
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use basic::{
+        config::{FRAME_SIZE, PROCESS_HEAP_MAX},
+        constants::{
+            aux::{AT_IGNORE, AT_PAGESZ},
+            io::{MMapFlags, ProtFlags},
+        },
+        AlienError,
+    };
+
+    use super::{AuxVec, MMapInfo, MMapRegion};
+
+    #[test]
+    fn aux_vec_set_get_del() {
+        let mut aux = AuxVec::new();
+        aux.set(AT_PAGESZ, 4096).unwrap();
+        aux.set(AT_PAGESZ, FRAME_SIZE as u64).unwrap();
+        assert_eq!(aux.get(AT_PAGESZ), Some(FRAME_SIZE as u64));
+        assert_eq!(aux.table().len(), 1);
+        assert_eq!(aux.del(AT_PAGESZ), Some(FRAME_SIZE as u64));
+        assert_eq!(aux.get(AT_PAGESZ), None);
+    }
+
+    #[test]
+    fn aux_vec_rejects_null_and_ignore() {
+        let mut aux = AuxVec::new();
+        assert!(matches!(aux.set(0, 1), Err(AlienError::EINVAL)));
+        assert!(matches!(aux.set(AT_IGNORE, 1), Err(AlienError::EINVAL)));
+        assert!(aux.table().is_empty());
+    }
+
+    #[test]
+    fn mmap_alloc_is_frame_aligned() {
+        let mut info = MMapInfo::new();
+        let first = info.alloc(1);
+        assert_eq!(first, PROCESS_HEAP_MAX..PROCESS_HEAP_MAX + FRAME_SIZE);
+        let second = info.alloc(FRAME_SIZE + 1);
+        assert_eq!(second.start, first.end);
+        assert_eq!(second.end - second.start, 2 * FRAME_SIZE);
+        assert_eq!(info.alloc(0), second.end..second.end);
+    }
+
+    #[test]
+    fn mmap_region_split_and_lookup() {
+        let mut info = MMapInfo::new();
+        let range = info.alloc(3 * FRAME_SIZE);
+        let region = MMapRegion::new(
+            range.start,
+            3 * FRAME_SIZE,
+            3 * FRAME_SIZE,
+            ProtFlags::PROT_READ,
+            MMapFlags::MAP_ANONYMOUS,
+            None,
+            0x100,
+        );
+        let (low, high) = region.split(range.start + FRAME_SIZE);
+        assert_eq!((low.len, low.map_len), (FRAME_SIZE, FRAME_SIZE));
+        assert_eq!(high.start, range.start + FRAME_SIZE);
+        assert_eq!(high.len, 2 * FRAME_SIZE);
+        assert_eq!(high.offset, 0x100 + FRAME_SIZE);
+
+        info.add_region(low);
+        info.add_region(high);
+        assert_eq!(
+            info.get_region(range.start + 10).unwrap().start,
+            range.start
+        );
+        info.remove_region(range.start);
+        assert!(info.get_region(range.start).is_none());
+        assert!(info.get_region(range.end - 1).is_some());
+    }
+}
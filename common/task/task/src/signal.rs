@@ -0,0 +1,578 @@
+//! Signal generation and delivery.
+//!
+//! Pending signals are kept per thread in the Linux sigset layout, `signum` at bit
+//! `signum - 1`, like the mask in [`SignalReceivers`](basic::constants::signal::SignalReceivers).
+//! A signal is delivered when its thread returns from a syscall: the default action is
+//! taken, or a [`SignalFrame`] is pushed on the user stack and the handler is entered. The
+//! handler returns through `rt_sigreturn`, either via `sa_restorer` or via the trampoline
+//! page at [`SIGRETURN_TRAMPOLINE`]. A frame that cannot be pushed or restored kills the
+//! process with `SIGSEGV`.
+//!
+//! A stop signal whose default action is taken stops every thread of the process: each of
+//! them parks on its way back to user space until `SIGCONT` or `SIGKILL` is generated.
+//!
+//! The kernel steps `sepc` past the `ecall` before a syscall runs and writes the result of
+//! the syscall to `a0` afterwards, so the frame records the result as the saved `a0`.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::mem::size_of;
+
+use basic::{
+    config::{FRAME_SIZE, MAX_THREAD_NUM, TRAP_CONTEXT_BASE},
+    constants::signal::{SigAction, SigActionFlags, SignalNumber, SignalStack, SimpleBitSet},
+    AlienError, AlienResult,
+};
+use memory_addr::VirtAddr;
+use pod::Pod;
+
+use crate::{
+    processor::{current_task, find_task, find_threads},
+    syscall::{exit::exit_with_status, futex::FUTEX_WAITER, pgrp::is_orphaned_pgrp},
+    task::Task,
+    timer,
+};
+
+/// A user page with `li a7, 139; ecall`, the return address of handlers without
+/// `SA_RESTORER`. It sits below the trap contexts of all threads.
+pub const SIGRETURN_TRAMPOLINE: usize = TRAP_CONTEXT_BASE - FRAME_SIZE * MAX_THREAD_NUM;
+pub const SIGRETURN_CODE: [u32; 2] = [0x08b0_0893, 0x0000_0073];
+
+/// The largest signal number
+pub const SIGNAL_MAX: usize = 64;
+const SIGRTMIN: usize = 32;
+
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+/// `si_code` values
+pub const SI_USER: i32 = 0;
+pub const SI_KERNEL: i32 = 0x80;
+pub const SI_TKILL: i32 = -6;
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+
+/// `ss_flags` of a disabled alternate stack
+const SS_DISABLE: i32 = 2;
+const SS_ONSTACK: i32 = 1;
+
+/// Registers in the trap frame
+const REG_RA: usize = 1;
+const REG_SP: usize = 2;
+const REG_A0: usize = 10;
+const REG_A1: usize = 11;
+const REG_A2: usize = 12;
+
+const fn bit(signum: SignalNumber) -> usize {
+    1 << (signum as usize - 1)
+}
+
+/// Signals that can be neither blocked nor handled
+const UNBLOCKABLE: usize = bit(SignalNumber::SIGKILL) | bit(SignalNumber::SIGSTOP);
+
+/// Signals whose default action is to do nothing. `SIGCONT` continues the process as soon
+/// as it is generated, so there is nothing left to do on delivery.
+const DEFAULT_IGNORED: usize = bit(SignalNumber::SIGCHLD)
+    | bit(SignalNumber::SIGURG)
+    | bit(SignalNumber::SIGWINCH)
+    | bit(SignalNumber::SIGCONT);
+
+/// Signals whose default action is to stop the process
+const STOP_SIGNALS: usize = bit(SignalNumber::SIGSTOP) | TTY_STOP_SIGNALS;
+/// Stop signals from the terminal, which are discarded for orphaned process groups
+const TTY_STOP_SIGNALS: usize =
+    bit(SignalNumber::SIGTSTP) | bit(SignalNumber::SIGTTIN) | bit(SignalNumber::SIGTTOU);
+
+/// `siginfo_t` of riscv64 Linux
+#[derive(Debug, Copy, Clone, Pod)]
+#[repr(C)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    _pad: i32,
+    pub pid: i32,
+    pub uid: u32,
+    /// `si_value` of queued signals, `si_status` of `SIGCHLD`
+    pub value: usize,
+    _rest: [usize; 12],
+}
+
+impl SigInfo {
+    pub fn new(signum: usize, code: i32, pid: usize) -> Self {
+        Self {
+            signo: signum as i32,
+            errno: 0,
+            code,
+            _pad: 0,
+            pid: pid as i32,
+            uid: 0,
+            value: 0,
+            _rest: [0; 12],
+        }
+    }
+}
+
+/// `ucontext_t` of riscv64 Linux. The floating point registers are not saved.
+#[derive(Debug, Copy, Clone, Pod)]
+#[repr(C)]
+pub struct UContext {
+    pub flags: usize,
+    pub link: usize,
+    pub stack: SignalStack,
+    pub sigmask: usize,
+    /// The rest of the 128 byte sigset, followed by padding to align `regs` to 16 bytes
+    _unused: [usize; 16],
+    /// `pc`, followed by `x1` to `x31`
+    pub regs: [usize; 32],
+    pub fp: [usize; 66],
+}
+
+/// What is pushed on the user stack before a handler is entered
+#[derive(Debug, Copy, Clone, Pod)]
+#[repr(C)]
+pub struct SignalFrame {
+    pub info: SigInfo,
+    pub uc: UContext,
+}
+
+#[derive(Debug, Default)]
+pub struct PendingSignals {
+    /// Pending signals, bit `signum - 1`
+    set: usize,
+    /// The siginfo of each pending signal. Real-time signals are queued, a standard signal
+    /// that is already pending is dropped.
+    queue: VecDeque<SigInfo>,
+    /// Signals the thread waits for in `rt_sigtimedwait`, which wake it even if blocked
+    waiting: usize,
+    /// The mask to restore once `rt_sigsuspend` was interrupted
+    saved_mask: Option<usize>,
+    /// The wait status of the thread group, once one of its threads took a fatal signal
+    group_exit: Option<i32>,
+    /// Whether the thread group is stopped
+    stopped: bool,
+    /// The wait status of the last stop or continue of the thread group that `wait4` has
+    /// not reported yet. Only kept on the thread group leader.
+    wait_report: Option<i32>,
+}
+
+impl PendingSignals {
+    pub const fn new() -> Self {
+        Self {
+            set: 0,
+            queue: VecDeque::new(),
+            waiting: 0,
+            saved_mask: None,
+            group_exit: None,
+            stopped: false,
+            wait_report: None,
+        }
+    }
+
+    fn push(&mut self, info: SigInfo) {
+        let signum = info.signo as usize;
+        let bit = 1 << (signum - 1);
+        if self.set & bit != 0 && signum < SIGRTMIN {
+            return;
+        }
+        self.set |= bit;
+        self.queue.push_back(info);
+    }
+
+    /// Remove the lowest pending signal in `set`.
+    fn take(&mut self, set: usize) -> Option<SigInfo> {
+        let pending = self.set & set;
+        if pending == 0 {
+            return None;
+        }
+        let signum = pending.trailing_zeros() as usize + 1;
+        let index = self
+            .queue
+            .iter()
+            .position(|info| info.signo as usize == signum)?;
+        let info = self.queue.remove(index)?;
+        if !self.queue.iter().any(|info| info.signo as usize == signum) {
+            self.set &= !(1 << (signum - 1));
+        }
+        Some(info)
+    }
+
+    /// Drop every pending signal in `set`.
+    fn discard(&mut self, set: usize) {
+        self.set &= !set;
+        self.queue
+            .retain(|info| set & (1 << (info.signo as usize - 1)) == 0);
+    }
+}
+
+fn signal_mask(task: &Task) -> usize {
+    task.signal_receivers.lock().mask.bits() & !UNBLOCKABLE
+}
+
+pub fn set_signal_mask(task: &Task, mask: usize) {
+    task.signal_receivers.lock().mask = SimpleBitSet::from(mask & !UNBLOCKABLE);
+}
+
+fn action(task: &Task, signum: usize) -> SigAction {
+    let mut action = SigAction::empty();
+    task.signal_handlers.lock().get_action(signum, &mut action);
+    action
+}
+
+/// Whether `signum` would be discarded on delivery to `task`.
+fn ignored(task: &Task, signum: usize) -> bool {
+    match action(task, signum).handler {
+        SIG_IGN => true,
+        // init only gets the signals it handles
+        SIG_DFL => task.pid() == 1 || DEFAULT_IGNORED & (1 << (signum - 1)) != 0,
+        _ => false,
+    }
+}
+
+/// Whether `signum` is blocked by `task` or ignored by its process.
+pub fn blocked_or_ignored(task: &Task, signum: usize) -> bool {
+    signal_mask(task) & (1 << (signum - 1)) != 0 || action(task, signum).handler == SIG_IGN
+}
+
+/// Whether a signal interrupts the blocking calls of `task`.
+pub fn has_pending(task: &Task) -> bool {
+    let pending = task.pending_signals.lock();
+    pending.group_exit.is_some() || pending.set & (!signal_mask(task) | pending.waiting) != 0
+}
+
+/// Wake `task` from whatever it blocks on. Blocking calls return `EINTR` once they see
+/// [`has_pending`].
+fn interrupt(task: &Task) -> AlienResult<()> {
+    let tid = task.tid();
+    if basic::current_tid()? == Some(tid) {
+        return Ok(());
+    }
+    timer::cancel_sleep(tid);
+    FUTEX_WAITER.lock().cancel(tid);
+    basic::wake_up_wait_task(tid)
+}
+
+/// Generate `info.signo` for the thread `task`.
+pub fn send_to_thread(task: &Arc<Task>, info: SigInfo) -> AlienResult<()> {
+    let signum = info.signo as usize;
+    let bit = 1 << (signum - 1);
+    if signum == SignalNumber::SIGKILL as usize {
+        if task.pid() == 1 {
+            return Ok(());
+        }
+        return kill_group(task.pid(), signum as i32);
+    }
+    if signum == SignalNumber::SIGCONT as usize {
+        continue_group(task.pid())?;
+    } else if STOP_SIGNALS & bit != 0 {
+        // a stop cancels a pending continue
+        for thread in find_threads(task.pid()) {
+            thread
+                .pending_signals
+                .lock()
+                .discard(self::bit(SignalNumber::SIGCONT));
+        }
+    }
+    let mut pending = task.pending_signals.lock();
+    let blocked = signal_mask(task) & bit != 0;
+    if !blocked && pending.waiting & bit == 0 && ignored(task, signum) {
+        return Ok(());
+    }
+    pending.push(info);
+    let wake = !blocked || pending.waiting & bit != 0;
+    drop(pending);
+    if wake {
+        interrupt(task)?;
+    }
+    Ok(())
+}
+
+/// Generate `info.signo` for process `pid`. It goes to the first thread that does not
+/// block it, preferring the thread group leader.
+pub fn send_to_process(pid: usize, info: SigInfo) -> AlienResult<()> {
+    let mut threads = find_threads(pid);
+    if threads.is_empty() {
+        return Err(AlienError::ESRCH);
+    }
+    threads.sort_by_key(|thread| thread.tid() != pid);
+    let bit = 1 << (info.signo as usize - 1);
+    let target = threads
+        .iter()
+        .find(|thread| signal_mask(thread) & bit == 0)
+        .unwrap_or(&threads[0]);
+    send_to_thread(target, info)
+}
+
+/// Make every thread of process `pid` exit with wait status `status`.
+fn kill_group(pid: usize, status: i32) -> AlienResult<()> {
+    for thread in find_threads(pid) {
+        thread
+            .pending_signals
+            .lock()
+            .group_exit
+            .get_or_insert(status);
+        interrupt(&thread)?;
+    }
+    Ok(())
+}
+
+/// Kill the process of `task` with `SIGSEGV`, as Linux does when a signal frame cannot be
+/// written to or read from the user stack.
+fn force_sigsegv(task: &Task) -> AlienResult<()> {
+    kill_group(task.pid(), SignalNumber::SIGSEGV as i32)
+}
+
+/// Stop every thread of the process of `task`, which took the stop signal `signum`.
+fn stop_group(task: &Task, signum: usize) -> AlienResult<()> {
+    for thread in find_threads(task.pid()) {
+        thread.pending_signals.lock().stopped = true;
+        interrupt(&thread)?;
+    }
+    notify_parent(task.pid(), CLD_STOPPED, signum)
+}
+
+/// Continue the threads of process `pid` if it is stopped. Pending stop signals are
+/// discarded either way.
+fn continue_group(pid: usize) -> AlienResult<()> {
+    let mut stopped = false;
+    for thread in find_threads(pid) {
+        let mut pending = thread.pending_signals.lock();
+        pending.discard(STOP_SIGNALS);
+        if pending.stopped {
+            pending.stopped = false;
+            stopped = true;
+            drop(pending);
+            basic::wake_up_wait_task(thread.tid())?;
+        }
+    }
+    if stopped {
+        notify_parent(pid, CLD_CONTINUED, SignalNumber::SIGCONT as usize)?;
+    }
+    Ok(())
+}
+
+/// Leave the stop or continue of process `pid` for `wait4` and tell its parent with
+/// `SIGCHLD`, unless the parent set `SA_NOCLDSTOP`.
+fn notify_parent(pid: usize, code: i32, signum: usize) -> AlienResult<()> {
+    let Some(leader) = find_task(pid) else {
+        return Ok(());
+    };
+    let status = if code == CLD_STOPPED {
+        ((signum as i32) << 8) | 0x7f
+    } else {
+        0xffff
+    };
+    leader.pending_signals.lock().wait_report = Some(status);
+    let parent = leader
+        .inner()
+        .parent
+        .clone()
+        .and_then(|parent| parent.upgrade());
+    let Some(parent) = parent else {
+        return Ok(());
+    };
+    let sigchld = SignalNumber::SIGCHLD as usize;
+    if action(&parent, sigchld)
+        .flags
+        .contains(SigActionFlags::SA_NOCLDSTOP)
+    {
+        return Ok(());
+    }
+    let mut info = SigInfo::new(sigchld, code, pid);
+    info.value = signum;
+    send_to_process(parent.pid(), info)
+}
+
+/// Take the stop or continue of the process led by `leader` that `wait4` has not reported
+/// yet, if `untraced` or `continued` asks for it. `keep` leaves it to be reported again.
+pub fn take_wait_report(leader: &Task, untraced: bool, continued: bool, keep: bool) -> Option<i32> {
+    let mut pending = leader.pending_signals.lock();
+    let status = pending.wait_report?;
+    let wanted = if status == 0xffff {
+        continued
+    } else {
+        untraced
+    };
+    if !wanted {
+        return None;
+    }
+    if !keep {
+        pending.wait_report = None;
+    }
+    Some(status)
+}
+
+/// Deliver the pending signals of the current task before it returns to user space.
+///
+/// `result` is the value the finished syscall returns in `a0`. Returns the signal number if
+/// a handler is entered, which the syscall has to return instead.
+pub fn deliver(result: isize) -> AlienResult<Option<usize>> {
+    let Some(task) = current_task() else {
+        return Ok(None);
+    };
+    loop {
+        let mut pending = task.pending_signals.lock();
+        if let Some(status) = pending.group_exit {
+            drop(pending);
+            exit_with_status(status)?;
+        }
+        if pending.stopped {
+            drop(pending);
+            crate::cpu_time::parked(basic::wait_now)?;
+            continue;
+        }
+        let Some(info) = pending.take(!signal_mask(&task)) else {
+            break;
+        };
+        drop(pending);
+        let signum = info.signo as usize;
+        let bit = 1 << (signum - 1);
+        let action = action(&task, signum);
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL if DEFAULT_IGNORED & bit != 0 || task.pid() == 1 => {}
+            SIG_DFL if STOP_SIGNALS & bit != 0 => {
+                let pgid = task.inner().pgid;
+                if TTY_STOP_SIGNALS & bit == 0 || !is_orphaned_pgrp(pgid) {
+                    stop_group(&task, signum)?;
+                }
+            }
+            SIG_DFL => {
+                kill_group(task.pid(), signum as i32)?;
+            }
+            _ => {
+                if enter_handler(&task, &info, &action, result).is_err() {
+                    force_sigsegv(&task)?;
+                    continue;
+                }
+                return Ok(Some(signum));
+            }
+        }
+    }
+    let saved_mask = task.pending_signals.lock().saved_mask.take();
+    if let Some(mask) = saved_mask {
+        set_signal_mask(&task, mask);
+    }
+    Ok(None)
+}
+
+/// Push a [`SignalFrame`] and point the trap frame at the handler of `action`.
+fn enter_handler(
+    task: &Arc<Task>,
+    info: &SigInfo,
+    action: &SigAction,
+    result: isize,
+) -> AlienResult<()> {
+    let signum = info.signo as usize;
+    let trap_frame = task.trap_frame();
+    let stack = task.inner().ss_stack;
+    let mut sp = trap_frame.regs()[REG_SP];
+    let alt_stack = stack.ss_flags as i32 & SS_DISABLE == 0 && stack.ss_size != 0;
+    let on_alt_stack = alt_stack && (stack.ss_sp..stack.ss_sp + stack.ss_size).contains(&sp);
+    if action.flags.contains(SigActionFlags::SA_ONSTACK) && alt_stack && !on_alt_stack {
+        sp = stack.ss_sp + stack.ss_size;
+    }
+    sp = (sp - size_of::<SignalFrame>()) & !0xf;
+
+    let mask = signal_mask(task);
+    let old_mask = task
+        .pending_signals
+        .lock()
+        .saved_mask
+        .take()
+        .unwrap_or(mask);
+    let mut frame = SignalFrame::new_zeroed();
+    frame.info = *info;
+    frame.uc.stack = stack;
+    if on_alt_stack {
+        frame.uc.stack.ss_flags = SS_ONSTACK as _;
+    }
+    frame.uc.sigmask = old_mask;
+    frame.uc.regs.copy_from_slice(trap_frame.regs());
+    frame.uc.regs[0] = trap_frame.sepc();
+    frame.uc.regs[REG_A0] = result as usize;
+    task.write_val_to_user(VirtAddr::from(sp), &frame)?;
+
+    let regs = trap_frame.regs();
+    regs[REG_RA] = if action.flags.contains(SigActionFlags::SA_RESTORER) {
+        action.restorer
+    } else {
+        SIGRETURN_TRAMPOLINE
+    };
+    regs[REG_SP] = sp;
+    regs[REG_A0] = signum;
+    regs[REG_A1] = sp;
+    regs[REG_A2] = sp + size_of::<SigInfo>();
+    trap_frame.set_sepc(action.handler);
+
+    let mut new_mask = mask | action.mask;
+    if !action.flags.contains(SigActionFlags::SA_NODEFER) {
+        new_mask |= 1 << (signum - 1);
+    }
+    set_signal_mask(task, new_mask);
+    if action.flags.contains(SigActionFlags::SA_RESETHAND) {
+        task.signal_handlers
+            .lock()
+            .set_action(signum, &SigAction::empty());
+    }
+    Ok(())
+}
+
+/// Restore the context saved by [`enter_handler`]. Returns the saved `a0`, which the
+/// kernel writes back as the result of `rt_sigreturn`.
+pub fn sigreturn() -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let trap_frame = task.trap_frame();
+    // the handler returns with the stack pointer it was entered with
+    let frame_addr = trap_frame.regs()[REG_SP];
+    let Ok(frame) = task.read_val_from_user::<SignalFrame>(VirtAddr::from(frame_addr)) else {
+        force_sigsegv(&task)?;
+        return Ok(0);
+    };
+    let regs = trap_frame.regs();
+    regs[1..].copy_from_slice(&frame.uc.regs[1..]);
+    trap_frame.set_sepc(frame.uc.regs[0]);
+    set_signal_mask(&task, frame.uc.sigmask);
+    Ok(frame.uc.regs[REG_A0] as isize)
+}
+
+/// Wait for one of the signals in `set`, until the timer reaches `deadline` if given.
+pub fn wait_for(set: usize, deadline: Option<usize>) -> AlienResult<SigInfo> {
+    let task = current_task().unwrap();
+    let set = set & !UNBLOCKABLE;
+    let res = loop {
+        let mut pending = task.pending_signals.lock();
+        if let Some(info) = pending.take(set) {
+            break Ok(info);
+        }
+        if pending.group_exit.is_some() || pending.set & !signal_mask(&task) != 0 {
+            break Err(AlienError::EINTR);
+        }
+        pending.waiting = set;
+        drop(pending);
+        let res = match deadline {
+            Some(deadline) if deadline <= basic::time::read_timer() => Err(AlienError::EAGAIN),
+            Some(deadline) => timer::sleep_until(deadline),
+            None => crate::cpu_time::parked(basic::wait_now),
+        };
+        match res {
+            Ok(()) | Err(AlienError::EINTR) => continue,
+            Err(e) => break Err(e),
+        }
+    };
+    task.pending_signals.lock().waiting = 0;
+    res
+}
+
+/// Replace the signal mask until a signal is delivered and wait for one.
+pub fn suspend(mask: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let old_mask = signal_mask(&task);
+    set_signal_mask(&task, mask);
+    task.pending_signals.lock().saved_mask = Some(old_mask);
+    while !has_pending(&task) {
+        crate::cpu_time::parked(basic::wait_now)?;
+    }
+    Err(AlienError::EINTR)
+}
@@ -0,0 +1,115 @@
+use alloc::{vec, vec::Vec};
+use core::mem::size_of;
+
+use basic::{AlienError, AlienResult};
+use memory_addr::VirtAddr;
+
+use crate::{cred::NGROUPS_MAX, processor::current_task};
+
+pub fn do_getresuid() -> AlienResult<(u32, u32, u32)> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().uid.res();
+    Ok(res)
+}
+
+pub fn do_getresgid() -> AlienResult<(u32, u32, u32)> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().gid.res();
+    Ok(res)
+}
+
+pub fn do_setuid(uid: u32) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().set_uid(uid);
+    res
+}
+
+pub fn do_setgid(gid: u32) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().set_gid(gid);
+    res
+}
+
+pub fn do_setreuid(ruid: u32, euid: u32) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().set_reuid(ruid, euid);
+    res
+}
+
+pub fn do_setregid(rgid: u32, egid: u32) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().set_regid(rgid, egid);
+    res
+}
+
+pub fn do_setresuid(ruid: u32, euid: u32, suid: u32) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().set_resuid(ruid, euid, suid);
+    res
+}
+
+pub fn do_setresgid(rgid: u32, egid: u32, sgid: u32) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let res = task.cred.lock().set_resgid(rgid, egid, sgid);
+    res
+}
+
+/// Copy the supplementary groups to `list`, or only count them if `size` is 0.
+pub fn do_getgroups(size: usize, list: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let groups = task.cred.lock().groups.clone();
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if size < groups.len() {
+        return Err(AlienError::EINVAL);
+    }
+    let bytes = groups
+        .iter()
+        .flat_map(|gid| gid.to_ne_bytes())
+        .collect::<Vec<u8>>();
+    task.write_bytes_to_user(VirtAddr::from(list), &bytes)?;
+    Ok(groups.len() as isize)
+}
+
+pub fn do_setgroups(size: usize, list: usize) -> AlienResult<isize> {
+    if size > NGROUPS_MAX {
+        return Err(AlienError::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut bytes = vec![0u8; size * size_of::<u32>()];
+    if size > 0 {
+        task.read_bytes_from_user(VirtAddr::from(list), &mut bytes)?;
+    }
+    let mut groups = bytes
+        .chunks_exact(size_of::<u32>())
+        .map(|gid| u32::from_ne_bytes(gid.try_into().unwrap()))
+        .collect::<Vec<u32>>();
+    groups.sort_unstable();
+    groups.dedup();
+    task.cred.lock().set_groups(groups)?;
+    Ok(0)
+}
+
+/// The user and group id that permission checks of the current task use: the filesystem
+/// ids, or the real ids for `access`. Work done outside a task runs as root.
+pub fn do_access_ids(real: bool) -> AlienResult<(u32, u32)> {
+    let task = match current_task() {
+        Some(task) => task,
+        None => return Ok((0, 0)),
+    };
+    let cred = task.cred.lock();
+    if real {
+        Ok((cred.uid.real, cred.gid.real))
+    } else {
+        Ok((cred.uid.fs, cred.gid.fs))
+    }
+}
+
+pub fn do_in_group(gid: u32) -> AlienResult<bool> {
+    let in_group = match current_task() {
+        Some(task) => task.cred.lock().in_groups(gid),
+        None => false,
+    };
+    Ok(in_group)
+}
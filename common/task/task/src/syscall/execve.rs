@@ -6,7 +6,7 @@ use alloc::{
 use basic::{AlienError, AlienResult};
 use memory_addr::VirtAddr;
 
-use crate::processor::current_task;
+use crate::{pin::pinned_by_others, processor::current_task};
 
 pub fn do_execve(
     filename_ptr: VirtAddr,
@@ -28,12 +28,18 @@ pub fn do_execve(
         path_str = "./busybox".to_string();
         args.insert(0, "sh\0".to_string());
     }
+    let (mode, uid, gid) = crate::vfs_shim::exec_attr(&path_str)?;
     let mut data = Vec::new();
     if crate::vfs_shim::read_all(&path_str, &mut data) {
+        // the old address space goes away, wait for the transfers into it to end
+        while pinned_by_others(&task) {
+            basic::yield_now()?;
+        }
         let res = task.do_execve(&path_str, data.as_slice(), args, envs);
         if res.is_err() {
             return Err(AlienError::ENOEXEC);
         }
+        task.cred.lock().exec(mode, uid, gid);
         info!("exec {} success", path_str);
         Ok(0)
     } else {
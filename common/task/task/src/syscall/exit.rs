@@ -1,18 +1,29 @@
 use alloc::sync::Arc;
 
-use basic::{constants::ipc::FutexOp, println, println_color, AlienResult};
+use basic::{
+    constants::{ipc::FutexOp, signal::SignalNumber},
+    println, println_color, AlienResult,
+};
 use memory_addr::VirtAddr;
 use ptable::VmIo;
 use task_meta::TaskStatus;
 
 use crate::{
+    cpu_time,
     init::INIT_PROCESS,
+    pin,
     processor::{current_task, remove_task},
+    signal::{self, SigInfo, CLD_EXITED, CLD_KILLED},
+    timer,
 };
 
 pub fn do_exit(exit_code: i32) -> AlienResult<isize> {
+    exit_with_status((exit_code & 0xff) << 8)
+}
+
+/// Exit the current thread with the wait status `exit_code`.
+pub fn exit_with_status(exit_code: i32) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let exit_code = (exit_code & 0xff) << 8;
     if task.pid() == 1 {
         println!("Init process exit with code {}", exit_code);
         panic!("Init process exit");
@@ -42,7 +53,26 @@ pub fn do_exit(exit_code: i32) -> AlienResult<isize> {
         info!("exit clear_child_tid is 0");
     }
     if task.send_sigchld_when_exit || task.pid() == task.tid() {
-        //send_signal(parent.pid, SignalNumber::SIGCHLD as usize);
+        let parent = task
+            .inner()
+            .parent
+            .clone()
+            .and_then(|parent| parent.upgrade());
+        if let Some(parent) = parent {
+            let (code, status) = if exit_code & 0x7f == 0 {
+                (CLD_EXITED, exit_code >> 8)
+            } else {
+                (CLD_KILLED, exit_code & 0x7f)
+            };
+            let mut info = SigInfo::new(SignalNumber::SIGCHLD as usize, code, task.pid());
+            info.value = status as usize;
+            let _ = signal::send_to_process(parent.pid(), info);
+        }
+    }
+    pin::unpin_all(&task);
+    cpu_time::thread_exit();
+    if task.pid() == task.tid() {
+        timer::remove_real_timer(task.pid());
     }
     remove_task(task.tid()); // remove task from global task manager
     task.inner().status = TaskStatus::Terminated;
@@ -1,12 +1,17 @@
-use alloc::sync::Arc;
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use basic::{constants::io::Fcntl64Cmd, AlienError, AlienResult};
+use basic::{constants::io::Fcntl64Cmd, sync::Mutex, AlienError, AlienResult};
 use interface::InodeID;
 use memory_addr::VirtAddr;
 use pod::Pod;
 
 use crate::{processor::current_task, vfs_shim::ShimFile};
 
+/// Files sent in `SCM_RIGHTS` messages that no process has received yet, by handle
+static HELD_FILES: Mutex<BTreeMap<usize, Arc<ShimFile>>> = Mutex::new(BTreeMap::new());
+static HELD_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub fn do_fcntl(fd: usize, cmd: usize) -> AlienResult<(InodeID, usize)> {
     let cmd = Fcntl64Cmd::try_from(cmd as u32).unwrap();
     let task = current_task().unwrap();
@@ -32,6 +37,30 @@ pub fn do_dup(old_fd: usize, new_fd: Option<usize>) -> AlienResult<isize> {
         Ok(new_fd as isize)
     }
 }
+/// Keep the open file of `fd` alive apart from any fd table and return a handle to it.
+pub fn hold_fd(fd: usize) -> AlienResult<usize> {
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(AlienError::EBADF)?;
+    let id = HELD_FILE_ID.fetch_add(1, Ordering::SeqCst);
+    HELD_FILES.lock().insert(id, file);
+    Ok(id)
+}
+
+/// Give the held file `id` a new fd in the current task.
+pub fn install_held_fd(id: usize) -> AlienResult<usize> {
+    let file = HELD_FILES.lock().remove(&id).ok_or(AlienError::EBADF)?;
+    let task = current_task().unwrap();
+    let fd = task.add_file(file);
+    Ok(fd)
+}
+
+pub fn release_held_fd(id: usize) -> AlienResult<()> {
+    let file = HELD_FILES.lock().remove(&id);
+    // closing the file may release other held files
+    drop(file);
+    Ok(())
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod)]
 struct FdPair {
@@ -11,8 +11,10 @@ use memory_addr::VirtAddr;
 use ptable::VmIo;
 
 use crate::{
+    cpu_time,
     futex::{FutexWaitManager, FutexWaiter},
     processor::current_task,
+    signal,
 };
 
 pub static FUTEX_WAITER: Mutex<FutexWaitManager> = Mutex::new(FutexWaitManager::new());
@@ -50,12 +52,16 @@ pub fn futex(
             futex_waiter.add_waiter(uaddr, waiter);
             drop(futex_waiter);
             // switch to other task
-            basic::wait_now()?;
+            cpu_time::parked(basic::wait_now)?;
             warn!("Because of futex, we switch to other task");
             // checkout the timeout flag
             let timeout_flag = timeout_flag.lock();
             if *timeout_flag {
-                return Ok(0);
+                return Err(AlienError::ETIMEDOUT);
+            }
+            if signal::has_pending(&task) {
+                FUTEX_WAITER.lock().cancel(tid);
+                return Err(AlienError::EINTR);
             }
         };
     }
@@ -179,6 +179,9 @@ pub fn do_munmap(start: usize, len: usize) -> AlienResult<isize> {
     if region.start != start || len != region.len {
         return Err(AlienError::EINVAL);
     }
+    if region.pins > 0 {
+        return Err(AlienError::EBUSY);
+    }
     task.address_space.lock().unmap(start).unwrap();
     mmap.remove_region(start);
     Ok(0)
@@ -189,6 +192,9 @@ pub fn do_mprotect(addr: usize, len: usize, prot: u32) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let mut mmap = task.mmap.lock();
     let region = mmap.get_region_mut(addr).ok_or(AlienError::EINVAL)?;
+    if region.pins > 0 {
+        return Err(AlienError::EBUSY);
+    }
     // no V flag
     let map_flags = from_prot(prot);
     // basic::println_color!(32, "mprotect: region:{:#x?}", region);
@@ -221,7 +227,7 @@ pub fn do_load_page_fault(addr: usize) -> AlienResult<()> {
     Ok(())
 }
 
-fn from_prot(prot_flags: ProtFlags) -> MappingFlags {
+pub(crate) fn from_prot(prot_flags: ProtFlags) -> MappingFlags {
     let mut perm = MappingFlags::USER;
     if prot_flags.contains(ProtFlags::PROT_READ) {
         perm |= MappingFlags::READ;
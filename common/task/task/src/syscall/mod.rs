@@ -1,10 +1,13 @@
 pub mod clone;
+pub mod cred;
 pub mod execve;
 pub mod exit;
 pub mod fs;
 pub mod futex;
 pub mod mmap;
+pub mod pgrp;
 pub mod priority;
 pub mod prlimit;
 pub mod signal;
+pub mod timer;
 pub mod wait;
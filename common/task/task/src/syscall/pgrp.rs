@@ -0,0 +1,171 @@
+//! Process groups, sessions and the job control checks of terminals.
+//!
+//! The process group and session ids live in every thread of a process and are changed
+//! for all of them at once. Terminals keep the session they control and its foreground
+//! group themselves and ask [`do_tty_job_control`] whether the caller may use them.
+
+use alloc::sync::Arc;
+
+use basic::{constants::signal::SignalNumber, AlienError, AlienResult};
+
+use crate::{
+    processor::{current_task, find_processes, find_threads},
+    signal::{self, SigInfo, SI_KERNEL},
+    task::Task,
+};
+
+fn find_process(pid: usize) -> AlienResult<Arc<Task>> {
+    find_threads(pid)
+        .into_iter()
+        .next()
+        .ok_or(AlienError::ESRCH)
+}
+
+fn set_process_ids(pid: usize, pgid: usize, sid: usize) {
+    for thread in find_threads(pid) {
+        let mut inner = thread.inner();
+        inner.pgid = pgid;
+        inner.sid = sid;
+    }
+}
+
+/// Whether a process of session `sid` is in group `pgid`.
+pub fn pgrp_in_session(pgid: usize, sid: usize) -> bool {
+    !find_processes(|process| {
+        let inner = process.inner();
+        inner.pgid == pgid && inner.sid == sid
+    })
+    .is_empty()
+}
+
+/// Whether no member of group `pgid` has a parent in another group of the same session,
+/// so that no shell is left to continue the group once it is stopped.
+pub fn is_orphaned_pgrp(pgid: usize) -> bool {
+    let members = find_processes(|process| process.inner().pgid == pgid);
+    !members.into_iter().any(|pid| {
+        let Ok(process) = find_process(pid) else {
+            return false;
+        };
+        let (parent, sid) = {
+            let inner = process.inner();
+            (inner.parent.clone(), inner.sid)
+        };
+        parent
+            .and_then(|parent| parent.upgrade())
+            .is_some_and(|parent| {
+                let inner = parent.inner();
+                inner.pgid != pgid && inner.sid == sid
+            })
+    })
+}
+
+/// See https://man7.org/linux/man-pages/man2/setpgid.2.html
+pub fn do_setpgid(pid: usize, pgid: usize) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let pid = if pid == 0 { task.pid() } else { pid };
+    let pgid = if pgid == 0 { pid } else { pgid };
+    let target = find_process(pid)?;
+    let (parent, target_sid) = {
+        let inner = target.inner();
+        (inner.parent.clone(), inner.sid)
+    };
+    let sid = task.inner().sid;
+    if pid != task.pid() {
+        let is_child = parent
+            .and_then(|parent| parent.upgrade())
+            .is_some_and(|parent| parent.pid() == task.pid());
+        if !is_child {
+            return Err(AlienError::ESRCH);
+        }
+        if target_sid != sid {
+            return Err(AlienError::EPERM);
+        }
+    }
+    // a session leader can not leave its group
+    if target_sid == pid {
+        return Err(AlienError::EPERM);
+    }
+    if pgid != pid && !pgrp_in_session(pgid, sid) {
+        return Err(AlienError::EPERM);
+    }
+    set_process_ids(pid, pgid, target_sid);
+    Ok(())
+}
+
+pub fn do_getpgid(pid: usize) -> AlienResult<usize> {
+    let task = match pid {
+        0 => current_task().unwrap(),
+        pid => find_process(pid)?,
+    };
+    let pgid = task.inner().pgid;
+    Ok(pgid)
+}
+
+pub fn do_getsid(pid: usize) -> AlienResult<usize> {
+    let task = match pid {
+        0 => current_task().unwrap(),
+        pid => find_process(pid)?,
+    };
+    let sid = task.inner().sid;
+    Ok(sid)
+}
+
+/// Start a new session, without a controlling terminal, in a new group. Fails if a group
+/// with the id of the caller exists already.
+pub fn do_setsid() -> AlienResult<usize> {
+    let task = current_task().unwrap();
+    let pid = task.pid();
+    if !find_processes(|process| process.inner().pgid == pid).is_empty() {
+        return Err(AlienError::EPERM);
+    }
+    set_process_ids(pid, pid, pid);
+    Ok(pid)
+}
+
+/// The pid, process group and session of the current task
+pub fn job_control_ids() -> AlienResult<(usize, usize, usize)> {
+    let task = current_task().unwrap();
+    let inner = task.inner();
+    Ok((task.pid(), inner.pgid, inner.sid))
+}
+
+/// Send `signum` from the kernel to every process in group `pgid`.
+pub fn do_kill_pgrp(pgid: usize, signum: usize) -> AlienResult<()> {
+    let pids = find_processes(|process| process.inner().pgid == pgid);
+    if pids.is_empty() {
+        return Err(AlienError::ESRCH);
+    }
+    for pid in pids {
+        let _ = signal::send_to_process(pid, SigInfo::new(signum, SI_KERNEL, 0));
+    }
+    Ok(())
+}
+
+/// Check whether the current task may read (`SIGTTIN`) or write (`SIGTTOU`) the terminal
+/// that controls session `tty_sid` with the foreground group `fg_pgid`.
+///
+/// Background groups of that session get `signum` and the call fails with `EINTR`. If the
+/// caller blocks or ignores `signum`, reads fail with `EIO` and writes go ahead. Both fail
+/// with `EIO` if no one could continue the group.
+pub fn do_tty_job_control(tty_sid: usize, fg_pgid: usize, signum: usize) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let (pgid, sid) = {
+        let inner = task.inner();
+        (inner.pgid, inner.sid)
+    };
+    if sid != tty_sid || pgid == fg_pgid {
+        return Ok(());
+    }
+    if signal::blocked_or_ignored(&task, signum) {
+        return if signum == SignalNumber::SIGTTIN as usize {
+            Err(AlienError::EIO)
+        } else {
+            Ok(())
+        };
+    }
+    if is_orphaned_pgrp(pgid) {
+        return Err(AlienError::EIO);
+    }
+    do_kill_pgrp(pgid, signum)?;
+    Err(AlienError::EINTR)
+}
@@ -2,12 +2,16 @@ use alloc::vec::Vec;
 
 use basic::{
     constants::signal::{SignalStack, *},
-    AlienResult,
+    AlienError, AlienResult,
 };
 use memory_addr::VirtAddr;
 use pod::Pod;
 
-use crate::processor::current_task;
+use crate::{
+    processor::{current_task, find_processes, find_task, find_threads},
+    signal::{self, SigInfo, SIGNAL_MAX, SI_TKILL, SI_USER},
+    task::Task,
+};
 
 pub fn do_sigaction(sig: u8, action: usize, old_action: usize) -> AlienResult<isize> {
     let action = action as *const SigAction;
@@ -65,6 +69,152 @@ pub fn do_sigprocmask(how: usize, set: usize, oldset: usize) -> AlienResult<isiz
     Ok(0)
 }
 
+/// Replace the signal mask of the current task and return the old one.
+///
+/// Used by syscalls that take a temporary mask, like `epoll_pwait`.
+pub fn do_set_sigmask(mask: usize) -> AlienResult<usize> {
+    let task = current_task().unwrap();
+    let mut signal_receivers = task.signal_receivers.lock();
+    let old = signal_receivers.mask.bits();
+    signal_receivers.mask = SimpleBitSet::from(mask);
+    Ok(old)
+}
+
+fn check_signum(signum: usize) -> AlienResult<()> {
+    if signum > SIGNAL_MAX {
+        return Err(AlienError::EINVAL);
+    }
+    Ok(())
+}
+
+/// Whether `sender` may send signals to `target`: root may signal every user process,
+/// others only processes whose real or saved user id is their real or effective user id.
+fn may_signal(sender: &Task, target: &Task) -> AlienResult<()> {
+    if target.is_kernel_thread() {
+        return Err(AlienError::EPERM);
+    }
+    let sender = sender.cred.lock().uid;
+    let target = target.cred.lock().uid;
+    if sender.effective == 0
+        || [sender.real, sender.effective]
+            .iter()
+            .any(|&id| id == target.real || id == target.saved)
+    {
+        return Ok(());
+    }
+    Err(AlienError::EPERM)
+}
+
+/// Check the permission and send `info` to process `pid`. Signal 0 only checks.
+fn signal_process(sender: &Task, pid: usize, info: SigInfo) -> AlienResult<()> {
+    let threads = find_threads(pid);
+    let target = threads.first().ok_or(AlienError::ESRCH)?;
+    may_signal(sender, target)?;
+    if info.signo == 0 {
+        return Ok(());
+    }
+    signal::send_to_process(pid, info)
+}
+
+/// Send `info` to each of `pids` the sender may signal. Fails with the last error if no
+/// process got the signal.
+fn signal_processes(
+    sender: &Task,
+    pids: impl IntoIterator<Item = usize>,
+    info: SigInfo,
+) -> AlienResult<()> {
+    let mut res = Err(AlienError::ESRCH);
+    for pid in pids {
+        match signal_process(sender, pid, info) {
+            Ok(()) => res = Ok(()),
+            Err(e) if res.is_err() => res = Err(e),
+            Err(_) => {}
+        }
+    }
+    res
+}
+
+/// See https://man7.org/linux/man-pages/man2/kill.2.html
+pub fn do_kill(pid: isize, signum: usize) -> AlienResult<isize> {
+    check_signum(signum)?;
+    let task = current_task().unwrap();
+    let info = SigInfo::new(signum, SI_USER, task.pid());
+    match pid {
+        0 => {
+            let pgid = task.inner().pgid;
+            let pids = find_processes(|process| process.inner().pgid == pgid);
+            signal_processes(&task, pids, info)?;
+        }
+        -1 => {
+            let pids = find_processes(|process| {
+                process.pid() != 1 && process.pid() != task.pid() && !process.is_kernel_thread()
+            });
+            signal_processes(&task, pids, info)?;
+        }
+        pid if pid < 0 => {
+            let pgid = pid.unsigned_abs();
+            let pids = find_processes(|process| process.inner().pgid == pgid);
+            signal_processes(&task, pids, info)?;
+        }
+        pid => signal_process(&task, pid as usize, info)?,
+    }
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/tgkill.2.html
+///
+/// `tgid` of `None` is `tkill`, which accepts a thread of any process.
+pub fn do_tgkill(tgid: Option<usize>, tid: usize, signum: usize) -> AlienResult<isize> {
+    check_signum(signum)?;
+    let task = current_task().unwrap();
+    let target = find_task(tid).ok_or(AlienError::ESRCH)?;
+    if tgid.is_some_and(|tgid| tgid != target.pid()) {
+        return Err(AlienError::ESRCH);
+    }
+    may_signal(&task, &target)?;
+    if signum != 0 {
+        let info = SigInfo::new(signum, SI_TKILL, task.pid());
+        signal::send_to_thread(&target, info)?;
+    }
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigqueueinfo.2.html
+pub fn do_sigqueueinfo(pid: usize, signum: usize, info: usize) -> AlienResult<isize> {
+    check_signum(signum)?;
+    let task = current_task().unwrap();
+    let mut info = task.read_val_from_user::<SigInfo>(VirtAddr::from(info))?;
+    // the kernel and kill(2) codes can not be sent to other processes
+    if pid != task.pid() && (info.code >= 0 || info.code == SI_TKILL) {
+        return Err(AlienError::EPERM);
+    }
+    info.signo = signum as i32;
+    signal_process(&task, pid, info)?;
+    Ok(0)
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigreturn.2.html
+pub fn do_sigreturn() -> AlienResult<isize> {
+    signal::sigreturn()
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigtimedwait.2.html
+///
+/// `deadline` is in timer cycles, `None` waits forever.
+pub fn do_sigtimedwait(set: usize, info: usize, deadline: Option<usize>) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let sig_info = signal::wait_for(set, deadline)?;
+    if info != 0 {
+        task.write_val_to_user(VirtAddr::from(info), &sig_info)?;
+    }
+    Ok(sig_info.signo as isize)
+}
+
+/// See https://man7.org/linux/man-pages/man2/rt_sigsuspend.2.html
+pub fn do_sigsuspend(mask: usize) -> AlienResult<isize> {
+    signal::suspend(mask)
+}
+
 /// See https://man7.org/linux/man-pages/man2/sigaltstack.2.html
 pub fn do_signal_stack(uss: usize, uoss: usize) -> AlienResult<isize> {
     // println_color!(32, "sigaltstack: uss: {:x}, uoss: {:x}", uss, uoss);
@@ -0,0 +1,25 @@
+use basic::{AlienError, AlienResult};
+
+use crate::{processor::current_task, timer};
+
+/// The only interval timer, counting real time and sending `SIGALRM`
+const ITIMER_REAL: usize = 0;
+
+/// See https://man7.org/linux/man-pages/man2/setitimer.2.html
+///
+/// `interval` and `value` are in timer cycles. Returns the old `(interval, value)`.
+pub fn do_setitimer(which: usize, interval: usize, value: usize) -> AlienResult<(usize, usize)> {
+    if which != ITIMER_REAL {
+        return Err(AlienError::EINVAL);
+    }
+    let task = current_task().unwrap();
+    Ok(timer::set_real_timer(task.pid(), interval, value))
+}
+
+pub fn do_getitimer(which: usize) -> AlienResult<(usize, usize)> {
+    if which != ITIMER_REAL {
+        return Err(AlienError::EINVAL);
+    }
+    let task = current_task().unwrap();
+    Ok(timer::get_real_timer(task.pid()))
+}
@@ -4,7 +4,7 @@ use basic::{constants::task::WaitOptions, println, println_color, AlienError, Al
 use memory_addr::VirtAddr;
 use task_meta::TaskStatus;
 
-use crate::{processor::current_task, task::Task};
+use crate::{cpu_time, processor::current_task, signal, task::Task};
 
 pub fn do_wait4(
     pid: isize,
@@ -32,7 +32,11 @@ pub fn do_wait4(
                     assert_eq!(pid, tid);
                 } else {
                     // recycle the task now
-                    task.inner().children.remove(&pid);
+                    let child_times = wait_task.inner().cpu_time.total();
+                    let mut inner = task.inner();
+                    inner.children.remove(&pid);
+                    inner.cpu_time.children += child_times;
+                    drop(inner);
                     basic::remove_task(tid).expect("remove task failed");
                     println!("release task [{}-{}]", pid, tid);
                     assert_eq!(
@@ -48,10 +52,18 @@ pub fn do_wait4(
                 return Ok(pid as isize);
             }
         }
+        if let Some((pid, status)) = filter_stop_report(&task, pid, wait_options) {
+            if exit_code_ptr != 0 {
+                task.write_val_to_user(VirtAddr::from(exit_code_ptr), &status)?;
+            }
+            return Ok(pid as isize);
+        }
         if wait_options.contains(WaitOptions::WNOHANG) {
             return Ok(0);
+        } else if signal::has_pending(&task) {
+            return Err(AlienError::EINTR);
         } else {
-            basic::yield_now().unwrap();
+            cpu_time::parked(basic::yield_now).unwrap();
         }
     }
 }
@@ -72,3 +84,25 @@ fn filter_exit_task(task: &Arc<Task>, pid: isize) -> AlienResult<Option<Arc<Task
         .find(|task| task.status() == TaskStatus::Terminated);
     Ok(term_task.cloned())
 }
+
+/// A child that stopped or continued since the last `wait4`, with its wait status, if the
+/// options ask for it.
+fn filter_stop_report(task: &Arc<Task>, pid: isize, options: WaitOptions) -> Option<(usize, i32)> {
+    let untraced = options.contains(WaitOptions::WUNTRACED);
+    let continued = options.contains(WaitOptions::WCONTINUED);
+    if !untraced && !continued {
+        return None;
+    }
+    let children = task
+        .inner()
+        .children
+        .values()
+        .filter(|child| child.pid() == pid as usize || pid == -1)
+        .cloned()
+        .collect::<Vec<_>>();
+    let keep = options.contains(WaitOptions::WNOWAIT);
+    children.iter().find_map(|child| {
+        signal::take_wait_report(child, untraced, continued, keep)
+            .map(|status| (child.pid(), status))
+    })
+}
@@ -18,7 +18,7 @@ use basic::{
     sync::{Mutex, MutexGuard},
     task::{TaskContext, TaskContextExt, TrapFrame},
     vm::frame::FrameTracker,
-    AlienResult,
+    AlienError, AlienResult,
 };
 use interface::{InodeID, VFS_ROOT_ID};
 use memory_addr::{PhysAddr, VirtAddr};
@@ -29,11 +29,15 @@ use small_index::IndexAllocator;
 use task_meta::{TaskBasicInfo, TaskMeta, TaskSchedulingInfo, TaskStatus};
 
 use crate::{
+    cpu_time::CpuTime,
+    cred::Credentials,
     elf::{
         build_vm_space, clone_vm_space, extend_thread_vm_space, FrameTrackerWrapper,
         VmmPageAllocator,
     },
+    pin::{self, PinnedBuffers},
     resource::{AuxVec, FdManager, HeapInfo, MMapInfo, ResourceLimits, TidHandle, UserStack},
+    signal::PendingSignals,
     vfs_shim::{ShimFile, STDIN, STDOUT},
 };
 
@@ -60,8 +64,14 @@ pub struct Task {
     /// 信号量对应的一组处理函数。
     /// 因为发送信号是通过 pid/tid 查找的，因此放在 inner 中一起调用时更容易导致死锁
     pub signal_handlers: Arc<Mutex<SignalHandlers>>,
+    /// 进程的凭证（用户和组），同一进程的线程共享
+    pub cred: Arc<Mutex<Credentials>>,
     /// 接收信号的结构。每个线程中一定是独特的，而上面的 handler 可能是共享的
     pub signal_receivers: Arc<Mutex<SignalReceivers>>,
+    /// 待处理的信号
+    pub pending_signals: Mutex<PendingSignals>,
+    /// 正在进行的大块 I/O 固定的用户缓冲区
+    pub pinned_buffers: Mutex<PinnedBuffers>,
     /// 线程计数器，用于分配同一个线程组中的线程序号
     pub threads: Arc<Mutex<IndexAllocator<MAX_THREAD_NUM>>>,
     /// 更详细的信息
@@ -80,6 +90,10 @@ pub struct TaskInner {
     pub parent: Option<Weak<Task>>,
     /// 孩子任务控制块的集合
     pub children: BTreeMap<usize, Arc<Task>>,
+    /// 进程组号
+    pub pgid: usize,
+    /// 会话号
+    pub sid: usize,
     /// 文件系统的信息
     pub fs_info: FsContext,
     /// 返回值
@@ -96,6 +110,8 @@ pub struct TaskInner {
     /// - SS_ONSTACK = 1
     /// - SS_DISABLE = 2
     pub ss_stack: SignalStack,
+    /// 用户态和内核态的运行时间
+    pub cpu_time: CpuTime,
 }
 
 #[derive(Debug, Clone)]
@@ -104,6 +120,8 @@ pub struct FsContext {
     pub cwd: Arc<ShimFile>,
     /// root directory
     pub root: Arc<ShimFile>,
+    /// permission bits cleared from the mode of new files
+    pub umask: u32,
 }
 
 impl FsContext {
@@ -111,6 +129,7 @@ impl FsContext {
         Self {
             cwd: Arc::new(ShimFile::new(cwd)),
             root: Arc::new(ShimFile::new(root)),
+            umask: 0o022,
         }
     }
 }
@@ -132,6 +151,11 @@ impl Task {
         self.inner.lock()
     }
 
+    /// Kernel threads have no user stack.
+    pub fn is_kernel_thread(&self) -> bool {
+        self.inner.lock().stack.is_empty()
+    }
+
     pub fn status(&self) -> TaskStatus {
         let inner = self.inner.lock();
         inner.status
@@ -163,25 +187,31 @@ impl Task {
 
     pub fn read_bytes_from_user(&self, src: VirtAddr, dest: &mut [u8]) -> AlienResult<()> {
         let vm_space = self.address_space.lock();
-        vm_space.read_bytes(src, dest).unwrap();
+        vm_space
+            .read_bytes(src, dest)
+            .map_err(|_| AlienError::EFAULT)?;
         Ok(())
     }
 
     pub fn read_val_from_user<T: Pod>(&self, src: VirtAddr) -> AlienResult<T> {
         let vm_space = self.address_space.lock();
-        let val = vm_space.read_val(src).unwrap();
+        let val = vm_space.read_val(src).map_err(|_| AlienError::EFAULT)?;
         Ok(val)
     }
 
     pub fn write_bytes_to_user(&self, dest: VirtAddr, src: &[u8]) -> AlienResult<()> {
         let mut vm_space = self.address_space.lock();
-        vm_space.write_bytes(dest, src).unwrap();
+        vm_space
+            .write_bytes(dest, src)
+            .map_err(|_| AlienError::EFAULT)?;
         Ok(())
     }
 
     pub fn write_val_to_user<T: Pod>(&self, dest: VirtAddr, val: &T) -> AlienResult<()> {
         let mut vm_space = self.address_space.lock();
-        vm_space.write_val(dest, val).unwrap();
+        vm_space
+            .write_val(dest, val)
+            .map_err(|_| AlienError::EFAULT)?;
         Ok(())
     }
 
@@ -196,8 +226,7 @@ impl Task {
             s.push(c);
             ptr += core::mem::size_of::<u8>();
         }
-        let str = String::from_utf8(s).unwrap();
-        Ok(str)
+        String::from_utf8(s).map_err(|_| AlienError::EINVAL)
     }
 
     pub fn trap_frame(&self) -> &'static mut TrapFrame {
@@ -218,6 +247,10 @@ impl Task {
 
     pub fn extend_heap(&self, addr: usize) -> usize {
         let mut heap = self.heap.lock();
+        // another thread may be moving data to or from the pages above the new break
+        if addr < heap.current && pin::pinned_by_others(self) {
+            return heap.current;
+        }
         heap.current = addr;
         if addr < heap.end {
             return heap.current;
@@ -270,8 +303,11 @@ impl Task {
             pid,
             address_space: Arc::new(Mutex::new(address_space)),
             mmap: Arc::new(Mutex::new(MMapInfo::new())),
+            cred: Arc::new(Mutex::new(Credentials::root())),
             signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
             signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
+            pending_signals: Mutex::new(PendingSignals::new()),
+            pinned_buffers: Mutex::new(PinnedBuffers::new()),
             fd_table: {
                 let mut fd_table = FdManager::new();
                 fd_table.insert(STDIN.clone());
@@ -295,6 +331,8 @@ impl Task {
                 status: TaskStatus::Ready,
                 parent: None,
                 children: BTreeMap::new(),
+                pgid: 1,
+                sid: 1,
                 fs_info: FsContext::new(VFS_ROOT_ID, VFS_ROOT_ID),
                 exit_code: 0,
                 clear_child_tid: 0,
@@ -305,6 +343,7 @@ impl Task {
                     ss_flags: 0x2,
                     ss_size: 0,
                 },
+                cpu_time: CpuTime::new(),
             }),
             send_sigchld_when_exit: false,
         };
@@ -361,10 +400,12 @@ impl Task {
             Some(Arc::downgrade(self))
         };
 
-        let (name, fs_info, stack) = (
+        let (name, fs_info, stack, pgid, sid) = (
             inner.name.clone(),
             inner.fs_info.clone(),
             inner.stack.clone(),
+            inner.pgid,
+            inner.sid,
         );
 
         let mmap = self.mmap.clone();
@@ -392,6 +433,12 @@ impl Task {
             tid.clone()
         };
 
+        let cred = if clone_args.flags.contains(CloneFlags::CLONE_THREAD) {
+            self.cred.clone()
+        } else {
+            Arc::new(Mutex::new(self.cred.lock().clone()))
+        };
+
         let heap = if clone_args.flags.contains(CloneFlags::CLONE_VM) {
             self.heap.clone()
         } else {
@@ -406,8 +453,11 @@ impl Task {
             threads,
             address_space,
             mmap,
+            cred,
             signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
             signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
+            pending_signals: Mutex::new(PendingSignals::new()),
+            pinned_buffers: Mutex::new(PinnedBuffers::new()),
             fd_table,
             heap,
             inner: Mutex::new(TaskInner {
@@ -416,6 +466,8 @@ impl Task {
                 status: TaskStatus::Ready,
                 parent,
                 children: BTreeMap::new(),
+                pgid,
+                sid,
                 fs_info,
                 exit_code: 0,
                 clear_child_tid: if clone_args.flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
@@ -430,6 +482,7 @@ impl Task {
                     ss_flags: 0x2,
                     ss_size: 0,
                 },
+                cpu_time: CpuTime::new(),
             }),
             send_sigchld_when_exit: clone_args.sig == SignalNumber::SIGCHLD,
         };
@@ -0,0 +1,145 @@
+//! Timed waits and interval timers.
+//!
+//! Deadlines are in timer cycles, like the wait time of a [`FutexWaiter`]. They are checked
+//! by [`check_timers`], which runs in the loop of the `kthread_init` kernel thread.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use basic::{
+    constants::signal::SignalNumber, sync::Mutex, time::read_timer, AlienError, AlienResult,
+};
+
+use crate::{
+    cpu_time,
+    futex::FutexWaiter,
+    processor::current_task,
+    signal::{self, SigInfo, SI_KERNEL},
+    syscall::futex::FUTEX_WAITER,
+};
+
+/// Tasks parked in [`park_until`]
+static SLEEPERS: Mutex<Vec<FutexWaiter>> = Mutex::new(Vec::new());
+
+/// The `ITIMER_REAL` timers, by pid
+static REAL_TIMERS: Mutex<BTreeMap<usize, ITimer>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Copy, Clone)]
+struct ITimer {
+    interval: usize,
+    deadline: usize,
+}
+
+/// Park the current task until `deadline`.
+///
+/// Fails with `EINTR` if the task was interrupted by a signal before the deadline.
+pub fn sleep_until(deadline: usize) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    while read_timer() < deadline {
+        if signal::has_pending(&task) {
+            return Err(AlienError::EINTR);
+        }
+        park_until(deadline)?;
+    }
+    Ok(())
+}
+
+/// Park the current task until it is woken or `deadline` passes, whichever comes first.
+///
+/// Callers that wait for something else than the deadline register with its waker first
+/// and re-check their condition afterwards.
+pub fn park_until(deadline: usize) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    if deadline <= read_timer() {
+        return Ok(());
+    }
+    let timeout_flag = Arc::new(Mutex::new(false));
+    let waiter = FutexWaiter::new(task.tid(), Some(deadline), timeout_flag, 0);
+    SLEEPERS.lock().push(waiter);
+    cpu_time::parked(basic::wait_now)?;
+    cancel_sleep(task.tid());
+    Ok(())
+}
+
+/// Take `tid` out of the sleepers of [`park_until`] without waking it.
+pub fn cancel_sleep(tid: usize) {
+    SLEEPERS.lock().retain(|waiter| waiter.tid() != Some(tid));
+}
+
+/// Set the real timer of `pid` and return the old `(interval, value)`.
+///
+/// A `value` of 0 disarms the timer.
+pub fn set_real_timer(pid: usize, interval: usize, value: usize) -> (usize, usize) {
+    let now = read_timer();
+    let mut timers = REAL_TIMERS.lock();
+    let old = timers
+        .get(&pid)
+        .map(|timer| (timer.interval, remaining(timer, now)))
+        .unwrap_or((0, 0));
+    if value == 0 {
+        timers.remove(&pid);
+    } else {
+        let deadline = now + value;
+        timers.insert(pid, ITimer { interval, deadline });
+    }
+    old
+}
+
+/// The `(interval, value)` of the real timer of `pid`.
+pub fn get_real_timer(pid: usize) -> (usize, usize) {
+    let now = read_timer();
+    REAL_TIMERS
+        .lock()
+        .get(&pid)
+        .map(|timer| (timer.interval, remaining(timer, now)))
+        .unwrap_or((0, 0))
+}
+
+pub fn remove_real_timer(pid: usize) {
+    REAL_TIMERS.lock().remove(&pid);
+}
+
+/// An armed timer that has not been handled yet still reports some time left.
+fn remaining(timer: &ITimer, now: usize) -> usize {
+    timer.deadline.saturating_sub(now).max(1)
+}
+
+/// Wake the sleepers and futex waiters whose deadline has passed and fire the due timers.
+pub fn check_timers() -> AlienResult<()> {
+    let now = read_timer();
+    let mut woken = Vec::new();
+    SLEEPERS
+        .lock()
+        .retain_mut(|waiter| match waiter.check_timeout(now) {
+            Some(tid) => {
+                woken.push(tid);
+                false
+            }
+            None => true,
+        });
+    for tid in woken {
+        basic::wake_up_wait_task(tid)?;
+    }
+    FUTEX_WAITER.lock().check_timeout(now)?;
+
+    let mut expired = Vec::new();
+    REAL_TIMERS.lock().retain(|pid, timer| {
+        if timer.deadline > now {
+            return true;
+        }
+        expired.push(*pid);
+        if timer.interval == 0 {
+            return false;
+        }
+        // periods missed while the check did not run are dropped
+        let missed = (now - timer.deadline) / timer.interval;
+        timer.deadline += (missed + 1) * timer.interval;
+        true
+    });
+    for pid in expired {
+        let info = SigInfo::new(SignalNumber::SIGALRM as usize, SI_KERNEL, 0);
+        if signal::send_to_process(pid, info).is_err() {
+            remove_real_timer(pid);
+        }
+    }
+    Ok(())
+}
@@ -11,6 +11,9 @@ use vfscore::utils::VfsFileStat;
 
 use crate::processor::current_task;
 
+/// The `X_OK` bit of an access check
+const MAY_EXEC: u32 = 1;
+
 static VFS_DOMAIN: Once<Arc<dyn VfsDomain>> = Once::new();
 
 pub fn init_vfs_domain(vfs_domain: Arc<dyn VfsDomain>) {
@@ -86,6 +89,18 @@ pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
     true
 }
 
+/// Check that the current task may execute `file_name` and return its mode and owner.
+pub fn exec_attr(file_name: &str) -> AlienResult<(u32, u32, u32)> {
+    let path = user_path_at(AT_FDCWD, file_name)?;
+    let name = DVec::from_slice(file_name.as_bytes());
+    let vfs = VFS_DOMAIN.get().unwrap();
+    let id = vfs.vfs_open(path.1, &name, name.len(), 0, OpenFlags::O_PATH.bits())?;
+    let shim_file = ShimFile::new(id);
+    vfs.vfs_access(id, MAY_EXEC, false)?;
+    let attr = shim_file.get_attr()?;
+    Ok((attr.st_mode, attr.st_uid, attr.st_gid))
+}
+
 fn user_path_at(fd: isize, path: &str) -> AlienResult<(InodeID, InodeID)> {
     info!("user_path_at fd: {},path:{}", fd, path);
     let task = current_task().unwrap();
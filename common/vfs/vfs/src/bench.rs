@@ -0,0 +1,112 @@
+//! Read benchmark that runs in the kernel, built with the `bench` feature.
+//!
+//! Once the filesystems are mounted, a file of the test disk is read into pages of the
+//! kernel twice: straight into the pages, as pinned reads do, and through a shared heap
+//! stage whose contents are then copied to the pages, as reads did before they were
+//! pinned. Both reads go through fatfs and cache_blk.
+
+use alloc::vec::Vec;
+
+use basic::{
+    config::FRAME_SIZE, constants::io::OpenFlags, println, time::read_time_ms,
+    vm::frame::FrameTracker, AlienError, AlienResult,
+};
+use shared_heap::DVec;
+use vfscore::path::VfsPath;
+
+use crate::{
+    get_file, insert_dentry,
+    kfile::File,
+    remove_file,
+    tree::system_root_fs,
+    user_buf::{direct_read, UserBuffer},
+};
+
+/// The largest file of the test disk
+const BENCH_FILE: &str = "/tests/busybox";
+const BUF_PAGES: usize = 256;
+const BUF_LEN: usize = BUF_PAGES * FRAME_SIZE;
+/// The shared heap buffer reads went through before they were pinned
+const STAGE_CHUNK: usize = 64 * 1024;
+const ROUNDS: usize = 16;
+
+pub fn run() {
+    if let Err(e) = bench_read() {
+        println!("bench: reading {} failed: {:?}", BENCH_FILE, e);
+    }
+}
+
+fn bench_read() -> AlienResult<()> {
+    let root = system_root_fs();
+    let dentry = VfsPath::new(root.clone(), root)
+        .join(BENCH_FILE)
+        .and_then(|path| path.open(None))
+        .map_err(|_| AlienError::ENOENT)?;
+    let id = insert_dentry(dentry, OpenFlags::O_RDONLY);
+    let res = bench_file(&*get_file(id).unwrap());
+    remove_file(id);
+    res
+}
+
+fn bench_file(file: &dyn File) -> AlienResult<()> {
+    let frame = FrameTracker::new(BUF_PAGES);
+    let pages = (0..BUF_PAGES)
+        .map(|i| frame.start_phy_addr().as_usize() + i * FRAME_SIZE)
+        .collect::<Vec<_>>();
+    let buf = UserBuffer::new(&pages, 0, BUF_LEN)?;
+
+    let start = read_time_ms();
+    let mut direct_len = 0;
+    for _ in 0..ROUNDS {
+        direct_len += read_file(|offset| direct_read(file, Some(offset), &buf))?;
+    }
+    let direct = read_time_ms() - start;
+
+    let start = read_time_ms();
+    let mut staged_len = 0;
+    for _ in 0..ROUNDS {
+        staged_len += read_file(|offset| {
+            let mut stage = DVec::new_uninit(STAGE_CHUNK);
+            let mut total = 0;
+            while total < BUF_LEN {
+                let r;
+                (stage, r) = file.read_at(offset + total as u64, stage)?;
+                buf.write(total, &stage.as_slice()[..r])?;
+                total += r;
+                if r < STAGE_CHUNK {
+                    break;
+                }
+            }
+            Ok(total)
+        })?;
+    }
+    let staged = read_time_ms() - start;
+
+    println!(
+        "bench: read {} {} times, {} into the pages: {} ms, {} KiB/s; through a stage: {} ms, {} KiB/s",
+        BENCH_FILE,
+        ROUNDS,
+        direct_len / ROUNDS,
+        direct,
+        throughput(direct_len, direct),
+        staged,
+        throughput(staged_len, staged)
+    );
+    Ok(())
+}
+
+/// Read the whole file with `read_at`, which reads up to [`BUF_LEN`] bytes at an offset.
+fn read_file(mut read_at: impl FnMut(u64) -> AlienResult<usize>) -> AlienResult<usize> {
+    let mut total = 0;
+    loop {
+        let r = read_at(total as u64)?;
+        total += r;
+        if r < BUF_LEN {
+            return Ok(total);
+        }
+    }
+}
+
+fn throughput(len: usize, ms: u64) -> u64 {
+    len as u64 * 1000 / 1024 / ms.max(1)
+}
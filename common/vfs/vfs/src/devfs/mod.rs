@@ -4,10 +4,22 @@ use alloc::sync::Arc;
 
 use basic::println;
 use id::alloc_device_id;
-use interface::DevFsDomain;
+use interface::{BufUartDomain, DevFsDomain, DomainType};
 use shared_heap::DVec;
+use spin::Once;
 use vfscore::{dentry::VfsDentry, utils::VfsNodeType};
 
+/// The device id of `/dev/tty` and the uart behind it
+static UART_DEVICE: Once<(u64, Arc<dyn BufUartDomain>)> = Once::new();
+
+/// The uart with device id `rdev`, if there is one.
+pub fn uart_device(rdev: u64) -> Option<Arc<dyn BufUartDomain>> {
+    UART_DEVICE
+        .get()
+        .filter(|(id, _)| *id == rdev)
+        .map(|(_, uart)| uart.clone())
+}
+
 ///```bash
 /// |
 /// |-- null
@@ -87,8 +99,11 @@ pub fn scan_system_devices(devfs_domain: &Arc<dyn DevFsDomain>, root_dt: &Arc<dy
     let rtc = basic::get_domain("goldfish"); // unique name
 
     match uart {
-        Some(_) => {
+        Some(uart) => {
             let uart_id = alloc_device_id(VfsNodeType::CharDevice);
+            if let DomainType::BufUartDomain(uart) = uart {
+                UART_DEVICE.call_once(|| (uart_id.id(), uart));
+            }
             devfs_domain
                 .register(uart_id.id(), &DVec::from_slice(b"buf_uart"))
                 .unwrap();
@@ -1,66 +1,218 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 
 use basic::{
     constants::{
-        epoll::{EpollCtlOp, EpollEvent},
-        io::{OpenFlags, SeekFrom},
+        epoll::{EpollCtlOp, EpollEvent, EpollEventType},
+        io::{OpenFlags, PollEvents, SeekFrom},
+        time::TimeSpec,
     },
     sync::{Mutex, MutexGuard},
+    time::{read_timer, ToClock},
     AlienError, AlienResult,
 };
+use interface::TaskDomain;
 use shared_heap::DVec;
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
-use crate::kfile::File;
+use crate::{get_file, kfile::File};
+
+#[derive(Debug)]
+pub struct EpollEntry {
+    pub event: EpollEvent,
+    /// The events that were ready in the last scan, to detect edges for `EPOLLET` on files
+    /// that cannot wake waiters
+    ready: EpollEventType,
+    /// The wakeups of the file up to the last scan. `EPOLLET` entries are re-armed by every
+    /// wakeup after it
+    wakes: Option<usize>,
+    /// Set after an `EPOLLONESHOT` event was reported, until the fd is modified
+    disabled: bool,
+}
+
+impl EpollEntry {
+    fn new(event: EpollEvent) -> Self {
+        Self {
+            event,
+            ready: EpollEventType::empty(),
+            wakes: None,
+            disabled: false,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct EpollFile {
-    #[allow(unused)]
-    flags: OpenFlags,
-    interest: Mutex<BTreeMap<usize, EpollEvent>>,
+    flags: Mutex<OpenFlags>,
+    interest: Mutex<BTreeMap<usize, EpollEntry>>,
 }
 
 impl EpollFile {
     pub fn new(flags: OpenFlags) -> Self {
         EpollFile {
-            flags,
+            flags: Mutex::new(flags),
             interest: Mutex::new(BTreeMap::new()),
         }
     }
     pub fn ctl(&self, op: EpollCtlOp, fd: usize, events: EpollEvent) -> AlienResult<()> {
+        let mut interest = self.interest.lock();
         match op {
             EpollCtlOp::EpollCtlAdd => {
-                self.interest.lock().insert(fd, events);
+                if interest.contains_key(&fd) {
+                    return Err(AlienError::EEXIST);
+                }
+                interest.insert(fd, EpollEntry::new(events));
                 Ok(())
             }
             EpollCtlOp::EpollCtlDel => {
-                self.interest.lock().remove(&fd);
+                interest.remove(&fd).ok_or(AlienError::ENOENT)?;
                 Ok(())
             }
             EpollCtlOp::EpollCtlMod => {
-                self.interest.lock().insert(fd, events);
+                let entry = interest.get_mut(&fd).ok_or(AlienError::ENOENT)?;
+                *entry = EpollEntry::new(events);
                 Ok(())
             }
         }
     }
-    pub fn interest(&self) -> MutexGuard<BTreeMap<usize, EpollEvent>> {
+    pub fn interest(&self) -> MutexGuard<BTreeMap<usize, EpollEntry>> {
         self.interest.lock()
     }
+
+    /// Wait until some of the files in the interest list are ready and store their events
+    /// in `events`.
+    ///
+    /// `timeout` of `None` waits forever. The task sleeps until one of the files wakes it or
+    /// the timeout passes, if all of them can wake it; otherwise it keeps polling them.
+    pub fn wait(
+        &self,
+        task_domain: &Arc<dyn TaskDomain>,
+        events: &mut [EpollEvent],
+        timeout: Option<TimeSpec>,
+    ) -> AlienResult<usize> {
+        let deadline = timeout.map(|t| read_timer() + t.to_clock());
+        let tid = basic::current_tid()?.unwrap();
+        loop {
+            let files = self.files(task_domain);
+            // register before scanning, so no wakeup between the scan and the sleep is lost
+            let mut sleep = true;
+            for file in files.values() {
+                sleep &= file.poll_register(tid)?;
+            }
+            let count = self.scan(&files, events);
+            let expired = deadline.is_some_and(|deadline| deadline <= read_timer());
+            if matches!(count, Ok(0)) && !expired {
+                match deadline {
+                    _ if !sleep => basic::yield_now()?,
+                    Some(deadline) => task_domain.do_park_until(deadline)?,
+                    None => basic::wait_now()?,
+                }
+            }
+            let interrupted = task_domain.do_has_pending_signal()?;
+            for file in files.values() {
+                file.poll_unregister(tid)?;
+            }
+            match count? {
+                0 if interrupted => return Err(AlienError::EINTR),
+                0 if !expired => continue,
+                count => return Ok(count),
+            }
+        }
+    }
+
+    /// The open files of the interest list. Entries of closed fds are removed.
+    fn files(&self, task_domain: &Arc<dyn TaskDomain>) -> BTreeMap<usize, Arc<dyn File>> {
+        let mut interest = self.interest.lock();
+        let mut files = BTreeMap::new();
+        let mut closed = Vec::new();
+        for fd in interest.keys() {
+            match task_domain.get_fd(*fd).ok().and_then(get_file) {
+                Some(file) => {
+                    files.insert(*fd, file);
+                }
+                None => closed.push(*fd),
+            }
+        }
+        for fd in closed {
+            interest.remove(&fd);
+        }
+        files
+    }
+
+    fn scan(
+        &self,
+        files: &BTreeMap<usize, Arc<dyn File>>,
+        events: &mut [EpollEvent],
+    ) -> AlienResult<usize> {
+        let mut count = 0;
+        let mut interest = self.interest.lock();
+        for (fd, entry) in interest.iter_mut() {
+            if count == events.len() {
+                break;
+            }
+            let Some(file) = files.get(fd) else {
+                continue;
+            };
+            if entry.disabled {
+                continue;
+            }
+            // errors and hang ups are always reported
+            let wanted =
+                (entry.event.events - EpollEventType::EPOLLET - EpollEventType::EPOLLONESHOT)
+                    | EpollEventType::EPOLLERR
+                    | EpollEventType::EPOLLHUP;
+            // read before polling, so a wakeup during the poll re-arms the entry
+            let wakes = file.poll_wakes()?;
+            let ready = file.poll(PollEvents::from_bits_truncate(wanted.bits() as _))?;
+            let ready = EpollEventType::from_bits_truncate(ready.bits() as _) & wanted;
+            let report = if !entry.event.events.contains(EpollEventType::EPOLLET) {
+                ready
+            } else if wakes.is_none() {
+                ready - entry.ready
+            } else if wakes != entry.wakes {
+                ready
+            } else {
+                EpollEventType::empty()
+            };
+            entry.ready = ready;
+            entry.wakes = wakes;
+            if report.is_empty() {
+                continue;
+            }
+            events[count] = EpollEvent {
+                events: report,
+                data: entry.event.data,
+            };
+            count += 1;
+            if entry.event.events.contains(EpollEventType::EPOLLONESHOT) {
+                entry.disabled = true;
+            }
+        }
+        Ok(count)
+    }
 }
 
 impl File for EpollFile {
     fn read(&self, _buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
-        todo!()
+        Err(AlienError::EINVAL)
     }
     fn write(&self, _buf: &DVec<u8>) -> AlienResult<usize> {
-        todo!()
+        Err(AlienError::EINVAL)
     }
     fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
         Err(AlienError::ENOSYS)
     }
 
     fn get_attr(&self) -> AlienResult<VfsFileStat> {
-        todo!()
+        Ok(VfsFileStat::default())
+    }
+
+    /// Waits do not look at `O_NONBLOCK`, it is only kept for `F_GETFL`.
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.flags.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.flags.lock()
     }
 
     fn dentry(&self) -> Arc<dyn VfsDentry> {
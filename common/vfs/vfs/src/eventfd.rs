@@ -1,10 +1,10 @@
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::sync::Arc;
 use core::{fmt::Debug, sync::atomic::AtomicU32};
 
 use basic::{
     constants::{
         epoll::EventFdFlags,
-        io::{PollEvents, SeekFrom},
+        io::{OpenFlags, PollEvents, SeekFrom},
     },
     sync::Mutex,
     AlienError, AlienResult,
@@ -12,7 +12,7 @@ use basic::{
 use shared_heap::DVec;
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
-use crate::kfile::File;
+use crate::{kfile::File, wait_queue::WaitQueue};
 
 static EVENTFD_ID: AtomicU32 = AtomicU32::new(0);
 
@@ -31,7 +31,7 @@ impl EventFd {
 }
 pub struct EventFdInode {
     eventfd: Mutex<EventFd>,
-    wait_queue: Mutex<VecDeque<usize>>,
+    wait_queue: WaitQueue,
 }
 
 impl Debug for EventFdInode {
@@ -46,7 +46,7 @@ impl EventFdInode {
     pub fn new(eventfd: EventFd) -> Self {
         EventFdInode {
             eventfd: Mutex::new(eventfd),
-            wait_queue: Mutex::new(VecDeque::new()),
+            wait_queue: WaitQueue::new(),
         }
     }
 }
@@ -57,21 +57,14 @@ impl File for EventFdInode {
             return Err(AlienError::EINVAL);
         }
         let mut val = loop {
-            let val = self.eventfd.lock().count;
-            if val != 0 {
-                break val;
+            let eventfd = self.eventfd.lock();
+            if eventfd.count != 0 {
+                break eventfd.count;
             }
-            if self
-                .eventfd
-                .lock()
-                .flags
-                .contains(EventFdFlags::EFD_NONBLOCK)
-            {
+            if eventfd.flags.contains(EventFdFlags::EFD_NONBLOCK) {
                 return Err(AlienError::EAGAIN);
             }
-            let tid = basic::current_tid()?.unwrap();
-            self.wait_queue.lock().push_back(tid);
-            basic::wait_now()?; // yield current task
+            self.wait_queue.sleep(eventfd)?; // yield current task
         };
         let mut eventfd = self.eventfd.lock();
         if eventfd.flags.contains(EventFdFlags::EFD_SEMAPHORE) {
@@ -80,6 +73,9 @@ impl File for EventFdInode {
         } else {
             eventfd.count = 0;
         }
+        drop(eventfd);
+        // wake up the writers blocked on a full counter
+        self.wait_queue.wake_all()?;
         let val_bytes = val.to_ne_bytes();
         buf.as_mut_slice()[..8].copy_from_slice(&val_bytes);
         Ok((buf, 8))
@@ -103,17 +99,12 @@ impl File for EventFdInode {
             if eventfd.flags.contains(EventFdFlags::EFD_NONBLOCK) {
                 return Err(AlienError::EAGAIN);
             }
-            drop(eventfd);
-            // self.wait_queue.sleep();
-            let tid = basic::current_tid()?.unwrap();
-            self.wait_queue.lock().push_back(tid);
-            basic::wait_now()?; // yield current task
+            self.wait_queue.sleep(eventfd)?; // yield current task
         }
         let mut eventfd = self.eventfd.lock();
         eventfd.count += val;
-        while let Some(tid) = self.wait_queue.lock().pop_front() {
-            basic::wake_up_wait_task(tid)?;
-        }
+        drop(eventfd);
+        self.wait_queue.wake_all()?;
         Ok(8)
     }
     fn read_at(&self, _offset: u64, buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
@@ -131,6 +122,26 @@ impl File for EventFdInode {
         Err(AlienError::ENOSYS)
     }
 
+    /// Only `O_NONBLOCK` can change, it is kept as `EFD_NONBLOCK`.
+    fn set_open_flag(&self, flag: OpenFlags) {
+        let nonblock = flag.contains(OpenFlags::O_NONBLOCK);
+        self.eventfd
+            .lock()
+            .flags
+            .set(EventFdFlags::EFD_NONBLOCK, nonblock);
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        let mut flag = OpenFlags::O_RDWR;
+        let nonblock = self
+            .eventfd
+            .lock()
+            .flags
+            .contains(EventFdFlags::EFD_NONBLOCK);
+        flag.set(OpenFlags::O_NONBLOCK, nonblock);
+        flag
+    }
+
     fn dentry(&self) -> Arc<dyn VfsDentry> {
         panic!("EventFdInode::dentry() is not implemented")
     }
@@ -161,6 +172,20 @@ impl File for EventFdInode {
         }
         return Ok(events);
     }
+
+    fn poll_register(&self, tid: usize) -> AlienResult<bool> {
+        self.wait_queue.register(tid);
+        Ok(true)
+    }
+
+    fn poll_unregister(&self, tid: usize) -> AlienResult<()> {
+        self.wait_queue.unregister(tid);
+        Ok(())
+    }
+
+    fn poll_wakes(&self) -> AlienResult<Option<usize>> {
+        Ok(Some(self.wait_queue.wakes()))
+    }
 }
 
 pub fn eventfd(init_val: u32, flags: u32) -> AlienResult<Arc<dyn File>> {
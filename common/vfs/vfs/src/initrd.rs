@@ -37,7 +37,7 @@ fn parse_initrd_data(root: Arc<dyn VfsDentry>, initrd: &[u8]) -> AlienResult<()>
             } else if mode.contains(Mode::REGULAR_FILE) {
                 // create file
                 let f = path.join(name)?.open(Some(inode_mode))?;
-                let data = DVec::from_other_rvec_slice(entry.file());
+                let data = DVec::from_slice(entry.file());
                 f.inode()?.write_at(0, &data)?;
             }
         }
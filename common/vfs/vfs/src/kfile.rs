@@ -10,7 +10,8 @@ use basic::{
     AlienResult,
 };
 use downcast_rs::{impl_downcast, DowncastSync};
-use interface::InodeID;
+use interface::{BufUartDomain, InodeID};
+use ioctl::{ArgKind, Ioctl, IoctlArg, FIONBIO, FIONREAD};
 use shared_heap::DVec;
 use storage::CustomStorge;
 use vfs_common::meta::KernelFileMeta;
@@ -22,7 +23,12 @@ use vfscore::{
     utils::{VfsFileStat, VfsNodeType, VfsPollEvents},
 };
 
-use crate::{shim::FsShimInode, system_root_fs};
+use crate::{
+    devfs,
+    shim::FsShimInode,
+    system_root_fs, task_domain,
+    user_buf::{self, UserBuffer},
+};
 
 pub struct KernelFile {
     inode_id: u64,
@@ -82,6 +88,16 @@ impl KernelFile {
             inode_id,
         }
     }
+
+    /// The uart behind the file if it is the tty device.
+    fn uart(&self) -> AlienResult<Option<Arc<dyn BufUartDomain>>> {
+        let inode = self.dentry.inode()?;
+        if inode.inode_type() != VfsNodeType::CharDevice {
+            return Ok(None);
+        }
+        let rdev = inode.get_attr()?.st_rdev;
+        Ok(devfs::uart_device(rdev))
+    }
 }
 
 pub trait File: DowncastSync + Debug {
@@ -93,6 +109,14 @@ pub trait File: DowncastSync + Debug {
     fn write_at(&self, _offset: u64, _buf: &DVec<u8>) -> AlienResult<usize> {
         Err(LinuxErrno::ENOSYS)
     }
+    /// Read into the pinned user buffer `buf`, at `offset` or at the file position.
+    fn read_user(&self, offset: Option<u64>, buf: &UserBuffer) -> AlienResult<usize> {
+        user_buf::direct_read(self, offset, buf)
+    }
+    /// Write the pinned user buffer `buf`, at `offset` or at the file position.
+    fn write_user(&self, offset: Option<u64>, buf: &UserBuffer) -> AlienResult<usize> {
+        user_buf::direct_write(self, offset, buf)
+    }
     fn flush(&self) -> AlienResult<()> {
         Ok(())
     }
@@ -123,10 +147,55 @@ pub trait File: DowncastSync + Debug {
     fn poll(&self, _event: PollEvents) -> AlienResult<PollEvents> {
         Err(LinuxErrno::ENOSYS)
     }
+    /// Wake task `tid` once the result of [`File::poll`] may have changed.
+    ///
+    /// Returns false if the file cannot wake waiters, they have to keep polling it.
+    fn poll_register(&self, _tid: usize) -> AlienResult<bool> {
+        Ok(false)
+    }
+    fn poll_unregister(&self, _tid: usize) -> AlienResult<()> {
+        Ok(())
+    }
+    /// How often the file has woken its waiters so far, which re-arms `EPOLLET` entries.
+    ///
+    /// None if the file cannot wake waiters, then only changes of the readiness count.
+    fn poll_wakes(&self) -> AlienResult<Option<usize>> {
+        Ok(None)
+    }
 }
 
 impl_downcast!(sync  File);
 
+/// Commands that all files support, checked before the file gets the command.
+pub static FILE_IOCTLS: &[Ioctl<dyn File>] = &[Ioctl::with_arg(
+    FIONBIO,
+    ArgKind::input::<i32>(),
+    set_nonblocking,
+)];
+
+fn set_nonblocking(file: &dyn File, arg: &mut IoctlArg) -> AlienResult<usize> {
+    let mut flag = file.get_open_flag();
+    flag.set(OpenFlags::O_NONBLOCK, arg.read::<i32>()? != 0);
+    file.set_open_flag(flag);
+    Ok(0)
+}
+
+static KERNEL_FILE_IOCTLS: &[Ioctl<KernelFile>] = &[Ioctl::with_arg(
+    FIONREAD,
+    ArgKind::output::<i32>(),
+    KernelFile::unread_bytes,
+)];
+
+impl KernelFile {
+    /// The bytes between the file position and the end of the file.
+    fn unread_bytes(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let size = self.get_attr()?.st_size;
+        let pos = self.meta.lock().pos;
+        arg.write(&(size.saturating_sub(pos) as i32))?;
+        Ok(0)
+    }
+}
+
 impl File for KernelFile {
     fn read(&self, buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
         let pos = self.meta.lock().pos;
@@ -205,9 +274,13 @@ impl File for KernelFile {
         self.dentry.inode()?.get_attr().map_err(Into::into)
     }
 
-    fn ioctl(&self, _cmd: u32, _arg: usize) -> AlienResult<usize> {
-        let inode = self.dentry.inode().unwrap();
-        inode.ioctl(_cmd, _arg).map_err(Into::into)
+    /// Regular files answer their own commands, other inodes get them as they are.
+    fn ioctl(&self, cmd: u32, arg: usize) -> AlienResult<usize> {
+        let inode = self.dentry.inode()?;
+        if inode.inode_type() == VfsNodeType::File {
+            return ioctl::dispatch(KERNEL_FILE_IOCTLS, self, &**task_domain()?, cmd, arg);
+        }
+        inode.ioctl(cmd, arg).map_err(Into::into)
     }
 
     fn set_open_flag(&self, flag: OpenFlags) {
@@ -288,6 +361,30 @@ impl File for KernelFile {
             .map(|e| PollEvents::from_bits_truncate(e.bits() as u32));
         res.map_err(Into::into)
     }
+
+    fn poll_register(&self, tid: usize) -> AlienResult<bool> {
+        match self.uart()? {
+            Some(uart) => {
+                uart.poll_register(tid)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn poll_unregister(&self, tid: usize) -> AlienResult<()> {
+        if let Some(uart) = self.uart()? {
+            uart.poll_unregister(tid)?;
+        }
+        Ok(())
+    }
+
+    fn poll_wakes(&self) -> AlienResult<Option<usize>> {
+        match self.uart()? {
+            Some(uart) => Ok(Some(uart.poll_wakes()?)),
+            None => Ok(None),
+        }
+    }
 }
 
 fn vfsnodetype2dirent64(ty: VfsNodeType) -> DirentType {
@@ -0,0 +1,84 @@
+//! Permission checks against the owner and mode of an inode.
+//!
+//! The ids come from the task domain: the filesystem ids of the current task, or its real
+//! ids for `access`. Before the task domain is registered only the kernel itself opens
+//! files, as root. Root may do anything except execute a file without any execute bit.
+
+use alloc::sync::Arc;
+
+use basic::{AlienError, AlienResult};
+use vfscore::{
+    inode::VfsInode,
+    utils::{VfsFileStat, VfsNodeType},
+};
+
+use crate::task_domain;
+
+pub const MAY_EXEC: u32 = 1;
+pub const MAY_WRITE: u32 = 2;
+pub const MAY_READ: u32 = 4;
+
+/// The uid and gid of the current task, its real ones if `real`.
+fn access_ids(real: bool) -> AlienResult<(u32, u32)> {
+    match task_domain() {
+        Ok(task_domain) => task_domain.do_access_ids(real),
+        Err(_) => Ok((0, 0)),
+    }
+}
+
+/// Check that the current task may access `inode` for every bit in `want`.
+pub fn check(inode: &Arc<dyn VfsInode>, want: u32, real: bool) -> AlienResult<()> {
+    let want = want & (MAY_READ | MAY_WRITE | MAY_EXEC);
+    if want == 0 {
+        return Ok(());
+    }
+    let (uid, gid) = access_ids(real)?;
+    let is_dir = inode.inode_type() == VfsNodeType::Dir;
+    if uid == 0 && (want & MAY_EXEC == 0 || is_dir) {
+        return Ok(());
+    }
+    let attr = inode.get_attr()?;
+    let mode = attr.st_mode;
+    if uid == 0 {
+        // executing needs at least one execute bit even for root
+        return if mode & 0o111 != 0 {
+            Ok(())
+        } else {
+            Err(AlienError::EACCES)
+        };
+    }
+    let granted = if uid == attr.st_uid {
+        (mode >> 6) & 0o7
+    } else if gid == attr.st_gid || task_domain()?.do_in_group(attr.st_gid)? {
+        (mode >> 3) & 0o7
+    } else {
+        mode & 0o7
+    };
+    if granted & want == want {
+        Ok(())
+    } else {
+        Err(AlienError::EACCES)
+    }
+}
+
+/// Check that the current task may change the mode of `stat` and its owner to `uid` and
+/// `gid`.
+///
+/// Only the owner may change the mode. Root may give a file away, the owner may only move
+/// it to one of its own groups.
+pub fn check_set_attr(stat: &VfsFileStat, uid: Option<u32>, gid: Option<u32>) -> AlienResult<()> {
+    let (cur_uid, cur_gid) = access_ids(false)?;
+    if cur_uid == 0 {
+        return Ok(());
+    }
+    let changes_owner = uid.is_some_and(|uid| uid != stat.st_uid);
+    if cur_uid != stat.st_uid || changes_owner {
+        return Err(AlienError::EPERM);
+    }
+    if let Some(gid) = gid {
+        if gid != stat.st_gid && gid != cur_gid && !task_domain()?.do_in_group(gid)? {
+            return Err(AlienError::EPERM);
+        }
+    }
+    Ok(())
+}
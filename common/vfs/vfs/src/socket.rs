@@ -165,6 +165,19 @@ impl File for SocketFile {
     fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
         self.net_stack_domain.poll(self.socket_id, event)
     }
+
+    fn poll_register(&self, tid: usize) -> AlienResult<bool> {
+        self.net_stack_domain.poll_register(self.socket_id, tid)?;
+        Ok(true)
+    }
+
+    fn poll_unregister(&self, tid: usize) -> AlienResult<()> {
+        self.net_stack_domain.poll_unregister(self.socket_id, tid)
+    }
+
+    fn poll_wakes(&self) -> AlienResult<Option<usize>> {
+        self.net_stack_domain.poll_wakes(self.socket_id).map(Some)
+    }
 }
 
 pub struct SocketInode;
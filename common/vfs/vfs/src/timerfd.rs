@@ -0,0 +1,192 @@
+use alloc::sync::Arc;
+use core::fmt::Debug;
+
+use basic::{
+    constants::io::{OpenFlags, PollEvents, SeekFrom},
+    sync::Mutex,
+    time::read_timer,
+    AlienError, AlienResult,
+};
+use shared_heap::DVec;
+use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
+
+use crate::{kfile::File, task_domain, wait_queue::WaitQueue};
+
+/// The state of a timerfd. Times are in timer cycles.
+#[derive(Debug, Default)]
+struct TimerState {
+    interval: usize,
+    /// 0 if the timer is disarmed
+    deadline: usize,
+    /// Expirations since the last read
+    expirations: u64,
+}
+
+impl TimerState {
+    /// Count the expirations until `now` and move the deadline past it.
+    fn update(&mut self, now: usize) {
+        if self.deadline == 0 || now < self.deadline {
+            return;
+        }
+        if self.interval == 0 {
+            self.expirations += 1;
+            self.deadline = 0;
+        } else {
+            let count = (now - self.deadline) / self.interval + 1;
+            self.expirations += count as u64;
+            self.deadline += count * self.interval;
+        }
+    }
+
+    fn remaining(&self, now: usize) -> usize {
+        match self.deadline {
+            0 => 0,
+            deadline => deadline.saturating_sub(now).max(1),
+        }
+    }
+}
+
+pub struct TimerFd {
+    clock_id: usize,
+    flags: Mutex<OpenFlags>,
+    state: Mutex<TimerState>,
+    /// Readers of a disarmed timer
+    wait_queue: WaitQueue,
+}
+
+impl Debug for TimerFd {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TimerFd")
+            .field("clock_id", &self.clock_id)
+            .field("flags", &self.flags)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl TimerFd {
+    pub fn new(clock_id: usize, flags: OpenFlags) -> Self {
+        TimerFd {
+            clock_id,
+            flags: Mutex::new(flags),
+            state: Mutex::new(TimerState::default()),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn clock_id(&self) -> usize {
+        self.clock_id
+    }
+
+    /// Arm the timer to expire at `deadline` and then every `interval`, or disarm it if
+    /// `deadline` is 0. Returns the old `(interval, remaining)`.
+    pub fn set_time(&self, interval: usize, deadline: usize) -> AlienResult<(usize, usize)> {
+        let now = read_timer();
+        let mut state = self.state.lock();
+        state.update(now);
+        let old = (state.interval, state.remaining(now));
+        *state = TimerState {
+            interval,
+            deadline,
+            expirations: 0,
+        };
+        drop(state);
+        self.wait_queue.wake_all()?;
+        Ok(old)
+    }
+
+    /// The `(interval, remaining)` of the timer.
+    pub fn get_time(&self) -> (usize, usize) {
+        let now = read_timer();
+        let mut state = self.state.lock();
+        state.update(now);
+        (state.interval, state.remaining(now))
+    }
+}
+
+impl File for TimerFd {
+    fn read(&self, mut buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
+        if buf.len() < 8 {
+            return Err(AlienError::EINVAL);
+        }
+        let val = loop {
+            let mut state = self.state.lock();
+            state.update(read_timer());
+            if state.expirations != 0 {
+                break core::mem::take(&mut state.expirations);
+            }
+            if self.flags.lock().contains(OpenFlags::O_NONBLOCK) {
+                return Err(AlienError::EAGAIN);
+            }
+            match state.deadline {
+                0 => self.wait_queue.sleep(state)?,
+                deadline => {
+                    drop(state);
+                    task_domain()?.do_sleep_until(deadline)?;
+                }
+            }
+        };
+        buf.as_mut_slice()[..8].copy_from_slice(&val.to_ne_bytes());
+        Ok((buf, 8))
+    }
+    fn write(&self, _buf: &DVec<u8>) -> AlienResult<usize> {
+        Err(AlienError::EINVAL)
+    }
+    fn read_at(&self, _offset: u64, buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
+        self.read(buf)
+    }
+    fn write_at(&self, _offset: u64, buf: &DVec<u8>) -> AlienResult<usize> {
+        self.write(buf)
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(AlienError::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        Err(AlienError::ENOSYS)
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.flags.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        panic!("TimerFd::dentry() is not implemented")
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        panic!("TimerFd::inode() is not implemented")
+    }
+
+    fn is_readable(&self) -> bool {
+        true
+    }
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let mut state = self.state.lock();
+        state.update(read_timer());
+        let mut events = PollEvents::empty();
+        if state.expirations != 0 && event.contains(PollEvents::EPOLLIN) {
+            events |= PollEvents::EPOLLIN;
+        }
+        Ok(events)
+    }
+}
+
+pub fn timerfd(clock_id: usize, flags: u32) -> AlienResult<Arc<dyn File>> {
+    let flags = OpenFlags::from_bits_truncate(flags as usize);
+    Ok(Arc::new(TimerFd::new(clock_id, flags)))
+}
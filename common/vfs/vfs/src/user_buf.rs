@@ -0,0 +1,361 @@
+//! User buffers pinned by the task domain for one large read or write.
+//!
+//! The syscall domain pins the buffer and passes the physical addresses of its pages here
+//! instead of a copy of the data. The pages are used in place: pipes copy between their
+//! ring buffer and the pages, and the other files read into and write from `DVec`s that
+//! borrow runs of physically contiguous pages, so the filesystem domains move the data to
+//! or from the user themselves.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    cmp::{max, min},
+    ops::Range,
+};
+
+use basic::{config::FRAME_SIZE, vm::frame::FrameTracker, AlienError, AlienResult};
+use shared_heap::DVec;
+
+use crate::kfile::File;
+
+/// Memory the bytes of a run of pages live in
+trait Pages: Send + Sync {
+    fn with_bytes(&self, f: &mut dyn FnMut(&mut [u8]));
+}
+
+impl Pages for FrameTracker {
+    fn with_bytes(&self, f: &mut dyn FnMut(&mut [u8])) {
+        f(self.as_mut_slice_with(0))
+    }
+}
+
+/// A run of physically contiguous pages of a user buffer
+struct Run {
+    pages: Box<dyn Pages>,
+    /// Where the part of the buffer in the run starts in the pages
+    skip: usize,
+    len: usize,
+}
+
+impl Run {
+    /// Call `f` with the part of the buffer in the run.
+    fn with_part<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> R {
+        let mut f = Some(f);
+        let mut res = None;
+        self.pages.with_bytes(&mut |bytes| {
+            let f = f.take().unwrap();
+            res = Some(f(&mut bytes[self.skip..self.skip + self.len]));
+        });
+        res.unwrap()
+    }
+}
+
+pub struct UserBuffer {
+    runs: Vec<Run>,
+    len: usize,
+}
+
+impl UserBuffer {
+    /// The buffer of `len` bytes that starts `start` bytes into the first of `pages`, the
+    /// physical addresses of all its pages.
+    pub fn new(pages: &[usize], start: usize, len: usize) -> AlienResult<Self> {
+        if start >= FRAME_SIZE || pages.len() != (start + len).div_ceil(FRAME_SIZE) {
+            return Err(AlienError::EINVAL);
+        }
+        let mut runs = Vec::new();
+        let mut first = 0;
+        let mut begin = 0;
+        for i in 1..=pages.len() {
+            if i < pages.len() && pages[i] == pages[i - 1] + FRAME_SIZE {
+                continue;
+            }
+            let end = min(i * FRAME_SIZE - start, len);
+            let frame = FrameTracker::from_phy_range(pages[first]..pages[i - 1] + FRAME_SIZE);
+            runs.push(Run {
+                pages: Box::new(frame),
+                skip: if first == 0 { start } else { 0 },
+                len: end - begin,
+            });
+            first = i;
+            begin = end;
+        }
+        Ok(Self { runs, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Call `f` with every piece of `range` of the buffer that lies in one run, and where
+    /// the piece starts in `range`.
+    fn for_each_piece(
+        &self,
+        range: Range<usize>,
+        mut f: impl FnMut(usize, &mut [u8]),
+    ) -> AlienResult<()> {
+        if range.end > self.len {
+            return Err(AlienError::EFAULT);
+        }
+        let mut run_start = 0;
+        for run in &self.runs {
+            let from = max(range.start, run_start);
+            let to = min(range.end, run_start + run.len);
+            if from < to {
+                run.with_part(|part| {
+                    f(
+                        from - range.start,
+                        &mut part[from - run_start..to - run_start],
+                    )
+                });
+            }
+            run_start += run.len;
+        }
+        Ok(())
+    }
+
+    /// Copy `data` to the user at `offset` in the buffer.
+    pub fn write(&self, offset: usize, data: &[u8]) -> AlienResult<()> {
+        self.for_each_piece(offset..offset + data.len(), |at, piece| {
+            piece.copy_from_slice(&data[at..at + piece.len()])
+        })
+    }
+
+    /// Fill `data` from the user at `offset` in the buffer.
+    pub fn read(&self, offset: usize, data: &mut [u8]) -> AlienResult<()> {
+        self.for_each_piece(offset..offset + data.len(), |at, piece| {
+            data[at..at + piece.len()].copy_from_slice(piece)
+        })
+    }
+}
+
+/// A transfer that already moved some bytes reports them instead of the error.
+fn partial(total: usize, err: AlienError) -> AlienResult<usize> {
+    if total > 0 {
+        Ok(total)
+    } else {
+        Err(err)
+    }
+}
+
+/// Read `file` straight into the pages of `buf`, with one read per run of pages.
+pub fn direct_read<F: File + ?Sized>(
+    file: &F,
+    offset: Option<u64>,
+    buf: &UserBuffer,
+) -> AlienResult<usize> {
+    let mut total = 0;
+    for run in &buf.runs {
+        let res = run.with_part(|part| {
+            let part = DVec::from_other_rvec_slice(part);
+            match offset {
+                Some(offset) => file.read_at(offset + total as u64, part),
+                None => file.read(part),
+            }
+            .map(|(_, r)| r)
+        });
+        let r = match res {
+            Ok(r) => r,
+            Err(e) => return partial(total, e),
+        };
+        total += r;
+        if r < run.len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Write the pages of `buf` straight to `file`, with one write per run of pages.
+pub fn direct_write<F: File + ?Sized>(
+    file: &F,
+    offset: Option<u64>,
+    buf: &UserBuffer,
+) -> AlienResult<usize> {
+    let mut total = 0;
+    for run in &buf.runs {
+        let res = run.with_part(|part| {
+            let part = DVec::from_other_rvec_slice(part);
+            match offset {
+                Some(offset) => file.write_at(offset + total as u64, &part),
+                None => file.write(&part),
+            }
+        });
+        let w = match res {
+            Ok(w) => w,
+            Err(e) => return partial(total, e),
+        };
+        total += w;
+        if w < run.len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use alloc::{sync::Arc, vec, vec::Vec};
+    use std::{println, time::Instant};
+
+    use basic::{constants::io::SeekFrom, sync::Mutex};
+    use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
+
+    use super::*;
+
+    /// Pages backed by host memory
+    struct HostPages(Mutex<Vec<u8>>);
+
+    impl Pages for HostPages {
+        fn with_bytes(&self, f: &mut dyn FnMut(&mut [u8])) {
+            f(&mut self.0.lock())
+        }
+    }
+
+    /// A buffer of `len` bytes that starts `start` bytes into its first page, in runs of
+    /// `run_pages` pages.
+    fn host_buffer(start: usize, len: usize, run_pages: usize) -> UserBuffer {
+        let mut runs = Vec::new();
+        let mut begin = 0;
+        while begin < len {
+            let skip = if begin == 0 { start } else { 0 };
+            let run_len = min(run_pages * FRAME_SIZE - skip, len - begin);
+            let pages = HostPages(Mutex::new(vec![0; run_pages * FRAME_SIZE]));
+            runs.push(Run {
+                pages: Box::new(pages),
+                skip,
+                len: run_len,
+            });
+            begin += run_len;
+        }
+        UserBuffer { runs, len }
+    }
+
+    #[derive(Debug)]
+    struct MemFile(Mutex<Vec<u8>>);
+
+    impl File for MemFile {
+        fn read(&self, _buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
+            Err(AlienError::ENOSYS)
+        }
+        fn write(&self, _buf: &DVec<u8>) -> AlienResult<usize> {
+            Err(AlienError::ENOSYS)
+        }
+        fn read_at(&self, offset: u64, mut buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
+            let data = self.0.lock();
+            let offset = min(offset as usize, data.len());
+            let r = min(buf.len(), data.len() - offset);
+            buf.as_mut_slice()[..r].copy_from_slice(&data[offset..offset + r]);
+            Ok((buf, r))
+        }
+        fn write_at(&self, offset: u64, buf: &DVec<u8>) -> AlienResult<usize> {
+            let mut data = self.0.lock();
+            let offset = offset as usize;
+            if data.len() < offset + buf.len() {
+                data.resize(offset + buf.len(), 0);
+            }
+            data[offset..offset + buf.len()].copy_from_slice(buf.as_slice());
+            Ok(buf.len())
+        }
+        fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+            Err(AlienError::ESPIPE)
+        }
+        fn get_attr(&self) -> AlienResult<VfsFileStat> {
+            Err(AlienError::ENOSYS)
+        }
+        fn dentry(&self) -> Arc<dyn VfsDentry> {
+            unimplemented!()
+        }
+        fn inode(&self) -> Arc<dyn VfsInode> {
+            unimplemented!()
+        }
+        fn is_readable(&self) -> bool {
+            true
+        }
+        fn is_writable(&self) -> bool {
+            true
+        }
+        fn is_append(&self) -> bool {
+            false
+        }
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn pieces_cross_runs() {
+        let buf = host_buffer(100, 5 * FRAME_SIZE, 2);
+        assert_eq!(buf.runs.len(), 3);
+        let data = pattern(3 * FRAME_SIZE);
+        buf.write(FRAME_SIZE, &data).unwrap();
+        let mut back = vec![0; data.len()];
+        buf.read(FRAME_SIZE, &mut back).unwrap();
+        assert_eq!(back, data);
+        assert!(matches!(
+            buf.write(5 * FRAME_SIZE - 1, &[0; 2]),
+            Err(AlienError::EFAULT)
+        ));
+    }
+
+    #[test]
+    fn direct_read_and_write_go_through_the_runs() {
+        let data = pattern(3 * FRAME_SIZE);
+        let file = MemFile(Mutex::new(data.clone()));
+        let buf = host_buffer(FRAME_SIZE - 1, 4 * FRAME_SIZE, 1);
+        // the file ends inside the buffer
+        assert_eq!(direct_read(&file, Some(0), &buf).unwrap(), data.len());
+        let mut back = vec![0; data.len()];
+        buf.read(0, &mut back).unwrap();
+        assert_eq!(back, data);
+
+        let copy = MemFile(Mutex::new(Vec::new()));
+        assert_eq!(direct_write(&copy, Some(8), &buf).unwrap(), buf.len());
+        assert_eq!(&copy.0.lock()[8..8 + data.len()], &data[..]);
+    }
+
+    /// Reads through a shared heap stage as pinned reads did before they got the pages.
+    fn staged_read(file: &MemFile, buf: &UserBuffer) -> usize {
+        const STAGE_CHUNK: usize = 64 * 1024;
+        let mut stage = DVec::new_uninit(STAGE_CHUNK);
+        let mut total = 0;
+        while total < buf.len() {
+            let r;
+            (stage, r) = file.read_at(total as u64, stage).unwrap();
+            buf.write(total, &stage.as_slice()[..r]).unwrap();
+            total += r;
+            if r < STAGE_CHUNK {
+                break;
+            }
+        }
+        total
+    }
+
+    /// Run with `cargo domain test -n vfs -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_pinned_read() {
+        const LEN: usize = 64 << 20;
+        const ROUNDS: usize = 16;
+        let file = MemFile(Mutex::new(pattern(LEN)));
+        let buf = host_buffer(0, LEN, 16);
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            assert_eq!(direct_read(&file, Some(0), &buf).unwrap(), LEN);
+        }
+        let direct = start.elapsed();
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            assert_eq!(staged_read(&file, &buf), LEN);
+        }
+        let staged = start.elapsed();
+        println!(
+            "{} reads of {} MiB: into the pages {:?}, through a stage {:?}",
+            ROUNDS,
+            LEN >> 20,
+            direct,
+            staged
+        );
+    }
+}
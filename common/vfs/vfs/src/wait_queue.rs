@@ -0,0 +1,68 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use basic::{
+    sync::{Mutex, MutexGuard},
+    AlienError, AlienResult,
+};
+
+use crate::task_domain;
+
+/// Tasks waiting for the state of a file to change, identified by tid.
+#[derive(Debug, Default)]
+pub struct WaitQueue {
+    tids: Mutex<VecDeque<usize>>,
+    /// The number of [`WaitQueue::wake_all`] calls, which re-arm `EPOLLET` entries
+    wakes: AtomicUsize,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            tids: Mutex::new(VecDeque::new()),
+            wakes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add `tid` to the tasks woken by the next [`WaitQueue::wake_all`].
+    pub fn register(&self, tid: usize) {
+        let mut tids = self.tids.lock();
+        if !tids.contains(&tid) {
+            tids.push_back(tid);
+        }
+    }
+
+    pub fn unregister(&self, tid: usize) {
+        self.tids.lock().retain(|t| *t != tid);
+    }
+
+    /// Block the current task until the queue is woken.
+    ///
+    /// The task is queued before `guard` is released, so a waker that needs the same lock
+    /// cannot miss it. Callers re-check their condition after waking up. Fails with `EINTR`
+    /// if a signal is pending for the task.
+    pub fn sleep<T>(&self, guard: MutexGuard<'_, T>) -> AlienResult<()> {
+        let tid = basic::current_tid()?.unwrap();
+        self.register(tid);
+        drop(guard);
+        basic::wait_now()?;
+        if task_domain()?.do_has_pending_signal()? {
+            self.unregister(tid);
+            return Err(AlienError::EINTR);
+        }
+        Ok(())
+    }
+
+    pub fn wakes(&self) -> usize {
+        self.wakes.load(Ordering::Acquire)
+    }
+
+    pub fn wake_all(&self) -> AlienResult<()> {
+        self.wakes.fetch_add(1, Ordering::AcqRel);
+        let tids: Vec<usize> = self.tids.lock().drain(..).collect();
+        for tid in tids {
+            basic::wake_up_wait_task(tid)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use alloc::{string::ToString, sync::Arc};
+
+use basic::{AlienError, AlienResult};
+use shared_heap::DVec;
+use vfscore::{dentry::VfsDentry, inode::VfsInode, path::VfsPath, utils::VfsNodeType};
+
+use crate::{
+    get_file,
+    perm::{self, MAY_EXEC},
+    shim::FsShimInode,
+    task_domain,
+    tree::system_root_fs,
+};
+
+/// The most symbolic links followed while resolving one path, as on Linux.
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+const MAX_LINK_LEN: usize = 4096;
+
+/// Resolve `path` relative to `start`.
+///
+/// Absolute paths and link targets start from the root directory of the current task.
+/// Symbolic links in the middle of the path are always followed, the last component is
+/// only followed if `follow` is set. Fails with `ELOOP` once more than
+/// [`MAX_SYMLINK_FOLLOWS`] links were followed.
+pub fn lookup(
+    start: Arc<dyn VfsDentry>,
+    path: &str,
+    follow: bool,
+) -> AlienResult<Arc<dyn VfsDentry>> {
+    let root = task_root()?;
+    let mut follows = 0;
+    resolve(&root, start, path, follow, &mut follows)
+}
+
+/// The root directory of the current task, the system root before the task domain is
+/// registered.
+fn task_root() -> AlienResult<Arc<dyn VfsDentry>> {
+    let Ok(task_domain) = task_domain() else {
+        return Ok(system_root_fs());
+    };
+    let (root, _) = task_domain.fs_info()?;
+    Ok(get_file(root).ok_or(AlienError::EBADF)?.dentry())
+}
+
+/// Resolve the directory that contains the last component of `path` and return it with
+/// the name of the last component.
+pub fn lookup_parent<'a>(
+    start: Arc<dyn VfsDentry>,
+    path: &'a str,
+) -> AlienResult<(Arc<dyn VfsDentry>, &'a str)> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(index) => (&path[..index], &path[index + 1..]),
+        None => ("", path),
+    };
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(AlienError::EINVAL);
+    }
+    Ok((lookup(start, dir, true)?, name))
+}
+
+fn resolve(
+    root: &Arc<dyn VfsDentry>,
+    start: Arc<dyn VfsDentry>,
+    path: &str,
+    follow: bool,
+    follows: &mut usize,
+) -> AlienResult<Arc<dyn VfsDentry>> {
+    let mut current = if path.starts_with('/') {
+        root.clone()
+    } else {
+        start
+    };
+    let mut components = path
+        .split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .peekable();
+    while let Some(name) = components.next() {
+        let last = components.peek().is_none();
+        let parent = current.clone();
+        perm::check(&parent.inode()?, MAY_EXEC, false)?;
+        if name != ".." {
+            let child = lookup_child(&parent, name)?;
+            if is_symlink(&child)? {
+                if last && !follow {
+                    return Ok(child);
+                }
+                *follows += 1;
+                if *follows > MAX_SYMLINK_FOLLOWS {
+                    return Err(AlienError::ELOOP);
+                }
+                let (target, len) = child.inode()?.readlink(DVec::new_uninit(MAX_LINK_LEN))?;
+                let target = core::str::from_utf8(&target.as_slice()[..len])
+                    .map_err(|_| AlienError::EINVAL)?
+                    .to_string();
+                current = resolve(root, parent, &target, true, follows)?;
+                continue;
+            }
+        }
+        // let the vfs cross mount points and handle `..`, which stops at the root
+        current = VfsPath::new(root.clone(), parent).join(name)?.open(None)?;
+    }
+    Ok(current)
+}
+
+/// Look up `name` in `parent` without following it if it is a symbolic link.
+pub fn lookup_child(parent: &Arc<dyn VfsDentry>, name: &str) -> AlienResult<Arc<dyn VfsDentry>> {
+    if let Some(child) = parent.find(name) {
+        return Ok(child);
+    }
+    let inode = parent.inode()?.lookup(name)?;
+    let child = parent.clone().insert(name, inode)?;
+    Ok(child)
+}
+
+pub fn is_symlink(dentry: &Arc<dyn VfsDentry>) -> AlienResult<bool> {
+    Ok(dentry.inode()?.inode_type() == VfsNodeType::SymLink)
+}
+
+/// Whether two inodes belong to the same filesystem instance.
+///
+/// Inodes that are not provided by an fs domain are left to the filesystem to reject.
+pub fn same_fs(a: &Arc<dyn VfsInode>, b: &Arc<dyn VfsInode>) -> bool {
+    match (
+        a.clone().downcast_arc::<FsShimInode>(),
+        b.clone().downcast_arc::<FsShimInode>(),
+    ) {
+        (Ok(a), Ok(b)) => a.fs_domain_ident() == b.fs_domain_ident(),
+        _ => true,
+    }
+}
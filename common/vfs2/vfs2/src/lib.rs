@@ -20,32 +20,44 @@ use basic::{
     *,
 };
 use interface::{
-    define_unwind_for_VfsDomain, Basic, DomainType, InodeID, NetDomain, SocketID, VfsDomain,
+    define_unwind_for_VfsDomain, Basic, DomainType, InodeID, NetDomain, SocketID, TaskDomain,
+    VfsDomain,
 };
+use ioctl::TIOCSCTTY;
 use log::debug;
 use shared_heap::{DBox, DVec};
 use spin::{Lazy, Once};
 use storage::CustomStorge;
 use vfscore::{
     dentry::VfsDentry,
+    inode::{InodeAttr, VfsInode},
     path::{SysContext, VfsPath},
     utils::{
-        VfsFileStat, VfsInodeMode, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime, VfsTimeSpec,
+        VfsFileStat, VfsFsStat, VfsInodeMode, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime,
+        VfsTimeSpec,
     },
 };
 
 use crate::{
     epoll::EpollFile,
-    kfile::{File, KernelFile},
+    kfile::{File, KernelFile, FILE_IOCTLS},
+    perm::{MAY_EXEC, MAY_READ, MAY_WRITE},
+    pipe::PipeFile,
     socket::SocketFile,
+    timerfd::TimerFd,
     tree::system_root_fs,
+    user_buf::UserBuffer,
 };
 
+#[cfg(feature = "bench")]
+mod bench;
 mod devfs;
 mod epoll;
 mod eventfd;
 mod initrd;
 mod kfile;
+mod mount;
+mod perm;
 mod pipe;
 mod pipefs;
 mod procfs;
@@ -53,9 +65,17 @@ mod ramfs;
 mod shim;
 mod socket;
 mod sys;
+mod timerfd;
 mod tree;
+mod user_buf;
+mod wait_queue;
+mod walk;
+
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
 
 static NET_STACK_DOMAIN: Once<Arc<dyn NetDomain>> = Once::new();
+static TASK_DOMAIN: Once<Arc<dyn TaskDomain>> = Once::new();
 static VFS_MAP: RwLock<BTreeMap<InodeID, Arc<dyn File>>> = RwLock::new(BTreeMap::new());
 
 static INODE_ID: Lazy<Arc<AtomicU64, CustomStorge>> =
@@ -109,6 +129,10 @@ impl VfsDomain for VfsDomainImpl {
     fn init(&self, initrd: &[u8]) -> AlienResult<()> {
         let is_init_done = VFS_INIT.load(core::sync::atomic::Ordering::SeqCst);
         tree::init_filesystem(initrd, is_init_done).unwrap();
+        #[cfg(feature = "bench")]
+        if !is_init_done {
+            bench::run();
+        }
         let net_stack_domain = basic::get_domain("net_stack").unwrap();
         match net_stack_domain {
             DomainType::NetDomain(net_stack_domain) => {
@@ -128,9 +152,15 @@ impl VfsDomain for VfsDomainImpl {
     }
 
     fn vfs_ioctl(&self, inode: InodeID, cmd: u32, arg: usize) -> AlienResult<usize> {
-        let file = get_file(inode).unwrap();
-        let res = file.ioctl(cmd, arg)?;
-        Ok(res)
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        if let Some(ioctl) = ioctl::find(FILE_IOCTLS, cmd) {
+            return ioctl.call(&*file, &**task_domain()?, arg);
+        }
+        // files without commands of their own are not terminals either
+        match file.ioctl(cmd, arg) {
+            Err(AlienError::ENOSYS) => Err(AlienError::ENOTTY),
+            res => res,
+        }
     }
 
     fn vfs_open(
@@ -151,9 +181,51 @@ impl VfsDomain for VfsDomainImpl {
             None
         };
         // println_color!(31,"vfs_open: path_name: {}, mode: {:?}", path_name, mode);
-        let path = VfsPath::new(root, start.dentry())
-            .join(path_name)?
-            .open(mode)?;
+        let mut created = false;
+        let path = match mode {
+            Some(mode) => {
+                // creating an entry needs write and search permission on its directory
+                match walk::lookup_parent(start.dentry(), path_name) {
+                    Ok((parent, name)) => {
+                        if walk::lookup_child(&parent, name).is_err() {
+                            perm::check(&parent.inode()?, MAY_WRITE | MAY_EXEC, false)?;
+                            created = true;
+                        }
+                    }
+                    // `/` and `.` always exist
+                    Err(AlienError::EINVAL) => {}
+                    Err(e) => return Err(e),
+                }
+                VfsPath::new(root, start.dentry())
+                    .join(path_name)?
+                    .open(Some(mode))?
+            }
+            None => {
+                let follow = !open_flags.contains(OpenFlags::O_NOFOLLOW);
+                let dentry = walk::lookup(start.dentry(), path_name, follow)?;
+                // only an `O_PATH` open may refer to the link itself
+                if !follow && !open_flags.contains(OpenFlags::O_PATH) && walk::is_symlink(&dentry)?
+                {
+                    return Err(AlienError::ELOOP);
+                }
+                dentry
+            }
+        };
+        if created {
+            // new files belong to the filesystem ids of their creator
+            let (uid, gid) = task_domain()?.do_access_ids(false)?;
+            if uid != 0 || gid != 0 {
+                let inode = path.inode()?;
+                let stat = inode.get_attr()?;
+                // filesystems without owners keep their own
+                let _ = set_attr(&inode, stat, None, Some(uid), Some(gid));
+            }
+        } else if !open_flags.contains(OpenFlags::O_PATH) {
+            perm::check(&path.inode()?, open_access(open_flags), false)?;
+        }
+        if !open_flags.intersects(OpenFlags::O_NOCTTY | OpenFlags::O_PATH) {
+            open_tty(&path.inode()?)?;
+        }
         let id = insert_dentry(path, open_flags);
         // println_color!(31,"vfs_open: path_name: {} with id: {}", path_name,id);
         Ok(id)
@@ -163,6 +235,25 @@ impl VfsDomain for VfsDomainImpl {
         Ok(())
     }
 
+    fn vfs_set_attr(
+        &self,
+        inode: InodeID,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> AlienResult<()> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        let inode = file.inode();
+        let stat = inode.get_attr()?;
+        perm::check_set_attr(&stat, uid, gid)?;
+        set_attr(&inode, stat, mode, uid, gid)
+    }
+
+    fn vfs_access(&self, inode: InodeID, mode: u32, real: bool) -> AlienResult<()> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        perm::check(&file.inode(), mode, real)
+    }
+
     fn vfs_getattr(
         &self,
         inode: InodeID,
@@ -211,6 +302,28 @@ impl VfsDomain for VfsDomainImpl {
             Ok(res)
         }
     }
+    fn vfs_read_user(
+        &self,
+        inode: InodeID,
+        offset: Option<u64>,
+        pages: &DVec<usize>,
+        start: usize,
+        len: usize,
+    ) -> AlienResult<usize> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        file.read_user(offset, &UserBuffer::new(pages.as_slice(), start, len)?)
+    }
+    fn vfs_write_user(
+        &self,
+        inode: InodeID,
+        offset: Option<u64>,
+        pages: &DVec<usize>,
+        start: usize,
+        len: usize,
+    ) -> AlienResult<usize> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        file.write_user(offset, &UserBuffer::new(pages.as_slice(), start, len)?)
+    }
     fn vfs_flush(&self, inode: InodeID) -> AlienResult<()> {
         let file = get_file(inode).unwrap();
         file.flush()?;
@@ -244,6 +357,57 @@ impl VfsDomain for VfsDomainImpl {
         buf.as_mut_slice()[..copy_len].copy_from_slice(&path[..copy_len]);
         Ok((buf, copy_len))
     }
+    fn vfs_mount(
+        &self,
+        root: InodeID,
+        source: &DVec<u8>,
+        source_len: usize,
+        target: &DVec<u8>,
+        target_len: usize,
+        fs_type: &DVec<u8>,
+        fs_type_len: usize,
+        flags: u32,
+    ) -> AlienResult<()> {
+        let start = get_file(root).ok_or(AlienError::EINVAL)?;
+        let source = core::str::from_utf8(&source.as_slice()[..source_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        let target = core::str::from_utf8(&target.as_slice()[..target_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        let fs_type = core::str::from_utf8(&fs_type.as_slice()[..fs_type_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        mount::do_mount(start.dentry(), source, target, fs_type, flags)
+    }
+
+    fn vfs_umount(
+        &self,
+        root: InodeID,
+        target: &DVec<u8>,
+        target_len: usize,
+        flags: u32,
+    ) -> AlienResult<()> {
+        let start = get_file(root).ok_or(AlienError::EINVAL)?;
+        let target = core::str::from_utf8(&target.as_slice()[..target_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        mount::do_umount(start.dentry(), target, flags)
+    }
+
+    fn vfs_statfs(
+        &self,
+        inode: InodeID,
+        mut fs_stat: DBox<VfsFsStat>,
+    ) -> AlienResult<DBox<VfsFsStat>> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        *fs_stat = mount::do_statfs(file.dentry())?;
+        Ok(fs_stat)
+    }
+
+    fn vfs_mounts(&self, mut buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
+        let info = mount::mounts_info();
+        let copy_len = core::cmp::min(info.len(), buf.len());
+        buf.as_mut_slice()[..copy_len].copy_from_slice(&info.as_bytes()[..copy_len]);
+        Ok((buf, copy_len))
+    }
+
     fn vfs_ftruncate(&self, inode: InodeID, len: u64) -> AlienResult<()> {
         let file = get_file(inode).unwrap();
         file.truncate(len)?;
@@ -308,6 +472,8 @@ impl VfsDomain for VfsDomainImpl {
         let file = get_file(inode).unwrap();
         let dentry = file.dentry();
         let path = core::str::from_utf8(&path.as_slice()[..path_len]).unwrap();
+        let (parent, _) = walk::lookup_parent(dentry.clone(), path)?;
+        perm::check(&parent.inode()?, MAY_WRITE | MAY_EXEC, false)?;
         let path = VfsPath::new(system_root_fs(), dentry).join(path)?;
         let flag = UnlinkatFlags::from_bits_truncate(flag as u32);
         if flag.contains(UnlinkatFlags::AT_REMOVEDIR) {
@@ -318,6 +484,81 @@ impl VfsDomain for VfsDomainImpl {
         Ok(())
     }
 
+    fn vfs_symlink(
+        &self,
+        root: InodeID,
+        target: &DVec<u8>,
+        target_len: usize,
+        path: &DVec<u8>,
+        path_len: usize,
+    ) -> AlienResult<()> {
+        let start = get_file(root).ok_or(AlienError::EINVAL)?;
+        let target = core::str::from_utf8(&target.as_slice()[..target_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        let path =
+            core::str::from_utf8(&path.as_slice()[..path_len]).map_err(|_| AlienError::EINVAL)?;
+        let (parent, name) = walk::lookup_parent(start.dentry(), path)?;
+        if walk::lookup_child(&parent, name).is_ok() {
+            return Err(AlienError::EEXIST);
+        }
+        VfsPath::new(system_root_fs(), parent)
+            .join(name)?
+            .symlink(target)?;
+        Ok(())
+    }
+
+    fn vfs_link(
+        &self,
+        old_root: InodeID,
+        old_path: &DVec<u8>,
+        old_len: usize,
+        new_root: InodeID,
+        new_path: &DVec<u8>,
+        new_len: usize,
+        follow: bool,
+    ) -> AlienResult<()> {
+        let old_start = get_file(old_root).ok_or(AlienError::EINVAL)?;
+        let new_start = get_file(new_root).ok_or(AlienError::EINVAL)?;
+        let old_path = core::str::from_utf8(&old_path.as_slice()[..old_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        let new_path = core::str::from_utf8(&new_path.as_slice()[..new_len])
+            .map_err(|_| AlienError::EINVAL)?;
+        let old = walk::lookup(old_start.dentry(), old_path, follow)?;
+        let old_inode = old.inode()?;
+        if old_inode.inode_type() == VfsNodeType::Dir {
+            return Err(AlienError::EPERM);
+        }
+        let (parent, name) = walk::lookup_parent(new_start.dentry(), new_path)?;
+        if walk::lookup_child(&parent, name).is_ok() {
+            return Err(AlienError::EEXIST);
+        }
+        let parent_inode = parent.inode()?;
+        if !walk::same_fs(&old_inode, &parent_inode) {
+            return Err(AlienError::EXDEV);
+        }
+        let inode = parent_inode.link(name, old_inode)?;
+        parent.insert(name, inode)?;
+        Ok(())
+    }
+
+    fn vfs_readlink(
+        &self,
+        root: InodeID,
+        path: &DVec<u8>,
+        path_len: usize,
+        buf: DVec<u8>,
+    ) -> AlienResult<(DVec<u8>, usize)> {
+        let start = get_file(root).ok_or(AlienError::EINVAL)?;
+        let path =
+            core::str::from_utf8(&path.as_slice()[..path_len]).map_err(|_| AlienError::EINVAL)?;
+        let dentry = walk::lookup(start.dentry(), path, false)?;
+        if !walk::is_symlink(&dentry)? {
+            return Err(AlienError::EINVAL);
+        }
+        let (buf, len) = dentry.inode()?.readlink(buf)?;
+        Ok((buf, len))
+    }
+
     fn do_fcntl(&self, inode: InodeID, cmd: usize, args: usize) -> AlienResult<isize> {
         const FD_CLOEXEC: usize = 1;
         let cmd = Fcntl64Cmd::try_from(cmd as u32).unwrap();
@@ -392,11 +633,140 @@ impl VfsDomain for VfsDomainImpl {
         let epoll_file = file.downcast_arc::<EpollFile>().unwrap();
         epoll_file.ctl(op, fd, *event)
     }
+    fn do_poll_wait(
+        &self,
+        inode: InodeID,
+        mut events: DVec<EpollEvent>,
+        timeout: Option<TimeSpec>,
+    ) -> AlienResult<(DVec<EpollEvent>, usize)> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        let epoll_file = file
+            .downcast_arc::<EpollFile>()
+            .map_err(|_| AlienError::EINVAL)?;
+        let count = epoll_file.wait(task_domain()?, events.as_mut_slice(), timeout)?;
+        Ok((events, count))
+    }
     fn do_eventfd(&self, init_val: u32, flags: u32) -> AlienResult<InodeID> {
         let eventfd_file = eventfd::eventfd(init_val, flags).unwrap();
         let id = insert_special_file(eventfd_file);
         Ok(id)
     }
+    fn do_timerfd_create(&self, clock_id: usize, flags: u32) -> AlienResult<InodeID> {
+        let timerfd_file = timerfd::timerfd(clock_id, flags)?;
+        let id = insert_special_file(timerfd_file);
+        Ok(id)
+    }
+    fn do_timerfd_settime(
+        &self,
+        inode: InodeID,
+        interval: usize,
+        deadline: usize,
+    ) -> AlienResult<(usize, usize)> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        let timerfd = file
+            .downcast_arc::<TimerFd>()
+            .map_err(|_| AlienError::EINVAL)?;
+        timerfd.set_time(interval, deadline)
+    }
+    fn do_timerfd_clock(&self, inode: InodeID) -> AlienResult<usize> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        let timerfd = file
+            .downcast_arc::<TimerFd>()
+            .map_err(|_| AlienError::EINVAL)?;
+        Ok(timerfd.clock_id())
+    }
+    fn do_timerfd_gettime(&self, inode: InodeID) -> AlienResult<(usize, usize)> {
+        let file = get_file(inode).ok_or(AlienError::EBADF)?;
+        let timerfd = file
+            .downcast_arc::<TimerFd>()
+            .map_err(|_| AlienError::EINVAL)?;
+        Ok(timerfd.get_time())
+    }
+    fn do_tee(
+        &self,
+        input: InodeID,
+        output: InodeID,
+        len: usize,
+        nonblock: bool,
+    ) -> AlienResult<usize> {
+        let input = get_file(input).ok_or(AlienError::EBADF)?;
+        let output = get_file(output).ok_or(AlienError::EBADF)?;
+        let input = input
+            .downcast_arc::<PipeFile>()
+            .map_err(|_| AlienError::EINVAL)?;
+        let output = output
+            .downcast_arc::<PipeFile>()
+            .map_err(|_| AlienError::EINVAL)?;
+        input.tee(&output, len, nonblock)
+    }
+}
+
+/// Replace the mode bits and owner of `inode`, whose current attributes are `stat`.
+///
+/// A new owner clears the set-user-ID and set-group-ID bits of anything but a directory.
+fn set_attr(
+    inode: &Arc<dyn VfsInode>,
+    stat: VfsFileStat,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> AlienResult<()> {
+    let mut new_mode = match mode {
+        Some(mode) => (stat.st_mode & !0o7777) | (mode & 0o7777),
+        None => stat.st_mode,
+    };
+    if (uid.is_some() || gid.is_some()) && inode.inode_type() != VfsNodeType::Dir {
+        new_mode &= !(S_ISUID | S_ISGID);
+    }
+    let now = TimeSpec::now();
+    let attr = InodeAttr {
+        mode: new_mode,
+        uid: uid.unwrap_or(stat.st_uid),
+        gid: gid.unwrap_or(stat.st_gid),
+        size: stat.st_size,
+        atime: stat.st_atime,
+        mtime: stat.st_mtime,
+        ctime: VfsTimeSpec::new(now.tv_sec as u64, now.tv_nsec as u64),
+    };
+    inode.set_attr(attr)?;
+    Ok(())
+}
+
+/// The permissions opening an existing file with `flags` needs.
+fn open_access(flags: OpenFlags) -> u32 {
+    let mut want = 0;
+    if !flags.contains(OpenFlags::O_WRONLY) {
+        want |= MAY_READ;
+    }
+    if flags.contains(OpenFlags::O_WRONLY)
+        || flags.contains(OpenFlags::O_RDWR)
+        || flags.contains(OpenFlags::O_TRUNC)
+    {
+        want |= MAY_WRITE;
+    }
+    want
+}
+
+/// Make the terminal `inode` the controlling terminal of the session of the caller, as
+/// opening a terminal does for a session leader.
+fn open_tty(inode: &Arc<dyn VfsInode>) -> AlienResult<()> {
+    if inode.inode_type() != VfsNodeType::CharDevice
+        || devfs::uart_device(inode.get_attr()?.st_rdev).is_none()
+    {
+        return Ok(());
+    }
+    // fails for other processes and for a terminal another session has
+    let _ = inode.ioctl(TIOCSCTTY, 0);
+    Ok(())
+}
+
+/// The task domain, which owns the fd tables that epoll resolves its interest list in, the
+/// timed waits of timerfds and the signals that interrupt blocking reads and writes.
+fn task_domain() -> AlienResult<&'static Arc<dyn TaskDomain>> {
+    TASK_DOMAIN.try_call_once(|| match basic::get_domain("task") {
+        Some(DomainType::TaskDomain(task_domain)) => Ok(task_domain),
+        _ => Err(AlienError::ENOSYS),
+    })
 }
 
 fn syscontext_for_vfs(fs_info: (InodeID, InodeID)) -> SysContext {
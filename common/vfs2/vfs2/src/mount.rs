@@ -0,0 +1,221 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::ffi::CStr;
+
+use basic::{sync::Mutex, AlienError, AlienResult};
+use interface::{DomainType, FsDomain, MountInfo};
+use shared_heap::{DBox, DVec};
+use vfscore::{
+    dentry::VfsDentry, fstype::FileSystemFlags, path::VfsPath, utils::VfsFsStat, VfsResult,
+};
+
+use crate::{
+    kfile::{File, KernelFile},
+    shim::RootShimDentry,
+    tree::system_root_fs,
+    VFS_MAP,
+};
+
+/// `umount2` flags
+const MNT_FORCE: u32 = 1;
+const MNT_DETACH: u32 = 2;
+
+struct MountEntry {
+    source: String,
+    /// The absolute path of the mount point
+    target: String,
+    fs_type: String,
+    /// The name of the fs domain instance, e.g. `ramfs-3`
+    fs_domain_ident: String,
+    /// The dentry the filesystem is mounted on
+    mount_point: Arc<dyn VfsDentry>,
+    root: Arc<dyn VfsDentry>,
+}
+
+/// The mount table, in mount order.
+static MOUNTS: Mutex<Vec<MountEntry>> = Mutex::new(Vec::new());
+
+/// Mount `root` on `target` while building the initial tree and record it.
+pub fn mount_initial(
+    path: &VfsPath,
+    target: &str,
+    root: Arc<dyn VfsDentry>,
+    source: &str,
+    fs_type: &str,
+) -> VfsResult<()> {
+    let target_path = path.join(target)?;
+    let mount_point = target_path.open(None)?;
+    target_path.mount(root.clone(), 0)?;
+    record(source, mount_point.path(), fs_type, mount_point, root);
+    Ok(())
+}
+
+/// Record the root filesystem, which is never unmounted.
+pub fn record_root(root: &Arc<dyn VfsDentry>, fs_type: &str) {
+    record(
+        "rootfs",
+        "/".to_string(),
+        fs_type,
+        root.clone(),
+        root.clone(),
+    );
+}
+
+fn record(
+    source: &str,
+    target: String,
+    fs_type: &str,
+    mount_point: Arc<dyn VfsDentry>,
+    root: Arc<dyn VfsDentry>,
+) {
+    let fs_domain_ident = root
+        .clone()
+        .downcast_arc::<RootShimDentry>()
+        .map(|root| root.fs_domain_ident_str().to_string())
+        .unwrap_or_default();
+    MOUNTS.lock().push(MountEntry {
+        source: source.to_string(),
+        target,
+        fs_type: fs_type.to_string(),
+        fs_domain_ident,
+        mount_point,
+        root,
+    });
+}
+
+/// The fs domain that implements a filesystem type passed to `mount(2)`.
+fn fs_domain_name(fs_type: &str) -> &str {
+    match fs_type {
+        "vfat" | "fat" | "fat32" => "fatfs",
+        "tmpfs" => "ramfs",
+        name => name,
+    }
+}
+
+/// Describe the device at `source` to the fs domain that is mounted on it.
+fn device_mount_info(start: Arc<dyn VfsDentry>, source: &str) -> AlienResult<MountInfo> {
+    let dev = VfsPath::new(system_root_fs(), start)
+        .join(source)?
+        .open(None)?;
+    let dev = dev
+        .downcast_arc::<RootShimDentry>()
+        .map_err(|_| AlienError::EINVAL)?;
+    let domain_ident = dev.fs_domain_ident_str();
+    let mut ident = [0; 32];
+    let min_copy = core::cmp::min(ident.len(), domain_ident.len());
+    ident[..min_copy].copy_from_slice(&domain_ident.as_bytes()[..min_copy]);
+    Ok(MountInfo {
+        mount_inode_id: dev.inode_id(),
+        domain_ident: ident,
+    })
+}
+
+/// Mount a new instance of the fs domain for `fs_type` on `target`.
+///
+/// Paths are resolved relative to `start`. `source` is only used by filesystems that
+/// need a device, e.g. `mount("/dev/sdb", "/mnt", "vfat", 0, NULL)`.
+pub fn do_mount(
+    start: Arc<dyn VfsDentry>,
+    source: &str,
+    target: &str,
+    fs_type: &str,
+    flags: u32,
+) -> AlienResult<()> {
+    let target_path = VfsPath::new(system_root_fs(), start.clone()).join(target)?;
+    let mount_point = target_path.open(None)?;
+    let target = mount_point.path();
+    let mut fs_ident = [0u8; 32];
+    let fs_domain = basic::create_domain(fs_domain_name(fs_type), fs_ident.as_mut_slice())
+        .ok_or(AlienError::ENODEV)?;
+    let fs_domain: Arc<dyn FsDomain> = match fs_domain {
+        DomainType::FsDomain(fs_domain) => fs_domain,
+        _ => return Err(AlienError::ENODEV),
+    };
+    let fs_ident = CStr::from_bytes_until_nul(fs_ident.as_ref())
+        .map_err(|_| AlienError::EINVAL)?
+        .to_str()
+        .map_err(|_| AlienError::EINVAL)?
+        .to_string();
+    let info = if fs_domain.fs_flag()?.contains(FileSystemFlags::REQUIRES_DEV) {
+        Some(DBox::new(device_mount_info(start, source)?))
+    } else {
+        None
+    };
+    let root_inode_id = fs_domain.mount(&DVec::from_slice(target.as_bytes()), info)?;
+    let root: Arc<dyn VfsDentry> =
+        RootShimDentry::new(fs_domain, root_inode_id, Arc::new(Vec::from(fs_ident)));
+    target_path.mount(root.clone(), flags)?;
+    record(source, target, fs_type, mount_point, root);
+    Ok(())
+}
+
+/// Unmount the filesystem mounted last on `target`.
+///
+/// Fails with `EBUSY` while files of the filesystem are open, unless `MNT_DETACH` or
+/// `MNT_FORCE` is given.
+pub fn do_umount(start: Arc<dyn VfsDentry>, target: &str, flags: u32) -> AlienResult<()> {
+    // the path resolves to the root of the mounted filesystem
+    let root = VfsPath::new(system_root_fs(), start)
+        .join(target)?
+        .open(None)?
+        .downcast_arc::<RootShimDentry>()
+        .map_err(|_| AlienError::EINVAL)?;
+    let mut mounts = MOUNTS.lock();
+    let index = mounts
+        .iter()
+        .rposition(|entry| {
+            entry.fs_domain_ident == root.fs_domain_ident_str()
+                && entry
+                    .root
+                    .clone()
+                    .downcast_arc::<RootShimDentry>()
+                    .is_ok_and(|r| r.inode_id() == root.inode_id())
+        })
+        .ok_or(AlienError::EINVAL)?;
+    if mounts[index].target == "/" {
+        return Err(AlienError::EBUSY);
+    }
+    if flags & (MNT_FORCE | MNT_DETACH) == 0 && is_busy(&mounts[index].fs_domain_ident) {
+        return Err(AlienError::EBUSY);
+    }
+    let entry = mounts.remove(index);
+    drop(mounts);
+    entry.root.inode()?.get_super_block()?.sync_fs(true)?;
+    entry.mount_point.clear_mount_point();
+    Ok(())
+}
+
+/// Whether a file of the fs domain instance `ident` is open.
+fn is_busy(ident: &str) -> bool {
+    let files: Vec<_> = VFS_MAP.read().values().cloned().collect();
+    files.into_iter().any(|file| {
+        let Ok(file) = file.downcast_arc::<KernelFile>() else {
+            return false;
+        };
+        file.dentry()
+            .downcast_arc::<RootShimDentry>()
+            .is_ok_and(|dentry| dentry.fs_domain_ident_str() == ident)
+    })
+}
+
+/// The statistics of the filesystem that contains `dentry`.
+pub fn do_statfs(dentry: Arc<dyn VfsDentry>) -> AlienResult<VfsFsStat> {
+    let stat = dentry.inode()?.get_super_block()?.stat_fs()?;
+    Ok(stat)
+}
+
+/// The mount table in the format of `/proc/mounts`.
+pub fn mounts_info() -> String {
+    let mut info = String::new();
+    for entry in MOUNTS.lock().iter() {
+        info.push_str(&format!(
+            "{} {} {} rw 0 0\n",
+            entry.source, entry.target, entry.fs_type
+        ));
+    }
+    info
+}
@@ -1,9 +1,13 @@
 use alloc::{
     string::String,
     sync::{Arc, Weak},
+    vec,
     vec::Vec,
 };
-use core::fmt::{Debug, Formatter};
+use core::{
+    cmp::min,
+    fmt::{Debug, Formatter},
+};
 
 use basic::{
     config::PIPE_BUF,
@@ -11,6 +15,7 @@ use basic::{
     sync::Mutex,
     AlienError, AlienResult,
 };
+use ioctl::{ArgKind, Ioctl, IoctlArg, FIONREAD};
 use log::debug;
 use shared_heap::DVec;
 use vfscore::{
@@ -26,7 +31,8 @@ use vfscore::{
     VfsResult,
 };
 
-use crate::kfile::File;
+use crate::{kfile::File, task_domain, user_buf::UserBuffer, wait_queue::WaitQueue};
+
 pub struct PipeFile {
     open_flag: Mutex<OpenFlags>,
     inode_copy: Arc<PipeInode>,
@@ -47,8 +53,51 @@ impl PipeFile {
             inode_copy,
         }
     }
+
+    /// Copy up to `len` bytes from this pipe to `out` without consuming them.
+    ///
+    /// Waits for data unless `nonblock` is set. Returns 0 once there is no writer left.
+    pub fn tee(&self, out: &PipeFile, len: usize, nonblock: bool) -> AlienResult<usize> {
+        if Arc::ptr_eq(&self.inode_copy, &out.inode_copy) {
+            return Err(AlienError::EINVAL);
+        }
+        let data = loop {
+            let data = self.inode_copy.data.lock();
+            if data.available_read() > 0 {
+                break data;
+            }
+            if !data.is_write_wait() {
+                return Ok(0);
+            }
+            if nonblock {
+                return Err(AlienError::EAGAIN);
+            }
+            self.inode_copy.wait_queue.sleep(data)?;
+        };
+        let mut buf = vec![0; min(len, data.available_read())];
+        let count = data.peek(&mut buf);
+        drop(data);
+        if nonblock && out.inode_copy.data.lock().available_write() == 0 {
+            return Err(AlienError::EAGAIN);
+        }
+        out.inode_copy
+            .write_bytes(&buf[..count])
+            .map_err(Into::into)
+    }
+
+    fn unread_bytes(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let available = self.inode_copy.data.lock().available_read();
+        arg.write(&(available as i32))?;
+        Ok(0)
+    }
 }
 
+static PIPE_IOCTLS: &[Ioctl<PipeFile>] = &[Ioctl::with_arg(
+    FIONREAD,
+    ArgKind::output::<i32>(),
+    PipeFile::unread_bytes,
+)];
+
 impl File for PipeFile {
     fn read(&self, buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
         if buf.is_empty() {
@@ -62,11 +111,23 @@ impl File for PipeFile {
         }
         self.inode_copy.write_at(0, buf).map_err(|e| e.into())
     }
-    fn read_at(&self, _offset: u64, buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
-        self.read(buf)
+    fn read_at(&self, _offset: u64, _buf: DVec<u8>) -> AlienResult<(DVec<u8>, usize)> {
+        Err(AlienError::ESPIPE)
     }
-    fn write_at(&self, _offset: u64, buf: &DVec<u8>) -> AlienResult<usize> {
-        self.write(buf)
+    fn write_at(&self, _offset: u64, _buf: &DVec<u8>) -> AlienResult<usize> {
+        Err(AlienError::ESPIPE)
+    }
+    fn read_user(&self, offset: Option<u64>, buf: &UserBuffer) -> AlienResult<usize> {
+        if offset.is_some() {
+            return Err(AlienError::ESPIPE);
+        }
+        self.inode_copy.read_user(buf)
+    }
+    fn write_user(&self, offset: Option<u64>, buf: &UserBuffer) -> AlienResult<usize> {
+        if offset.is_some() {
+            return Err(AlienError::ESPIPE);
+        }
+        self.inode_copy.write_user(buf)
     }
 
     fn flush(&self) -> AlienResult<()> {
@@ -85,6 +146,10 @@ impl File for PipeFile {
         Err(AlienError::ENOSYS)
     }
 
+    fn ioctl(&self, cmd: u32, arg: usize) -> AlienResult<usize> {
+        ioctl::dispatch(PIPE_IOCTLS, self, &**task_domain()?, cmd, arg)
+    }
+
     fn set_open_flag(&self, flag: OpenFlags) {
         *self.open_flag.lock() = flag;
     }
@@ -116,10 +181,24 @@ impl File for PipeFile {
             .map(|e| PollEvents::from_bits_truncate(e.bits() as u32));
         res.map_err(Into::into)
     }
+    fn poll_register(&self, tid: usize) -> AlienResult<bool> {
+        self.inode_copy.wait_queue.register(tid);
+        Ok(true)
+    }
+    fn poll_unregister(&self, tid: usize) -> AlienResult<()> {
+        self.inode_copy.wait_queue.unregister(tid);
+        Ok(())
+    }
+
+    fn poll_wakes(&self) -> AlienResult<Option<usize>> {
+        Ok(Some(self.inode_copy.wait_queue.wakes()))
+    }
 }
 
 pub struct PipeInode {
     data: Mutex<PipeInodeData>,
+    /// 在 读端、写端 以及 poll 中等待的进程
+    wait_queue: WaitQueue,
 }
 
 struct PipeInodeData {
@@ -174,6 +253,15 @@ impl PipeInodeData {
         count
     }
 
+    /// 复制缓冲区中的数据但不移除它们，返回复制的字节数
+    pub fn peek(&self, buf: &mut [u8]) -> usize {
+        let count = min(self.available_read(), buf.len());
+        for (i, byte) in buf[..count].iter_mut().enumerate() {
+            *byte = self.buf[(self.head + i) % PIPE_BUF];
+        }
+        count
+    }
+
     /// 从缓冲区中读取数据，返回读取的字节数
     pub fn read(&mut self, buf: &mut [u8]) -> usize {
         let mut count = 0;
@@ -185,6 +273,27 @@ impl PipeInodeData {
         count
     }
 
+    /// 缓冲区中可以连续读出的数据，到缓冲区末尾回绕为止
+    fn readable_slice(&self) -> &[u8] {
+        if self.head <= self.tail {
+            &self.buf[self.head..self.tail]
+        } else {
+            &self.buf[self.head..]
+        }
+    }
+
+    /// 缓冲区中可以连续写入的空间，到缓冲区末尾回绕为止
+    fn writable_slice(&mut self) -> &mut [u8] {
+        let end = if self.head > self.tail {
+            self.head - 1
+        } else if self.head == 0 {
+            PIPE_BUF - 1
+        } else {
+            PIPE_BUF
+        };
+        &mut self.buf[self.tail..end]
+    }
+
     /// 返回是否有进程在 写端等待
     pub fn is_write_wait(&self) -> bool {
         self.write_wait.is_some() && self.write_wait.as_ref().unwrap().upgrade().is_some()
@@ -207,6 +316,7 @@ impl PipeInode {
                 read_wait: None,
                 write_wait: None,
             }),
+            wait_queue: WaitQueue::new(),
         }
     }
 
@@ -218,6 +328,92 @@ impl PipeInode {
         let mut data = self.data.lock();
         data.write_wait = Some(Arc::downgrade(sender))
     }
+
+    /// 向管道写入数据，在管道满时等待读者，返回写入的字节数
+    pub fn write_bytes(&self, user_buf: &[u8]) -> VfsResult<usize> {
+        debug!("pipe_write: {:?}", user_buf.len());
+        let mut count = 0;
+        loop {
+            let mut buf = self.data.lock();
+            let available = buf.available_write();
+            if available == 0 {
+                if !buf.is_read_wait() {
+                    // if there is no process waiting for reading, we should return
+                    break;
+                }
+                // release lock and wait for reading
+                self.wait_queue.sleep(buf)?;
+            } else {
+                let min = core::cmp::min(available, user_buf.len() - count);
+                debug!("pipe_write: min:{}, count:{}", min, count);
+                count += buf.write(&user_buf[count..count + min]);
+                drop(buf);
+                // wake up the readers
+                self.wait_queue.wake_all()?;
+                break;
+            }
+        }
+        debug!("pipe_write: count:{}", count);
+        Ok(count)
+    }
+
+    /// 从管道读取数据直接写入用户缓冲区，返回读取的字节数
+    pub fn read_user(&self, user_buf: &UserBuffer) -> AlienResult<usize> {
+        let mut count = 0;
+        loop {
+            let mut buf = self.data.lock();
+            if buf.available_read() == 0 {
+                if !buf.is_write_wait() {
+                    break;
+                }
+                self.wait_queue.sleep(buf)?;
+                continue;
+            }
+            while count < user_buf.len() {
+                let data = buf.readable_slice();
+                if data.is_empty() {
+                    break;
+                }
+                let len = min(data.len(), user_buf.len() - count);
+                user_buf.write(count, &data[..len])?;
+                buf.head = (buf.head + len) % PIPE_BUF;
+                count += len;
+            }
+            drop(buf);
+            self.wait_queue.wake_all()?;
+            break;
+        }
+        Ok(count)
+    }
+
+    /// 从用户缓冲区直接写入管道，在管道满时等待读者，返回写入的字节数
+    pub fn write_user(&self, user_buf: &UserBuffer) -> AlienResult<usize> {
+        let mut count = 0;
+        loop {
+            let mut buf = self.data.lock();
+            if buf.available_write() == 0 {
+                if !buf.is_read_wait() {
+                    break;
+                }
+                self.wait_queue.sleep(buf)?;
+                continue;
+            }
+            while count < user_buf.len() {
+                let space = buf.writable_slice();
+                if space.is_empty() {
+                    break;
+                }
+                let len = min(space.len(), user_buf.len() - count);
+                user_buf.read(count, &mut space[..len])?;
+                buf.tail = (buf.tail + len) % PIPE_BUF;
+                count += len;
+            }
+            drop(buf);
+            self.wait_queue.wake_all()?;
+            break;
+        }
+        Ok(count)
+    }
 }
 
 impl VfsFile for PipeInode {
@@ -234,13 +430,15 @@ impl VfsFile for PipeInode {
                     break;
                 } else {
                     // wait for writing
-                    drop(buf);
-                    basic::yield_now().unwrap();
                     debug!("pipe_read: suspend");
+                    self.wait_queue.sleep(buf)?;
                 }
             } else {
                 let min = core::cmp::min(available, user_buf.len() - count);
                 count += buf.read(&mut user_buf.as_mut_slice()[count..count + min]);
+                drop(buf);
+                // wake up the writers
+                self.wait_queue.wake_all()?;
                 break;
             }
         }
@@ -248,29 +446,7 @@ impl VfsFile for PipeInode {
         Ok((user_buf, count))
     }
     fn write_at(&self, _offset: u64, user_buf: &DVec<u8>) -> VfsResult<usize> {
-        debug!("pipe_write: {:?}", user_buf.len());
-        let mut count = 0;
-        loop {
-            let mut buf = self.data.lock();
-            let available = buf.available_write();
-            if available == 0 {
-                if !buf.is_read_wait() {
-                    // if there is no process waiting for reading, we should return
-                    break;
-                }
-                // release lock
-                drop(buf);
-                // wait for reading
-                basic::yield_now().unwrap();
-            } else {
-                let min = core::cmp::min(available, user_buf.len() - count);
-                debug!("pipe_write: min:{}, count:{}", min, count);
-                count += buf.write(&user_buf.as_slice()[count..count + min]);
-                break;
-            }
-        }
-        debug!("pipe_write: count:{}", count);
-        Ok(count)
+        self.write_bytes(user_buf.as_slice())
     }
     fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
         let data = self.data.lock();
@@ -333,7 +509,11 @@ impl Drop for PipeFile {
         let data = self.inode_copy.data.lock();
         let is_reader = data.is_read_wait();
         let is_sender = data.is_write_wait();
-        if !is_reader && !is_sender {}
+        drop(data);
+        if is_reader || is_sender {
+            // the other end sees EOF or a broken pipe now
+            let _ = self.inode_copy.wait_queue.wake_all();
+        }
     }
 }
 
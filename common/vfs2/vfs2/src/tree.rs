@@ -9,7 +9,7 @@ use vfs_common::meta::KernelFileMeta;
 use vfscore::{dentry::VfsDentry, path::VfsPath, VfsResult};
 
 use crate::{
-    devfs, insert_dentry, kfile::KernelFile, pipefs, procfs, ramfs::init_ramfs,
+    devfs, insert_dentry, kfile::KernelFile, mount, pipefs, procfs, ramfs::init_ramfs,
     shim::RootShimDentry, sys, VFS_MAP, VFS_MAP_SHADOW,
 };
 
@@ -79,12 +79,13 @@ fn init_filesystem_before(initrd: &[u8]) -> VfsResult<Arc<dyn VfsDentry>> {
     let shm_ramfs_root = common_load_or_create_fs(true, "ramfs", b"/dev/shm", false);
     let domain_fs_root = common_load_or_create_fs(false, "domainfs", b"/domain", false);
     let path = VfsPath::new(ramfs_root.clone(), ramfs_root.clone());
-    path.join("proc")?.mount(procfs_root, 0)?;
-    path.join("sys")?.mount(sysfs_root, 0)?;
-    path.join("dev")?.mount(devfs_root, 0)?;
-    path.join("tmp")?.mount(tmpfs_root.clone(), 0)?;
-    path.join("dev/shm")?.mount(shm_ramfs_root, 0)?;
-    path.join("domain")?.mount(domain_fs_root, 0)?;
+    mount::record_root(&ramfs_root, "ramfs");
+    mount::mount_initial(&path, "proc", procfs_root, "proc", "procfs")?;
+    mount::mount_initial(&path, "sys", sysfs_root, "sysfs", "sysfs")?;
+    mount::mount_initial(&path, "dev", devfs_root, "devfs", "devfs")?;
+    mount::mount_initial(&path, "tmp", tmpfs_root.clone(), "tmpfs", "tmpfs")?;
+    mount::mount_initial(&path, "dev/shm", shm_ramfs_root, "tmpfs", "tmpfs")?;
+    mount::mount_initial(&path, "domain", domain_fs_root, "domainfs", "domainfs")?;
 
     crate::initrd::populate_initrd(ramfs_root.clone(), initrd)?;
 
@@ -135,7 +136,7 @@ fn init_filesystem_before(initrd: &[u8]) -> VfsResult<Arc<dyn VfsDentry>> {
             let root_inode_id = fatfs.mount(&mp, Some(DBox::new(info))).unwrap();
             let shim_inode =
                 RootShimDentry::new(fatfs, root_inode_id, Arc::new(Vec::from("fatfs-1")));
-            path.join("tests")?.mount(shim_inode, 0)?;
+            mount::mount_initial(&path, "tests", shim_inode, "/dev/sda", "fatfs")?;
         }
         _ => panic!("fatfs domain not found"),
     }
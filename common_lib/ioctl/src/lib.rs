@@ -0,0 +1,406 @@
+//! Typed `ioctl` commands and the tables devices declare them in.
+//!
+//! A command number holds, from the low bits up, its number within its type (8 bits), the
+//! type (8 bits), the size of the argument (14 bits) and the direction of the copy (2
+//! bits), as in `asm-generic/ioctl.h`. Each device lists the commands it supports in a
+//! table of [`Ioctl`]s. [`dispatch`] looks the command up, copies the argument in from
+//! user memory as far as the command says, runs the handler and copies the argument back
+//! out. Commands missing from the table fail with `ENOTTY`.
+//!
+//! Older commands such as `TCGETS` or `FIONREAD` predate the encoding and have a size of
+//! 0, so their entries give the argument type themselves.
+#![no_std]
+#![forbid(unsafe_code)]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{Debug, Formatter},
+    mem::size_of,
+};
+
+use basic::{AlienError, AlienResult};
+use interface::TaskDomain;
+use pod::Pod;
+
+const NR_BITS: u32 = 8;
+const TYPE_BITS: u32 = 8;
+const SIZE_BITS: u32 = 14;
+const DIR_BITS: u32 = 2;
+
+const NR_SHIFT: u32 = 0;
+const TYPE_SHIFT: u32 = NR_SHIFT + NR_BITS;
+const SIZE_SHIFT: u32 = TYPE_SHIFT + TYPE_BITS;
+const DIR_SHIFT: u32 = SIZE_SHIFT + SIZE_BITS;
+
+/// The argument is not copied
+pub const IOC_NONE: u32 = 0;
+/// The kernel reads the argument from the user
+pub const IOC_WRITE: u32 = 1;
+/// The kernel writes the argument to the user
+pub const IOC_READ: u32 = 2;
+
+pub const fn ioc(dir: u32, ty: u8, nr: u8, size: usize) -> u32 {
+    (dir << DIR_SHIFT)
+        | ((size as u32) << SIZE_SHIFT)
+        | ((ty as u32) << TYPE_SHIFT)
+        | ((nr as u32) << NR_SHIFT)
+}
+
+pub const fn io(ty: u8, nr: u8) -> u32 {
+    ioc(IOC_NONE, ty, nr, 0)
+}
+
+pub const fn ior(ty: u8, nr: u8, size: usize) -> u32 {
+    ioc(IOC_READ, ty, nr, size)
+}
+
+pub const fn iow(ty: u8, nr: u8, size: usize) -> u32 {
+    ioc(IOC_WRITE, ty, nr, size)
+}
+
+pub const fn iowr(ty: u8, nr: u8, size: usize) -> u32 {
+    ioc(IOC_READ | IOC_WRITE, ty, nr, size)
+}
+
+/// The number of bytes that can be read without blocking
+pub const FIONREAD: u32 = 0x541b;
+/// Set or clear `O_NONBLOCK`
+pub const FIONBIO: u32 = 0x5421;
+/// Make the terminal the controlling terminal of the session of the caller
+pub const TIOCSCTTY: u32 = 0x540e;
+/// The logical sector size of a block device
+pub const BLKSSZGET: u32 = io(0x12, 104);
+/// The size of a block device in bytes
+pub const BLKGETSIZE64: u32 = ior(0x12, 114, size_of::<u64>());
+
+/// A command number split into its fields
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct IoctlCmd(pub u32);
+
+impl IoctlCmd {
+    pub const fn dir(self) -> u32 {
+        (self.0 >> DIR_SHIFT) & ((1 << DIR_BITS) - 1)
+    }
+
+    pub const fn size(self) -> usize {
+        ((self.0 >> SIZE_SHIFT) & ((1 << SIZE_BITS) - 1)) as usize
+    }
+
+    pub const fn ty(self) -> u8 {
+        (self.0 >> TYPE_SHIFT) as u8
+    }
+
+    pub const fn nr(self) -> u8 {
+        (self.0 >> NR_SHIFT) as u8
+    }
+}
+
+impl Debug for IoctlCmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IoctlCmd")
+            .field("cmd", &format_args!("{:#x}", self.0))
+            .field("dir", &self.dir())
+            .field("size", &self.size())
+            .field("ty", &format_args!("{:#x}", self.ty()))
+            .field("nr", &self.nr())
+            .finish()
+    }
+}
+
+/// How the argument of a command is passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// A plain value, or no argument at all
+    Value,
+    /// A pointer to this many bytes that the kernel reads
+    In(usize),
+    /// A pointer to this many bytes that the kernel writes
+    Out(usize),
+    /// A pointer to this many bytes that the kernel reads and writes back
+    InOut(usize),
+}
+
+impl ArgKind {
+    /// The argument that the direction and size encoded in `cmd` describe.
+    pub const fn of(cmd: u32) -> Self {
+        let cmd = IoctlCmd(cmd);
+        match cmd.dir() {
+            IOC_WRITE => Self::In(cmd.size()),
+            IOC_READ => Self::Out(cmd.size()),
+            IOC_NONE => Self::Value,
+            _ => Self::InOut(cmd.size()),
+        }
+    }
+
+    pub const fn input<T>() -> Self {
+        Self::In(size_of::<T>())
+    }
+
+    pub const fn output<T>() -> Self {
+        Self::Out(size_of::<T>())
+    }
+
+    const fn size(self) -> usize {
+        match self {
+            Self::Value => 0,
+            Self::In(size) | Self::Out(size) | Self::InOut(size) => size,
+        }
+    }
+
+    const fn copies_in(self) -> bool {
+        matches!(self, Self::In(_) | Self::InOut(_))
+    }
+
+    const fn copies_out(self) -> bool {
+        matches!(self, Self::Out(_) | Self::InOut(_))
+    }
+}
+
+/// The argument of a command while its handler runs
+#[derive(Debug)]
+pub struct IoctlArg {
+    raw: usize,
+    buf: Vec<u8>,
+}
+
+impl IoctlArg {
+    /// The argument as passed, for commands that take a plain value.
+    pub fn value(&self) -> usize {
+        self.raw
+    }
+
+    /// The argument copied in from the user.
+    pub fn read<T: Pod>(&self) -> AlienResult<T> {
+        if self.buf.len() != size_of::<T>() {
+            return Err(AlienError::EINVAL);
+        }
+        let mut val = T::new_zeroed();
+        val.as_bytes_mut().copy_from_slice(&self.buf);
+        Ok(val)
+    }
+
+    /// Set the argument that is copied out to the user.
+    pub fn write<T: Pod>(&mut self, val: &T) -> AlienResult<()> {
+        if self.buf.len() != size_of::<T>() {
+            return Err(AlienError::EINVAL);
+        }
+        self.buf.copy_from_slice(val.as_bytes());
+        Ok(())
+    }
+}
+
+/// A command that a device of type `D` supports
+pub struct Ioctl<D: ?Sized> {
+    pub cmd: u32,
+    pub arg: ArgKind,
+    pub handler: fn(&D, &mut IoctlArg) -> AlienResult<usize>,
+}
+
+impl<D: ?Sized> Ioctl<D> {
+    /// A command with the argument its number describes.
+    pub const fn new(cmd: u32, handler: fn(&D, &mut IoctlArg) -> AlienResult<usize>) -> Self {
+        Self::with_arg(cmd, ArgKind::of(cmd), handler)
+    }
+
+    pub const fn with_arg(
+        cmd: u32,
+        arg: ArgKind,
+        handler: fn(&D, &mut IoctlArg) -> AlienResult<usize>,
+    ) -> Self {
+        Self { cmd, arg, handler }
+    }
+
+    /// Run the command on `dev` with the user argument `arg`. The argument is only
+    /// copied out if the handler succeeds.
+    pub fn call(&self, dev: &D, task_domain: &dyn TaskDomain, arg: usize) -> AlienResult<usize> {
+        self.call_with(
+            dev,
+            arg,
+            |buf| task_domain.copy_from_user(arg, buf),
+            |buf| task_domain.copy_to_user(arg, buf),
+        )
+    }
+
+    /// [`Ioctl::call`] with the copies from and to the user done by `copy_in` and
+    /// `copy_out`, which get as many bytes as the argument of the command has.
+    fn call_with(
+        &self,
+        dev: &D,
+        arg: usize,
+        copy_in: impl FnOnce(&mut [u8]) -> AlienResult<()>,
+        copy_out: impl FnOnce(&[u8]) -> AlienResult<()>,
+    ) -> AlienResult<usize> {
+        let mut ioarg = IoctlArg {
+            raw: arg,
+            buf: vec![0; self.arg.size()],
+        };
+        if self.arg.copies_in() {
+            copy_in(&mut ioarg.buf)?;
+        }
+        let res = (self.handler)(dev, &mut ioarg)?;
+        if self.arg.copies_out() {
+            copy_out(&ioarg.buf)?;
+        }
+        Ok(res)
+    }
+}
+
+pub fn find<D: ?Sized>(table: &[Ioctl<D>], cmd: u32) -> Option<&Ioctl<D>> {
+    table.iter().find(|ioctl| ioctl.cmd == cmd)
+}
+
+/// Run `cmd` on `dev` if `table` has it, or fail with `ENOTTY`.
+pub fn dispatch<D: ?Sized>(
+    table: &[Ioctl<D>],
+    dev: &D,
+    task_domain: &dyn TaskDomain,
+    cmd: u32,
+    arg: usize,
+) -> AlienResult<usize> {
+    let ioctl = find(table, cmd).ok_or(AlienError::ENOTTY)?;
+    ioctl.call(dev, task_domain, arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    use super::*;
+
+    #[test]
+    fn command_numbers_match_linux() {
+        assert_eq!(BLKGETSIZE64, 0x8008_1272);
+        assert_eq!(BLKSSZGET, 0x1268);
+        assert_eq!(FIONREAD, 0x541b);
+        assert_eq!(FIONBIO, 0x5421);
+    }
+
+    #[test]
+    fn commands_decode_into_their_fields() {
+        let cmd = IoctlCmd(BLKGETSIZE64);
+        assert_eq!(cmd.dir(), IOC_READ);
+        assert_eq!(cmd.size(), 8);
+        assert_eq!(cmd.ty(), 0x12);
+        assert_eq!(cmd.nr(), 114);
+        assert_eq!(ArgKind::of(BLKGETSIZE64), ArgKind::Out(8));
+
+        let cmd = IoctlCmd(BLKSSZGET);
+        assert_eq!((cmd.dir(), cmd.size()), (IOC_NONE, 0));
+        assert_eq!((cmd.ty(), cmd.nr()), (0x12, 104));
+        assert_eq!(ArgKind::of(BLKSSZGET), ArgKind::Value);
+
+        // the legacy numbers have no size, their table entries give it
+        assert_eq!(IoctlCmd(FIONREAD).size(), 0);
+        assert_eq!(ArgKind::of(FIONREAD), ArgKind::Value);
+
+        assert_eq!(ArgKind::of(iow(b'x', 1, 4)), ArgKind::In(4));
+        assert_eq!(ArgKind::of(iowr(b'x', 2, 16)), ArgKind::InOut(16));
+    }
+
+    #[test]
+    fn arg_checks_the_size() {
+        let mut arg = IoctlArg {
+            raw: 0,
+            buf: vec![0; 4],
+        };
+        assert!(matches!(arg.read::<u64>(), Err(AlienError::EINVAL)));
+        assert!(matches!(arg.write(&1u64), Err(AlienError::EINVAL)));
+        arg.write(&0x1234_5678u32).unwrap();
+        assert_eq!(arg.read::<u32>().unwrap(), 0x1234_5678);
+    }
+
+    struct Disk {
+        size: u64,
+        /// The value the last `SET_LIMIT` got
+        limit: RefCell<u32>,
+    }
+
+    const SET_LIMIT: u32 = iow(b'x', 1, size_of::<u32>());
+    const SCALE: u32 = iowr(b'x', 2, size_of::<u64>());
+
+    static DISK_IOCTLS: &[Ioctl<Disk>] = &[
+        Ioctl::new(BLKGETSIZE64, |disk, arg| {
+            arg.write(&disk.size)?;
+            Ok(0)
+        }),
+        Ioctl::new(BLKSSZGET, |_, _| Ok(512)),
+        Ioctl::with_arg(FIONREAD, ArgKind::output::<i32>(), |_, _| {
+            Err(AlienError::EIO)
+        }),
+        Ioctl::new(SET_LIMIT, |disk, arg| {
+            *disk.limit.borrow_mut() = arg.read()?;
+            Ok(0)
+        }),
+        Ioctl::new(SCALE, |_, arg| {
+            let val: u64 = arg.read()?;
+            arg.write(&(val * 3))?;
+            Ok(0)
+        }),
+    ];
+
+    /// Run `cmd` on `disk` with `user` as the user memory the argument points to.
+    /// Returns the result and the bytes copied in and out.
+    fn run(disk: &Disk, cmd: u32, user: &mut Vec<u8>) -> (AlienResult<usize>, usize, usize) {
+        let ioctl = find(DISK_IOCTLS, cmd).unwrap();
+        let copied_in = RefCell::new(0);
+        let copied_out = RefCell::new(None);
+        let res = ioctl.call_with(
+            disk,
+            0x1000,
+            |buf| {
+                *copied_in.borrow_mut() = buf.len();
+                buf.copy_from_slice(&user[..buf.len()]);
+                Ok(())
+            },
+            |buf| {
+                *copied_out.borrow_mut() = Some(buf.to_vec());
+                Ok(())
+            },
+        );
+        let out = copied_out.into_inner().unwrap_or_default();
+        user[..out.len()].copy_from_slice(&out);
+        (res, copied_in.into_inner(), out.len())
+    }
+
+    #[test]
+    fn copies_follow_the_argument_size() {
+        let disk = Disk {
+            size: 0x1_0000_0200,
+            limit: RefCell::new(0),
+        };
+        let mut user = vec![0xff; 16];
+        let (res, copied_in, copied_out) = run(&disk, BLKGETSIZE64, &mut user);
+        assert_eq!((res.unwrap(), copied_in, copied_out), (0, 0, 8));
+        assert_eq!(&user[..8], &0x1_0000_0200u64.to_ne_bytes());
+        assert_eq!(&user[8..], &[0xff; 8]);
+
+        let (res, copied_in, copied_out) = run(&disk, BLKSSZGET, &mut user);
+        assert_eq!((res.unwrap(), copied_in, copied_out), (512, 0, 0));
+
+        user[..4].copy_from_slice(&7u32.to_ne_bytes());
+        let (res, copied_in, copied_out) = run(&disk, SET_LIMIT, &mut user);
+        assert_eq!((res.unwrap(), copied_in, copied_out), (0, 4, 0));
+        assert_eq!(*disk.limit.borrow(), 7);
+
+        user[..8].copy_from_slice(&5u64.to_ne_bytes());
+        let (res, copied_in, copied_out) = run(&disk, SCALE, &mut user);
+        assert_eq!((res.unwrap(), copied_in, copied_out), (0, 8, 8));
+        assert_eq!(&user[..8], &15u64.to_ne_bytes());
+    }
+
+    #[test]
+    fn failed_commands_copy_nothing_out() {
+        let disk = Disk {
+            size: 0,
+            limit: RefCell::new(0),
+        };
+        let mut user = vec![0xff; 4];
+        let (res, _, copied_out) = run(&disk, FIONREAD, &mut user);
+        assert!(matches!(res, Err(AlienError::EIO)));
+        assert_eq!(copied_out, 0);
+        assert_eq!(user, [0xff; 4]);
+    }
+}
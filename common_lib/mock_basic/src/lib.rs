@@ -0,0 +1,135 @@
+//! Host "mock kernel" for unit tests of domain library crates.
+//!
+//! Everything in `basic` that does not need the kernel is re-exported unchanged. The
+//! kernel services domains use are replaced by in-process implementations:
+//!
+//! - `get_domain` returns domains registered with [`register_mock_domain`]
+//! - `current_tid` returns the tid set with [`set_current_tid`], by default every test
+//!   thread acts as a task of its own
+//! - `wait_now`/`wake_up_wait_task` park and unpark the thread acting as the task
+//! - `time::read_time_ms` counts from the first call
+//!
+//! `cargo domain test` links library crates against this crate instead of the real
+//! `basic`.
+
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread::Thread,
+};
+
+use interface::DomainType;
+pub use real_basic::*;
+
+static DOMAINS: Mutex<BTreeMap<String, DomainType>> = Mutex::new(BTreeMap::new());
+static WAIT_QUEUE: Mutex<WaitQueue> = Mutex::new(WaitQueue {
+    waiting: BTreeMap::new(),
+    woken: BTreeSet::new(),
+});
+
+struct WaitQueue {
+    /// Tasks blocked in `wait_now`
+    waiting: BTreeMap<usize, Thread>,
+    /// Tasks woken up before they waited
+    woken: BTreeSet<usize>,
+}
+
+/// Tids of test threads that did not set one, above those tests pick
+static NEXT_TID: AtomicUsize = AtomicUsize::new(1 << 20);
+
+thread_local! {
+    static CURRENT_TID: Cell<Option<usize>> =
+        Cell::new(Some(NEXT_TID.fetch_add(1, Ordering::Relaxed)));
+}
+
+/// Make `domain` available to `get_domain(name)`.
+pub fn register_mock_domain(name: &str, domain: DomainType) {
+    DOMAINS.lock().unwrap().insert(name.to_string(), domain);
+}
+
+pub fn get_domain(name: &str) -> Option<DomainType> {
+    DOMAINS.lock().unwrap().get(name).cloned()
+}
+
+/// Make the current test thread act as task `tid`.
+pub fn set_current_tid(tid: Option<usize>) {
+    CURRENT_TID.with(|cell| cell.set(tid));
+}
+
+pub fn current_tid() -> AlienResult<Option<usize>> {
+    Ok(CURRENT_TID.with(|cell| cell.get()))
+}
+
+pub fn yield_now() -> AlienResult<()> {
+    std::thread::yield_now();
+    Ok(())
+}
+
+/// Block the current task until `wake_up_wait_task` is called with its tid.
+///
+/// A wakeup that arrives before the task waits is not lost. A thread that acts as no
+/// task cannot be woken, so it does not wait.
+pub fn wait_now() -> AlienResult<()> {
+    let Some(tid) = current_tid()? else {
+        return Err(AlienError::EINVAL);
+    };
+    let mut queue = WAIT_QUEUE.lock().unwrap();
+    if queue.woken.remove(&tid) {
+        return Ok(());
+    }
+    queue.waiting.insert(tid, std::thread::current());
+    drop(queue);
+    // park may return spuriously, the tid stays in `waiting` until it is woken
+    while WAIT_QUEUE.lock().unwrap().waiting.contains_key(&tid) {
+        std::thread::park();
+    }
+    Ok(())
+}
+
+pub fn wake_up_wait_task(tid: usize) -> AlienResult<()> {
+    let mut queue = WAIT_QUEUE.lock().unwrap();
+    match queue.waiting.remove(&tid) {
+        Some(thread) => thread.unpark(),
+        None => {
+            queue.woken.insert(tid);
+        }
+    }
+    Ok(())
+}
+
+pub mod time {
+    use std::{sync::OnceLock, time::Instant};
+
+    pub use real_basic::time::*;
+
+    static BOOT: OnceLock<Instant> = OnceLock::new();
+
+    /// Milliseconds since the first call, standing in for the time since boot.
+    pub fn read_time_ms() -> u64 {
+        BOOT.get_or_init(Instant::now).elapsed().as_millis() as u64
+    }
+}
+
+/// Domain crates are `no_std`, so the macros cannot expand to `std::println!`.
+#[doc(hidden)]
+pub fn __print(args: core::fmt::Arguments) {
+    std::println!("{}", args);
+}
+
+#[macro_export]
+macro_rules! println {
+    ($($arg:tt)*) => {
+        $crate::__print(format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! println_color {
+    ($color:expr, $($arg:tt)*) => {
+        $crate::__print(format_args!($($arg)*))
+    };
+}
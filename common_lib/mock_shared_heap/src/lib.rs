@@ -0,0 +1,159 @@
+//! Host implementation of the shared heap.
+//!
+//! `DVec` and `DBox` are backed by the host allocator, and the domain id is a
+//! per-thread value so a test can act as different domains.
+
+use std::{
+    cell::Cell,
+    fmt::{Debug, Formatter},
+    ops::{Deref, DerefMut},
+};
+
+thread_local! {
+    static DOMAIN_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The id of the domain the current test thread acts as.
+pub fn domain_id() -> u64 {
+    DOMAIN_ID.with(|id| id.get())
+}
+
+/// Make the current test thread act as domain `id`.
+pub fn set_domain_id(id: u64) {
+    DOMAIN_ID.with(|cell| cell.set(id));
+}
+
+pub struct DBox<T> {
+    value: Box<T>,
+}
+
+impl<T> DBox<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: Box::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        *self.value
+    }
+}
+
+impl<T: Default> DBox<T> {
+    /// The kernel hands out uninitialized memory, the host a default value.
+    pub fn new_uninit() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Deref for DBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for DBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: Debug> Debug for DBox<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+pub struct DVec<T> {
+    data: Data<T>,
+}
+
+enum Data<T> {
+    Owned(Vec<T>),
+    /// Memory of the caller, see [`DVec::from_other_rvec_slice`]
+    Borrowed(*mut T, usize),
+}
+
+// like the kernel heap, a borrowed vector is only used while the memory it aliases lives
+unsafe impl<T: Send> Send for Data<T> {}
+unsafe impl<T: Sync> Sync for Data<T> {}
+
+impl<T: Clone> DVec<T> {
+    /// A vector of `len` copies of `value`.
+    pub fn new(value: T, len: usize) -> Self {
+        Self {
+            data: Data::Owned(vec![value; len]),
+        }
+    }
+
+    pub fn from_slice(slice: &[T]) -> Self {
+        Self {
+            data: Data::Owned(slice.to_vec()),
+        }
+    }
+
+    /// A vector that aliases `slice` instead of copying it, as in the kernel. Whoever gets
+    /// the vector may write to the memory of `slice`, so it is borrowed mutably.
+    pub fn from_other_rvec_slice(slice: &mut [T]) -> Self {
+        Self {
+            data: Data::Borrowed(slice.as_mut_ptr(), slice.len()),
+        }
+    }
+}
+
+impl<T: Clone + Default> DVec<T> {
+    /// The kernel hands out uninitialized memory, the host default values.
+    pub fn new_uninit(len: usize) -> Self {
+        Self::new(T::default(), len)
+    }
+}
+
+impl<T> DVec<T> {
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        match &self.data {
+            Data::Owned(data) => data,
+            Data::Borrowed(ptr, len) => unsafe { std::slice::from_raw_parts(*ptr, *len) },
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match &mut self.data {
+            Data::Owned(data) => data,
+            Data::Borrowed(ptr, len) => unsafe { std::slice::from_raw_parts_mut(*ptr, *len) },
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+}
+
+impl<T> Deref for DVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for DVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: Debug> Debug for DVec<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
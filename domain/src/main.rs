@@ -1,6 +1,10 @@
 mod subcommand;
 
 use clap::{Parser, Subcommand};
+use subcommand::{
+    graph::GraphFormat,
+    new::{DomainType, Placement},
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -16,6 +20,15 @@ enum Commands {
         /// The name of the domain project
         #[arg(short, long, value_name = "NAME")]
         name: String,
+        /// The domain type
+        #[arg(short, long, value_name = "TYPE")]
+        r#type: DomainType,
+        /// The domain interface name, e.g. BlkDeviceDomain
+        #[arg(short, long, value_name = "INTERFACE")]
+        interface: String,
+        /// Whether the domain is loaded at boot or from the disk
+        #[arg(short, long, value_name = "PLACEMENT", default_value = "init")]
+        placement: Placement,
     },
     /// Build a domain project
     Build {
@@ -28,6 +41,15 @@ enum Commands {
         /// The output directory
         #[arg(short, long, value_name = "OUTPUT", default_value = "./build")]
         output: String,
+        /// Rebuild even if the domain is up to date
+        #[arg(long)]
+        force: bool,
+        /// Comma separated list of features, overrides the features in domain-list.toml
+        #[arg(short = 'F', long, value_name = "FEATURES", value_delimiter = ',')]
+        features: Option<Vec<String>>,
+        /// The cargo profile, overrides the profile in domain-list.toml
+        #[arg(short, long, value_name = "PROFILE")]
+        profile: Option<String>,
     },
     /// Build all domain projects
     BuildAll {
@@ -37,43 +59,177 @@ enum Commands {
         /// The output directory
         #[arg(short, long, value_name = "OUTPUT", default_value = "./build")]
         output: String,
+        /// The number of domains to build in parallel, default is the number of CPUs
+        #[arg(short, long, value_name = "JOBS")]
+        jobs: Option<usize>,
+        /// Rebuild all domains even if they are up to date
+        #[arg(long)]
+        force: bool,
+    },
+    /// Package all built domains and their metadata into one archive
+    Package {
+        /// The output directory of the build
+        #[arg(short, long, value_name = "OUTPUT", default_value = "./build")]
+        output: String,
+        /// The archive to write, default is {OUTPUT}/domains.tar
+        #[arg(short, long, value_name = "ARCHIVE")]
+        archive: Option<String>,
+    },
+    /// Report the section sizes and the largest symbols of all built domains
+    Size {
+        /// The output directory of the build
+        #[arg(short, long, value_name = "OUTPUT", default_value = "./build")]
+        output: String,
+        /// The number of largest crates and symbols to show per domain
+        #[arg(short = 'n', long, value_name = "TOP", default_value_t = 10)]
+        top: usize,
+        /// A manifest.json of a previous build to compare the section sizes against
+        #[arg(short, long, value_name = "MANIFEST")]
+        diff: Option<String>,
+    },
+    /// Print the dependency graph of all domains and check the init order
+    Graph {
+        /// The output format
+        #[arg(short, long, value_name = "FORMAT", default_value = "dot")]
+        format: GraphFormat,
+    },
+    /// Check domain-list.toml against the domain projects on disk
+    Check,
+    /// Run the unit tests of a domain library crate on the host
+    Test {
+        /// The name of the domain project or of a crate in common_lib, default is all
+        /// domains
+        #[arg(short, long, value_name = "NAME", default_value = "")]
+        name: String,
+        /// Arguments passed on to the test binaries, e.g. `-- --ignored`
+        #[arg(last = true)]
+        args: Vec<String>,
     },
     /// Clean a domain project
     Clean {
         /// The name of the domain project
         #[arg(short, long, value_name = "NAME", default_value = "")]
         name: String,
+        /// Clean all domain projects of this type
+        #[arg(short, long, value_name = "TYPE", conflicts_with = "name")]
+        r#type: Option<DomainType>,
+        /// Clean every domain project on disk, also those not in domain-list.toml, and remove ./target and ./build
+        #[arg(short, long, conflicts_with_all = ["name", "type"])]
+        all: bool,
+        /// Only remove the built artifacts of domains no longer in init_members or disk_members
+        #[arg(long, conflicts_with_all = ["name", "type", "all"])]
+        stale: bool,
+        /// The output directory of the build, used with --stale
+        #[arg(short, long, value_name = "OUTPUT", default_value = "./build")]
+        output: String,
     },
     /// Format a domain project
     Fmt {
         /// The name of the domain project
         #[arg(short, long, value_name = "NAME", default_value = "")]
         name: String,
+        /// Format all domain projects of this type
+        #[arg(short, long, value_name = "TYPE", conflicts_with = "name")]
+        r#type: Option<DomainType>,
+        /// Format every domain project on disk, also those not in domain-list.toml
+        #[arg(short, long, conflicts_with_all = ["name", "type"])]
+        all: bool,
+        /// List unformatted files and fail if there are any, without formatting them
+        #[arg(long)]
+        check: bool,
     },
 }
 
 fn main() {
     let cli = Cli::parse();
     match &cli.command {
-        Some(Commands::New { name }) => {
+        Some(Commands::New {
+            name,
+            r#type,
+            interface,
+            placement,
+        }) => {
             println!("Creating new domain project: {}", name);
-            subcommand::new::create_domain(name);
+            subcommand::new::create_domain(name, *r#type, interface, *placement);
         }
-        Some(Commands::BuildAll { log, output }) => {
-            println!("Building all domain projects, LOG: {log}");
-            subcommand::build::build_all(log.to_string(), output);
+        Some(Commands::BuildAll {
+            log,
+            output,
+            jobs,
+            force,
+        }) => {
+            let jobs = jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1)
+            });
+            println!("Building all domain projects, LOG: {log}, JOBS: {jobs}");
+            subcommand::build::build_all(log.to_string(), output, jobs, *force);
         }
-        Some(Commands::Build { name, log, output }) => {
+        Some(Commands::Build {
+            name,
+            log,
+            output,
+            force,
+            features,
+            profile,
+        }) => {
             println!("Building domain project: {}, LOG: {}", name, log);
-            subcommand::build::build_single(name, log, output);
+            subcommand::build::build_single(
+                name,
+                log,
+                output,
+                *force,
+                features.clone(),
+                profile.clone(),
+            );
+        }
+        Some(Commands::Package { output, archive }) => {
+            let archive = archive
+                .clone()
+                .unwrap_or_else(|| format!("{}/domains.tar", output));
+            println!("Packaging domain projects into {}", archive);
+            subcommand::package::package_domains(output, &archive);
+        }
+        Some(Commands::Size { output, top, diff }) => {
+            println!("Reporting sizes of built domains in {}", output);
+            subcommand::size::size_report(output, *top, diff.as_deref());
+        }
+        Some(Commands::Graph { format }) => {
+            eprintln!("Analysing domain dependencies");
+            subcommand::graph::domain_graph(*format);
+        }
+        Some(Commands::Check) => {
+            println!("Checking domain list");
+            subcommand::check::check_domain_list();
+        }
+        Some(Commands::Test { name, args }) => {
+            println!("Testing domain project: {}", name);
+            subcommand::host_test::test_domain(name, args);
         }
-        Some(Commands::Clean { name }) => {
-            println!("Cleaning domain project: {}", name);
-            subcommand::clean::clean_domain(name.to_string());
+        Some(Commands::Clean {
+            name,
+            r#type,
+            all,
+            stale,
+            output,
+        }) => {
+            if *stale {
+                println!("Cleaning stale artifacts in {}", output);
+                subcommand::clean::clean_stale(output);
+            } else {
+                println!("Cleaning domain project: {}", name);
+                subcommand::clean::clean_domain(name.to_string(), *r#type, *all);
+            }
         }
-        Some(Commands::Fmt { name }) => {
+        Some(Commands::Fmt {
+            name,
+            r#type,
+            all,
+            check,
+        }) => {
             println!("Formatting domain project: {}", name);
-            subcommand::fmt::fmt_domain(name.to_string());
+            subcommand::fmt::fmt_domain(name.to_string(), *r#type, *all, *check);
         }
         None => {}
     }
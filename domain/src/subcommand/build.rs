@@ -1,6 +1,25 @@
-use std::{fs, path::Path};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{Display, Formatter},
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Output,
+    sync::Mutex,
+    thread,
+};
 
-use crate::subcommand::{Config, DOMAIN_SET};
+use crate::subcommand::{
+    domain_manifest,
+    graph::build_graph,
+    manifest::{
+        file_hash, source_hash, source_revision, BuildManifest, DomainRecord, RebuildReason,
+    },
+    size::section_sizes,
+    Config,
+};
+
+const TARGET_DIR: &str = "./target";
+const TARGET_LOCK: &str = "./target/.domain-build.lock";
 
 fn check_output_exist(output: &String) {
     let disk_path = format!("{}/disk", output);
@@ -9,16 +28,196 @@ fn check_output_exist(output: &String) {
     let init_dir = Path::new(init_path.as_str());
     if !disk_dir.exists() || !init_dir.exists() {
         println!("Output directory not exist, creating...");
-        fs::create_dir_all(&format!("{}/disk", output)).unwrap();
-        fs::create_dir_all(&format!("{}/init", output)).unwrap();
+        fs::create_dir_all(format!("{}/disk", output)).unwrap();
+        fs::create_dir_all(format!("{}/init", output)).unwrap();
+    }
+}
+
+/// Why a single domain could not be built and copied to the output directory.
+#[derive(Debug)]
+pub enum BuildError {
+    /// No `g{name}/Cargo.toml` exists under any of `DOMAIN_SET`.
+    NotFound,
+    /// `cargo` could not be spawned at all.
+    Spawn(String),
+    /// `cargo build` exited with a non-zero status.
+    Cargo(Option<i32>),
+    /// `cargo build` succeeded but did not produce the artifact.
+    ArtifactMissing(PathBuf),
+    /// The artifact could not be copied to the output directory.
+    Copy(String),
+    /// The domain sources could not be hashed.
+    Hash(String),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::NotFound => write!(f, "domain project not found"),
+            BuildError::Spawn(e) => write!(f, "failed to execute cargo build: {}", e),
+            BuildError::Cargo(Some(code)) => write!(f, "cargo build exited with code {}", code),
+            BuildError::Cargo(None) => write!(f, "cargo build terminated by signal"),
+            BuildError::ArtifactMissing(path) => write!(f, "artifact {:?} not produced", path),
+            BuildError::Copy(e) => write!(f, "failed to copy artifact: {}", e),
+            BuildError::Hash(e) => write!(f, "failed to hash domain sources: {}", e),
+        }
+    }
+}
+
+/// What happened to a domain that did not fail to build.
+#[derive(Debug, Copy, Clone)]
+pub enum BuildStatus {
+    Rebuilt(RebuildReason),
+    UpToDate,
+}
+
+impl Display for BuildStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildStatus::Rebuilt(reason) => write!(f, "rebuilt ({})", reason),
+            BuildStatus::UpToDate => write!(f, "up to date"),
+        }
+    }
+}
+
+/// The outcome of building one domain as part of `build-all`.
+pub struct BuildResult {
+    pub name: String,
+    pub dir: &'static str,
+    pub result: Result<BuildStatus, BuildError>,
+}
+
+/// Exclusive lock on the target directory.
+///
+/// Domains are built under `./target`, so two `cargo domain` invocations must not run
+/// at the same time, otherwise one may copy an artifact the other is still linking.
+/// The lock is released when the guard is dropped.
+pub struct TargetLock {
+    _file: File,
+}
+
+impl TargetLock {
+    pub fn acquire() -> std::io::Result<Self> {
+        fs::create_dir_all(TARGET_DIR)?;
+        let file = File::create(TARGET_LOCK)?;
+        if file.try_lock().is_err() {
+            println!(
+                "Waiting for another domain build to release {}",
+                TARGET_LOCK
+            );
+            file.lock()?;
+        }
+        Ok(Self { _file: file })
     }
 }
 
-pub fn build_single(name: &str, log: &str, output: &String) {
+/// Everything that determines how one variant of a domain is built.
+pub struct BuildSpec {
+    pub name: String,
+    /// `init` or `disk`
+    pub dir: &'static str,
+    pub log: String,
+    pub features: Vec<String>,
+    pub profile: String,
+    pub env: BTreeMap<String, String>,
+}
+
+impl BuildSpec {
+    /// Build settings of domain `name` as declared in `domain-list.toml`.
+    pub fn new(config: &Config, name: &str, dir: &'static str, log: &str) -> Self {
+        let build = config.build.get(name).cloned().unwrap_or_default();
+        let mut spec = Self {
+            name: name.to_string(),
+            dir,
+            log: log.to_string(),
+            features: Vec::new(),
+            profile: build.profile.unwrap_or_else(|| "release".to_string()),
+            env: build.env,
+        };
+        spec.set_features(build.features);
+        spec
+    }
+
+    pub fn set_features(&mut self, mut features: Vec<String>) {
+        features.sort();
+        features.dedup();
+        self.features = features;
+    }
+
+    /// The artifact file name.
+    ///
+    /// A default release build is `g{name}`; other variants append the profile and
+    /// features, e.g. `gvirtio_mmio_block-crash` or `gvf2_sd-debug-fs_test`, so they
+    /// can coexist in the output directory.
+    pub fn artifact_name(&self) -> String {
+        let mut name = format!("g{}", self.name);
+        if self.profile != "release" {
+            name.push('-');
+            name.push_str(&self.profile);
+        }
+        if !self.features.is_empty() {
+            name.push('-');
+            name.push_str(&self.features.join("+"));
+        }
+        name
+    }
+
+    /// The directory cargo puts the artifact in, relative to `target/riscv64`.
+    fn profile_dir(&self) -> &str {
+        match self.profile.as_str() {
+            "dev" | "debug" => "debug",
+            profile => profile,
+        }
+    }
+
+    /// Features in the form cargo accepts for the `g{name}` crate.
+    ///
+    /// Features not declared by `g{name}` are assumed to belong to the `{name}`
+    /// library crate, e.g. `crash` becomes `virtio_mmio_block/crash`.
+    fn cargo_features(&self, manifest: &Path) -> Vec<String> {
+        let declared = fs::read_to_string(manifest)
+            .ok()
+            .and_then(|content| content.parse::<toml::Table>().ok())
+            .and_then(|value| value.get("features").and_then(|f| f.as_table()).cloned())
+            .unwrap_or_default();
+        self.features
+            .iter()
+            .map(|feature| {
+                if feature.contains('/') || declared.contains_key(feature) {
+                    feature.clone()
+                } else {
+                    format!("{}/{}", self.name, feature)
+                }
+            })
+            .collect()
+    }
+
+    fn record(&self, source_hash: String) -> DomainRecord {
+        DomainRecord {
+            dir: self.dir.to_string(),
+            source_hash,
+            log: self.log.clone(),
+            features: self.features.clone(),
+            profile: self.profile.clone(),
+            env: self.env.clone(),
+            output_hash: String::new(),
+            revision: String::new(),
+            sections: None,
+        }
+    }
+}
+
+pub fn build_single(
+    name: &str,
+    log: &str,
+    output: &String,
+    force: bool,
+    features: Option<Vec<String>>,
+    profile: Option<String>,
+) {
     check_output_exist(output);
-    let domain_list = fs::read_to_string("./domain-list.toml").unwrap();
-    let config: Config = toml::from_str(&domain_list).unwrap();
-    let all_members = config.domains.get("members").unwrap();
+    let config = Config::load();
+    let all_members = &config.domains.members;
     let r_name = name;
     if !all_members.contains(&r_name.to_string()) {
         println!(
@@ -27,78 +226,183 @@ pub fn build_single(name: &str, log: &str, output: &String) {
         );
         return;
     }
-    let init_members = config.domains.get("init_members").unwrap();
-    if init_members.contains(&r_name.to_string()) {
-        build_domain(r_name, log.to_string(), "init", output);
+    let init_members = &config.domains.init_members;
+    let dir = if init_members.contains(&r_name.to_string()) {
+        "init"
     } else {
-        let disk_members = config.domains.get("disk_members").unwrap();
+        let disk_members = &config.domains.disk_members;
         if disk_members.contains(&r_name.to_string()) {
-            build_domain(r_name, log.to_string(), "disk", output);
+            "disk"
         } else {
             println!(
                 "Domain [{}] is not in the init or disk members list, skip building",
                 r_name
             );
+            return;
+        }
+    };
+    let mut spec = BuildSpec::new(&config, r_name, dir, log);
+    if let Some(features) = features {
+        spec.set_features(features);
+    }
+    if let Some(profile) = profile {
+        spec.profile = profile;
+    }
+    let _lock = TargetLock::acquire().expect("failed to lock target directory");
+    let manifest = Mutex::new(BuildManifest::load(output));
+    let result = build_incremental(&spec, output, TARGET_DIR, true, force, &manifest);
+    manifest
+        .into_inner()
+        .unwrap()
+        .save(output)
+        .expect("failed to write build manifest");
+    match result {
+        Ok(status) => println!("Domain [{}]: {}", spec.artifact_name(), status),
+        Err(e) => {
+            println!("Build domain [{}] project failed: {}", r_name, e);
+            std::process::exit(1);
         }
     }
 }
 
-pub fn build_domain(name: &str, log: String, dir: &str, output: &String) {
+/// Build a domain unless the build manifest shows that its sources, LOG level,
+/// features, profile and environment are unchanged and the copied artifact is intact.
+///
+/// The manifest entry is updated on success and removed on failure.
+pub fn build_incremental(
+    spec: &BuildSpec,
+    output: &str,
+    target_dir: &str,
+    verbose: bool,
+    force: bool,
+    manifest: &Mutex<BuildManifest>,
+) -> Result<BuildStatus, BuildError> {
+    let name = spec.name.as_str();
+    let artifact = spec.artifact_name();
+    let path = domain_manifest(name).ok_or(BuildError::NotFound)?;
+    let project = path.parent().and_then(Path::parent).unwrap();
+    let mut record =
+        spec.record(source_hash(project).map_err(|e| BuildError::Hash(e.to_string()))?);
+    let reason = if force {
+        Some(RebuildReason::Forced)
+    } else {
+        manifest
+            .lock()
+            .unwrap()
+            .stale_reason(&artifact, &record, output)
+    };
+    let Some(reason) = reason else {
+        println!("Domain [{}] is up to date, skip building", artifact);
+        return Ok(BuildStatus::UpToDate);
+    };
+    println!("Domain [{}] needs rebuilding: {}", artifact, reason);
+    let output_path = PathBuf::from(format!("{}/{}/{}", output, spec.dir, artifact));
+    let result = build_domain(spec, output, target_dir, verbose)
+        .and_then(|_| file_hash(&output_path).map_err(|e| BuildError::Copy(e.to_string())));
+    let mut manifest = manifest.lock().unwrap();
+    match result {
+        Ok(output_hash) => {
+            record.output_hash = output_hash;
+            record.revision = source_revision();
+            record.sections = section_sizes(&output_path).ok();
+            manifest.domains.insert(artifact, record);
+            Ok(BuildStatus::Rebuilt(reason))
+        }
+        Err(e) => {
+            manifest.domains.remove(&artifact);
+            Err(e)
+        }
+    }
+}
+
+/// Build a domain in `target_dir` and copy the artifact to
+/// `{output}/{dir}/{artifact name}`.
+///
+/// The previous artifact in the target directory is removed before building, so
+/// only a binary produced by this `cargo build` is ever copied. When `verbose` is
+/// false, cargo output is captured and only printed if the build fails.
+pub fn build_domain(
+    spec: &BuildSpec,
+    output: &str,
+    target_dir: &str,
+    verbose: bool,
+) -> Result<(), BuildError> {
+    let name = spec.name.as_str();
     println!("Building domain [{}] project", name);
-    for ty in DOMAIN_SET {
-        let path = format!("./{}/{}/g{}/Cargo.toml", ty, name, name);
-        let path = Path::new(&path);
-        if path.exists() {
-            let path = format!("./{}/{}/g{}/Cargo.toml", ty, name, name);
-            let path = Path::new(&path);
-            println!("Start building domain,path: {:?}", path);
-            let _cmd = std::process::Command::new("cargo")
-                .arg("build")
-                .arg("--release")
-                .env("LOG", log)
-                .arg("--manifest-path")
-                .arg(path)
-                .arg("--target")
-                .arg("./riscv64.json")
-                .arg("-Zbuild-std=core,alloc")
-                .arg("-Zbuild-std-features=compiler-builtins-mem")
-                .arg("--target-dir")
-                .arg("./target")
-                .status()
-                .expect("failed to execute cargo build");
-            println!("Build domain [{}] project success", name);
-            std::process::Command::new("cp")
-                .arg(format!("./target/riscv64/release/g{}", name))
-                .arg(format!("{}/{}/g{}", output, dir, name))
-                .status()
-                .expect("failed to execute cp");
-            println!("Copy domain [{}] project success", name);
-            return;
+    let path = domain_manifest(name).ok_or(BuildError::NotFound)?;
+    let artifact = PathBuf::from(format!(
+        "{}/riscv64/{}/g{}",
+        target_dir,
+        spec.profile_dir(),
+        name
+    ));
+    if artifact.exists() {
+        fs::remove_file(&artifact).map_err(|e| BuildError::Copy(e.to_string()))?;
+    }
+    println!("Start building domain,path: {:?}", path);
+    let mut cmd = std::process::Command::new("cargo");
+    cmd.arg("build")
+        .arg("--profile")
+        .arg(match spec.profile.as_str() {
+            "debug" => "dev",
+            profile => profile,
+        })
+        .envs(&spec.env)
+        .env("LOG", &spec.log)
+        .arg("--manifest-path")
+        .arg(&path)
+        .arg("--target")
+        .arg("./riscv64.json")
+        .arg("-Zbuild-std=core,alloc")
+        .arg("-Zbuild-std-features=compiler-builtins-mem")
+        .arg("--target-dir")
+        .arg(target_dir);
+    let features = spec.cargo_features(&path);
+    if !features.is_empty() {
+        cmd.arg("--features").arg(features.join(","));
+    }
+    let status = if verbose {
+        cmd.status().map_err(|e| BuildError::Spawn(e.to_string()))?
+    } else {
+        let Output {
+            status,
+            stdout,
+            stderr,
+        } = cmd.output().map_err(|e| BuildError::Spawn(e.to_string()))?;
+        if !status.success() {
+            println!("==== cargo output of domain [{}] ====", name);
+            println!("{}", String::from_utf8_lossy(&stdout));
+            println!("{}", String::from_utf8_lossy(&stderr));
         }
+        status
+    };
+    if !status.success() {
+        return Err(BuildError::Cargo(status.code()));
+    }
+    if !artifact.exists() {
+        return Err(BuildError::ArtifactMissing(artifact));
     }
+    println!("Build domain [{}] project success", name);
+    fs::copy(
+        &artifact,
+        format!("{}/{}/{}", output, spec.dir, spec.artifact_name()),
+    )
+    .map_err(|e| BuildError::Copy(e.to_string()))?;
+    println!("Copy domain [{}] project success", name);
+    Ok(())
 }
 
-pub fn build_all(log: String, output: &String) {
+pub fn build_all(log: String, output: &String, jobs: usize, force: bool) {
     check_output_exist(output);
-    let domain_list = fs::read_to_string("./domain-list.toml").unwrap();
-    let config: Config = toml::from_str(&domain_list).unwrap();
+    let config = Config::load();
     println!("Start building all domains");
-    let all_members = config.domains.get("members").unwrap().clone();
-    let init_members = config.domains.get("init_members").unwrap().clone();
-    for domain_name in init_members {
-        if !all_members.contains(&domain_name) {
-            println!(
-                "Domain [{}] is not in the members list, skip building",
-                domain_name
-            );
-            continue;
-        }
-        let value = log.to_string();
-        build_domain(&domain_name, value, "init", output)
-    }
-    let disk_members = config.domains.get("disk_members").unwrap().clone();
-    if !disk_members.is_empty() {
-        for domain_name in disk_members {
+    let all_members = config.domains.members.clone();
+    let init_members = config.domains.init_members.clone();
+    let disk_members = config.domains.disk_members.clone();
+    let init_members = in_init_order(&config, init_members);
+    let mut queue = VecDeque::new();
+    for (dir, members) in [("init", init_members), ("disk", disk_members)] {
+        for domain_name in members {
             if !all_members.contains(&domain_name) {
                 println!(
                     "Domain [{}] is not in the members list, skip building",
@@ -106,9 +410,114 @@ pub fn build_all(log: String, output: &String) {
                 );
                 continue;
             }
-            let value = log.to_string();
+            queue.push_back(BuildSpec::new(&config, &domain_name, dir, &log));
+        }
+    }
+    let jobs = jobs.max(1);
+    let _lock = TargetLock::acquire().expect("failed to lock target directory");
+    let manifest = Mutex::new(BuildManifest::load(output));
+    let results = build_parallel(queue, output, jobs, force, &manifest);
+    manifest
+        .into_inner()
+        .unwrap()
+        .save(output)
+        .expect("failed to write build manifest");
+    print_summary(&results);
+    if results.iter().any(|r| r.result.is_err()) {
+        std::process::exit(1);
+    }
+}
+
+/// Sort `init_members` by the init order of the dependency graph. Members the graph
+/// does not order keep their place at the end.
+fn in_init_order(config: &Config, mut init_members: Vec<String>) -> Vec<String> {
+    let order = build_graph(config).init_order;
+    init_members.sort_by_key(|name| order.iter().position(|n| n == name).unwrap_or(order.len()));
+    init_members
+}
 
-            build_domain(&domain_name, value, "disk", output)
+/// Build every queued domain with at most `jobs` cargo processes at a time.
+///
+/// Domains are linked on their own and never against each other, so the build order
+/// does not change any artifact. Jobs are still started in queue order, init domains
+/// in init order before disk domains, so a broken dependency fails before the
+/// domains that look it up.
+///
+/// Cargo locks its target directory for the whole build, so with more than one job
+/// every worker builds in its own `./target/job{n}`. The shared dependencies are
+/// compiled once per worker, and the directories are kept for the next run.
+fn build_parallel(
+    queue: VecDeque<BuildSpec>,
+    output: &str,
+    jobs: usize,
+    force: bool,
+    manifest: &Mutex<BuildManifest>,
+) -> Vec<BuildResult> {
+    let total = queue.len();
+    let queue = Mutex::new(queue);
+    let results = Mutex::new(Vec::with_capacity(total));
+    thread::scope(|s| {
+        for worker in 0..jobs.min(total) {
+            let (queue, results) = (&queue, &results);
+            let target_dir = if jobs == 1 {
+                TARGET_DIR.to_string()
+            } else {
+                format!("{}/job{}", TARGET_DIR, worker)
+            };
+            s.spawn(move || loop {
+                let Some(spec) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let result =
+                    build_incremental(&spec, output, &target_dir, jobs == 1, force, manifest);
+                let name = spec.artifact_name();
+                if let Err(e) = &result {
+                    println!("Build domain [{}] project failed: {}", name, e);
+                }
+                let dir = spec.dir;
+                results
+                    .lock()
+                    .unwrap()
+                    .push(BuildResult { name, dir, result });
+            });
         }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by(|a, b| (a.dir, &a.name).cmp(&(b.dir, &b.name)));
+    results
+}
+
+fn print_summary(results: &[BuildResult]) {
+    let width = results
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    println!();
+    println!("{:<width$}  {:<4}  RESULT", "DOMAIN", "DIR", width = width);
+    for r in results {
+        let status = match &r.result {
+            Ok(status) => status.to_string(),
+            Err(e) => format!("FAILED: {}", e),
+        };
+        println!(
+            "{:<width$}  {:<4}  {}",
+            r.name,
+            r.dir,
+            status,
+            width = width
+        );
     }
+    let failed = results.iter().filter(|r| r.result.is_err()).count();
+    let rebuilt = results
+        .iter()
+        .filter(|r| matches!(r.result, Ok(BuildStatus::Rebuilt(_))))
+        .count();
+    println!(
+        "{} domains rebuilt, {} up to date, {} failed",
+        rebuilt,
+        results.len() - rebuilt - failed,
+        failed
+    );
 }
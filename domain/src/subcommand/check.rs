@@ -0,0 +1,176 @@
+use std::{collections::BTreeMap, fs};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::subcommand::{domain_manifest, domains_on_disk, DOMAIN_LIST, DOMAIN_SET};
+
+/// `domain-list.toml` with the position of every entry, used to report line numbers.
+#[derive(Deserialize)]
+struct SpannedConfig {
+    domains: SpannedDomainList,
+    #[serde(default)]
+    build: BTreeMap<Spanned<String>, toml::Value>,
+}
+
+#[derive(Deserialize)]
+struct SpannedDomainList {
+    members: Spanned<Vec<Spanned<String>>>,
+    #[serde(default)]
+    init_members: Option<Spanned<Vec<Spanned<String>>>>,
+    #[serde(default)]
+    disk_members: Option<Spanned<Vec<Spanned<String>>>>,
+}
+
+struct Problem {
+    line: usize,
+    message: String,
+}
+
+/// Check `domain-list.toml` against the domain projects on disk.
+///
+/// Every problem is printed with the line it was found on, and the process exits
+/// with a non-zero code if there is any.
+pub fn check_domain_list() {
+    let content = fs::read_to_string(DOMAIN_LIST).unwrap();
+    let problems = check(&content);
+    for problem in &problems {
+        println!(
+            "{}:{}: error: {}",
+            DOMAIN_LIST, problem.line, problem.message
+        );
+    }
+    if problems.is_empty() {
+        println!("{} is consistent with the domain tree", DOMAIN_LIST);
+    } else {
+        println!("{} problems found", problems.len());
+        std::process::exit(1);
+    }
+}
+
+fn check(content: &str) -> Vec<Problem> {
+    let line_of = |offset: usize| content[..offset].matches('\n').count() + 1;
+    let config: SpannedConfig = match toml::from_str(content) {
+        Ok(config) => config,
+        Err(e) => {
+            let line = e.span().map(|span| line_of(span.start)).unwrap_or(1);
+            return vec![Problem {
+                line,
+                message: e.message().to_string(),
+            }];
+        }
+    };
+    let domains = config.domains;
+    let build = config.build;
+    let members_line = line_of(domains.members.span().start);
+    let members = index(domains.members.into_inner());
+    let init_members = index(
+        domains
+            .init_members
+            .map(Spanned::into_inner)
+            .unwrap_or_default(),
+    );
+    let disk_members = index(
+        domains
+            .disk_members
+            .map(Spanned::into_inner)
+            .unwrap_or_default(),
+    );
+
+    let mut problems = Vec::new();
+    let mut report = |offset: usize, message: String| {
+        problems.push(Problem {
+            line: line_of(offset),
+            message,
+        })
+    };
+    for (list, entries) in [
+        ("members", &members),
+        ("init_members", &init_members),
+        ("disk_members", &disk_members),
+    ] {
+        for (name, offsets) in entries {
+            for offset in &offsets[1..] {
+                report(
+                    *offset,
+                    format!("domain [{}] is listed more than once in {}", name, list),
+                );
+            }
+        }
+    }
+    for (name, offsets) in &members {
+        if domain_manifest(name).is_none() {
+            report(
+                offsets[0],
+                format!(
+                    "domain [{}] has no g{}/Cargo.toml under any of {:?}",
+                    name, name, DOMAIN_SET
+                ),
+            );
+        }
+        if !init_members.contains_key(name) && !disk_members.contains_key(name) {
+            report(
+                offsets[0],
+                format!(
+                    "domain [{}] is in neither init_members nor disk_members",
+                    name
+                ),
+            );
+        }
+    }
+    for (list, entries) in [
+        ("init_members", &init_members),
+        ("disk_members", &disk_members),
+    ] {
+        for (name, offsets) in entries {
+            if !members.contains_key(name) {
+                report(
+                    offsets[0],
+                    format!("domain [{}] in {} is not in members", name, list),
+                );
+            }
+        }
+    }
+    for (name, offsets) in &disk_members {
+        if init_members.contains_key(name) {
+            report(
+                offsets[0],
+                format!(
+                    "domain [{}] is listed in both init_members and disk_members",
+                    name
+                ),
+            );
+        }
+    }
+    for name in build.keys() {
+        if !members.contains_key(name.get_ref()) {
+            report(
+                name.span().start,
+                format!(
+                    "build settings for domain [{}] which is not in members",
+                    name.get_ref()
+                ),
+            );
+        }
+    }
+    for name in domains_on_disk() {
+        if !members.contains_key(&name) {
+            problems.push(Problem {
+                line: members_line,
+                message: format!("domain [{}] exists in the tree but is not in members", name),
+            });
+        }
+    }
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+/// Map every name in a list to the offsets it appears at.
+fn index(list: Vec<Spanned<String>>) -> BTreeMap<String, Vec<usize>> {
+    let mut map = BTreeMap::<String, Vec<usize>>::new();
+    for entry in list {
+        let offset = entry.span().start;
+        map.entry(entry.into_inner()).or_default().push(offset);
+    }
+    map
+}
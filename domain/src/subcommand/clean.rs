@@ -1,16 +1,23 @@
 use std::{fs, path::Path};
 
-use crate::subcommand::{Config, DOMAIN_SET};
+use crate::subcommand::{
+    domains_on_disk, manifest::BuildManifest, new::DomainType, package::artifacts, select_domains,
+    Config, DOMAIN_SET,
+};
 
-pub fn clean_domain(name: String) {
-    let domain_list = fs::read_to_string("./domain-list.toml").unwrap();
-    let config: Config = toml::from_str(&domain_list).unwrap();
-    if name.is_empty() {
+/// Clean the selected domain projects. With `all`, every domain project on disk is
+/// cleaned, also those missing from `domain-list.toml`.
+pub fn clean_domain(name: String, ty: Option<DomainType>, all: bool) {
+    let domains = if all {
+        domains_on_disk()
+    } else {
+        select_domains(&Config::load(), &name, ty)
+    };
+    for domain_name in domains {
+        clean_one_domain(&domain_name);
+    }
+    if all || (name.is_empty() && ty.is_none()) {
         // clean all domain projects
-        let all_members = config.domains.get("members").unwrap();
-        for domain_name in all_members {
-            clean_one_domain(domain_name);
-        }
         println!("Cleaning ELF");
         std::process::Command::new("rm")
             .arg("-rf")
@@ -22,11 +29,45 @@ pub fn clean_domain(name: String) {
             .arg("./build")
             .status()
             .expect("failed to clean domain project");
-    } else {
-        clean_one_domain(&name);
     }
 }
 
+/// Remove the artifacts in `{output}/init` and `{output}/disk` of domains that are no
+/// longer in `init_members` or `disk_members` respectively, and their manifest entries.
+pub fn clean_stale(output: &str) {
+    let config = Config::load();
+    let mut manifest = BuildManifest::load(output);
+    let mut removed = 0;
+    for (dir, members) in [
+        ("init", &config.domains.init_members),
+        ("disk", &config.domains.disk_members),
+    ] {
+        for path in artifacts(&Path::new(output).join(dir)) {
+            let artifact = path.file_name().unwrap().to_string_lossy().to_string();
+            let name = artifact[1..].split('-').next().unwrap();
+            if members.iter().any(|m| m == name) {
+                continue;
+            }
+            println!("Removing stale artifact {:?}", path);
+            fs::remove_file(&path).expect("failed to remove stale artifact");
+            if manifest
+                .domains
+                .get(&artifact)
+                .is_some_and(|record| record.dir == dir)
+            {
+                manifest.domains.remove(&artifact);
+            }
+            removed += 1;
+        }
+    }
+    if removed > 0 && Path::new(output).exists() {
+        manifest
+            .save(output)
+            .expect("failed to write build manifest");
+    }
+    println!("Removed {} stale artifacts", removed);
+}
+
 fn clean_one_domain(name: &String) {
     for prefix in DOMAIN_SET {
         let path = format!("./{}/{}/target", prefix, name);
@@ -1,34 +1,70 @@
-use std::fs;
+use crate::subcommand::{domains_on_disk, new::DomainType, select_domains, Config, DOMAIN_SET};
 
-use crate::subcommand::{Config, DOMAIN_SET};
-
-pub fn fmt_domain(name: String) {
-    let domain_list = fs::read_to_string("./domain-list.toml").unwrap();
-    let config: Config = toml::from_str(&domain_list).unwrap();
-    if name.is_empty() {
-        // format all domain projects
-        let all_members = config.domains.get("members").unwrap();
-        for domain_name in all_members {
-            fmt_one_domain(domain_name);
-        }
-        return;
+/// Format the selected domain projects. With `all`, every domain project on disk is
+/// formatted, also those missing from `domain-list.toml`.
+///
+/// With `check`, nothing is changed: the unformatted files are listed and the process
+/// exits with a non-zero code if there are any.
+pub fn fmt_domain(name: String, ty: Option<DomainType>, all: bool, check: bool) {
+    let domains = if all {
+        domains_on_disk()
     } else {
-        fmt_one_domain(&name);
+        select_domains(&Config::load(), &name, ty)
+    };
+    let mut unformatted = Vec::new();
+    for domain_name in domains {
+        unformatted.extend(fmt_one_domain(&domain_name, check));
+    }
+    if check {
+        if unformatted.is_empty() {
+            println!("All domain projects are formatted");
+        } else {
+            println!("Unformatted files:");
+            for file in &unformatted {
+                println!("  {}", file);
+            }
+            std::process::exit(1);
+        }
     }
 }
 
-fn fmt_one_domain(name: &String) {
+/// Format one domain project, or only list its unformatted files if `check` is set.
+fn fmt_one_domain(name: &String, check: bool) -> Vec<String> {
     for prefix in DOMAIN_SET {
         let path = format!("./{}/{}", prefix, name);
         let dir = std::path::Path::new(&path);
         if dir.exists() {
-            println!("Formatting domain project {}", path);
-            let _cmd = std::process::Command::new("cargo")
+            if !check {
+                println!("Formatting domain project {}", path);
+                let _cmd = std::process::Command::new("cargo")
+                    .arg("fmt")
+                    .current_dir(path)
+                    .status()
+                    .expect("failed to format domain project");
+                return Vec::new();
+            }
+            println!("Checking format of domain project {}", path);
+            let output = std::process::Command::new("cargo")
                 .arg("fmt")
-                .current_dir(path)
-                .status()
-                .expect("failed to format domain project");
-            return;
+                .arg("--check")
+                .arg("--message-format")
+                .arg("short")
+                .current_dir(&path)
+                .output()
+                .expect("failed to check format of domain project");
+            let files: Vec<String> = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string())
+                .collect();
+            if !output.status.success() && files.is_empty() {
+                // cargo fmt itself failed, e.g. the project does not parse
+                print!("{}", String::from_utf8_lossy(&output.stderr));
+                return vec![format!("{} (cargo fmt failed)", path)];
+            }
+            return files;
         }
     }
+    println!("Domain [{}] not found, skip formatting", name);
+    Vec::new()
 }
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::subcommand::{
+    source::{domain_interface, domain_lookups, domain_of_instance},
+    Config,
+};
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Node {
+    pub name: String,
+    /// `init`, `disk` or `none` if the domain is in neither list
+    pub placement: &'static str,
+    pub interface: Option<String>,
+}
+
+/// A `get_domain` call of `from` that looks up `to`.
+#[derive(Debug, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    /// The registered name that is looked up, e.g. `fatfs-1`
+    pub instance: String,
+    /// Whether `from` panics or fails if `to` is missing
+    pub required: bool,
+    /// The `DomainType` variants `from` accepts
+    pub expected: Vec<String>,
+    pub location: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// `get_domain` calls whose target is not a string literal
+    pub dynamic_lookups: Vec<String>,
+    /// A valid init order of `init_members`; domains in a cycle are kept together
+    pub init_order: Vec<String>,
+    /// Groups of domains that require each other
+    pub cycles: Vec<Vec<String>>,
+    pub problems: Vec<String>,
+}
+
+/// Print the dependency graph of all domains and report dependency problems.
+///
+/// The graph goes to stdout in the requested format, the init order and the problems
+/// to stderr. The process exits with a non-zero code if there is any problem.
+pub fn domain_graph(format: GraphFormat) {
+    let config = Config::load();
+    let graph = build_graph(&config);
+    match format {
+        GraphFormat::Dot => print!("{}", graph.to_dot()),
+        GraphFormat::Json => println!("{}", serde_json::to_string_pretty(&graph).unwrap()),
+    }
+    eprintln!("Init order: {}", graph.init_order.join(" -> "));
+    for problem in &graph.problems {
+        eprintln!("error: {}", problem);
+    }
+    if !graph.problems.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+pub fn build_graph(config: &Config) -> DependencyGraph {
+    let domains = &config.domains;
+    let placement = |name: &str| {
+        if domains.init_members.iter().any(|m| m == name) {
+            "init"
+        } else if domains.disk_members.iter().any(|m| m == name) {
+            "disk"
+        } else {
+            "none"
+        }
+    };
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut dynamic_lookups = Vec::new();
+    let mut problems = Vec::new();
+    for name in &domains.members {
+        nodes.push(Node {
+            name: name.clone(),
+            placement: placement(name),
+            interface: domain_interface(name),
+        });
+        for lookup in domain_lookups(name) {
+            let location = format!("{}:{}", lookup.file.display(), lookup.line);
+            let Some(instance) = lookup.target else {
+                dynamic_lookups.push(format!("{} at {}", name, location));
+                continue;
+            };
+            let to = domain_of_instance(&instance).to_string();
+            if to == *name {
+                continue;
+            }
+            if !domains.members.contains(&to) {
+                problems.push(format!(
+                    "[{}] looks up unknown domain [{}] at {}",
+                    name, instance, location
+                ));
+            }
+            edges.push(Edge {
+                from: name.clone(),
+                to,
+                instance,
+                required: lookup.required,
+                expected: lookup.expected,
+                location,
+            });
+        }
+    }
+    for edge in &edges {
+        let Some(interface) = nodes
+            .iter()
+            .find(|n| n.name == edge.to)
+            .and_then(|n| n.interface.as_ref())
+        else {
+            continue;
+        };
+        if !edge.expected.is_empty() && !edge.expected.contains(interface) {
+            problems.push(format!(
+                "[{}] expects [{}] to be {:?} but it is {} at {}",
+                edge.from, edge.instance, edge.expected, interface, edge.location
+            ));
+        }
+        if edge.required && placement(&edge.from) == "init" && placement(&edge.to) != "init" {
+            problems.push(format!(
+                "init domain [{}] requires [{}] which is not in init_members at {}",
+                edge.from, edge.to, edge.location
+            ));
+        }
+    }
+
+    // only required lookups constrain the init order
+    let mut deps = BTreeMap::<&str, BTreeSet<&str>>::new();
+    for name in &domains.init_members {
+        deps.entry(name).or_default();
+    }
+    for edge in edges.iter().filter(|e| e.required) {
+        if deps.contains_key(edge.from.as_str()) && deps.contains_key(edge.to.as_str()) {
+            deps.get_mut(edge.from.as_str()).unwrap().insert(&edge.to);
+        }
+    }
+    let components = strongly_connected(&deps);
+    let cycles: Vec<Vec<String>> = components
+        .iter()
+        .filter(|c| c.len() > 1)
+        .map(|c| c.iter().map(|s| s.to_string()).collect())
+        .collect();
+    for cycle in &cycles {
+        problems.push(format!("dependency cycle: {}", cycle.join(" <-> ")));
+    }
+    let init_order = components.into_iter().flatten().map(String::from).collect();
+
+    DependencyGraph {
+        nodes,
+        edges,
+        dynamic_lookups,
+        init_order,
+        cycles,
+        problems,
+    }
+}
+
+/// Tarjan's algorithm. Components are returned in reverse topological order, i.e.
+/// every component comes after the components it depends on.
+fn strongly_connected<'a>(deps: &BTreeMap<&'a str, BTreeSet<&'a str>>) -> Vec<Vec<&'a str>> {
+    struct State<'a> {
+        index: BTreeMap<&'a str, usize>,
+        low: BTreeMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: BTreeSet<&'a str>,
+        components: Vec<Vec<&'a str>>,
+    }
+
+    fn visit<'a>(node: &'a str, deps: &BTreeMap<&'a str, BTreeSet<&'a str>>, s: &mut State<'a>) {
+        let index = s.index.len();
+        s.index.insert(node, index);
+        s.low.insert(node, index);
+        s.stack.push(node);
+        s.on_stack.insert(node);
+        for &dep in &deps[node] {
+            if !s.index.contains_key(dep) {
+                visit(dep, deps, s);
+                let low = s.low[node].min(s.low[dep]);
+                s.low.insert(node, low);
+            } else if s.on_stack.contains(dep) {
+                let low = s.low[node].min(s.index[dep]);
+                s.low.insert(node, low);
+            }
+        }
+        if s.low[node] == s.index[node] {
+            let mut component = Vec::new();
+            while let Some(top) = s.stack.pop() {
+                s.on_stack.remove(top);
+                component.push(top);
+                if top == node {
+                    break;
+                }
+            }
+            component.sort();
+            s.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        index: BTreeMap::new(),
+        low: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+    for &node in deps.keys() {
+        if !state.index.contains_key(node) {
+            visit(node, deps, &mut state);
+        }
+    }
+    state.components
+}
+
+impl DependencyGraph {
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph domains {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let shape = match node.placement {
+                "init" => "box",
+                "disk" => "ellipse",
+                _ => "note",
+            };
+            dot.push_str(&format!(
+                "    \"{}\" [shape={}, label=\"{}\\n{}\"];\n",
+                node.name,
+                shape,
+                node.name,
+                node.interface.as_deref().unwrap_or("?")
+            ));
+        }
+        let mut seen = BTreeSet::new();
+        for edge in &self.edges {
+            if !seen.insert((&edge.from, &edge.to, edge.required)) {
+                continue;
+            }
+            let style = if edge.required { "solid" } else { "dashed" };
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [style={}];\n",
+                edge.from, edge.to, style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::subcommand::{source::domain_project, Config};
+
+/// Where the host manifests of the library crates are generated.
+const OVERLAY_DIR: &str = "./target/host-test";
+
+/// The crates shared by several domains, which `-n` also accepts.
+const COMMON_LIB_DIR: &str = "./common_lib";
+
+/// Host implementations of the crates that need the kernel, keyed by package name.
+const MOCKS: [(&str, &str); 2] = [
+    ("basic", "./common_lib/mock_basic"),
+    ("shared_heap", "./common_lib/mock_shared_heap"),
+];
+
+/// Run the `#[cfg(test)]` suites of the library crate of one or all domains on
+/// the host.
+///
+/// The library crates cannot be built for the host as they are, because `basic` and
+/// `shared_heap` need the kernel. For every crate a host manifest is generated under
+/// `target/host-test`, which points at the original sources but replaces those two
+/// dependencies with the mock kernel in `common_lib`. `args` go to the test binaries.
+pub fn test_domain(name: &str, args: &[String]) {
+    let config = Config::load();
+    let names = if name.is_empty() {
+        config.domains.members.clone()
+    } else {
+        vec![name.to_string()]
+    };
+    let mut overlay = Overlay::new();
+    let mut packages = Vec::new();
+    let mut members = Vec::new();
+    for name in &names {
+        let Some(dir) = library_crate(name) else {
+            println!("Domain [{}] not found, skip testing", name);
+            continue;
+        };
+        match overlay.add(&dir) {
+            Ok(dir) => {
+                packages.push(name.clone());
+                members.push(dir);
+            }
+            Err(e) => {
+                println!(
+                    "Error: failed to generate host manifest of [{}]: {}",
+                    name, e
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Err(e) = overlay.write_workspace(&members) {
+        println!("Error: failed to write {}: {}", OVERLAY_DIR, e);
+        std::process::exit(1);
+    }
+    let mut failed = Vec::new();
+    for package in &packages {
+        println!("Testing domain [{}] on the host", package);
+        let status = std::process::Command::new("cargo")
+            .arg("test")
+            .arg("--manifest-path")
+            .arg(Path::new(OVERLAY_DIR).join("Cargo.toml"))
+            .arg("-p")
+            .arg(package)
+            .arg("--")
+            .args(args)
+            .status()
+            .expect("failed to execute cargo test");
+        if !status.success() {
+            failed.push(package.clone());
+        }
+    }
+    if !failed.is_empty() {
+        println!("Host tests failed: {}", failed.join(", "));
+        std::process::exit(1);
+    }
+    println!("Host tests of {} domains passed", packages.len());
+}
+
+/// The library crate of the domain `name`, or the shared crate `common_lib/{name}`.
+fn library_crate(name: &str) -> Option<PathBuf> {
+    if let Some(project) = domain_project(name) {
+        return Some(project.join(name));
+    }
+    let dir = Path::new(COMMON_LIB_DIR).join(name);
+    dir.join("Cargo.toml").exists().then_some(dir)
+}
+
+/// Host manifests generated so far, keyed by the original crate directory.
+struct Overlay {
+    crates: BTreeMap<PathBuf, PathBuf>,
+    mocks: BTreeMap<&'static str, PathBuf>,
+}
+
+impl Overlay {
+    fn new() -> Self {
+        let mocks = MOCKS
+            .iter()
+            .map(|(package, path)| (*package, absolute(Path::new(path))))
+            .collect();
+        Self {
+            crates: BTreeMap::new(),
+            mocks,
+        }
+    }
+
+    /// Generate the host manifest of the crate at `dir` and of all local crates it
+    /// depends on. Returns the directory of the generated manifest.
+    fn add(&mut self, dir: &Path) -> std::io::Result<PathBuf> {
+        let dir = dir.canonicalize()?;
+        if let Some(overlay) = self.crates.get(&dir) {
+            return Ok(overlay.clone());
+        }
+        let content = fs::read_to_string(dir.join("Cargo.toml"))?;
+        let mut doc = content
+            .parse::<DocumentMut>()
+            .map_err(std::io::Error::other)?;
+        let package = doc["package"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let overlay = absolute(Path::new(OVERLAY_DIR))
+            .join("crates")
+            .join(format!("{}-{:x}", package, self.crates.len()));
+        self.crates.insert(dir.clone(), overlay.clone());
+
+        doc.remove("workspace");
+        doc.remove("bin");
+        let mut lib = doc
+            .remove("lib")
+            .and_then(|lib| lib.into_table().ok())
+            .unwrap_or_default();
+        let lib_path = lib
+            .get("path")
+            .and_then(|p| p.as_str())
+            .unwrap_or("src/lib.rs")
+            .to_string();
+        lib.insert("path", toml_edit::value(path_str(&dir.join(lib_path))));
+        doc.insert("lib", Item::Table(lib));
+        if let Some(build) = doc["package"].get("build").and_then(|b| b.as_str()) {
+            let build = path_str(&dir.join(build));
+            doc["package"]["build"] = toml_edit::value(build);
+        }
+        // the mock basic wraps the real one, which must not be redirected to itself
+        let is_mock = self.mocks.values().any(|mock| *mock == dir);
+        for table in ["dependencies", "dev-dependencies", "build-dependencies"] {
+            let Some(deps) = doc.get_mut(table).and_then(|t| t.as_table_like_mut()) else {
+                continue;
+            };
+            let keys: Vec<String> = deps.iter().map(|(k, _)| k.to_string()).collect();
+            for key in keys {
+                let dep = deps.get_mut(&key).unwrap();
+                let Some(path) = dep.get("path").and_then(|p| p.as_str()) else {
+                    continue;
+                };
+                let package = dep
+                    .get("package")
+                    .and_then(|p| p.as_str())
+                    .unwrap_or(&key)
+                    .to_string();
+                let target = match self.mocks.get(package.as_str()) {
+                    Some(mock) if !is_mock => mock.clone(),
+                    _ => dir.join(path),
+                };
+                let target = self.add(&target)?;
+                if let Some(dep) = dep.as_table_like_mut() {
+                    dep.insert("path", toml_edit::value(path_str(&target)));
+                }
+            }
+        }
+        fs::create_dir_all(&overlay)?;
+        fs::write(overlay.join("Cargo.toml"), doc.to_string())?;
+        Ok(overlay)
+    }
+
+    /// Write the workspace that ties the host manifests of the tested crates together.
+    ///
+    /// Only the tested crates are members: the mock `basic` depends on the real one,
+    /// and two members must not share a package name.
+    fn write_workspace(&self, members: &[PathBuf]) -> std::io::Result<()> {
+        let mut array = toml_edit::Array::new();
+        for member in members {
+            array.push(path_str(member));
+        }
+        let mut exclude = toml_edit::Array::new();
+        exclude.push("crates");
+        let mut workspace = Table::new();
+        workspace.insert("members", toml_edit::value(array));
+        workspace.insert("exclude", toml_edit::value(exclude));
+        workspace.insert("resolver", toml_edit::value("2"));
+        let mut doc = DocumentMut::new();
+        doc.insert("workspace", Item::Table(workspace));
+        fs::create_dir_all(OVERLAY_DIR)?;
+        fs::write(Path::new(OVERLAY_DIR).join("Cargo.toml"), doc.to_string())?;
+        println!(
+            "Generated host manifests of {} crates for {} domains",
+            self.crates.len(),
+            members.len()
+        );
+        Ok(())
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .unwrap_or_else(|_| std::env::current_dir().unwrap().join(path))
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::subcommand::size::SectionSizes;
+
+/// The build manifest kept in `{output}/manifest.json`.
+///
+/// It records, for every artifact copied to the output directory, the inputs it was
+/// built from and the hash of the copied file, so unchanged domains can be skipped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildManifest {
+    /// Keyed by artifact name, so every variant of a domain has its own entry
+    pub domains: BTreeMap<String, DomainRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainRecord {
+    /// `init` or `disk`
+    pub dir: String,
+    pub source_hash: String,
+    pub log: String,
+    pub features: Vec<String>,
+    pub profile: String,
+    pub env: BTreeMap<String, String>,
+    pub output_hash: String,
+    /// The git revision the artifact was built from, see [`source_revision`]
+    #[serde(default)]
+    pub revision: String,
+    /// Section sizes of the artifact, used by `cargo domain size` to compare builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sections: Option<SectionSizes>,
+}
+
+/// Why a domain has to be rebuilt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RebuildReason {
+    Forced,
+    NotBuilt,
+    SourceChanged,
+    LogChanged,
+    FeaturesChanged,
+    ProfileChanged,
+    EnvChanged,
+    PlacementChanged,
+    OutputMissing,
+    OutputModified,
+}
+
+impl Display for RebuildReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            RebuildReason::Forced => "forced",
+            RebuildReason::NotBuilt => "not built before",
+            RebuildReason::SourceChanged => "sources changed",
+            RebuildReason::LogChanged => "LOG level changed",
+            RebuildReason::FeaturesChanged => "features changed",
+            RebuildReason::ProfileChanged => "profile changed",
+            RebuildReason::EnvChanged => "environment changed",
+            RebuildReason::PlacementChanged => "moved between init and disk",
+            RebuildReason::OutputMissing => "output missing",
+            RebuildReason::OutputModified => "output modified",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl BuildManifest {
+    fn path(output: &str) -> PathBuf {
+        Path::new(output).join("manifest.json")
+    }
+
+    /// Load the manifest from the output directory, or an empty one if it does not
+    /// exist or cannot be parsed.
+    pub fn load(output: &str) -> Self {
+        Self::load_file(&Self::path(output)).unwrap_or_default()
+    }
+
+    /// Load a manifest from any path, e.g. one kept from a previous build.
+    pub fn load_file(path: &Path) -> Option<Self> {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+    }
+
+    pub fn save(&self, output: &str) -> std::io::Result<()> {
+        let content = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(Self::path(output), content)
+    }
+
+    /// Check whether the artifact must be rebuilt given its current inputs.
+    ///
+    /// Returns `None` if the recorded build is up to date.
+    pub fn stale_reason(
+        &self,
+        artifact: &str,
+        current: &DomainRecord,
+        output: &str,
+    ) -> Option<RebuildReason> {
+        let Some(record) = self.domains.get(artifact) else {
+            return Some(RebuildReason::NotBuilt);
+        };
+        if record.source_hash != current.source_hash {
+            return Some(RebuildReason::SourceChanged);
+        }
+        if record.log != current.log {
+            return Some(RebuildReason::LogChanged);
+        }
+        if record.features != current.features {
+            return Some(RebuildReason::FeaturesChanged);
+        }
+        if record.profile != current.profile {
+            return Some(RebuildReason::ProfileChanged);
+        }
+        if record.env != current.env {
+            return Some(RebuildReason::EnvChanged);
+        }
+        if record.dir != current.dir {
+            return Some(RebuildReason::PlacementChanged);
+        }
+        let path = format!("{}/{}/{}", output, record.dir, artifact);
+        match file_hash(Path::new(&path)) {
+            Err(_) => Some(RebuildReason::OutputMissing),
+            Ok(hash) if hash != record.output_hash => Some(RebuildReason::OutputModified),
+            Ok(_) => None,
+        }
+    }
+}
+
+pub fn file_hash(path: &Path) -> std::io::Result<String> {
+    let content = fs::read(path)?;
+    Ok(to_hex(&Sha256::digest(content)))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hash every source file of the domain project at `project`, including the local
+/// crates it depends on through `path = ...` dependencies, inside this repository or
+/// not, e.g. `../domain-lib/interface`.
+///
+/// Path dependencies that do not exist are hashed by their path, so the hash changes
+/// once they appear.
+pub fn source_hash(project: &Path) -> std::io::Result<String> {
+    let mut dirs = BTreeSet::new();
+    let mut missing = BTreeSet::new();
+    collect_source_dirs(project, &mut dirs, &mut missing)?;
+    let mut hasher = Sha256::new();
+    for dir in &dirs {
+        let mut files = Vec::new();
+        collect_files(dir, &mut files)?;
+        files.sort();
+        // Hash paths relative to the crate directory so the hash does not depend on
+        // where the repository is checked out.
+        let root = dir.parent().unwrap_or(dir);
+        for file in files {
+            let relative = file.strip_prefix(root).unwrap();
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update(fs::read(&file)?);
+        }
+    }
+    for dep in &missing {
+        hasher.update(b"missing:");
+        hasher.update(dep.as_bytes());
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// The domain project directory and the directories of all local crates it depends
+/// on, canonicalized.
+pub fn source_dirs(project: &Path) -> std::io::Result<BTreeSet<PathBuf>> {
+    let mut dirs = BTreeSet::new();
+    collect_source_dirs(project, &mut dirs, &mut BTreeSet::new())?;
+    Ok(dirs)
+}
+
+/// Add `dir` and the crates its manifests depend on to `dirs`. Path dependencies that
+/// do not exist go to `missing`, as written in the manifest.
+fn collect_source_dirs(
+    dir: &Path,
+    dirs: &mut BTreeSet<PathBuf>,
+    missing: &mut BTreeSet<String>,
+) -> std::io::Result<()> {
+    let dir = dir.canonicalize()?;
+    if dirs.iter().any(|d| dir.starts_with(d)) {
+        return Ok(());
+    }
+    dirs.insert(dir.clone());
+    let mut manifests = Vec::new();
+    collect_files(&dir, &mut manifests)?;
+    for manifest in manifests
+        .iter()
+        .filter(|f| f.file_name().is_some_and(|n| n == "Cargo.toml"))
+    {
+        for dep in path_dependencies(manifest) {
+            if dep.exists() {
+                collect_source_dirs(&dep, dirs, missing)?;
+            } else {
+                missing.insert(dep.to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The path dependencies of `manifest`, resolved against the directory of the
+/// manifest that declares them. This covers `[dependencies]`,
+/// `[build-dependencies]`, their `[target.*]` forms, `[workspace.dependencies]` and
+/// `{ workspace = true }` entries, which are looked up in the enclosing workspace.
+fn path_dependencies(manifest: &Path) -> Vec<PathBuf> {
+    let Some(value) = read_manifest(manifest) else {
+        return Vec::new();
+    };
+    let base = manifest.parent().unwrap();
+    let mut tables: Vec<&toml::Table> = dependency_tables(&value);
+    if let Some(deps) = value
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(|d| d.as_table())
+    {
+        tables.push(deps);
+    }
+    let mut paths = Vec::new();
+    let mut inherited = Vec::new();
+    for (name, dep) in tables.iter().flat_map(|deps| deps.iter()) {
+        if let Some(path) = dep.get("path").and_then(|p| p.as_str()) {
+            paths.push(base.join(path));
+        } else if dep.get("workspace").and_then(|w| w.as_bool()) == Some(true) {
+            inherited.push(name.as_str());
+        }
+    }
+    if !inherited.is_empty() {
+        if let Some((root, workspace)) = enclosing_workspace(base) {
+            let deps = workspace
+                .get("workspace")
+                .and_then(|w| w.get("dependencies"))
+                .and_then(|d| d.as_table());
+            for name in inherited {
+                let path = deps
+                    .and_then(|deps| deps.get(name))
+                    .and_then(|dep| dep.get("path"))
+                    .and_then(|p| p.as_str());
+                if let Some(path) = path {
+                    paths.push(root.join(path));
+                }
+            }
+        }
+    }
+    paths
+}
+
+fn read_manifest(manifest: &Path) -> Option<toml::Table> {
+    fs::read_to_string(manifest)
+        .ok()?
+        .parse::<toml::Table>()
+        .ok()
+}
+
+fn dependency_tables(value: &toml::Table) -> Vec<&toml::Table> {
+    let keys = ["dependencies", "build-dependencies"];
+    let mut tables: Vec<&toml::Table> = keys
+        .iter()
+        .filter_map(|key| value.get(*key).and_then(|deps| deps.as_table()))
+        .collect();
+    let targets = value.get("target").and_then(|t| t.as_table());
+    for target in targets.into_iter().flat_map(|t| t.values()) {
+        tables.extend(
+            keys.iter()
+                .filter_map(|key| target.get(*key).and_then(|deps| deps.as_table())),
+        );
+    }
+    tables
+}
+
+/// The closest directory above `dir`, or `dir` itself, whose manifest has a
+/// `[workspace]` table, with that manifest.
+fn enclosing_workspace(dir: &Path) -> Option<(PathBuf, toml::Table)> {
+    dir.ancestors().find_map(|dir| {
+        let manifest = read_manifest(&dir.join("Cargo.toml"))?;
+        manifest
+            .contains_key("workspace")
+            .then(|| (dir.to_path_buf(), manifest))
+    })
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap();
+        if name == "target" || name == ".git" {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `git rev-parse HEAD`, with a `-dirty` suffix if the tree has local changes.
+pub fn source_revision() -> String {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+    let Some(revision) = git(&["rev-parse", "HEAD"]) else {
+        return "unknown".to_string();
+    };
+    match git(&["status", "--porcelain", "--untracked-files=no"]) {
+        Some(status) if !status.is_empty() => format!("{}-dirty", revision),
+        _ => revision,
+    }
+}
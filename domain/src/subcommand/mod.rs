@@ -1,14 +1,117 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
+use new::DomainType;
 use serde::Deserialize;
 
 pub mod build;
+pub mod check;
 pub mod clean;
 pub mod fmt;
+pub mod graph;
+pub mod host_test;
+pub mod manifest;
 pub mod new;
+pub mod package;
+pub mod size;
+pub mod source;
 
+pub const DOMAIN_LIST: &str = "./domain-list.toml";
+
+/// The contents of `domain-list.toml`.
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
-    pub domains: BTreeMap<String, Vec<String>>,
+    pub domains: DomainList,
+    /// Per-domain build settings, keyed by domain name
+    #[serde(default)]
+    pub build: BTreeMap<String, DomainBuild>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DomainList {
+    /// All domains that can be built
+    pub members: Vec<String>,
+    /// Domains packed into the kernel image and loaded at boot
+    #[serde(default)]
+    pub init_members: Vec<String>,
+    /// Domains placed on the disk and loaded at runtime
+    #[serde(default)]
+    pub disk_members: Vec<String>,
+}
+
+/// How a domain is built, e.g. `[build.virtio_mmio_block]`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DomainBuild {
+    /// Extra cargo features, either of the `g{name}` crate or of the `{name}` library crate
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// The cargo profile, default is release
+    pub profile: Option<String>,
+    /// Extra environment variables passed to cargo
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let domain_list = fs::read_to_string(DOMAIN_LIST).unwrap();
+        match toml::from_str(&domain_list) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Error: invalid {}: {}", DOMAIN_LIST, e);
+                std::process::exit(1);
+            }
+        }
+    }
 }
 static DOMAIN_SET: [&str; 3] = ["common", "fs", "drivers"];
+
+/// The domains a subcommand applies to: `name` if it is given, otherwise all members,
+/// or only the members of type `ty`.
+pub fn select_domains(config: &Config, name: &str, ty: Option<DomainType>) -> Vec<String> {
+    if !name.is_empty() {
+        return vec![name.to_string()];
+    }
+    config
+        .domains
+        .members
+        .iter()
+        .filter(|member| match ty {
+            Some(ty) => Path::new(&format!("./{}/{}", ty.as_ref(), member)).exists(),
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// All domain projects in the tree, i.e. every `{ty}/{name}` with a `g{name}` crate.
+pub fn domains_on_disk() -> Vec<String> {
+    let mut names = Vec::new();
+    for ty in DOMAIN_SET {
+        let Ok(entries) = fs::read_dir(format!("./{}", ty)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().join(format!("g{}/Cargo.toml", name)).exists() {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+/// Find the `g{name}/Cargo.toml` of domain `name` under any of `DOMAIN_SET`.
+pub fn domain_manifest(name: &str) -> Option<PathBuf> {
+    DOMAIN_SET
+        .iter()
+        .map(|ty| PathBuf::from(format!("./{}/{}/g{}/Cargo.toml", ty, name, name)))
+        .find(|path| path.exists())
+}
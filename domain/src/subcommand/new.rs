@@ -1,12 +1,23 @@
 use std::{
+    collections::BTreeSet,
     fs::OpenOptions,
     io::{Read, Seek, Write},
     path::{Path, PathBuf},
 };
 
+use clap::ValueEnum;
 use fs_extra::dir::CopyOptions;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{FnArg, ImplItem, Item, Pat, ReturnType, Signature, TraitItem, Type, UseTree};
+use toml_edit::{DocumentMut, Value};
 
-#[derive(Debug, Copy, Clone)]
+use crate::subcommand::{
+    source::{collect_rs_files, known_interfaces, INTERFACE_SRC},
+    DOMAIN_LIST, DOMAIN_SET,
+};
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
 pub enum DomainType {
     Common,
     Fs,
@@ -33,72 +44,296 @@ impl AsRef<str> for DomainType {
     }
 }
 
-pub fn create_domain(name: &str) {
-    let mut chose = String::new();
-    println!("Please chose the domain type:");
-    println!("1. Common");
-    println!("2. Fs");
-    println!("3. Driver");
-    let ty = match std::io::stdin().read_line(&mut chose) {
-        Ok(_) => match chose.as_str() {
-            "1\n" => DomainType::Common,
-            "2\n" => DomainType::Fs,
-            "3\n" => DomainType::Driver,
-            _ => {
-                println!("Error: invalid input");
-                return;
+/// Where the kernel finds the domain: packed into the image or on the disk.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum Placement {
+    Init,
+    Disk,
+}
+
+impl Placement {
+    fn list(&self) -> &'static str {
+        match self {
+            Placement::Init => "init_members",
+            Placement::Disk => "disk_members",
+        }
+    }
+}
+
+pub fn create_domain(name: &str, ty: DomainType, interface: &str, placement: Placement) {
+    let known = known_interfaces();
+    if !known.contains(interface) {
+        println!("Error: unknown domain interface {}", interface);
+        let known: Vec<_> = known.into_iter().collect();
+        println!("Known interfaces: {}", known.join(", "));
+        std::process::exit(1);
+    }
+    println!(
+        "Creating new domain project: {}, type: {:?}, placement: {:?}",
+        name, ty, placement
+    );
+    println!("The domain interface: {}", interface);
+
+    if let Err(e) = create_project_dir(ty, name) {
+        println!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let stub = InterfaceStub::find(interface);
+    create_lib_crate(interface, name, ty, &stub);
+    create_bin_crate(interface, name, ty);
+    if let Err(e) = register_domain(name, placement) {
+        println!("Error: failed to update {}: {}", DOMAIN_LIST, e);
+        std::process::exit(1);
+    }
+    println!("Success: create domain project {}", name);
+}
+
+fn create_project_dir(ty: DomainType, name: &str) -> Result<(), String> {
+    if let Some(ty) = DOMAIN_SET
+        .iter()
+        .find(|ty| Path::new(&format!("./{}/{}", ty, name)).exists())
+    {
+        return Err(format!(
+            "the domain project already exists: ./{}/{}",
+            ty, name
+        ));
+    }
+    let path = PathBuf::from(format!("./{}/{}", ty.as_ref(), name));
+    std::fs::create_dir_all(&path).unwrap();
+    let mut from = OpenOptions::new()
+        .read(true)
+        .open(Path::new("./domain/template/Cargo.toml"))
+        .unwrap();
+    let mut to = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path.join("Cargo.toml"))
+        .unwrap();
+    let mut content = String::new();
+    from.read_to_string(&mut content).unwrap();
+    let new_content = content.replace("DOMAIN_NAME", name);
+    to.write_all(new_content.as_bytes()).unwrap();
+    std::fs::copy("./domain/template/rustfmt.toml", path.join("rustfmt.toml")).unwrap();
+    std::fs::create_dir_all(path.join(name)).unwrap();
+    std::fs::create_dir_all(path.join(format!("g{}", name))).unwrap();
+    Ok(())
+}
+
+/// The methods of a domain trait and the imports their signatures need.
+#[derive(Default)]
+struct InterfaceStub {
+    methods: Vec<Signature>,
+    uses: Vec<UseTree>,
+}
+
+impl InterfaceStub {
+    /// Look up the trait definition in the `interface` crate, falling back to an
+    /// existing implementation of the trait in the domain tree.
+    fn find(interface: &str) -> Self {
+        if let Some(stub) = Self::from_dir(Path::new(INTERFACE_SRC), interface) {
+            return stub;
+        }
+        for ty in DOMAIN_SET {
+            if let Some(stub) = Self::from_dir(Path::new(ty), interface) {
+                return stub;
             }
-        },
-        Err(_) => {
-            println!("Error: failed to read line");
-            return;
         }
-    };
-    // input interface
-    println!("Please input the domain interface name:");
-    let mut interface = String::new();
-    let interface = match std::io::stdin().read_line(&mut interface) {
-        Ok(_) => interface,
-        Err(_) => {
-            println!("Error: failed to read line");
-            return;
+        println!(
+            "Warning: no definition of {} found, generating an empty impl",
+            interface
+        );
+        Self::default()
+    }
+
+    fn from_dir(dir: &Path, interface: &str) -> Option<Self> {
+        let mut files = Vec::new();
+        collect_rs_files(dir, &mut files);
+        files.sort();
+        files
+            .iter()
+            .find_map(|file| Self::from_file(file, interface))
+    }
+
+    fn from_file(path: &Path, interface: &str) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        let file = syn::parse_file(&content).ok()?;
+        let methods = file.items.iter().find_map(|item| match item {
+            Item::Trait(t) if t.ident == interface => Some(
+                t.items
+                    .iter()
+                    .filter_map(|item| match item {
+                        TraitItem::Fn(f) => Some(f.sig.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Item::Impl(i)
+                if i.trait_
+                    .as_ref()
+                    .and_then(|(_, path, _)| path.segments.last())
+                    .is_some_and(|s| s.ident == interface) =>
+            {
+                Some(
+                    i.items
+                        .iter()
+                        .filter_map(|item| match item {
+                            ImplItem::Fn(f) => Some(f.sig.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        })?;
+        let uses = file
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Use(u) => Some(u.tree.clone()),
+                _ => None,
+            })
+            .collect();
+        Some(Self { methods, uses })
+    }
+
+    /// `use` items for every imported name the method signatures refer to.
+    fn imports(&self, interface: &str) -> TokenStream {
+        let mut idents = BTreeSet::new();
+        for sig in &self.methods {
+            collect_idents(sig.to_token_stream(), &mut idents);
+        }
+        for provided in ["Box", "Basic", interface] {
+            idents.remove(provided);
+        }
+        let mut imports = Vec::new();
+        for tree in &self.uses {
+            flatten_use(tree, &mut Vec::new(), &mut imports);
         }
+        let mut seen = BTreeSet::new();
+        let mut tokens = TokenStream::new();
+        if self.methods.iter().any(returns_result) && !idents.contains("AlienError") {
+            tokens.extend(quote!(
+                use basic::AlienError;
+            ));
+            seen.insert("AlienError".to_string());
+        }
+        for (mut path, name, alias) in imports {
+            let visible = alias.clone().unwrap_or_else(|| name.clone());
+            if !idents.contains(&visible) || !seen.insert(visible) {
+                continue;
+            }
+            match path.first().map(String::as_str) {
+                Some("crate") => path[0] = "interface".to_string(),
+                Some("super") | Some("self") | None => continue,
+                _ => {}
+            }
+            let path = path.iter().map(|s| format_ident!("{}", s));
+            let name = format_ident!("{}", name);
+            tokens.extend(match alias {
+                Some(alias) => {
+                    let alias = format_ident!("{}", alias);
+                    quote!(use #(#path::)* #name as #alias;)
+                }
+                None => quote!(use #(#path::)* #name;),
+            });
+        }
+        tokens
+    }
+
+    /// Methods returning a result fail with `ENOSYS`, except `init`, which succeeds so
+    /// the domain can be loaded. The others return nothing or the default value, so no
+    /// stub panics.
+    fn methods(&self) -> TokenStream {
+        let methods = self.methods.iter().map(|sig| {
+            let body = if matches!(sig.output, ReturnType::Default) {
+                quote!()
+            } else if !returns_result(sig) {
+                quote!(Default::default())
+            } else if sig.ident == "init" {
+                quote!(Ok(()))
+            } else {
+                quote!(Err(AlienError::ENOSYS))
+            };
+            let sig = unused_args(sig);
+            quote!(#sig { #body })
+        });
+        quote!(#(#methods)*)
+    }
+}
+
+/// The stubs do not use their arguments, prefix them with `_` and drop `mut`.
+fn unused_args(sig: &Signature) -> Signature {
+    let mut sig = sig.clone();
+    for arg in sig.inputs.iter_mut() {
+        if let FnArg::Typed(arg) = arg {
+            if let Pat::Ident(pat) = arg.pat.as_mut() {
+                pat.mutability = None;
+                pat.ident = format_ident!("_{}", pat.ident);
+            }
+        }
+    }
+    sig
+}
+
+fn returns_result(sig: &Signature) -> bool {
+    let ReturnType::Type(_, ty) = &sig.output else {
+        return false;
+    };
+    let Type::Path(path) = ty.as_ref() else {
+        return false;
     };
-    println!("Creating new domain project: {}, type: {:?}", name, ty);
-    println!("The domain interface: {}", interface.trim());
+    path.path
+        .segments
+        .last()
+        .is_some_and(|s| s.ident == "AlienResult" || s.ident == "Result")
+}
 
-    create_project_dir(ty, name);
-    create_lib_crate(&interface, name, ty);
-    create_bin_crate(&interface, name, ty);
-    println!("Success: create domain project {}", name);
+fn collect_idents(tokens: TokenStream, idents: &mut BTreeSet<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            _ => {}
+        }
+    }
 }
 
-fn create_project_dir(ty: DomainType, name: &str) {
-    let path = PathBuf::from(format!("./{}/{}", ty.as_ref(), name));
-    if path.exists() {
-        println!("Error: the domain project already exists");
-        return;
-    } else {
-        std::fs::create_dir_all(&path).unwrap();
-        let mut from = OpenOptions::new()
-            .read(true)
-            .open(Path::new("./domain/template/Cargo.toml"))
-            .unwrap();
-        let mut to = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path.join("Cargo.toml"))
-            .unwrap();
-        let mut content = String::new();
-        from.read_to_string(&mut content).unwrap();
-        let new_content = content.replace("DOMAIN_NAME", name);
-        to.write_all(new_content.as_bytes()).unwrap();
-        std::fs::create_dir_all(path.join(name)).unwrap();
-        std::fs::create_dir_all(path.join(format!("g{}", name))).unwrap();
-    }
-}
-
-fn create_lib_crate(interface_name: &str, domain_name: &str, ty: DomainType) {
+/// Flatten a use tree into `(path, name, alias)` for every imported name.
+fn flatten_use(
+    tree: &UseTree,
+    prefix: &mut Vec<String>,
+    imports: &mut Vec<(Vec<String>, String, Option<String>)>,
+) {
+    match tree {
+        UseTree::Path(p) => {
+            prefix.push(p.ident.to_string());
+            flatten_use(&p.tree, prefix, imports);
+            prefix.pop();
+        }
+        UseTree::Name(n) if n.ident == "self" => {
+            if let Some((name, path)) = prefix.split_last() {
+                imports.push((path.to_vec(), name.clone(), None));
+            }
+        }
+        UseTree::Name(n) => imports.push((prefix.clone(), n.ident.to_string(), None)),
+        UseTree::Rename(r) => imports.push((
+            prefix.clone(),
+            r.ident.to_string(),
+            Some(r.rename.to_string()),
+        )),
+        UseTree::Group(g) => {
+            for tree in &g.items {
+                flatten_use(tree, prefix, imports);
+            }
+        }
+        UseTree::Glob(_) => {}
+    }
+}
+
+fn create_lib_crate(interface_name: &str, domain_name: &str, ty: DomainType, stub: &InterfaceStub) {
     let path = PathBuf::from(format!("./{}/{}/{}", ty.as_ref(), domain_name, domain_name));
     // copy lib-template dir
     let copy_options = CopyOptions::new().content_only(true);
@@ -110,14 +345,28 @@ fn create_lib_crate(interface_name: &str, domain_name: &str, ty: DomainType) {
     let mut lib = std::fs::OpenOptions::new()
         .write(true)
         .read(true)
-        .open(lib_path)
+        .open(&lib_path)
         .unwrap();
     let mut content = String::new();
     lib.read_to_string(&mut content).unwrap();
-    let new_content = content.replace("INTERFACE", interface_name.trim());
+    let impl_name = format!("{}DomainImpl", camel_case(domain_name));
+    let new_content = content
+        .replace("USES", &stub.imports(interface_name).to_string())
+        .replace("METHODS", &stub.methods().to_string())
+        .replace("INTERFACE", interface_name)
+        .replace("IMPL", &impl_name);
     lib.set_len(0).unwrap();
     lib.seek(std::io::SeekFrom::Start(0)).unwrap();
     lib.write_all(new_content.as_bytes()).unwrap();
+    // the generated imports and stubs are a single line of tokens until formatted
+    let formatted = std::process::Command::new("rustfmt")
+        .arg("--edition")
+        .arg("2021")
+        .arg(&lib_path)
+        .status();
+    if !formatted.is_ok_and(|status| status.success()) {
+        println!("Warning: failed to format {:?}", lib_path);
+    }
 
     let dep_path = path.join("Cargo.toml");
     let mut dep = std::fs::OpenOptions::new()
@@ -133,6 +382,17 @@ fn create_lib_crate(interface_name: &str, domain_name: &str, ty: DomainType) {
     dep.write_all(new_content.as_bytes()).unwrap();
 }
 
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let mut chars = s.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
 fn create_bin_crate(interface_name: &str, domain_name: &str, ty: DomainType) {
     let path = PathBuf::from(format!(
         "./{}/{}/g{}",
@@ -176,3 +436,39 @@ fn create_bin_crate(interface_name: &str, domain_name: &str, ty: DomainType) {
     main.seek(std::io::SeekFrom::Start(0)).unwrap();
     main.write_all(new_main_content.as_bytes()).unwrap();
 }
+
+/// Add the domain to `members` and to `init_members` or `disk_members`, keeping the
+/// one-name-per-line layout of `domain-list.toml`.
+fn register_domain(name: &str, placement: Placement) -> Result<(), String> {
+    let content = std::fs::read_to_string(DOMAIN_LIST).map_err(|e| e.to_string())?;
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut doc = content.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+    let domains = doc["domains"]
+        .as_table_mut()
+        .ok_or("missing [domains] table")?;
+    for list in ["members", placement.list()] {
+        let array = domains
+            .entry(list)
+            .or_insert(toml_edit::value(toml_edit::Array::new()))
+            .as_array_mut()
+            .ok_or(format!("{} is not an array", list))?;
+        if array.iter().any(|v| v.as_str() == Some(name)) {
+            continue;
+        }
+        // the line break before `]` may be attached to the last element
+        if let Some(last) = array.iter_mut().last() {
+            last.decor_mut().set_suffix("");
+        }
+        array.push_formatted(Value::from(name).decorated(format!("{}    ", newline), ""));
+        array.set_trailing(newline);
+    }
+    // toml_edit normalizes line endings, restore the ones the file uses
+    let content = doc.to_string().replace("\r\n", "\n").replace('\n', newline);
+    std::fs::write(DOMAIN_LIST, content).map_err(|e| e.to_string())?;
+    println!("Registered domain {} in {}", name, placement.list());
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::subcommand::{
+    manifest::{file_hash, BuildManifest},
+    source::{domain_interface, domain_lookups, domain_of_instance, known_interfaces},
+};
+
+/// The metadata stored next to every domain in the package.
+#[derive(Debug, Serialize)]
+pub struct DomainEntry {
+    pub name: String,
+    /// The artifact file name, e.g. `gvirtio_mmio_block-crash`
+    pub artifact: String,
+    /// `init` or `disk`
+    pub dir: String,
+    /// The `DomainTypeRaw` variant, which is named after the interface
+    pub domain_type: Option<String>,
+    pub interface_version: String,
+    /// Domains looked up through `basic::get_domain`
+    pub dependencies: Vec<String>,
+    pub size: u64,
+    pub sha256: String,
+    /// The git revision the artifact was built from
+    pub revision: String,
+}
+
+/// The version of the `interface` crate, as referenced by the domain crates.
+const INTERFACE_MANIFEST: &str = "../domain-lib/interface/Cargo.toml";
+
+/// Write every built domain in `{output}/init` and `{output}/disk` into a tar
+/// archive, each artifact followed by a `{artifact}.json` entry with its metadata,
+/// and a `package.json` index of all entries at the end.
+pub fn package_domains(output: &str, archive: &str) {
+    let manifest = BuildManifest::load(output);
+    let interface_version = interface_version();
+    let interfaces = known_interfaces();
+    let mut entries = Vec::new();
+    for dir in ["init", "disk"] {
+        for path in artifacts(&Path::new(output).join(dir)) {
+            let artifact = path.file_name().unwrap().to_string_lossy().to_string();
+            let name = artifact[1..].split('-').next().unwrap().to_string();
+            let sha256 = file_hash(&path).unwrap();
+            let Some(record) = manifest.domains.get(&artifact) else {
+                println!(
+                    "Error: {:?} has no build record, rebuild it with build-all first",
+                    path
+                );
+                std::process::exit(1);
+            };
+            if record.output_hash != sha256 {
+                println!(
+                    "Error: {:?} was modified after it was built, rebuild it first",
+                    path
+                );
+                std::process::exit(1);
+            }
+            let domain_type = domain_interface(&name).filter(|i| interfaces.contains(i));
+            if domain_type.is_none() {
+                println!("Warning: cannot determine the domain type of [{}]", name);
+            }
+            let dependencies = domain_lookups(&name)
+                .into_iter()
+                .filter_map(|lookup| lookup.target)
+                .map(|target| domain_of_instance(&target).to_string())
+                .filter(|target| *target != name)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            entries.push((
+                path.clone(),
+                DomainEntry {
+                    name,
+                    artifact,
+                    dir: dir.to_string(),
+                    domain_type,
+                    interface_version: interface_version.clone(),
+                    dependencies,
+                    size: fs::metadata(&path).unwrap().len(),
+                    sha256,
+                    revision: record.revision.clone(),
+                },
+            ));
+        }
+    }
+    if entries.is_empty() {
+        println!("Error: no built domains in {}, run build-all first", output);
+        std::process::exit(1);
+    }
+    if let Err(e) = write_archive(archive, &entries) {
+        println!("Error: failed to write {}: {}", archive, e);
+        std::process::exit(1);
+    }
+    for (_, entry) in &entries {
+        println!(
+            "{:<4} {:<28} {:>8}KB  {:<20} deps: {:?}",
+            entry.dir,
+            entry.artifact,
+            entry.size / 1024,
+            entry.domain_type.as_deref().unwrap_or("?"),
+            entry.dependencies
+        );
+    }
+    println!("Packaged {} domains into {}", entries.len(), archive);
+}
+
+/// The domain artifacts in `dir`, sorted by name.
+pub fn artifacts(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('g'))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn write_archive(archive: &str, entries: &[(PathBuf, DomainEntry)]) -> std::io::Result<()> {
+    let file = fs::File::create(archive)?;
+    let mut builder = tar::Builder::new(file);
+    let mut append = |name: String, data: &[u8], mode: u32| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        // keep the archive reproducible
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data)
+    };
+    for (path, entry) in entries {
+        let name = format!("{}/{}", entry.dir, entry.artifact);
+        append(name.clone(), &fs::read(path)?, 0o755)?;
+        let meta = serde_json::to_vec_pretty(entry).map_err(std::io::Error::other)?;
+        append(format!("{}.json", name), &meta, 0o644)?;
+    }
+    let index: Vec<_> = entries.iter().map(|(_, entry)| entry).collect();
+    let index = serde_json::to_vec_pretty(&index).map_err(std::io::Error::other)?;
+    append("package.json".to_string(), &index, 0o644)?;
+    builder.into_inner()?;
+    Ok(())
+}
+
+fn interface_version() -> String {
+    fs::read_to_string(INTERFACE_MANIFEST)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+        .and_then(|value| {
+            value
+                .get("package")?
+                .get("version")?
+                .as_str()
+                .map(|v| v.to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+use serde::{Deserialize, Serialize};
+
+use crate::subcommand::{manifest::BuildManifest, package::artifacts};
+
+/// The sizes of the loaded sections of a domain ELF, in bytes.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionSizes {
+    pub text: u64,
+    pub rodata: u64,
+    pub data: u64,
+    pub bss: u64,
+}
+
+impl SectionSizes {
+    pub fn total(&self) -> u64 {
+        self.text + self.rodata + self.data + self.bss
+    }
+}
+
+/// The size report of one artifact.
+#[derive(Debug)]
+pub struct DomainSize {
+    pub artifact: String,
+    /// `init` or `disk`
+    pub dir: String,
+    pub sections: SectionSizes,
+    /// Demangled symbols, largest first
+    pub symbols: Vec<(String, u64)>,
+    /// The total size of the symbols of every crate, largest first
+    pub crates: Vec<(String, u64)>,
+}
+
+/// Read the section sizes of the ELF at `path`.
+pub fn section_sizes(path: &Path) -> Result<SectionSizes, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let file = object::File::parse(&*data).map_err(|e| e.to_string())?;
+    let mut sizes = SectionSizes::default();
+    for section in file.sections() {
+        let size = section.size();
+        match section.kind() {
+            SectionKind::Text => sizes.text += size,
+            SectionKind::ReadOnlyData
+            | SectionKind::ReadOnlyDataWithRel
+            | SectionKind::ReadOnlyString => sizes.rodata += size,
+            SectionKind::Data | SectionKind::Tls => sizes.data += size,
+            SectionKind::UninitializedData | SectionKind::UninitializedTls => sizes.bss += size,
+            _ => {}
+        }
+    }
+    Ok(sizes)
+}
+
+fn domain_size(path: &Path, dir: &str) -> Result<DomainSize, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let file = object::File::parse(&*data).map_err(|e| e.to_string())?;
+    let mut symbols = BTreeMap::<String, u64>::new();
+    for symbol in file.symbols() {
+        if symbol.size() == 0 || !matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data) {
+            continue;
+        }
+        let Ok(name) = symbol.name() else {
+            continue;
+        };
+        let name = format!("{:#}", rustc_demangle::demangle(name));
+        // generic instances demangle to the same name without the hash
+        *symbols.entry(name).or_default() += symbol.size();
+    }
+    let mut crates = BTreeMap::<String, u64>::new();
+    for (name, size) in &symbols {
+        *crates.entry(crate_of(name).to_string()).or_default() += size;
+    }
+    Ok(DomainSize {
+        artifact: path.file_name().unwrap().to_string_lossy().to_string(),
+        dir: dir.to_string(),
+        sections: section_sizes(path)?,
+        symbols: largest_first(symbols),
+        crates: largest_first(crates),
+    })
+}
+
+/// The crate a demangled symbol belongs to, e.g. `lru` for `lru::LruCache<K, V>::get`
+/// and `virtio_drivers` for `<virtio_drivers::Hal as core::fmt::Debug>::fmt`.
+fn crate_of(symbol: &str) -> &str {
+    let symbol = symbol.trim_start_matches(['<', '&', '*']);
+    let symbol = symbol.strip_prefix("mut ").unwrap_or(symbol);
+    let Some(end) = symbol.find("::") else {
+        return "[unknown]";
+    };
+    // `<T as alloc::slice::ConvertVec>::to_vec` belongs to the crate of the trait
+    match symbol[..end].rsplit_once(" as ") {
+        Some((_, name)) => name.trim_start_matches(['<', '&', '*']),
+        None => &symbol[..end],
+    }
+}
+
+fn largest_first(map: BTreeMap<String, u64>) -> Vec<(String, u64)> {
+    let mut list: Vec<_> = map.into_iter().collect();
+    list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    list
+}
+
+/// Report the section sizes, the largest crates and the `top` largest symbols of
+/// every built domain in `{output}/init` and `{output}/disk`.
+///
+/// With `baseline`, the section sizes are compared against the ones recorded in a
+/// previous build manifest.
+pub fn size_report(output: &str, top: usize, baseline: Option<&str>) {
+    let mut reports = Vec::new();
+    for dir in ["init", "disk"] {
+        for path in artifacts(&Path::new(output).join(dir)) {
+            match domain_size(&path, dir) {
+                Ok(report) => reports.push(report),
+                Err(e) => println!("Warning: cannot read {:?}: {}", path, e),
+            }
+        }
+    }
+    if reports.is_empty() {
+        println!("Error: no built domains in {}, run build-all first", output);
+        std::process::exit(1);
+    }
+    for report in &reports {
+        println!(
+            "\n{}/{}: text {}, rodata {}, data {}, bss {}, total {}",
+            report.dir,
+            report.artifact,
+            kb(report.sections.text),
+            kb(report.sections.rodata),
+            kb(report.sections.data),
+            kb(report.sections.bss),
+            kb(report.sections.total())
+        );
+        println!("  Largest crates:");
+        for (name, size) in report.crates.iter().take(top) {
+            println!("    {:>10}  {}", kb(*size), name);
+        }
+        println!("  Largest symbols:");
+        for (name, size) in report.symbols.iter().take(top) {
+            println!("    {:>10}  {}", kb(*size), name);
+        }
+    }
+    println!(
+        "\n{:<4} {:<32} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "dir", "artifact", "text", "rodata", "data", "bss", "total"
+    );
+    for report in &reports {
+        println!(
+            "{:<4} {:<32} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            report.dir,
+            report.artifact,
+            kb(report.sections.text),
+            kb(report.sections.rodata),
+            kb(report.sections.data),
+            kb(report.sections.bss),
+            kb(report.sections.total())
+        );
+    }
+    if let Some(baseline) = baseline {
+        size_diff(&reports, baseline);
+    }
+}
+
+/// Print how the section sizes changed since the build recorded in `baseline`.
+fn size_diff(reports: &[DomainSize], baseline: &str) {
+    let Some(old) = BuildManifest::load_file(&PathBuf::from(baseline)) else {
+        println!("Error: cannot read build manifest {}", baseline);
+        std::process::exit(1);
+    };
+    println!("\nSize changes since {}:", baseline);
+    let mut changed = false;
+    for report in reports {
+        let Some(record) = old.domains.get(&report.artifact) else {
+            println!(
+                "  {:<32} new, {}",
+                report.artifact,
+                kb(report.sections.total())
+            );
+            changed = true;
+            continue;
+        };
+        let Some(old) = record.sections else {
+            println!(
+                "  {:<32} no sizes recorded in the baseline",
+                report.artifact
+            );
+            continue;
+        };
+        let new = report.sections;
+        if new == old {
+            continue;
+        }
+        changed = true;
+        println!(
+            "  {:<32} total {} ({}), text {}, rodata {}, data {}, bss {}",
+            report.artifact,
+            kb(new.total()),
+            delta(old.total(), new.total()),
+            delta(old.text, new.text),
+            delta(old.rodata, new.rodata),
+            delta(old.data, new.data),
+            delta(old.bss, new.bss)
+        );
+    }
+    for artifact in old.domains.keys() {
+        if !reports.iter().any(|r| r.artifact == *artifact) {
+            println!("  {:<32} removed", artifact);
+            changed = true;
+        }
+    }
+    if !changed {
+        println!("  no changes");
+    }
+}
+
+fn kb(size: u64) -> String {
+    format!("{:.1}KB", size as f64 / 1024.0)
+}
+
+fn delta(old: u64, new: u64) -> String {
+    let diff = new as i64 - old as i64;
+    let percent = if old == 0 {
+        String::new()
+    } else {
+        format!(" {:+.1}%", diff as f64 * 100.0 / old as f64)
+    };
+    format!("{:+}B{}", diff, percent)
+}
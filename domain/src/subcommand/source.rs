@@ -0,0 +1,616 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use syn::{
+    spanned::Spanned,
+    visit::{self, Visit},
+    Arm, Block, Expr, ExprCall, ExprIf, ExprLit, GenericArgument, Item, Lit, Local, Macro, Pat,
+    PathArguments, ReturnType, Stmt, Type, Visibility,
+};
+
+use crate::subcommand::{domain_manifest, manifest::source_dirs, DOMAIN_SET};
+
+/// The sources of the `interface` crate, as referenced by the domains' Cargo.toml.
+pub const INTERFACE_SRC: &str = "../domain-lib/interface/src";
+
+/// A `basic::get_domain(..)` call found in the sources of a domain.
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    /// The looked-up domain, or `None` if the name is not a string literal
+    pub target: Option<String>,
+    pub file: PathBuf,
+    pub line: usize,
+    /// Whether the caller panics or fails if the domain is missing: it unwraps the result,
+    /// propagates it with `?` or panics in the `None` arm of a `match` or `let else`
+    pub required: bool,
+    /// The `DomainType` variants the caller accepts
+    pub expected: Vec<String>,
+}
+
+/// The domain project directory of domain `name`, e.g. `./common/syscall`.
+pub fn domain_project(name: &str) -> Option<PathBuf> {
+    let manifest = domain_manifest(name)?;
+    manifest.parent()?.parent().map(Path::to_path_buf)
+}
+
+/// The interface the domain implements, taken from the return type of the
+/// `Box<dyn INTERFACE>` returned by `g{name}`'s `main`.
+pub fn domain_interface(name: &str) -> Option<String> {
+    let main = domain_manifest(name)?.parent()?.join("src/main.rs");
+    let file = syn::parse_file(&fs::read_to_string(main).ok()?).ok()?;
+    file.items.iter().find_map(|item| match item {
+        Item::Fn(f) if f.sig.ident == "main" => match &f.sig.output {
+            ReturnType::Type(_, ty) => boxed_trait(ty),
+            ReturnType::Default => None,
+        },
+        _ => None,
+    })
+}
+
+/// The domain traits a domain can implement: every `pub trait ...Domain` of the
+/// `interface` crate. Without the crate, the interfaces the domains in the tree
+/// implement.
+pub fn known_interfaces() -> BTreeSet<String> {
+    let mut files = Vec::new();
+    collect_rs_files(Path::new(INTERFACE_SRC), &mut files);
+    let mut interfaces = BTreeSet::new();
+    for file in files {
+        let Some(ast) = fs::read_to_string(&file)
+            .ok()
+            .and_then(|content| syn::parse_file(&content).ok())
+        else {
+            continue;
+        };
+        for item in ast.items {
+            if let Item::Trait(t) = item {
+                let name = t.ident.to_string();
+                if matches!(t.vis, Visibility::Public(_)) && name.ends_with("Domain") {
+                    interfaces.insert(name);
+                }
+            }
+        }
+    }
+    if interfaces.is_empty() {
+        for ty in DOMAIN_SET {
+            let Ok(entries) = fs::read_dir(ty) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                interfaces.extend(domain_interface(&name));
+            }
+        }
+    }
+    interfaces
+}
+
+fn boxed_trait(ty: &Type) -> Option<String> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(Type::TraitObject(object)) => {
+            object.bounds.iter().find_map(|bound| match bound {
+                syn::TypeParamBound::Trait(t) => {
+                    t.path.segments.last().map(|s| s.ident.to_string())
+                }
+                _ => None,
+            })
+        }
+        _ => None,
+    })
+}
+
+/// Strip the instance suffix of a registered domain name, e.g. `fatfs-1` is an
+/// instance of the `fatfs` domain.
+pub fn domain_of_instance(instance: &str) -> &str {
+    match instance.rsplit_once('-') {
+        Some((name, n)) if n.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => instance,
+    }
+}
+
+/// Every `get_domain` call in the domain and in the local crates of this
+/// repository it depends on.
+pub fn domain_lookups(name: &str) -> Vec<Lookup> {
+    let mut lookups = Vec::new();
+    for file in domain_source_files(name) {
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let Ok(ast) = syn::parse_file(&content) else {
+            continue;
+        };
+        lookups.extend(file_lookups(&file, &ast));
+    }
+    lookups
+}
+
+fn file_lookups(file: &Path, ast: &syn::File) -> Vec<Lookup> {
+    let mut visitor = LookupVisitor::new(file);
+    visitor.visit_file(ast);
+    visitor.finish()
+}
+
+/// The `.rs` files of the domain and of the local crates of this repository it
+/// depends on through `path = ...` dependencies.
+pub fn domain_source_files(name: &str) -> Vec<PathBuf> {
+    let Some(project) = domain_project(name) else {
+        return Vec::new();
+    };
+    let Ok(root) = Path::new(".").canonicalize() else {
+        return Vec::new();
+    };
+    let dirs = source_dirs(&project).unwrap_or_default();
+    let mut files = Vec::new();
+    for dir in dirs.iter().filter(|dir| dir.starts_with(&root)) {
+        collect_rs_files(dir, &mut files);
+    }
+    files.sort();
+    files
+        .into_iter()
+        .map(|f| Path::new(".").join(f.strip_prefix(&root).unwrap()))
+        .collect()
+}
+
+pub fn collect_rs_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.file_name().is_some_and(|n| n == "target") {
+            continue;
+        }
+        if path.is_dir() {
+            collect_rs_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// Finds `get_domain` calls and follows their result through `let` bindings, `match`
+/// arms and `if let` to see how the caller treats a missing domain and which
+/// `DomainType` variants it accepts.
+struct LookupVisitor<'a> {
+    file: &'a Path,
+    lookups: Vec<Lookup>,
+    /// Names bound in the enclosing blocks, innermost last, with the lookup they hold
+    scopes: Vec<Vec<(String, Option<usize>)>>,
+}
+
+impl<'a> LookupVisitor<'a> {
+    fn new(file: &'a Path) -> Self {
+        Self {
+            file,
+            lookups: Vec::new(),
+            scopes: vec![Vec::new()],
+        }
+    }
+
+    fn record(&mut self, call: &ExprCall) -> usize {
+        self.lookups.push(Lookup {
+            target: call.args.first().and_then(string_literal),
+            file: self.file.to_path_buf(),
+            line: call.span().start().line,
+            required: false,
+            expected: Vec::new(),
+        });
+        for arg in &call.args {
+            self.visit_expr(arg);
+        }
+        self.lookups.len() - 1
+    }
+
+    fn finish(mut self) -> Vec<Lookup> {
+        self.lookups.sort_by_key(|l| l.line);
+        self.lookups
+    }
+
+    fn bind(&mut self, name: String, lookup: Option<usize>) {
+        self.scopes.last_mut().unwrap().push((name, lookup));
+    }
+
+    fn binding(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, _)| n == name)
+            .and_then(|(_, lookup)| *lookup)
+    }
+
+    fn expect(&mut self, lookup: usize, pat: &Pat) {
+        let mut variants = Vec::new();
+        domain_variants(pat, &mut variants);
+        let expected = &mut self.lookups[lookup].expected;
+        for variant in variants {
+            if !expected.contains(&variant) {
+                expected.push(variant);
+            }
+        }
+    }
+
+    /// The lookup whose result `expr` is, recording the `get_domain` call if `expr`
+    /// contains it. `unwrap`, `expect`, `?` and `unwrap_or_else` with a panicking
+    /// closure make the lookup required.
+    ///
+    /// Returns `None` without visiting anything if `expr` is not such a result.
+    fn lookup_of(&mut self, expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Call(call) if is_get_domain(call) => Some(self.record(call)),
+            Expr::Path(path) => path
+                .path
+                .get_ident()
+                .and_then(|ident| self.binding(&ident.to_string())),
+            Expr::Paren(e) => self.lookup_of(&e.expr),
+            Expr::Reference(e) => self.lookup_of(&e.expr),
+            Expr::Try(e) => {
+                let lookup = self.lookup_of(&e.expr)?;
+                self.lookups[lookup].required = true;
+                Some(lookup)
+            }
+            Expr::MethodCall(call) => {
+                let required = match call.method.to_string().as_str() {
+                    "unwrap" | "expect" => true,
+                    "unwrap_or_else" => call.args.iter().any(|arg| match arg {
+                        Expr::Closure(c) => diverges(&c.body),
+                        _ => false,
+                    }),
+                    "clone" | "as_ref" => false,
+                    _ => return None,
+                };
+                let lookup = self.lookup_of(&call.receiver)?;
+                self.lookups[lookup].required |= required;
+                for arg in &call.args {
+                    self.visit_expr(arg);
+                }
+                Some(lookup)
+            }
+            _ => None,
+        }
+    }
+
+    /// A `match` on a lookup: the arm patterns name the accepted variants, and a
+    /// panicking catch-all or `None` arm makes the lookup required.
+    fn visit_lookup_match(&mut self, lookup: usize, arms: &[Arm]) {
+        for arm in arms {
+            self.expect(lookup, &arm.pat);
+            if is_catch_all(&arm.pat) && diverges(&arm.body) {
+                self.lookups[lookup].required = true;
+            }
+            self.scopes.push(Vec::new());
+            for name in aliases(&arm.pat) {
+                self.bind(name, Some(lookup));
+            }
+            if let Some((_, guard)) = &arm.guard {
+                self.visit_expr(guard);
+            }
+            self.visit_expr(&arm.body);
+            self.scopes.pop();
+        }
+    }
+}
+
+fn is_get_domain(call: &ExprCall) -> bool {
+    match call.func.as_ref() {
+        Expr::Path(func) => func
+            .path
+            .segments
+            .last()
+            .is_some_and(|s| s.ident == "get_domain"),
+        _ => false,
+    }
+}
+
+/// Whether evaluating `expr` always panics.
+fn diverges(expr: &Expr) -> bool {
+    match expr {
+        Expr::Macro(m) => is_panic_macro(&m.mac),
+        Expr::Block(b) => b.block.stmts.last().is_some_and(|stmt| match stmt {
+            Stmt::Expr(e, _) => diverges(e),
+            Stmt::Macro(m) => is_panic_macro(&m.mac),
+            _ => false,
+        }),
+        Expr::Paren(e) => diverges(&e.expr),
+        _ => false,
+    }
+}
+
+fn is_panic_macro(mac: &Macro) -> bool {
+    mac.path.segments.last().is_some_and(|s| {
+        ["panic", "unreachable", "todo", "unimplemented"]
+            .iter()
+            .any(|m| s.ident == m)
+    })
+}
+
+/// Whether the pattern matches a missing domain: `None`, `_` or a plain binding.
+fn is_catch_all(pat: &Pat) -> bool {
+    match pat {
+        Pat::Wild(_) => true,
+        Pat::Ident(p) => p.subpat.is_none(),
+        Pat::Path(p) => p.path.is_ident("None"),
+        Pat::Or(p) => p.cases.iter().any(is_catch_all),
+        _ => false,
+    }
+}
+
+/// Names the pattern binds to the looked-up `Option<DomainType>` or `DomainType`
+/// itself, e.g. `d` in `Some(d)`, but not the domain inside `DomainType::X(d)`.
+fn aliases(pat: &Pat) -> Vec<String> {
+    match pat {
+        Pat::Ident(p) if p.ident != "None" => vec![p.ident.to_string()],
+        Pat::TupleStruct(p) if p.path.is_ident("Some") => {
+            p.elems.iter().flat_map(aliases).collect()
+        }
+        Pat::Reference(p) => aliases(&p.pat),
+        Pat::Paren(p) => aliases(&p.pat),
+        _ => Vec::new(),
+    }
+}
+
+/// The `DomainType::X` variants matched anywhere in the pattern.
+fn domain_variants(pat: &Pat, variants: &mut Vec<String>) {
+    let mut variant = |path: &syn::Path| {
+        let segments: Vec<_> = path.segments.iter().collect();
+        if let [.., ty, v] = segments.as_slice() {
+            if ty.ident == "DomainType" {
+                variants.push(v.ident.to_string());
+            }
+        }
+    };
+    match pat {
+        Pat::TupleStruct(p) => {
+            variant(&p.path);
+            for elem in &p.elems {
+                domain_variants(elem, variants);
+            }
+        }
+        Pat::Struct(p) => variant(&p.path),
+        Pat::Path(p) => variant(&p.path),
+        Pat::Or(p) => p.cases.iter().for_each(|c| domain_variants(c, variants)),
+        Pat::Reference(p) => domain_variants(&p.pat, variants),
+        Pat::Paren(p) => domain_variants(&p.pat, variants),
+        Pat::Tuple(p) => p.elems.iter().for_each(|e| domain_variants(e, variants)),
+        Pat::Ident(p) => {
+            if let Some((_, sub)) = &p.subpat {
+                domain_variants(sub, variants);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every name the pattern binds.
+fn bound_names(pat: &Pat, names: &mut Vec<String>) {
+    match pat {
+        Pat::Ident(p) => {
+            names.push(p.ident.to_string());
+            if let Some((_, sub)) = &p.subpat {
+                bound_names(sub, names);
+            }
+        }
+        Pat::TupleStruct(p) => p.elems.iter().for_each(|e| bound_names(e, names)),
+        Pat::Tuple(p) => p.elems.iter().for_each(|e| bound_names(e, names)),
+        Pat::Struct(p) => p.fields.iter().for_each(|f| bound_names(&f.pat, names)),
+        Pat::Or(p) => p.cases.iter().for_each(|c| bound_names(c, names)),
+        Pat::Reference(p) => bound_names(&p.pat, names),
+        Pat::Paren(p) => bound_names(&p.pat, names),
+        Pat::Type(p) => bound_names(&p.pat, names),
+        Pat::Slice(p) => p.elems.iter().for_each(|e| bound_names(e, names)),
+        _ => {}
+    }
+}
+
+impl<'ast> Visit<'ast> for LookupVisitor<'_> {
+    fn visit_block(&mut self, block: &'ast Block) {
+        self.scopes.push(Vec::new());
+        visit::visit_block(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_local(&mut self, local: &'ast Local) {
+        let mut lookup = None;
+        if let Some(init) = &local.init {
+            lookup = self.lookup_of(&init.expr);
+            if lookup.is_none() {
+                self.visit_expr(&init.expr);
+            }
+            if let Some((_, diverge)) = &init.diverge {
+                // `let PAT = lookup else { panic!() };`
+                if let Some(lookup) = lookup {
+                    self.expect(lookup, &local.pat);
+                    if diverges(diverge) {
+                        self.lookups[lookup].required = true;
+                    }
+                }
+                self.visit_expr(diverge);
+            }
+        }
+        let aliases = aliases(&local.pat);
+        let mut names = Vec::new();
+        bound_names(&local.pat, &mut names);
+        for name in names {
+            let alias = lookup.filter(|_| aliases.contains(&name));
+            self.bind(name, alias);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if self.lookup_of(expr).is_some() {
+            return;
+        }
+        match expr {
+            Expr::Match(m) => match self.lookup_of(&m.expr) {
+                Some(lookup) => self.visit_lookup_match(lookup, &m.arms),
+                None => visit::visit_expr_match(self, m),
+            },
+            Expr::If(ExprIf {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            }) => {
+                let Expr::Let(cond) = cond.as_ref() else {
+                    return visit::visit_expr(self, expr);
+                };
+                let lookup = self.lookup_of(&cond.expr);
+                let Some(lookup) = lookup else {
+                    return visit::visit_expr(self, expr);
+                };
+                self.expect(lookup, &cond.pat);
+                self.scopes.push(Vec::new());
+                for name in aliases(&cond.pat) {
+                    self.bind(name, Some(lookup));
+                }
+                self.visit_block(then_branch);
+                self.scopes.pop();
+                if let Some((_, else_branch)) = else_branch {
+                    if diverges(else_branch) {
+                        self.lookups[lookup].required = true;
+                    }
+                    self.visit_expr(else_branch);
+                }
+            }
+            _ => visit::visit_expr(self, expr),
+        }
+    }
+}
+
+fn string_literal(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) => Some(s.value()),
+        Expr::Reference(r) => string_literal(&r.expr),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookups(source: &str) -> Vec<Lookup> {
+        let ast = syn::parse_file(source).unwrap();
+        file_lookups(Path::new("test.rs"), &ast)
+    }
+
+    fn lookup<'a>(lookups: &'a [Lookup], target: &str) -> &'a Lookup {
+        lookups
+            .iter()
+            .find(|l| l.target.as_deref() == Some(target))
+            .unwrap()
+    }
+
+    /// The optional lookups of `scan_system_devices` in the vfs domain: a lookup is
+    /// only required if its own `None` arm panics, and a `DomainType` pattern in the
+    /// arm of one lookup is not attributed to the lookups declared after it.
+    #[test]
+    fn optional_lookups_bound_then_matched() {
+        let lookups = lookups(
+            r#"
+            fn scan() {
+                let uart = basic::get_domain("buf_uart");
+                let blk = basic::get_domain("cache_blk-1");
+                let rtc = basic::get_domain("goldfish");
+                match uart {
+                    Some(uart) => {
+                        if let DomainType::BufUartDomain(uart) = uart {
+                            UART.call_once(|| uart);
+                        }
+                    }
+                    None => {
+                        panic!("uart domain not found");
+                    }
+                }
+                match blk {
+                    Some(_) => register(),
+                    None => panic!("blk domain not found"),
+                }
+                match rtc {
+                    Some(_) => register(),
+                    None => {
+                        println!("rtc domain not found");
+                    }
+                };
+            }
+            "#,
+        );
+        let uart = lookup(&lookups, "buf_uart");
+        assert!(uart.required);
+        assert_eq!(uart.expected, ["BufUartDomain"]);
+        assert!(lookup(&lookups, "cache_blk-1").required);
+        let rtc = lookup(&lookups, "goldfish");
+        assert!(!rtc.required);
+        assert!(rtc.expected.is_empty());
+    }
+
+    #[test]
+    fn unwrapped_lookup_matched_later() {
+        let lookups = lookups(
+            r#"
+            fn main() {
+                let vfs = basic::get_domain("vfs").unwrap();
+                let vfs = match vfs {
+                    DomainType::VfsDomain(vfs) => vfs,
+                    _ => panic!("vfs domain not found"),
+                };
+                let gpu = basic::get_domain("virtio_mmio_gpu");
+                let gpu = match gpu {
+                    Some(DomainType::GpuDomain(gpu)) => Some(gpu),
+                    _ => None,
+                };
+            }
+            "#,
+        );
+        let vfs = lookup(&lookups, "vfs");
+        assert!(vfs.required);
+        assert_eq!(vfs.expected, ["VfsDomain"]);
+        let gpu = lookup(&lookups, "virtio_mmio_gpu");
+        assert!(!gpu.required);
+        assert_eq!(gpu.expected, ["GpuDomain"]);
+    }
+
+    #[test]
+    fn let_else_try_and_closures() {
+        let lookups = lookups(
+            r#"
+            fn init() -> AlienResult<()> {
+                let Some(DomainType::RtcDomain(rtc)) = basic::get_domain("goldfish") else {
+                    panic!("no rtc");
+                };
+                let Some(DomainType::RtcDomain(rtc)) = basic::get_domain("goldfish-2") else {
+                    return seed_from_time();
+                };
+                let dev = basic::get_domain(name)?;
+                let fs = basic::get_domain("fatfs-1")
+                    .unwrap_or_else(|| panic!("fatfs domain not found"));
+                let task = TASK.try_call_once(|| match basic::get_domain("task") {
+                    Some(DomainType::TaskDomain(task)) => Ok(task),
+                    _ => Err(AlienError::ENOSYS),
+                });
+            }
+            "#,
+        );
+        let rtc = lookup(&lookups, "goldfish");
+        assert!(rtc.required);
+        assert_eq!(rtc.expected, ["RtcDomain"]);
+        assert!(!lookup(&lookups, "goldfish-2").required);
+        let dynamic = lookups.iter().find(|l| l.target.is_none()).unwrap();
+        assert!(dynamic.required);
+        assert!(lookup(&lookups, "fatfs-1").required);
+        let task = lookup(&lookups, "task");
+        assert!(!task.required);
+        assert_eq!(task.expected, ["TaskDomain"]);
+    }
+}
@@ -1,9 +1,25 @@
-#![no_std]
-#![forbid(unsafe_code)]
-extern crate alloc;
-use alloc::boxed::Box;
-use interface::INTERFACE;
-
-pub fn main()->Box<dyn INTERFACE>{
-
-}
+#![no_std]
+#![forbid(unsafe_code)]
+extern crate alloc;
+use alloc::boxed::Box;
+USES
+use interface::{define_unwind_for_INTERFACE, Basic, INTERFACE};
+
+#[derive(Debug)]
+pub struct IMPL;
+
+impl Basic for IMPL {
+    fn domain_id(&self) -> u64 {
+        shared_heap::domain_id()
+    }
+}
+
+impl INTERFACE for IMPL {
+    METHODS
+}
+
+define_unwind_for_INTERFACE!(IMPL);
+
+pub fn main() -> Box<dyn INTERFACE> {
+    Box::new(UnwindWrap::new(IMPL))
+}
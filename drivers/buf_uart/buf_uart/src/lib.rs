@@ -2,29 +2,57 @@
 #![forbid(unsafe_code)]
 extern crate alloc;
 
-use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
 use core::fmt::Debug;
 
 use basic::{
+    constants::signal::SignalNumber,
     println,
     sync::{Mutex, Once, OnceGet},
     AlienError, AlienResult,
 };
 use interface::{
-    define_unwind_for_BufUartDomain, Basic, BufUartDomain, DeviceBase, DomainType, UartDomain,
+    define_unwind_for_BufUartDomain, Basic, BufUartDomain, DeviceBase, DomainType, TaskDomain,
+    UartDomain,
 };
 use shared_heap::DVec;
 
+/// The characters that generate `SIGINT` and `SIGTSTP`, Ctrl-C and Ctrl-Z
+const INTR_CHAR: u8 = 0x03;
+const SUSP_CHAR: u8 = 0x1a;
+
 #[derive(Debug)]
 pub struct Uart {
     inner: Mutex<UartInner>,
     uart: Once<Arc<dyn UartDomain>>,
+    task: Once<Arc<dyn TaskDomain>>,
 }
 
 #[derive(Debug)]
 struct UartInner {
     rx_buf: VecDeque<u8>,
     wait_queue: VecDeque<usize>,
+    /// Tasks waiting in epoll for data to arrive
+    poll_waiters: Vec<usize>,
+    /// How often `poll_waiters` were woken
+    poll_wakes: usize,
+    /// The foreground process group of the terminal, 0 if there is none
+    foreground_pgid: usize,
+    /// Whether the control characters generate signals
+    isig: bool,
+}
+
+impl UartInner {
+    fn signal_for(&self, ch: u8) -> Option<SignalNumber> {
+        if !self.isig || self.foreground_pgid == 0 {
+            return None;
+        }
+        match ch {
+            INTR_CHAR => Some(SignalNumber::SIGINT),
+            SUSP_CHAR => Some(SignalNumber::SIGTSTP),
+            _ => None,
+        }
+    }
 }
 
 impl Default for Uart {
@@ -38,10 +66,15 @@ impl Uart {
         let inner = UartInner {
             rx_buf: VecDeque::new(),
             wait_queue: VecDeque::new(),
+            poll_waiters: Vec::new(),
+            poll_wakes: 0,
+            foreground_pgid: 0,
+            isig: false,
         };
         Uart {
             inner: Mutex::new(inner),
             uart: Once::new(),
+            task: Once::new(),
         }
     }
 }
@@ -57,12 +90,26 @@ impl DeviceBase for Uart {
         let mut inner = self.inner.lock();
         let uart = self.uart.get_must();
         while let Ok(Some(c)) = uart.getc() {
+            if let Some(signum) = inner.signal_for(c) {
+                // like on Linux the signal characters discard the pending input
+                inner.rx_buf.clear();
+                if let Some(task) = self.task.get() {
+                    let _ = task.do_kill_pgrp(inner.foreground_pgid, signum as usize);
+                }
+                continue;
+            }
             inner.rx_buf.push_back(c);
             if !inner.wait_queue.is_empty() {
                 let tid = inner.wait_queue.pop_front().unwrap();
                 basic::wake_up_wait_task(tid)?
             }
         }
+        if !inner.rx_buf.is_empty() {
+            inner.poll_wakes += 1;
+            for tid in inner.poll_waiters.drain(..) {
+                basic::wake_up_wait_task(tid)?
+            }
+        }
         Ok(())
     }
 }
@@ -115,9 +162,43 @@ impl BufUartDomain for Uart {
         Ok(!self.inner.lock().rx_buf.is_empty())
     }
 
+    fn pending_bytes(&self) -> AlienResult<usize> {
+        Ok(self.inner.lock().rx_buf.len())
+    }
+
     fn have_space_to_put(&self) -> AlienResult<bool> {
         Ok(true)
     }
+
+    fn poll_register(&self, tid: usize) -> AlienResult<()> {
+        let mut inner = self.inner.lock();
+        if !inner.poll_waiters.contains(&tid) {
+            inner.poll_waiters.push(tid);
+        }
+        Ok(())
+    }
+
+    fn poll_unregister(&self, tid: usize) -> AlienResult<()> {
+        self.inner.lock().poll_waiters.retain(|t| *t != tid);
+        Ok(())
+    }
+
+    fn poll_wakes(&self) -> AlienResult<usize> {
+        Ok(self.inner.lock().poll_wakes)
+    }
+
+    fn set_job_control(&self, foreground_pgid: usize, isig: bool) -> AlienResult<()> {
+        // the signals are sent through the task domain, which is up once a terminal is used
+        self.task
+            .try_call_once(|| match basic::get_domain("task") {
+                Some(DomainType::TaskDomain(task)) => Ok(task),
+                _ => Err(AlienError::ENOSYS),
+            })?;
+        let mut inner = self.inner.lock();
+        inner.foreground_pgid = foreground_pgid;
+        inner.isig = isig;
+        Ok(())
+    }
 }
 
 define_unwind_for_BufUartDomain!(Uart);
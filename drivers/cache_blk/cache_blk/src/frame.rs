@@ -76,12 +76,12 @@ impl GenericBlockDevice {
             }
             let cache = PageCache(frame);
             let old_cache = cache_lock.push(page_id, cache);
-            if let Some((id, old_cache)) = old_cache {
+            if let Some((id, mut old_cache)) = old_cache {
                 let start_block = id * FRAME_SIZE / 512;
                 let end_block = start_block + FRAME_SIZE / 512;
                 for i in start_block..end_block {
                     let target_buf =
-                        &old_cache.0[(i - start_block) * 512..(i - start_block + 1) * 512];
+                        &mut old_cache.0[(i - start_block) * 512..(i - start_block + 1) * 512];
                     let tmp_buf = DVec::from_other_rvec_slice(target_buf);
                     device.write_block(i as u32, &tmp_buf).unwrap();
                     self.dirty.lock().retain(|&x| x != id);
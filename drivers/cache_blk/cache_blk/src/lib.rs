@@ -192,3 +192,106 @@ pub fn main() -> Box<dyn CacheBlkDeviceDomain> {
 }
 
 // pub use frame::main;
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec, vec::Vec};
+
+    use basic::{sync::Mutex, AlienResult};
+    use interface::{Basic, CacheBlkDeviceDomain, DeviceBase, DomainType, ShadowBlockDomain};
+    use shared_heap::DVec;
+
+    use super::{GenericBlockDevice, PageCache, FRAME_SIZE};
+
+    /// A disk in memory with the block device interface
+    #[derive(Debug)]
+    struct MemDisk(Mutex<Vec<u8>>);
+
+    impl Basic for MemDisk {
+        fn domain_id(&self) -> u64 {
+            shared_heap::domain_id()
+        }
+    }
+
+    impl DeviceBase for MemDisk {
+        fn handle_irq(&self) -> AlienResult<()> {
+            Ok(())
+        }
+    }
+
+    impl ShadowBlockDomain for MemDisk {
+        fn init(&self, _device_name: &str) -> AlienResult<()> {
+            Ok(())
+        }
+
+        fn read_block(&self, block: u32, mut data: DVec<u8>) -> AlienResult<DVec<u8>> {
+            let start = block as usize * 512;
+            data.as_mut_slice()
+                .copy_from_slice(&self.0.lock()[start..start + 512]);
+            Ok(data)
+        }
+
+        fn write_block(&self, block: u32, data: &DVec<u8>) -> AlienResult<usize> {
+            let start = block as usize * 512;
+            self.0.lock()[start..start + 512].copy_from_slice(data.as_slice());
+            Ok(data.len())
+        }
+
+        fn get_capacity(&self) -> AlienResult<u64> {
+            Ok(self.0.lock().len() as u64)
+        }
+
+        fn flush(&self) -> AlienResult<()> {
+            Ok(())
+        }
+    }
+
+    fn page_cache(fill: impl Fn(usize) -> u8) -> PageCache {
+        let slices = (0..FRAME_SIZE / 512)
+            .map(|i| {
+                let bytes = (0..512).map(|j| fill(i * 512 + j)).collect::<Vec<_>>();
+                DVec::from_slice(&bytes)
+            })
+            .collect();
+        PageCache(slices)
+    }
+
+    #[test]
+    fn copy_to_crosses_slices() {
+        let cache = page_cache(|i| i as u8);
+        let mut buf = [0u8; 600];
+        cache.copy_to(500, &mut buf);
+        assert!(buf.iter().enumerate().all(|(i, &b)| b == (500 + i) as u8));
+    }
+
+    #[test]
+    fn copy_from_crosses_slices() {
+        let mut cache = page_cache(|_| 0);
+        cache.copy_from(1000, &[0xaa; 100]);
+        assert!(cache.get(1).as_slice()[488..].iter().all(|&b| b == 0xaa));
+        assert!(cache.get(2).as_slice()[..76].iter().all(|&b| b == 0xaa));
+        assert_eq!(cache.get(2).as_slice()[76], 0);
+        assert_eq!(cache.get(1).as_slice()[487], 0);
+    }
+
+    #[test]
+    fn evicted_pages_are_written_back() {
+        let disk = Arc::new(MemDisk(Mutex::new(vec![0; 4 * FRAME_SIZE])));
+        basic::register_mock_domain("mem_disk", DomainType::ShadowBlockDomain(disk.clone()));
+        let dev = GenericBlockDevice::new(1);
+        dev.init("mem_disk").unwrap();
+
+        // a write across the first page boundary
+        let offset = FRAME_SIZE as u64 - 8;
+        let data = DVec::from_slice(&[0x5a; 16]);
+        assert_eq!(dev.write(offset, &data).unwrap(), 16);
+        // reading another page pushes the second one out of the single cache frame
+        dev.read(2 * FRAME_SIZE as u64, DVec::new_uninit(1))
+            .unwrap();
+        assert_eq!(disk.0.lock()[FRAME_SIZE..FRAME_SIZE + 8], [0x5a; 8]);
+
+        let buf = dev.read(offset, DVec::new(0, 16)).unwrap();
+        assert_eq!(buf.as_slice(), &[0x5a; 16]);
+        assert_eq!(dev.get_capacity().unwrap(), 4 * FRAME_SIZE as u64);
+    }
+}
@@ -0,0 +1,195 @@
+//! A ChaCha20 based CSPRNG.
+//!
+//! The generator keeps a 256 bit key and a block counter. Every request is served from the
+//! keystream and followed by a fresh key taken from the same keystream, so the output that
+//! was already handed out cannot be recomputed from a later state.
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+const KEY_WORDS: usize = 8;
+const BLOCK_LEN: usize = 64;
+
+/// Reseed after this many bytes of output...
+const RESEED_BYTES: usize = 1 << 20;
+/// ...or after this many milliseconds, whichever comes first.
+const RESEED_INTERVAL_MS: u64 = 5 * 60 * 1000;
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block of `key` at `counter`, with a zero nonce.
+fn block(key: &[u32; KEY_WORDS], counter: u64) -> [u8; BLOCK_LEN] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+    let mut out = [0; BLOCK_LEN];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+#[derive(Debug)]
+pub struct ChaChaRng {
+    key: [u32; KEY_WORDS],
+    counter: u64,
+    seeded: bool,
+    /// Bytes handed out since the last reseed
+    output: usize,
+    last_reseed_ms: u64,
+}
+
+impl ChaChaRng {
+    pub const fn new() -> Self {
+        Self {
+            key: [0; KEY_WORDS],
+            counter: 0,
+            seeded: false,
+            output: 0,
+            last_reseed_ms: 0,
+        }
+    }
+
+    /// Whether the generator should be reseeded before serving more output at `now_ms`.
+    pub fn needs_reseed(&self, now_ms: u64) -> bool {
+        !self.seeded
+            || self.output >= RESEED_BYTES
+            || now_ms.saturating_sub(self.last_reseed_ms) >= RESEED_INTERVAL_MS
+    }
+
+    /// Mix `entropy` into the key. Entropy that was mixed in before is kept.
+    pub fn mix(&mut self, entropy: &[u8]) {
+        for chunk in entropy.chunks(KEY_WORDS * 4) {
+            for (i, byte) in chunk.iter().enumerate() {
+                self.key[i / 4] ^= (*byte as u32) << (8 * (i % 4));
+            }
+            self.rekey();
+        }
+    }
+
+    /// Mix `entropy` into the key and count it as a fresh seed.
+    pub fn reseed(&mut self, entropy: &[u8], now_ms: u64) {
+        self.mix(entropy);
+        self.seeded = true;
+        self.output = 0;
+        self.last_reseed_ms = now_ms;
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(BLOCK_LEN) {
+            let block = block(&self.key, self.counter);
+            self.counter = self.counter.wrapping_add(1);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.rekey();
+        self.output = self.output.saturating_add(buf.len());
+    }
+
+    /// Replace the key with the next keystream block.
+    fn rekey(&mut self) {
+        let block = block(&self.key, self.counter);
+        self.counter = self.counter.wrapping_add(1);
+        for (word, bytes) in self.key.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// RFC 8439, A.1, test vector #1: all-zero key, nonce and block counter
+    const ZERO_KEY_BLOCK_0: [u8; BLOCK_LEN] = [
+        0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86, 0xbd,
+        0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc, 0x8b, 0x77,
+        0x0d, 0xc7, 0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d, 0x77, 0x24, 0xe0, 0x3f, 0xb8,
+        0xd8, 0x4a, 0x37, 0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c, 0xc3, 0x87, 0xb6, 0x69,
+        0xb2, 0xee, 0x65, 0x86,
+    ];
+    /// RFC 8439, A.1, test vector #2: as #1 with block counter 1
+    const ZERO_KEY_BLOCK_1: [u8; BLOCK_LEN] = [
+        0x9f, 0x07, 0xe7, 0xbe, 0x55, 0x51, 0x38, 0x7a, 0x98, 0xba, 0x97, 0x7c, 0x73, 0x2d, 0x08,
+        0x0d, 0xcb, 0x0f, 0x29, 0xa0, 0x48, 0xe3, 0x65, 0x69, 0x12, 0xc6, 0x53, 0x3e, 0x32, 0xee,
+        0x7a, 0xed, 0x29, 0xb7, 0x21, 0x76, 0x9c, 0xe6, 0x4e, 0x43, 0xd5, 0x71, 0x33, 0xb0, 0x74,
+        0xd8, 0x39, 0xd5, 0x31, 0xed, 0x1f, 0x28, 0x51, 0x0a, 0xfb, 0x45, 0xac, 0xe1, 0x0a, 0x1f,
+        0x4b, 0x79, 0x4d, 0x6f,
+    ];
+
+    fn key_of(block: &[u8; BLOCK_LEN]) -> [u32; KEY_WORDS] {
+        let mut key = [0; KEY_WORDS];
+        for (word, bytes) in key.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        key
+    }
+
+    #[test]
+    fn block_matches_rfc8439() {
+        assert_eq!(block(&[0; KEY_WORDS], 0), ZERO_KEY_BLOCK_0);
+        assert_eq!(block(&[0; KEY_WORDS], 1), ZERO_KEY_BLOCK_1);
+    }
+
+    #[test]
+    fn fill_rekeys_from_the_keystream() {
+        let mut rng = ChaChaRng::new();
+        let mut first = [0; BLOCK_LEN];
+        rng.fill(&mut first);
+        assert_eq!(first, ZERO_KEY_BLOCK_0);
+        // the block after the output becomes the key
+        assert_eq!(rng.key, key_of(&ZERO_KEY_BLOCK_1));
+        assert_eq!(rng.counter, 2);
+
+        let mut second = [0; BLOCK_LEN];
+        rng.fill(&mut second);
+        assert_eq!(second, block(&key_of(&ZERO_KEY_BLOCK_1), 2));
+        assert_ne!(rng.key, key_of(&ZERO_KEY_BLOCK_1));
+    }
+
+    #[test]
+    fn reseed_after_enough_output() {
+        let mut rng = ChaChaRng::new();
+        assert!(rng.needs_reseed(0));
+        rng.reseed(b"entropy", 1000);
+        assert!(!rng.needs_reseed(1000));
+        rng.fill(&mut vec![0; RESEED_BYTES - 1]);
+        assert!(!rng.needs_reseed(1000));
+        rng.fill(&mut [0; 1]);
+        assert!(rng.needs_reseed(1000));
+        rng.reseed(b"more entropy", 1000);
+        assert!(!rng.needs_reseed(1000));
+    }
+
+    #[test]
+    fn reseed_after_the_interval() {
+        let mut rng = ChaChaRng::new();
+        rng.reseed(b"entropy", 1000);
+        assert!(!rng.needs_reseed(1000 + RESEED_INTERVAL_MS - 1));
+        assert!(rng.needs_reseed(1000 + RESEED_INTERVAL_MS));
+        // a clock that went backwards does not force a reseed
+        assert!(!rng.needs_reseed(0));
+    }
+}
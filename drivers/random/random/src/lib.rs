@@ -2,15 +2,82 @@
 #![forbid(unsafe_code)]
 extern crate alloc;
 
-use alloc::boxed::Box;
+mod chacha;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::fmt::Debug;
 
-use basic::AlienResult;
-use interface::{define_unwind_for_EmptyDeviceDomain, Basic, EmptyDeviceDomain};
-use shared_heap::DVec;
+use basic::{
+    constants::io::RtcTime,
+    sync::Mutex,
+    time::{read_time_ms, read_timer},
+    AlienResult,
+};
+use interface::{define_unwind_for_EmptyDeviceDomain, Basic, DomainType, EmptyDeviceDomain};
+use shared_heap::{DBox, DVec};
+use storage::CustomStorge;
+
+use crate::chacha::ChaChaRng;
+
+/// Timer samples taken per reseed
+const JITTER_SAMPLES: usize = 256;
+
+/// The generator lives in the storage, so it keeps its entropy when the domain is replaced.
+type Rng = Arc<Mutex<ChaChaRng>, CustomStorge>;
 
 #[derive(Debug)]
-pub struct RandomDeviceDomainImpl;
+pub struct RandomDeviceDomainImpl {
+    rng: Rng,
+}
+
+impl RandomDeviceDomainImpl {
+    fn new() -> Self {
+        let rng = storage::get_or_insert("random_state", || Mutex::new(ChaChaRng::new()));
+        Self { rng }
+    }
+}
+
+/// The low bits of the time a short busy loop takes vary with caches, interrupts and
+/// the other harts.
+fn timer_jitter(pool: &mut Vec<u8>) {
+    let mut last = read_timer();
+    for i in 0..JITTER_SAMPLES {
+        let mut x = last;
+        for j in 0..(last & 0xf) + 1 {
+            x = core::hint::black_box(x.rotate_left(7) ^ j ^ i);
+        }
+        let now = read_timer();
+        let delta = now.wrapping_sub(last);
+        pool.push((delta ^ (delta >> 8) ^ x) as u8);
+        last = now;
+    }
+}
+
+/// The wall clock makes the seed differ between boots of the same image.
+fn rtc_entropy(pool: &mut Vec<u8>) {
+    // unique name
+    let Some(DomainType::RtcDomain(rtc)) = basic::get_domain("goldfish") else {
+        return;
+    };
+    if let Ok(time) = rtc.read_time(DBox::new(RtcTime::default())) {
+        for v in [
+            time.year, time.mon, time.mday, time.hour, time.min, time.sec,
+        ] {
+            pool.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+}
+
+fn collect_entropy(with_rtc: bool) -> Vec<u8> {
+    let mut pool = Vec::with_capacity(JITTER_SAMPLES + 64);
+    pool.extend_from_slice(&read_timer().to_le_bytes());
+    pool.extend_from_slice(&read_time_ms().to_le_bytes());
+    if with_rtc {
+        rtc_entropy(&mut pool);
+    }
+    timer_jitter(&mut pool);
+    pool
+}
 
 impl Basic for RandomDeviceDomainImpl {
     fn domain_id(&self) -> u64 {
@@ -20,18 +87,29 @@ impl Basic for RandomDeviceDomainImpl {
 
 impl EmptyDeviceDomain for RandomDeviceDomainImpl {
     fn init(&self) -> AlienResult<()> {
+        // a replaced domain mixes new entropy into the state it took over
+        let entropy = collect_entropy(true);
+        self.rng.lock().reseed(&entropy, read_time_ms());
         Ok(())
     }
 
+    /// The generator is seeded in `init`, so reads never have to wait for entropy.
     fn read(&self, mut data: DVec<u8>) -> AlienResult<DVec<u8>> {
-        let mut current_time = basic::time::read_time_ms();
-        data.as_mut_slice().iter_mut().for_each(|x| {
-            *x = current_time as u8;
-            current_time = current_time.wrapping_sub(1);
-        });
+        let now = read_time_ms();
+        let needs_reseed = self.rng.lock().needs_reseed(now);
+        // collect without holding the lock, the jitter loop takes a while
+        let entropy = needs_reseed.then(|| collect_entropy(false));
+        let mut rng = self.rng.lock();
+        if let Some(entropy) = entropy {
+            rng.reseed(&entropy, now);
+        }
+        rng.fill(data.as_mut_slice());
         Ok(data)
     }
+
+    /// Written data is mixed into the generator, like on Linux it is not credited as entropy.
     fn write(&self, data: &DVec<u8>) -> AlienResult<usize> {
+        self.rng.lock().mix(data.as_slice());
         Ok(data.len())
     }
 }
@@ -39,5 +117,5 @@ impl EmptyDeviceDomain for RandomDeviceDomainImpl {
 define_unwind_for_EmptyDeviceDomain!(RandomDeviceDomainImpl);
 
 pub fn main() -> Box<dyn EmptyDeviceDomain> {
-    Box::new(UnwindWrap::new(RandomDeviceDomainImpl))
+    Box::new(UnwindWrap::new(RandomDeviceDomainImpl::new()))
 }
@@ -1,7 +1,8 @@
 use alloc::sync::Arc;
 
-use basic::constants::DeviceId;
-use interface::CacheBlkDeviceDomain;
+use basic::{constants::DeviceId, AlienResult};
+use interface::{CacheBlkDeviceDomain, TaskDomain};
+use ioctl::{ArgKind, Ioctl, IoctlArg, BLKGETSIZE64, BLKSSZGET};
 use shared_heap::DVec;
 use vfscore::{
     error::VfsError,
@@ -11,17 +12,50 @@ use vfscore::{
     VfsResult,
 };
 
+/// The logical sector size reported to the user
+const SECTOR_SIZE: usize = 512;
+
 pub struct BLKDevice {
     device_id: DeviceId,
     device: Arc<dyn CacheBlkDeviceDomain>,
+    task_domain: Arc<dyn TaskDomain>,
 }
 
 impl BLKDevice {
-    pub fn new(device_id: DeviceId, device: Arc<dyn CacheBlkDeviceDomain>) -> Self {
-        Self { device_id, device }
+    pub fn new(
+        device_id: DeviceId,
+        device: Arc<dyn CacheBlkDeviceDomain>,
+        task: Arc<dyn TaskDomain>,
+    ) -> Self {
+        Self {
+            device_id,
+            device,
+            task_domain: task,
+        }
+    }
+
+    fn get_size(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let size = self.device.get_capacity()? as u64;
+        arg.write(&size)?;
+        Ok(0)
+    }
+
+    fn get_sector_size(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        arg.write(&(SECTOR_SIZE as i32))?;
+        Ok(0)
     }
 }
 
+/// `BLKSSZGET` predates the size encoding, so its entry gives the argument.
+static BLK_IOCTLS: &[Ioctl<BLKDevice>] = &[
+    Ioctl::new(BLKGETSIZE64, BLKDevice::get_size),
+    Ioctl::with_arg(
+        BLKSSZGET,
+        ArgKind::output::<i32>(),
+        BLKDevice::get_sector_size,
+    ),
+];
+
 impl VfsFile for BLKDevice {
     fn read_at(&self, offset: u64, buf: DVec<u8>) -> VfsResult<(DVec<u8>, usize)> {
         let len = buf.len();
@@ -40,8 +74,14 @@ impl VfsFile for BLKDevice {
     fn poll(&self, _event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
         unimplemented!()
     }
-    fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
-        unimplemented!()
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        Ok(ioctl::dispatch(
+            BLK_IOCTLS,
+            self,
+            &*self.task_domain,
+            cmd,
+            arg,
+        )?)
     }
     fn flush(&self) -> VfsResult<()> {
         Ok(())
@@ -59,7 +99,7 @@ impl VfsInode for BLKDevice {
         Ok(VfsFileStat {
             st_rdev: self.device_id.id(),
             st_size: self.device.get_capacity().unwrap(),
-            st_blksize: 512,
+            st_blksize: SECTOR_SIZE as _,
             ..Default::default()
         })
     }
@@ -54,13 +54,14 @@ impl DevKernelProvider for ProviderImpl {
                 let device_domain = basic::get_domain(device_domain_name.as_str())?;
                 match device_domain {
                     DomainType::CacheBlkDeviceDomain(blk) => {
-                        let dev = Arc::new(BLKDevice::new(rdev.into(), blk));
+                        let task_domain = TASK_DOMAIN.get_must().clone();
+                        let dev = Arc::new(BLKDevice::new(rdev.into(), blk, task_domain));
                         dev_shim.insert(rdev, dev.clone());
                         Some(dev)
                     }
                     DomainType::BufUartDomain(uart) => {
                         let task_domain = TASK_DOMAIN.get_must().clone();
-                        let dev = Arc::new(UARTDevice::new(rdev.into(), uart, task_domain));
+                        let dev = Arc::new(UARTDevice::new(rdev.into(), uart, task_domain).ok()?);
                         dev_shim.insert(rdev, dev.clone());
                         Some(dev)
                     }
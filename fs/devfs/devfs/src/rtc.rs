@@ -1,12 +1,15 @@
 use alloc::{format, sync::Arc};
 use core::{cmp::min, ops::Deref};
 
-use basic::constants::{
-    io::{RtcTime, TeletypeCommand},
-    DeviceId,
+use basic::{
+    constants::{
+        io::{RtcTime, TeletypeCommand},
+        DeviceId,
+    },
+    AlienResult,
 };
 use interface::{RtcDomain, TaskDomain};
-use pod::Pod;
+use ioctl::{ArgKind, Ioctl, IoctlArg};
 use shared_heap::{DBox, DVec};
 use vfscore::{
     error::VfsError,
@@ -31,8 +34,20 @@ impl RTCDevice {
             task_domain: task,
         }
     }
+
+    fn read_time(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let time = self.device.read_time(DBox::new(RtcTime::default()))?;
+        arg.write(time.deref())?;
+        Ok(0)
+    }
 }
 
+static RTC_IOCTLS: &[Ioctl<RTCDevice>] = &[Ioctl::with_arg(
+    TeletypeCommand::RTC_RD_TIME as u32,
+    ArgKind::output::<RtcTime>(),
+    RTCDevice::read_time,
+)];
+
 impl VfsFile for RTCDevice {
     fn read_at(&self, _offset: u64, mut buf: DVec<u8>) -> VfsResult<(DVec<u8>, usize)> {
         let mut time = DBox::new(RtcTime::default());
@@ -47,18 +62,13 @@ impl VfsFile for RTCDevice {
         todo!()
     }
     fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
-        let cmd = TeletypeCommand::try_from(cmd).map_err(|_| VfsError::Invalid)?;
-        match cmd {
-            TeletypeCommand::RTC_RD_TIME => {
-                let mut time = DBox::new(RtcTime::default());
-                time = self.device.read_time(time).unwrap();
-                self.task_domain
-                    .copy_to_user(arg, time.deref().as_bytes())
-                    .unwrap();
-            }
-            _ => return Err(VfsError::Invalid),
-        }
-        Ok(0)
+        Ok(ioctl::dispatch(
+            RTC_IOCTLS,
+            self,
+            &*self.task_domain,
+            cmd,
+            arg,
+        )?)
     }
     fn flush(&self) -> VfsResult<()> {
         Ok(())
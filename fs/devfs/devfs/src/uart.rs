@@ -3,12 +3,14 @@ use alloc::sync::Arc;
 use basic::{
     constants::{
         io::{LocalModes, TeletypeCommand, Termios, WinSize},
+        signal::SignalNumber,
         DeviceId,
     },
     sync::Mutex,
+    AlienError, AlienResult,
 };
 use interface::{BufUartDomain, TaskDomain};
-use pod::Pod;
+use ioctl::{ArgKind, Ioctl, IoctlArg, FIONREAD, TIOCSCTTY};
 use shared_heap::DVec;
 use vfscore::{
     error::VfsError,
@@ -19,8 +21,14 @@ use vfscore::{
     VfsResult,
 };
 
+/// `ioctl` commands for the controlling terminal
+const TIOCNOTTY: u32 = 0x5422;
+const TIOCGSID: u32 = 0x5429;
+
 #[derive(Debug, Default)]
 pub struct IoData {
+    /// The session this is the controlling terminal of, 0 if none
+    session: usize,
     foreground_pgid: u32,
     winsize: WinSize,
     termios: Termios,
@@ -33,23 +41,224 @@ pub struct UARTDevice {
     task_domain: Arc<dyn TaskDomain>,
 }
 
+/// The session of init, whose standard files the kernel opens on the terminal
+const INIT_SID: usize = 1;
+
 impl UARTDevice {
+    /// The terminal starts out as the controlling terminal of the session of init.
     pub fn new(
         device_id: DeviceId,
         device: Arc<dyn BufUartDomain>,
         task: Arc<dyn TaskDomain>,
-    ) -> Self {
-        Self {
+    ) -> VfsResult<Self> {
+        let io = IoData {
+            session: INIT_SID,
+            foreground_pgid: INIT_SID as u32,
+            ..Default::default()
+        };
+        let dev = Self {
             device_id,
             device,
-            io: Mutex::new(IoData::default()),
+            io: Mutex::new(io),
             task_domain: task,
+        };
+        dev.update_job_control(&dev.io.lock())?;
+        Ok(dev)
+    }
+}
+
+impl UARTDevice {
+    /// Make `pgid` the foreground group, which gets the signals of the control characters.
+    fn set_foreground(&self, io: &mut IoData, pgid: u32) -> VfsResult<()> {
+        io.foreground_pgid = pgid;
+        self.update_job_control(io)
+    }
+
+    fn update_job_control(&self, io: &IoData) -> VfsResult<()> {
+        let isig = LocalModes::from_bits_truncate(io.termios.lflag).contains(LocalModes::ISIG);
+        let pgid = if io.session == 0 {
+            0
+        } else {
+            io.foreground_pgid as usize
+        };
+        self.device.set_job_control(pgid, isig)?;
+        Ok(())
+    }
+
+    /// Stop background groups of the controlling session from reading, or writing if
+    /// `TOSTOP` is set.
+    fn job_control(&self, signum: SignalNumber) -> VfsResult<()> {
+        let io = self.io.lock();
+        if signum == SignalNumber::SIGTTOU
+            && !LocalModes::from_bits_truncate(io.termios.lflag).contains(LocalModes::TOSTOP)
+        {
+            return Ok(());
+        }
+        let (session, pgid) = (io.session, io.foreground_pgid as usize);
+        drop(io);
+        self.task_domain
+            .do_tty_job_control(session, pgid, signum as usize)?;
+        Ok(())
+    }
+
+    fn get_termios(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        arg.write(&self.io.lock().termios)?;
+        Ok(0)
+    }
+
+    fn set_termios(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let mut io = self.io.lock();
+        io.termios = arg.read()?;
+        self.update_job_control(&io)?;
+        Ok(0)
+    }
+
+    fn get_foreground_pgrp(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        arg.write(&self.io.lock().foreground_pgid)?;
+        Ok(0)
+    }
+
+    fn set_foreground_pgrp(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let io = self.io.lock();
+        let (session, foreground) = (io.session, io.foreground_pgid as usize);
+        let (_, _, sid) = self.task_domain.job_control_ids()?;
+        if session != sid {
+            return Err(AlienError::ENOTTY);
         }
+        // a background group has to be allowed to take the terminal
+        drop(io);
+        self.task_domain
+            .do_tty_job_control(session, foreground, SignalNumber::SIGTTOU as usize)?;
+        let pgid: u32 = arg.read()?;
+        if !self.task_domain.pgrp_in_session(pgid as usize, sid)? {
+            return Err(AlienError::EPERM);
+        }
+        let mut io = self.io.lock();
+        self.set_foreground(&mut io, pgid)?;
+        Ok(0)
+    }
+
+    fn get_winsize(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        arg.write(&self.io.lock().winsize)?;
+        Ok(0)
+    }
+
+    fn set_winsize(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        self.io.lock().winsize = arg.read()?;
+        Ok(0)
+    }
+
+    /// Take the terminal for the session of the caller, with its group in the foreground.
+    /// Only a session leader may, from another session only if root asks for it with the
+    /// argument 1. The VFS asks for it when a session leader opens the terminal.
+    fn set_ctty(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let mut io = self.io.lock();
+        let (pid, pgid, sid) = self.task_domain.job_control_ids()?;
+        if io.session == sid {
+            return Ok(0);
+        }
+        if sid != pid {
+            return Err(AlienError::EPERM);
+        }
+        if io.session != 0 && (arg.value() != 1 || self.task_domain.do_access_ids(false)?.0 != 0) {
+            return Err(AlienError::EPERM);
+        }
+        io.session = sid;
+        self.set_foreground(&mut io, pgid as u32)?;
+        Ok(0)
+    }
+
+    fn release_ctty(&self, _arg: &mut IoctlArg) -> AlienResult<usize> {
+        let mut io = self.io.lock();
+        let (pid, _, sid) = self.task_domain.job_control_ids()?;
+        if io.session != sid {
+            return Err(AlienError::ENOTTY);
+        }
+        // the session leader gives the terminal up for the whole session
+        if sid == pid {
+            io.session = 0;
+            self.update_job_control(&io)?;
+        }
+        Ok(0)
+    }
+
+    fn get_session(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let io = self.io.lock();
+        let (_, _, sid) = self.task_domain.job_control_ids()?;
+        if io.session != sid {
+            return Err(AlienError::ENOTTY);
+        }
+        arg.write(&(io.session as u32))?;
+        Ok(0)
+    }
+
+    fn pending_input(&self, arg: &mut IoctlArg) -> AlienResult<usize> {
+        let pending = self.device.pending_bytes()?;
+        arg.write(&(pending as i32))?;
+        Ok(0)
     }
 }
 
+/// The terminal commands carry no size, so their entries give the argument.
+static UART_IOCTLS: &[Ioctl<UARTDevice>] = &[
+    Ioctl::with_arg(
+        TeletypeCommand::TCGETS as u32,
+        ArgKind::output::<Termios>(),
+        UARTDevice::get_termios,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TCGETA as u32,
+        ArgKind::output::<Termios>(),
+        UARTDevice::get_termios,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TCSETS as u32,
+        ArgKind::input::<Termios>(),
+        UARTDevice::set_termios,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TCSETSW as u32,
+        ArgKind::input::<Termios>(),
+        UARTDevice::set_termios,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TCSETSF as u32,
+        ArgKind::input::<Termios>(),
+        UARTDevice::set_termios,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TIOCGPGRP as u32,
+        ArgKind::output::<u32>(),
+        UARTDevice::get_foreground_pgrp,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TIOCSPGRP as u32,
+        ArgKind::input::<u32>(),
+        UARTDevice::set_foreground_pgrp,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TIOCGWINSZ as u32,
+        ArgKind::output::<WinSize>(),
+        UARTDevice::get_winsize,
+    ),
+    Ioctl::with_arg(
+        TeletypeCommand::TIOCSWINSZ as u32,
+        ArgKind::input::<WinSize>(),
+        UARTDevice::set_winsize,
+    ),
+    Ioctl::with_arg(TIOCSCTTY, ArgKind::Value, UARTDevice::set_ctty),
+    Ioctl::with_arg(TIOCNOTTY, ArgKind::Value, UARTDevice::release_ctty),
+    Ioctl::with_arg(TIOCGSID, ArgKind::output::<u32>(), UARTDevice::get_session),
+    Ioctl::with_arg(
+        FIONREAD,
+        ArgKind::output::<i32>(),
+        UARTDevice::pending_input,
+    ),
+];
+
 impl VfsFile for UARTDevice {
     fn read_at(&self, _offset: u64, mut _buf: DVec<u8>) -> VfsResult<(DVec<u8>, usize)> {
+        self.job_control(SignalNumber::SIGTTIN)?;
         let buf = _buf.as_mut_slice();
         // read util \r and transform to \n
         let mut read_count = 0;
@@ -80,6 +289,7 @@ impl VfsFile for UARTDevice {
         Ok((_buf, read_count))
     }
     fn write_at(&self, _offset: u64, buf: &DVec<u8>) -> VfsResult<usize> {
+        self.job_control(SignalNumber::SIGTTOU)?;
         self.device.put_bytes(buf).unwrap();
         Ok(buf.len())
     }
@@ -94,47 +304,13 @@ impl VfsFile for UARTDevice {
         Ok(res)
     }
     fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
-        let mut io = self.io.lock();
-        let cmd = TeletypeCommand::try_from(cmd).unwrap();
-        match cmd {
-            TeletypeCommand::TCGETS | TeletypeCommand::TCGETA => {
-                self.task_domain
-                    .copy_to_user(arg, io.termios.as_bytes())
-                    .unwrap();
-                Ok(0)
-            }
-            TeletypeCommand::TCSETS | TeletypeCommand::TCSETSW | TeletypeCommand::TCSETSF => {
-                let buf = io.termios.as_bytes_mut();
-                self.task_domain.copy_from_user(arg, buf).unwrap();
-                Ok(0)
-            }
-            TeletypeCommand::TIOCGPGRP => {
-                self.task_domain
-                    .write_val_to_user(arg, &io.foreground_pgid)
-                    .unwrap();
-                Ok(0)
-            }
-            TeletypeCommand::TIOCSPGRP => {
-                let word = self.task_domain.read_val_from_user(arg).unwrap();
-                io.foreground_pgid = word;
-                Ok(0)
-            }
-            TeletypeCommand::TIOCGWINSZ => {
-                self.task_domain
-                    .copy_to_user(arg, io.winsize.as_bytes())
-                    .unwrap();
-                Ok(0)
-            }
-            TeletypeCommand::TIOCSWINSZ => {
-                self.task_domain
-                    .copy_from_user(arg, io.winsize.as_bytes_mut())
-                    .unwrap();
-                Ok(0)
-            }
-            _ => {
-                unimplemented!("ioctl cmd: {:?}", cmd)
-            }
-        }
+        Ok(ioctl::dispatch(
+            UART_IOCTLS,
+            self,
+            &*self.task_domain,
+            cmd,
+            arg,
+        )?)
     }
     fn flush(&self) -> VfsResult<()> {
         Ok(())
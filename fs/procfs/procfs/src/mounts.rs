@@ -1,6 +1,7 @@
 use alloc::sync::Arc;
 use core::cmp::min;
 
+use generic::VFS_DOMAIN;
 use shared_heap::DVec;
 use vfscore::{
     error::VfsError,
@@ -11,18 +12,26 @@ use vfscore::{
     VfsResult,
 };
 
-// todo!(dynamic mount info)
-const MOUNT_INFO: &str = r"
- rootfs / rootfs rw 0 0
- devfs /dev devfs rw 0 0
- fat32 / fat rw 0 0
-";
+/// The largest mount table `/proc/mounts` shows.
+const MOUNT_INFO_SIZE: usize = 4096;
+
 pub struct MountInfo;
 
+impl MountInfo {
+    /// The live mount table of the vfs domain.
+    fn mount_info(&self) -> VfsResult<(DVec<u8>, usize)> {
+        let vfs = VFS_DOMAIN.get().ok_or(VfsError::NoSys)?;
+        let (info, len) = vfs.vfs_mounts(DVec::new_uninit(MOUNT_INFO_SIZE))?;
+        Ok((info, len))
+    }
+}
+
 impl VfsFile for MountInfo {
     fn read_at(&self, offset: u64, mut buf: DVec<u8>) -> VfsResult<(DVec<u8>, usize)> {
-        let min_len = min(buf.len(), MOUNT_INFO.as_bytes().len() - offset as usize);
-        buf.as_mut_slice()[..min_len].copy_from_slice(&MOUNT_INFO.as_bytes()[..min_len]);
+        let (info, len) = self.mount_info()?;
+        let offset = min(offset as usize, len);
+        let min_len = min(buf.len(), len - offset);
+        buf.as_mut_slice()[..min_len].copy_from_slice(&info.as_slice()[offset..offset + min_len]);
         Ok((buf, min_len))
     }
 }
@@ -40,7 +49,7 @@ impl VfsInode for MountInfo {
 
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         Ok(VfsFileStat {
-            st_size: MOUNT_INFO.as_bytes().len() as u64,
+            st_size: self.mount_info()?.1 as u64,
             ..Default::default()
         })
     }